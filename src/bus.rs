@@ -0,0 +1,76 @@
+use std::fmt::{Display, Formatter};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::config::Config;
+
+// 驱动任务的状态变化；由驱动任务发布，桌面通知、OSD等订阅者各自在后台线程中展示
+#[derive(Clone, Debug)]
+pub enum StateEvent {
+    SchemaSwitched { index: usize, name: Option<String> },
+    FineAdjust(bool),            // 进入（true）或退出（false）微调模式
+    AreaChanged(Option<String>), // 切换到的区域预设；为None时表示切换回默认区域
+    MonitorChanged(String),
+    Paused(bool),
+    PressureAdjusted(f32), // 通过pressureSofter、pressureHarder调整后的压力曲线
+    TiltEnabled(bool),
+    SmoothingChanged(f32), // 通过toggleSmoothing、cycleSmoothing切换后的平滑强度
+    RotationChanged(u16),  // 通过rotateMapping切换后的旋转角度
+}
+impl Display for StateEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SchemaSwitched {
+                index,
+                name: Some(name),
+            } => {
+                write!(f, "已切换到按键映射方案{}（{}）", index, name)
+            }
+            Self::SchemaSwitched { index, name: None } => {
+                write!(f, "已切换到按键映射方案{}", index)
+            }
+            Self::FineAdjust(true) => f.write_str("微调模式"),
+            Self::FineAdjust(false) => f.write_str("已退出微调模式"),
+            Self::AreaChanged(Some(name)) => write!(f, "已切换到区域预设{}", name),
+            Self::AreaChanged(None) => f.write_str("已切换回默认区域"),
+            Self::MonitorChanged(name) => write!(f, "已切换到显示器{}", name),
+            Self::Paused(true) => f.write_str("已暂停"),
+            Self::Paused(false) => f.write_str("已恢复"),
+            Self::PressureAdjusted(curve) => write!(f, "压力曲线：{:.2}", curve),
+            Self::TiltEnabled(true) => f.write_str("已开启倾斜"),
+            Self::TiltEnabled(false) => f.write_str("已关闭倾斜"),
+            Self::SmoothingChanged(smoothing) if *smoothing <= 0f32 => f.write_str("已关闭平滑"),
+            Self::SmoothingChanged(smoothing) => write!(f, "平滑强度：{:.2}", smoothing),
+            Self::RotationChanged(rotation) => write!(f, "旋转角度：{}°", rotation),
+        }
+    }
+}
+
+// 订阅者是否接收某个事件；按当前的配置判断，使相关配置支持热更新
+type EventFilter = fn(&Config, &StateEvent) -> bool;
+
+struct Subscriber {
+    filter: EventFilter,
+    sender: Sender<StateEvent>,
+}
+
+// 驱动任务内部的事件总线：把状态变化分发给各个订阅者
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+}
+impl EventBus {
+    pub fn subscribe(&mut self, filter: EventFilter) -> Receiver<StateEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(Subscriber { filter, sender });
+        receiver
+    }
+
+    pub fn publish(&self, conf: &Config, event: StateEvent) {
+        for subscriber in &self.subscribers {
+            if (subscriber.filter)(conf, &event) {
+                // 订阅者的后台线程已退出时忽略
+                let _ = subscriber.sender.send(event.clone());
+            }
+        }
+    }
+}
@@ -1,44 +1,265 @@
-use std::sync::Arc;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::sync::{Arc, OnceLock, Weak};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::eventfd::{EfdFlags, EventFd};
 use parking_lot::Mutex;
 
+use crate::error;
+
+type Callback = Box<dyn FnMut() + Send + Sync>;
+
+// register_callback返回的标识，用于注销回调
+#[derive(Clone, Copy, PartialEq)]
+pub struct CallbackId(u64);
+
 #[derive(Clone)]
 pub struct CancelToken {
-    inner: Arc<Mutex<CancelTokenInner>>,
+    inner: Arc<CancelTokenInner>,
 }
 struct CancelTokenInner {
+    state: Mutex<CancelTokenState>,
+    eventfd: OnceLock<EventFd>, // 首次调用as_fd时创建
+    parent: Option<(CancelToken, CallbackId)>, // 子令牌在父令牌中注册的回调
+}
+struct CancelTokenState {
     cancelled: bool,
-    listeners: Vec<Box<dyn FnMut() + Send + Sync>>,
+    next_id: u64,
+    listeners: Vec<(CallbackId, Callback)>,
 }
-impl CancelToken {
-    pub fn new() -> Self {
+impl CancelTokenInner {
+    fn new(parent: Option<(CancelToken, CallbackId)>) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(CancelTokenInner {
+            state: Mutex::new(CancelTokenState {
                 cancelled: false,
+                next_id: 0,
                 listeners: Vec::new(),
-            })),
+            }),
+            eventfd: OnceLock::new(),
+            parent,
         }
     }
+}
+impl CancelToken {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(CancelTokenInner::new(None)),
+        }
+    }
+
+    // 创建子令牌：父令牌取消时子令牌随之取消，子令牌可以单独取消（例如只停止一个绘图板的任务）；
+    // 子令牌的所有副本都被释放后，自动注销在父令牌中注册的回调
+    pub fn child(&self) -> Self {
+        let inner = Arc::new_cyclic(|weak: &Weak<CancelTokenInner>| {
+            let weak = weak.clone();
+            let id = self.register_callback(move || {
+                if let Some(inner) = weak.upgrade() {
+                    CancelToken { inner }.cancel();
+                }
+            });
+            CancelTokenInner::new(Some((self.clone(), id)))
+        });
+        let child = Self { inner };
+        // 父令牌已经取消时，回调在子令牌创建完成前就已被调用
+        if self.cancelled() {
+            child.cancel();
+        }
+        child
+    }
 
-    pub fn register_callback<F>(&self, callback: F)
+    // 取消时调用回调；已经取消时立即调用
+    pub fn register_callback<F>(&self, callback: F) -> CallbackId
     where
         F: FnMut() + Send + Sync + 'static,
     {
-        let mut inner = self.inner.lock();
-        inner.listeners.push(Box::new(callback));
+        let mut callback: Callback = Box::new(callback);
+        let mut state = self.inner.state.lock();
+        let id = CallbackId(state.next_id);
+        state.next_id += 1;
+        if state.cancelled {
+            drop(state);
+            callback();
+            return id;
+        }
+        state.listeners.push((id, callback));
+        id
+    }
+
+    pub fn unregister_callback(&self, id: CallbackId) {
+        self.inner.state.lock().listeners.retain(|(x, _)| *x != id);
     }
 
     pub fn cancelled(&self) -> bool {
-        self.inner.lock().cancelled
+        self.inner.state.lock().cancelled
     }
 
-    pub fn cancel(&self) {
-        let mut inner = self.inner.lock();
-        if inner.cancelled {
-            return;
+    // 等待取消，最多等待timeout；返回是否已取消
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.wait_until(Instant::now().checked_add(timeout))
+    }
+
+    pub fn wait(&self) {
+        self.wait_until(None);
+    }
+
+    // 通过poll等待as_fd返回的eventfd变为可读；deadline为None时不设超时
+    fn wait_until(&self, deadline: Option<Instant>) -> bool {
+        // 无法创建或等待eventfd时，退化为按该间隔检查是否已取消
+        const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+        loop {
+            if self.cancelled() {
+                return true;
+            }
+            let remaining = deadline.map(|x| x.saturating_duration_since(Instant::now()));
+            if remaining.is_some_and(|x| x.is_zero()) {
+                return false;
+            }
+            let timeout = match remaining {
+                Some(x) => PollTimeout::try_from(x).unwrap_or(PollTimeout::MAX),
+                None => PollTimeout::NONE,
+            };
+            let result = self.as_fd().and_then(|fd| {
+                match poll(&mut [PollFd::new(fd, PollFlags::POLLIN)], timeout) {
+                    Ok(_) | Err(Errno::EINTR) => Ok(()),
+                    Err(e) => Err(e).context("poll"),
+                }
+            });
+            if let Err(e) = result {
+                error!("无法通过eventfd等待取消: {:?}", e);
+                std::thread::sleep(remaining.map_or(RETRY_INTERVAL, |x| x.min(RETRY_INTERVAL)));
+            }
         }
-        inner.cancelled = true;
-        for listener in &mut inner.listeners {
+    }
+
+    // 取消后变为可读的文件描述符，可以直接加入epoll；不要读取其中的值
+    pub fn as_fd(&self) -> Result<BorrowedFd<'_>> {
+        let state = self.inner.state.lock();
+        let eventfd = match self.inner.eventfd.get() {
+            Some(eventfd) => eventfd,
+            None => {
+                let eventfd =
+                    EventFd::from_value_and_flags(state.cancelled as u32, EfdFlags::EFD_NONBLOCK)
+                        .context("EventFd::from_value_and_flags")?;
+                self.inner.eventfd.get_or_init(|| eventfd)
+            }
+        };
+        Ok(eventfd.as_fd())
+    }
+
+    pub fn cancel(&self) {
+        let listeners = {
+            let mut state = self.inner.state.lock();
+            if state.cancelled {
+                return;
+            }
+            state.cancelled = true;
+            if let Some(eventfd) = self.inner.eventfd.get()
+                && let Err(e) = eventfd.write(1)
+            {
+                error!("无法通过写eventfd通知取消: {}", e);
+            }
+            std::mem::take(&mut state.listeners)
+        };
+        // 在锁外调用回调，回调中可以访问本令牌（例如注销回调）
+        for (_, mut listener) in listeners {
             listener();
         }
     }
 }
+impl Drop for CancelTokenInner {
+    fn drop(&mut self) {
+        if let Some((parent, id)) = &self.parent {
+            parent.unregister_callback(*id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn readable(fd: BorrowedFd) -> bool {
+        let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+        poll(&mut fds, PollTimeout::ZERO).unwrap() == 1
+    }
+
+    #[test]
+    fn child_tokens() {
+        let parent = CancelToken::new();
+        let child = parent.child();
+        let grandchild = child.child();
+        // 子令牌单独取消时不影响父令牌
+        let sibling = parent.child();
+        sibling.cancel();
+        assert!(!parent.cancelled() && !child.cancelled());
+        parent.cancel();
+        assert!(child.cancelled() && grandchild.cancelled());
+        // 父令牌已经取消时，新建的子令牌立即取消
+        assert!(parent.child().cancelled());
+        // 子令牌释放后，在父令牌中注册的回调随之注销
+        let parent = CancelToken::new();
+        drop(parent.child());
+        assert!(parent.inner.state.lock().listeners.is_empty());
+    }
+
+    #[test]
+    fn callbacks() {
+        let token = CancelToken::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let callback = |count: &Arc<AtomicUsize>| {
+            let count = count.clone();
+            move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+        token.register_callback(callback(&count));
+        let id = token.register_callback(callback(&count));
+        token.unregister_callback(id);
+        token.cancel();
+        token.cancel();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        // 已经取消时立即调用
+        token.register_callback(callback(&count));
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn fd_becomes_readable() {
+        let token = CancelToken::new();
+        assert!(!readable(token.as_fd().unwrap()));
+        token.cancel();
+        assert!(readable(token.as_fd().unwrap()));
+        // 取消之后才创建的eventfd同样可读
+        let token = CancelToken::new();
+        token.cancel();
+        assert!(readable(token.as_fd().unwrap()));
+        // 父令牌取消时子令牌的eventfd也可读
+        let parent = CancelToken::new();
+        let child = parent.child();
+        let fd = child.as_fd().unwrap();
+        parent.cancel();
+        assert!(readable(fd));
+    }
+
+    #[test]
+    fn wait() {
+        let token = CancelToken::new();
+        assert!(!token.wait_timeout(Duration::from_millis(10)));
+        let thread = std::thread::spawn({
+            let token = token.clone();
+            move || {
+                token.wait();
+                token.wait_timeout(Duration::from_secs(60))
+            }
+        });
+        std::thread::sleep(Duration::from_millis(10));
+        token.cancel();
+        assert!(thread.join().unwrap());
+    }
+}
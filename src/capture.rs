@@ -0,0 +1,169 @@
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow};
+use evdev_rs::DeviceWrapper;
+use rusb::{DeviceHandle as UsbDeviceHandle, Error as UsbError, GlobalContext};
+
+use crate::cancel::CancelToken;
+use crate::config::DeviceConfig;
+use crate::driver::{self, IN_ENDPOINT, INPUT_USAGE_BUF_SIZE, READ_INTERRUPT_TIMEOUT};
+use crate::profile::DeviceProfile;
+use crate::{info, warn};
+
+const MAX_BUTTON_COUNT: usize = 8;
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+const SWEEP_TIMEOUT: Duration = Duration::from_secs(60);
+const PROFILE_FILENAME: &str = "profile.toml";
+const FIXTURE_FILENAME: &str = "fixture.log";
+
+// capture-profile子命令：引导用户依次按下各按钮、转动转环并用画笔扫过整个绘图板，
+// 据此生成候选的设备描述与测试用的原始事件记录，便于为新型号添加支持
+pub fn run(cancel_token: CancelToken, device: &DeviceConfig, output_dir: &Path) -> Result<()> {
+    let name = match driver::open_evdev(device) {
+        Ok(evdev) => evdev.name().unwrap_or_default().to_string(),
+        Err(e) => {
+            warn!("无法打开事件设备，设备描述中将不包含设备名称: {:?}", e);
+            String::new()
+        }
+    };
+    let handle = driver::open_usb_device_handle(device).context("无法打开USB设备句柄")?;
+    let mut capture = Capture {
+        cancel_token,
+        handle,
+        started_at: Instant::now(),
+        fixture: String::new(),
+    };
+    let mut profile = DeviceProfile {
+        name,
+        vendor_id: device.vendor_id,
+        product_id: device.product_id,
+        ..Default::default()
+    };
+
+    for i in 0..MAX_BUTTON_COUNT {
+        info!(
+            "请按下并松开按钮{}（没有该按钮时等待{}秒跳过）",
+            i,
+            STEP_TIMEOUT.as_secs()
+        );
+        match capture.wait_button(STEP_TIMEOUT)? {
+            Some(code) => {
+                info!("按钮{}的按键码为{:#06x}", i, code);
+                profile.buttons.push(code);
+            }
+            None => {
+                info!("没有检测到按钮{}，跳过其余按钮", i);
+                break;
+            }
+        }
+    }
+    for (desc, field) in [
+        ("顺时针转动转环", &mut profile.ring_cw),
+        ("逆时针转动转环", &mut profile.ring_ccw),
+        ("按下转环中心按钮", &mut profile.ring_button),
+    ] {
+        info!(
+            "请{}（没有转环时等待{}秒跳过）",
+            desc,
+            STEP_TIMEOUT.as_secs()
+        );
+        *field = capture.wait_button(STEP_TIMEOUT)?;
+        if let Some(code) = field {
+            info!("{}的按键码为{:#06x}", desc, code);
+        }
+    }
+
+    info!(
+        "请用画笔扫过绘图板的整个区域（包括四个角），并用力按压笔尖；完成后按下任意按钮（最长{}秒）",
+        SWEEP_TIMEOUT.as_secs()
+    );
+    let deadline = Instant::now() + SWEEP_TIMEOUT;
+    while let Some(report) = capture.read_until(deadline)? {
+        match report[0] & 0xf0 {
+            0xf0 if report[1..3] != [0, 0] => break,
+            0xa0 | 0xc0 => {
+                // 调换原始输入的X、Y坐标
+                let y = u16::from_le_bytes([report[1], report[2]]);
+                let x = u16::from_le_bytes([report[3], report[4]]);
+                let pressure = u16::from_le_bytes([report[5], report[6]]);
+                profile.x_max_value = profile.x_max_value.max(x);
+                profile.y_max_value = profile.y_max_value.max(y);
+                profile.pressure_max_value = profile.pressure_max_value.max(pressure);
+            }
+            _ => {}
+        }
+    }
+    info!(
+        "观测到的坐标最大值为({},{})，压力最大值为{}",
+        profile.x_max_value, profile.y_max_value, profile.pressure_max_value
+    );
+
+    std::fs::create_dir_all(output_dir).context(format!("无法创建目录{}", output_dir.display()))?;
+    let profile_path = output_dir.join(PROFILE_FILENAME);
+    let content = toml::to_string(&profile).context("无法序列化设备描述")?;
+    std::fs::write(&profile_path, content)
+        .context(format!("无法写入文件{}", profile_path.display()))?;
+    let fixture_path = output_dir.join(FIXTURE_FILENAME);
+    std::fs::write(&fixture_path, &capture.fixture)
+        .context(format!("无法写入文件{}", fixture_path.display()))?;
+    info!(
+        "已将候选的设备描述写入{}，原始事件记录写入{}",
+        profile_path.display(),
+        fixture_path.display()
+    );
+    Ok(())
+}
+
+struct Capture {
+    cancel_token: CancelToken,
+    handle: UsbDeviceHandle<GlobalContext>,
+    started_at: Instant,
+    fixture: String, // 每行为「毫秒时间戳 原始报告的十六进制」
+}
+impl Capture {
+    // 读取下一个0x02用途的报告（不含报告ID）；超过期限时返回None
+    fn read_until(&mut self, deadline: Instant) -> Result<Option<Vec<u8>>> {
+        let mut buf = [0u8; INPUT_USAGE_BUF_SIZE];
+        while Instant::now() < deadline {
+            if self.cancel_token.cancelled() {
+                return Err(anyhow!("已取消"));
+            }
+            let len =
+                match self
+                    .handle
+                    .read_interrupt(IN_ENDPOINT, &mut buf, READ_INTERRUPT_TIMEOUT)
+                {
+                    Ok(len) => len,
+                    Err(UsbError::Timeout) => continue,
+                    Err(e) => return Err(anyhow!("读取USB设备的中断端点时发生错误: {}", e)),
+                };
+            let report = &buf[..len];
+            let _ = write!(self.fixture, "{}", self.started_at.elapsed().as_millis());
+            for byte in report {
+                let _ = write!(self.fixture, " {:02x}", byte);
+            }
+            self.fixture.push('\n');
+            if report.len() == INPUT_USAGE_BUF_SIZE && report[0] == 0x02 {
+                return Ok(Some(report[1..].to_vec()));
+            }
+        }
+        Ok(None)
+    }
+
+    // 等待按下按钮（或转动转环），返回其按键码；超时返回None
+    fn wait_button(&mut self, timeout: Duration) -> Result<Option<u16>> {
+        let deadline = Instant::now() + timeout;
+        while let Some(report) = self.read_until(deadline)? {
+            if report[0] & 0xf0 != 0xf0 {
+                continue;
+            }
+            let code = ((report[1] as u16) << 8) | (report[2] as u16);
+            if code != 0x0000 {
+                return Ok(Some(code));
+            }
+        }
+        Ok(None)
+    }
+}
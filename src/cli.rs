@@ -0,0 +1,364 @@
+use std::path::PathBuf;
+
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+
+use crate::config::ConfigOverrides;
+
+// 版本号与构建时的git提交哈希，用于--version、启动日志与诊断信息
+pub const VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), " (", env!("GIT_HASH"), ")");
+
+const HELP_TEMPLATE: &str = "{about-with-newline}\n用法：{usage}\n\n{all-args}{after-help}";
+
+// 命令行参数：不指定子命令时运行驱动程序；每个子命令只接受各自的选项，
+// 例如status -h打印status的帮助，而不是驱动程序的帮助
+#[derive(Parser)]
+#[command(
+    version = VERSION,
+    about = "Parblo Intangbo M绘图板的用户态驱动程序",
+    after_help = "未指定配置文件时，依次查找$XDG_CONFIG_HOME/parblo-intangbo-m/config.toml与/etc/parblo-intangbo-m/config.toml。",
+    disable_version_flag = true,
+    disable_help_subcommand = true,
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub run: RunArgs,
+    #[arg(long, exclusive = true, help = "打印带注释的默认配置文件")]
+    pub print_default_config: bool,
+    #[arg(short, long, global = true, action = ArgAction::Help, help = "打印本帮助")]
+    help: Option<bool>,
+    #[arg(short = 'V', long, action = ArgAction::Version, help = "打印版本号")]
+    version: Option<bool>,
+}
+impl Cli {
+    // 与Parser::parse相同，但帮助信息使用中文的标题
+    pub fn parse() -> Self {
+        let matches = localize(Self::command()).get_matches();
+        Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+}
+
+fn localize(command: clap::Command) -> clap::Command {
+    command
+        .help_template(HELP_TEMPLATE)
+        .subcommand_help_heading("子命令")
+        .subcommand_value_name("子命令")
+        .disable_help_flag(true)
+        .mut_args(|arg| {
+            let heading = if arg.is_positional() {
+                "参数"
+            } else {
+                "选项"
+            };
+            arg.help_heading(heading)
+        })
+        .mut_subcommands(localize)
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "校准xMap、yMap并写回配置文件")]
+    Calibrate(CalibrateArgs),
+    #[command(about = "回放record记录的原始报告")]
+    Replay(ReplayArgs),
+    #[command(about = "采集设备描述")]
+    CaptureProfile(ConfigArgs),
+    #[command(about = "列出候选的设备")]
+    ListDevices(ConfigArgs),
+    #[command(about = "打印事件设备的属性")]
+    Probe(ConfigArgs),
+    #[command(about = "检查配置文件")]
+    Check(CheckArgs),
+    #[command(about = "导入xsetwacom的设置")]
+    ImportXsetwacom(ImportXsetwacomArgs),
+    #[command(about = "安装授予访问权限的udev规则")]
+    InstallUdevRules(InstallUdevRulesArgs),
+    #[command(about = "安装systemd服务")]
+    InstallService(InstallServiceArgs),
+    #[command(flatten)]
+    Client(ClientCommand),
+}
+
+// 配置文件的路径，可以写在最后，也可以通过--config指定
+#[derive(Args, Default)]
+pub struct ConfigArgs {
+    #[arg(
+        long = "config",
+        value_name = "路径",
+        help = "配置文件的路径，与<配置文件>参数相同"
+    )]
+    config_flag: Option<PathBuf>,
+    #[arg(
+        value_name = "配置文件",
+        conflicts_with = "config_flag",
+        help = "配置文件的路径"
+    )]
+    config: Option<PathBuf>,
+}
+impl ConfigArgs {
+    pub fn path(self) -> Option<PathBuf> {
+        self.config_flag.or(self.config)
+    }
+}
+
+// 驱动、校准与回放模式共用的选项
+#[derive(Args, Default)]
+pub struct DriverOptions {
+    #[arg(long, help = "不创建虚拟设备，打印将要写入的事件")]
+    pub dry_run: bool,
+    #[command(flatten)]
+    pub overrides: ConfigOverrides,
+}
+
+// 只用于驱动模式的选项
+#[derive(Args, Default)]
+pub struct DaemonOptions {
+    #[arg(long, help = "后台运行")]
+    pub daemon: bool,
+    #[arg(long, value_name = "路径", help = "写入PID文件")]
+    pub pid_file: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "路径",
+        requires = "daemon",
+        help = "后台运行时的日志文件"
+    )]
+    pub log_file: Option<PathBuf>,
+}
+
+#[derive(Args, Default)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub options: DriverOptions,
+    #[command(flatten)]
+    pub daemon: DaemonOptions,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Args)]
+pub struct CalibrateArgs {
+    #[command(flatten)]
+    pub options: DriverOptions,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    #[arg(value_name = "记录文件", help = "record子命令写入的记录文件")]
+    pub record: PathBuf,
+    #[command(flatten)]
+    pub options: DriverOptions,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Args)]
+pub struct CheckArgs {
+    #[arg(value_name = "配置文件", help = "要检查的配置文件")]
+    pub config: PathBuf,
+}
+
+#[derive(Args)]
+#[group(id = "source", required = true, multiple = true, args = ["area", "map_to_output"])]
+pub struct ImportXsetwacomArgs {
+    // 既可以整体作为一个参数，也可以分为四个参数，便于直接粘贴xsetwacom get的输出
+    #[arg(
+        long,
+        num_args = 1..=4,
+        value_name = "x1 y1 x2 y2",
+        help = "xsetwacom的Area"
+    )]
+    pub area: Option<Vec<String>>,
+    #[arg(
+        long,
+        value_name = "显示器名称|HEAD-<序号>|宽x高+X+Y|desktop",
+        help = "xsetwacom的MapToOutput"
+    )]
+    pub map_to_output: Option<String>,
+    #[arg(
+        long,
+        value_name = "配置文件",
+        requires = "map_to_output",
+        help = "把xMap、yMap写入配置文件"
+    )]
+    pub write: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct InstallUdevRulesArgs {
+    #[arg(long, help = "只打印规则，不写入")]
+    pub print: bool,
+    #[arg(
+        long,
+        value_name = "用户组",
+        help = "授予用户组访问权限，而不是当前登录的用户"
+    )]
+    pub group: Option<String>,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+#[derive(Args)]
+pub struct InstallServiceArgs {
+    #[arg(long, help = "安装为当前用户的服务")]
+    pub user: bool,
+    #[arg(long, help = "同时启用并启动服务")]
+    pub enable: bool,
+    #[arg(long, help = "只打印服务文件，不写入")]
+    pub print: bool,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+}
+
+// 向正在运行的驱动程序发送命令的子命令
+#[derive(Subcommand)]
+pub enum ClientCommand {
+    #[command(about = "直接发送控制协议中的命令，并打印JSON格式的回复")]
+    Ctl {
+        #[arg(value_name = "命令")]
+        command: String,
+        #[command(flatten)]
+        target: ClientTarget,
+    },
+    #[command(about = "查询各绘图板的状态与运行统计")]
+    Status(ClientTarget),
+    #[command(about = "切换按键映射方案")]
+    SwitchSchema {
+        #[arg(value_name = "方案名称或下标")]
+        schema: String,
+        #[command(flatten)]
+        target: ClientTarget,
+    },
+    #[command(about = "重新加载配置文件")]
+    Reload(ClientTarget),
+    #[command(about = "暂停驱动并关闭设备")]
+    Pause(ClientTarget),
+    #[command(about = "恢复驱动")]
+    Resume(ClientTarget),
+    #[command(about = "在运行时修改属性，值按JSON解析，为null时恢复为配置文件中的值")]
+    Set {
+        #[arg(value_name = "属性")]
+        property: String,
+        #[arg(value_name = "值|null", allow_hyphen_values = true)]
+        value: String,
+        #[command(flatten)]
+        target: ClientTarget,
+    },
+    #[command(about = "打印属性当前生效的值")]
+    Get {
+        #[arg(value_name = "属性")]
+        property: Option<String>,
+        #[command(flatten)]
+        target: ClientTarget,
+    },
+    #[command(about = "持续打印解码后的输入事件")]
+    Monitor(ClientTarget),
+    #[command(about = "把原始报告记录到文件，可用于replay子命令")]
+    Record {
+        #[arg(value_name = "文件")]
+        output: PathBuf,
+        #[command(flatten)]
+        target: ClientTarget,
+    },
+}
+
+#[derive(Args)]
+pub struct ClientTarget {
+    #[arg(
+        long,
+        value_name = "路径",
+        help = "控制套接字的路径（配置了controlSocket时）"
+    )]
+    pub socket: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "序号",
+        help = "绘图板的序号；省略时作用于所有绘图板"
+    )]
+    pub tablet: Option<usize>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        let args = std::iter::once("parblo-intangbo-m-driver").chain(args.iter().copied());
+        let matches = localize(Cli::command()).try_get_matches_from(args)?;
+        Cli::from_arg_matches(&matches)
+    }
+
+    #[test]
+    fn definition_is_valid() {
+        localize(Cli::command()).debug_assert();
+    }
+
+    #[test]
+    fn driver_options_and_config() {
+        let cli = parse(&["--dry-run", "--rotation", "180", "config.toml"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(cli.run.options.dry_run);
+        assert_eq!(cli.run.config.path(), Some(PathBuf::from("config.toml")));
+        let cli = parse(&["--config", "config.toml"]).unwrap();
+        assert_eq!(cli.run.config.path(), Some(PathBuf::from("config.toml")));
+        assert!(parse(&["--config", "a.toml", "b.toml"]).is_err());
+        assert!(parse(&["--rotation", "45"]).is_err());
+        assert!(parse(&["--log-file", "driver.log"]).is_err());
+    }
+
+    #[test]
+    fn subcommands_parse_their_own_flags() {
+        // -h属于子命令，不会被驱动程序的帮助截获
+        let e = parse(&["status", "-h"]).err().unwrap();
+        assert_eq!(e.kind(), clap::error::ErrorKind::DisplayHelp);
+        assert!(e.to_string().contains("status"));
+        assert!(parse(&["status", "--dry-run"]).is_err());
+        assert!(parse(&["calibrate", "--daemon", "config.toml"]).is_err());
+        let cli = parse(&["replay", "--dry-run", "stroke.log", "config.toml"]).unwrap();
+        let Some(Command::Replay(args)) = cli.command else {
+            panic!("应当解析为replay");
+        };
+        assert_eq!(args.record, PathBuf::from("stroke.log"));
+        assert!(args.options.dry_run);
+        assert_eq!(args.config.path(), Some(PathBuf::from("config.toml")));
+        let cli = parse(&["set", "--tablet", "1", "rotation", "-90"]).unwrap();
+        let Some(Command::Client(ClientCommand::Set { value, target, .. })) = cli.command else {
+            panic!("应当解析为set");
+        };
+        assert_eq!((value.as_str(), target.tablet), ("-90", Some(1)));
+    }
+
+    #[test]
+    fn import_xsetwacom_area() {
+        let cli = parse(&["import-xsetwacom", "--area", "0", "0", "100", "100"]).unwrap();
+        let Some(Command::ImportXsetwacom(args)) = cli.command else {
+            panic!("应当解析为import-xsetwacom");
+        };
+        assert_eq!(args.area.map(|x| x.len()), Some(4));
+        assert!(parse(&["import-xsetwacom"]).is_err());
+        assert!(
+            parse(&[
+                "import-xsetwacom",
+                "--area",
+                "0 0 100 100",
+                "--write",
+                "a.toml"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn print_default_config_is_exclusive() {
+        assert!(
+            parse(&["--print-default-config"])
+                .unwrap()
+                .print_default_config
+        );
+        assert!(parse(&["--print-default-config", "--dry-run"]).is_err());
+    }
+}
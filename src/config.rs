@@ -1,25 +1,36 @@
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter};
+use std::os::fd::{AsFd, BorrowedFd};
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Error, Result, anyhow};
-use evdev_rs::enums::EV_KEY;
+use evdev_rs::enums::{EV_KEY, int_to_ev_key};
+use log::LevelFilter;
 use nix::errno::Errno;
-use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
 use nix::sys::eventfd::{EfdFlags, EventFd};
-use nix::sys::inotify::{self, Inotify, InotifyEvent};
+use nix::sys::inotify::{self, Inotify, InotifyEvent, WatchDescriptor};
 use serde::Deserialize;
 
-use crate::cancel::CancelToken;
-use crate::error;
+use crate::event_loop::EventHandler;
+use crate::logger::{self, LogFormat};
+use crate::paths;
+use crate::profile::{DeviceProfile, PadKey};
+use crate::regex::Regex;
+use crate::rule::{Rule, TimeRange};
+use crate::screen::{self, MonitorMap};
 use crate::warn;
+use crate::{debug, error};
 
 macro_rules! try_into {
     ($value: ident => $($field:ident),+ $(,)?) => {
         Ok(Self {
             $(
-                $field: $value.$field.try_into().context(concat!("转换字段'", stringify!($field), "'时发生错误"))?
+                $field: $value.$field.try_into().map_err(|e| {
+                    with_field_path(Error::from(e), &camel_case(stringify!($field)))
+                })?
             ),+
         })
     };
@@ -40,13 +51,292 @@ struct RawConfig {
     // Y轴的比例映射
     y_map: Option<(f32, f32)>,
 
+    // 绘图板的有效区域
+    area: Option<RawAreaConfig>,
+
+    // 可通过cycleArea依次切换的有效区域预设
+    #[serde(default, rename = "areaPreset")]
+    area_presets: Vec<RawAreaPresetConfig>,
+
+    // X轴的响应曲线：以中心为原点的幂函数指数；大于1时边缘更灵敏，小于1时中心更灵敏
+    x_gamma: Option<f32>,
+
+    // Y轴的响应曲线
+    y_gamma: Option<f32>,
+
+    // 压力曲线：归一化压力的幂函数指数；大于1时需要更用力，小于1时更轻的压力即可画出粗线
+    pressure_curve: Option<f32>,
+
+    // pressureSofter、pressureHarder每次调整压力曲线的幅度
+    pressure_step: Option<f32>,
+
+    // 坐标平滑强度：0（默认，不平滑）到1（不含）之间；越大越平滑，但画笔的跟随越滞后
+    smoothing: Option<f32>,
+
+    // cycleSmoothing依次切换的平滑强度，每个的取值范围与smoothing相同；默认为[0, 0.5, 0.8]
+    smoothing_presets: Option<Vec<f32>>,
+
+    // 按键或转环操作后，在该时长（毫秒）内忽略新的落笔与笔侧键按下，避免手掌误触
+    palm_block: Option<u64>,
+
+    // 绘图板的旋转角度（顺时针）：0（默认）、90、180或270
+    rotation: Option<u16>,
+
+    // 微调模式下画笔移动的增益
+    fine_adjust_gain: Option<f32>,
+
+    // 相对模式下每个设备坐标单位对应的指针移动量
+    relative_speed: Option<f32>,
+
+    // 画笔移动报告的最大上报频率（Hz）；超出时合并为最新的一个报告
+    max_report_rate: Option<u32>,
+
+    // 统计画笔报告从读取到写入虚拟设备的延迟
+    latency_stats: Option<bool>,
+
+    // 画笔离开感应范围且没有按键输入达到该时长（毫秒）后进入空闲状态，减少程序醒来的次数
+    idle_timeout: Option<u64>,
+
+    // 坐标超出最大值时（设备可能切换了工作模式）的处理方式："warn"（默认）、"rescale"或"off"
+    mode_detection: Option<String>,
+
+    // 映射到指定的显示器或桌面区域，自动计算X、Y轴的比例映射
+    screen: Option<RawScreenConfig>,
+
+    // 跟随鼠标指针所在的显示器自动切换映射区域
+    follow_monitor: Option<bool>,
+
+    // 悬停点击：画笔悬停且保持不动一段时间后自动点击（辅助功能）
+    dwell_click: Option<RawDwellClickConfig>,
+
+    // 按获得焦点的窗口自动切换按键映射方案的规则（仅支持X11）
+    #[serde(default, rename = "appProfile")]
+    app_profiles: Vec<RawAppProfileConfig>,
+
+    // 按获得焦点的窗口、显示器与时间段自动选择按键映射方案、区域预设与压力曲线的规则
+    #[serde(default, rename = "rule")]
+    rules: Vec<RawRuleConfig>,
+
+    // 切换按键映射方案等状态变化时发送桌面通知
+    notifications: Option<bool>,
+
+    // 切换按键映射方案、微调模式等状态变化时在屏幕上短暂显示
+    osd: Option<bool>,
+
+    // 在本地记录功能使用统计
+    usage_statistics: Option<bool>,
+
+    // 是否允许exec行为；以root身份运行时默认不允许
+    allow_exec: Option<bool>,
+
+    // exec行为允许执行的程序列表；未配置时不做限制
+    exec_allowlist: Option<Vec<String>>,
+
+    // 设备匹配规则
+    device: Option<RawDeviceConfig>,
+
+    // 虚拟绘图板的设备名称
+    virtual_device_name: Option<String>,
+
+    // 虚拟键盘的设备名称
+    virtual_keyboard_name: Option<String>,
+
+    // 虚拟鼠标的设备名称
+    virtual_mouse_name: Option<String>,
+
+    // 按键与转环的输出方式："keymap"（默认，按按键映射方案转换为键盘按键等）或"native"（由虚拟的Pad设备上报BTN_0等原始事件）
+    pad_mode: Option<String>,
+
+    // padMode为native时虚拟Pad设备的名称
+    virtual_pad_name: Option<String>,
+
+    // 虚拟绘图板各坐标轴的fuzz、flat与分辨率
+    virtual_axes: Option<RawVirtualAxesConfig>,
+
+    // 控制套接字的路径
+    control_socket: Option<PathBuf>,
+
+    // 以root身份启动时，所有绘图板的虚拟设备创建完成后切换到的用户；只能在顶层配置
+    run_as_user: Option<String>,
+
+    // 切换到的用户组；未配置时使用runAsUser的主用户组
+    run_as_group: Option<String>,
+
+    // 登录会话变为非活动状态（切换虚拟终端、切换用户）时暂停注入事件
+    pause_on_inactive_session: Option<bool>,
+
+    // 程序不属于任何登录会话时，跟随该用户在seat0上的会话
+    session_user: Option<String>,
+
+    // 初始化完成后是否通过seccomp限制可用的系统调用；需要运行外部程序时不会启用
+    sandbox: Option<bool>,
+
+    // 日志级别（off、error、warn、info、debug、trace）；环境变量RUST_LOG优先
+    log_level: Option<String>,
+
+    // 日志格式（text、json）
+    log_format: Option<String>,
+
+    // 把按键映射中无效的配置替换为none并输出警告，而不是拒绝整个配置文件
+    ignore_invalid_keymaps: Option<bool>,
+
+    // 配置文件中存在未知的配置项（例如拼错了名称）时拒绝整个配置文件，而不是只输出警告
+    deny_unknown_keys: Option<bool>,
+
+    // 应用于每个按键映射方案的默认配置：方案中未配置的字段先使用这里的配置，再按fallback处理
+    keymap_defaults: Option<RawKeymapConfig>,
+
     // 按键映射配置方案
     #[serde(rename = "keymap")]
     keymaps: Vec<RawKeymapConfig>,
+
+    // 由同一进程驱动的其他绘图板；每一项都是一份完整的配置（不能再嵌套tablet）
+    #[serde(default, rename = "tablet")]
+    tablets: Vec<RawConfig>,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawAreaConfig {
+    // 取值范围的单位："raw"（设备坐标，默认）或"mm"（毫米）
+    unit: Option<String>,
+
+    // X轴的有效范围
+    x: Option<(f32, f32)>,
+
+    // Y轴的有效范围（从上往下）
+    y: Option<(f32, f32)>,
+
+    // 画笔在有效区域外时的行为："clamp"（限制在边界上，默认）或"ignore"（忽略输入，直到画笔重新进入有效区域）
+    outside: Option<String>,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawAreaPresetConfig {
+    // 预设名称，用于日志输出
+    name: String,
+
+    #[serde(flatten)]
+    area: RawAreaConfig,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawDwellClickConfig {
+    // 保持不动多长时间后触发（毫秒）；默认为1000
+    delay: Option<u64>,
+
+    // 视为保持不动的移动范围（设备坐标）；默认为100
+    radius: Option<u16>,
+
+    // 触发的动作："click"（默认，鼠标左键单击）、"rightClick"或按键组合，例如"ctrl+z"
+    action: Option<String>,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawAppProfileConfig {
+    // 窗口的WM_CLASS（实例名或类名，不区分大小写），例如"krita"
+    app: String,
+
+    // 窗口获得焦点时切换到的按键映射方案：方案名称或下标
+    schema: RawSchemaRef,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawRuleConfig {
+    // 规则名称，用于日志输出
+    name: Option<String>,
+
+    // 匹配窗口WM_CLASS（实例名或类名）的正则表达式
+    class: Option<String>,
+
+    // 匹配窗口标题的正则表达式
+    title: Option<String>,
+
+    // 绘图板当前映射到的显示器（通过followMonitor或cycleMonitor切换）
+    monitor: Option<String>,
+
+    // 一天中的时间段，例如"09:00-18:00"
+    time: Option<String>,
+
+    // 规则生效时切换到的按键映射方案：方案名称或下标
+    schema: Option<RawSchemaRef>,
+
+    // 规则生效时切换到的区域预设名称
+    area_preset: Option<String>,
+
+    // 规则生效时使用的压力曲线
+    pressure_curve: Option<f32>,
+}
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawSchemaRef {
+    Index(usize),
+    Name(String),
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawDeviceConfig {
+    // USB设备的vendor_id
+    vendor_id: Option<u16>,
+
+    // USB设备的product_id
+    product_id: Option<u16>,
+
+    // 通过2.4G无线接收器连接时的product_id
+    dongle_product_id: Option<u16>,
+
+    // 通过蓝牙连接时的product_id
+    bluetooth_product_id: Option<u16>,
+
+    // USB设备所在的端口，格式同sysfs中的设备名称，例如"1-2.3"；用于区分多个相同型号的绘图板
+    port: Option<String>,
+
+    // 事件设备名称的匹配模式，支持*与?通配符
+    name: Option<String>,
+
+    // 事件设备的路径，例如/dev/input/by-id/...；不能与name同时配置
+    path: Option<PathBuf>,
+
+    // 设备描述文件的路径（可由capture-profile生成）；未配置时使用内置的Parblo Intangbo M的描述
+    profile: Option<PathBuf>,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawVirtualAxesConfig {
+    // 调换后的X轴
+    x: Option<RawAxisConfig>,
+
+    // 调换后的Y轴
+    y: Option<RawAxisConfig>,
+
+    // 压力轴
+    pressure: Option<RawAxisConfig>,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawAxisConfig {
+    // 变化小于该值时视为抖动；默认为0
+    fuzz: Option<u16>,
+
+    // 中心死区；默认为0
+    flat: Option<u16>,
+
+    // 分辨率（坐标轴为设备坐标/毫米）；未配置时使用设备描述或事件设备提供的值
+    resolution: Option<u16>,
+}
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawScreenConfig {
+    // 显示器名称，例如"DP-1"
+    monitor: Option<String>,
+
+    // 桌面中的像素区域，格式为"宽x高+X+Y"
+    geometry: Option<String>,
 }
 #[derive(Deserialize)]
 #[serde(default, rename_all = "camelCase")]
 struct RawKeymapConfig {
+    name: Option<String>,    // 方案名称，可用于switch-schema命令
+    inherit: Option<String>, // 未配置的字段从该名称的方案中获取，而不是前一个方案
     button0: String,
     button1: String,
     button2: String,
@@ -58,6 +348,88 @@ struct RawKeymapConfig {
     ring0: String,
     ring1: String,
     ring_button: String,
+    tip_mode: String,
+    pen_mode: String,
+    x_map: Option<(f32, f32)>,           // 覆盖全局的xMap
+    y_map: Option<(f32, f32)>,           // 覆盖全局的yMap
+    proximity: RawProximityKeymapConfig, // 画笔在感应范围内时使用的按键映射
+}
+// 未配置的字段使用所在方案中的按键映射；不会沿用上一个方案的proximity配置
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct RawProximityKeymapConfig {
+    button0: Option<String>,
+    button1: Option<String>,
+    button2: Option<String>,
+    button3: Option<String>,
+    button4: Option<String>,
+    button5: Option<String>,
+    button6: Option<String>,
+    button7: Option<String>,
+    ring0: Option<String>,
+    ring1: Option<String>,
+    ring_button: Option<String>,
+}
+impl RawConfig {
+    // keymapDefaults与所有的按键映射方案及其路径；prefix为空或形如tablet[0].
+    fn keymaps_mut(&mut self, prefix: &str) -> Vec<(String, &mut RawKeymapConfig)> {
+        let mut keymaps = Vec::with_capacity(self.keymaps.len() + 1);
+        if let Some(defaults) = &mut self.keymap_defaults {
+            keymaps.push((format!("{}keymapDefaults", prefix), defaults));
+        }
+        for (i, keymap) in self.keymaps.iter_mut().enumerate() {
+            keymaps.push((format!("{}keymap[{}]", prefix, i), keymap));
+        }
+        keymaps
+    }
+}
+impl RawKeymapConfig {
+    // 所有按键映射字段及其名称，包括proximity中已配置的字段
+    fn keymap_fields_mut(&mut self) -> Vec<(String, &mut String)> {
+        let mut fields = Vec::new();
+        macro_rules! push {
+            ($($field:ident => $name:literal),+ $(,)?) => {
+                $(
+                    fields.push(($name.to_string(), &mut self.$field));
+                )+
+                $(
+                    if let Some(value) = &mut self.proximity.$field {
+                        fields.push((format!("proximity.{}", $name), value));
+                    }
+                )+
+            };
+        }
+        push! {
+            button0 => "button0", button1 => "button1", button2 => "button2", button3 => "button3",
+            button4 => "button4", button5 => "button5", button6 => "button6", button7 => "button7",
+            ring0 => "ring0", ring1 => "ring1", ring_button => "ringButton",
+        }
+        fields
+    }
+
+    // path形如keymap[0]或tablet[0].keymap[0]，用于输出警告
+    fn replace_invalid(&mut self, path: &str) {
+        for (name, value) in self.keymap_fields_mut() {
+            if let Err(e) = ImmediateKeymap::try_from(value.clone()) {
+                warn!(
+                    "{}.{}的配置'{}'无效，已替换为none: {}",
+                    path, name, value, e
+                );
+                *value = "none".to_string();
+            }
+        }
+    }
+
+    // 列出所有无效的按键映射，而不是遇到第一个就停止；用于check子命令
+    fn invalid_keymaps(&mut self, path: &str) -> Vec<Error> {
+        let mut errors = Vec::new();
+        for (name, value) in self.keymap_fields_mut() {
+            if let Err(e) = ImmediateKeymap::try_from(value.clone()) {
+                errors.push(anyhow!("{}.{}的配置'{}'无效: {}", path, name, value, e));
+            }
+        }
+        errors
+    }
 }
 impl Default for RawKeymapConfig {
     fn default() -> Self {
@@ -67,12 +439,17 @@ impl Default for RawKeymapConfig {
                     $(
                         $field: "fallback".to_string(),
                     )+
+                    name: None,
+                    inherit: None,
+                    x_map: None,
+                    y_map: None,
+                    proximity: RawProximityKeymapConfig::default(),
                 }
             };
         }
         default_fallback! {
             button0, button1, button2, button3, button4, button5, button6, button7,
-            ring0, ring1, ring_button,
+            ring0, ring1, ring_button, tip_mode, pen_mode,
         }
     }
 }
@@ -82,11 +459,59 @@ enum ImmediateKeymap {
     None,
     Press(Arc<Vec<EV_KEY>>),
     SwitchSchema,
+    FineAdjust,
+    CycleMonitor,
+    CycleArea,
+    Exec(Arc<Vec<String>>),
+    Zoom(Arc<Vec<EV_KEY>>),
+    Latch(Arc<Vec<EV_KEY>>),
+    MapToMonitor(Arc<String>),
+    PressureSofter,
+    PressureHarder,
+    ToggleTilt,
+    ToggleSmoothing,
+    CycleSmoothing,
+    RotateMapping,
     Fallback,
 }
 impl TryFrom<String> for ImmediateKeymap {
     type Error = Error;
     fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        // 不经过shell，直接按空白字符拆分命令行
+        if let Some(command) = value.trim().strip_prefix("exec:") {
+            let argv = command
+                .split_whitespace()
+                .map(paths::expand)
+                .collect::<Result<Vec<_>>>()?;
+            if argv.is_empty() {
+                return Err(anyhow!("exec的命令不能为空"));
+            }
+            return Ok(ImmediateKeymap::Exec(Arc::new(argv)));
+        }
+
+        // zoom或zoom:<修饰键>，例如zoom:ctrl+shift；未指定修饰键时为ctrl
+        let trimmed = value.trim();
+        if trimmed == "zoom" || trimmed.starts_with("zoom:") {
+            let codes = parse_modifiers(trimmed.strip_prefix("zoom:").unwrap_or("ctrl"))?;
+            return Ok(ImmediateKeymap::Zoom(Arc::new(codes)));
+        }
+
+        // mapToMonitor:<显示器名称>，例如mapToMonitor:DP-1
+        if let Some(name) = trimmed.strip_prefix("mapToMonitor:") {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(anyhow!("mapToMonitor的显示器名称不能为空"));
+            }
+            return Ok(ImmediateKeymap::MapToMonitor(Arc::new(name.to_string())));
+        }
+
+        // latch:<修饰键>，例如latch:shift
+        if let Some(modifiers) = trimmed.strip_prefix("latch:") {
+            return Ok(ImmediateKeymap::Latch(Arc::new(parse_modifiers(
+                modifiers,
+            )?)));
+        }
+
         let iter = value.split("+").map(|s| s.trim());
         let mut parts = Vec::new();
         for part in iter {
@@ -94,23 +519,31 @@ impl TryFrom<String> for ImmediateKeymap {
                 parts.push(part);
             }
         }
-        if parts.contains(&"switchSchema") {
-            if parts.len() > 1 {
-                return Err(anyhow!("不能把'switchSchema'和其他键组合"));
-            }
-            return Ok(ImmediateKeymap::SwitchSchema);
-        }
-        if parts.contains(&"fallback") {
-            if parts.len() > 1 {
-                return Err(anyhow!("不能把'fallback'和其他键组合"));
-            }
-            return Ok(ImmediateKeymap::Fallback);
+        macro_rules! match_action {
+            ($($action:literal => $keymap:expr),+ $(,)?) => {
+                $(
+                    if parts.contains(&$action) {
+                        if parts.len() > 1 {
+                            return Err(anyhow!(concat!("不能把'", $action, "'和其他键组合")));
+                        }
+                        return Ok($keymap);
+                    }
+                )+
+            };
         }
-        if parts.contains(&"none") {
-            if parts.len() > 1 {
-                return Err(anyhow!("不能把'none'和其他键组合"));
-            }
-            return Ok(ImmediateKeymap::None);
+        match_action! {
+            "switchSchema" => ImmediateKeymap::SwitchSchema,
+            "fineAdjust" => ImmediateKeymap::FineAdjust,
+            "cycleMonitor" => ImmediateKeymap::CycleMonitor,
+            "cycleArea" => ImmediateKeymap::CycleArea,
+            "pressureSofter" => ImmediateKeymap::PressureSofter,
+            "pressureHarder" => ImmediateKeymap::PressureHarder,
+            "toggleTilt" => ImmediateKeymap::ToggleTilt,
+            "toggleSmoothing" => ImmediateKeymap::ToggleSmoothing,
+            "cycleSmoothing" => ImmediateKeymap::CycleSmoothing,
+            "rotateMapping" => ImmediateKeymap::RotateMapping,
+            "fallback" => ImmediateKeymap::Fallback,
+            "none" => ImmediateKeymap::None,
         }
 
         let mut codes = Vec::with_capacity(parts.len());
@@ -121,7 +554,7 @@ impl TryFrom<String> for ImmediateKeymap {
                         codes.push($code);
                     }),+
                     _ => {
-                        return Err(anyhow!("'{}'不是有效的按键映射配置", $value));
+                        codes.push(parse_raw_key($value)?);
                     }
                 }
             };
@@ -158,8 +591,90 @@ impl TryFrom<String> for ImmediateKeymap {
         Ok(ImmediateKeymap::Press(Arc::new(codes)))
     }
 }
+// 不在上面的列表中的按键：evdev的按键名称（例如KEY_VOLUMEUP）或key:<按键码>（例如key:115）；
+// 只接受键盘按键，BTN_开头的鼠标、手柄按键会使虚拟键盘被识别为其他类型的设备
+fn parse_raw_key(value: &str) -> Result<EV_KEY> {
+    let code = if let Some(code) = value.strip_prefix("key:") {
+        let code = code
+            .trim()
+            .parse::<u32>()
+            .with_context(|| format!("'{}'不是有效的按键码", value))?;
+        int_to_ev_key(code).ok_or_else(|| anyhow!("按键码{}不存在", code))?
+    } else if value.starts_with("KEY_") {
+        value
+            .parse::<EV_KEY>()
+            .map_err(|_| anyhow!("'{}'不是evdev的按键名称", value))?
+    } else {
+        return Err(anyhow!("'{}'不是有效的按键映射配置", value));
+    };
+    if !format!("{:?}", code).starts_with("KEY_") {
+        return Err(anyhow!("{:?}不是键盘按键", code));
+    }
+    Ok(code)
+}
+
+// 以+组合的修饰键，用于zoom与latch
+fn parse_modifiers(value: &str) -> Result<Vec<EV_KEY>> {
+    let mut codes = vec![];
+    for part in value.split('+') {
+        let code = match part.trim() {
+            "ctrl" => EV_KEY::KEY_LEFTCTRL,
+            "shift" => EV_KEY::KEY_LEFTSHIFT,
+            "alt" => EV_KEY::KEY_LEFTALT,
+            "meta" => EV_KEY::KEY_LEFTMETA,
+            x => {
+                return Err(anyhow!(
+                    "'{}'不是有效的修饰键（可选值为ctrl、shift、alt、meta）",
+                    x
+                ));
+            }
+        };
+        if !codes.contains(&code) {
+            codes.push(code);
+        }
+    }
+    Ok(codes)
+}
+
+#[derive(Clone)]
+enum ImmediateTipMode {
+    Touch,
+    LeftClick,
+    Fallback,
+}
+impl TryFrom<String> for ImmediateTipMode {
+    type Error = Error;
+    fn try_from(value: String) -> Result<Self> {
+        match value.trim() {
+            "touch" => Ok(Self::Touch),
+            "leftClick" => Ok(Self::LeftClick),
+            "fallback" => Ok(Self::Fallback),
+            x => Err(anyhow!("'{}'不是有效的笔尖模式配置", x)),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum ImmediatePenMode {
+    Absolute,
+    Relative,
+    Fallback,
+}
+impl TryFrom<String> for ImmediatePenMode {
+    type Error = Error;
+    fn try_from(value: String) -> Result<Self> {
+        match value.trim() {
+            "absolute" => Ok(Self::Absolute),
+            "relative" => Ok(Self::Relative),
+            "fallback" => Ok(Self::Fallback),
+            x => Err(anyhow!("'{}'不是有效的画笔模式配置", x)),
+        }
+    }
+}
 
 struct ImmediateKeymapConfig {
+    name: Option<String>,
+    inherit: Option<String>,
     button0: ImmediateKeymap,
     button1: ImmediateKeymap,
     button2: ImmediateKeymap,
@@ -171,16 +686,40 @@ struct ImmediateKeymapConfig {
     ring0: ImmediateKeymap,
     ring1: ImmediateKeymap,
     ring_button: ImmediateKeymap,
+    tip_mode: ImmediateTipMode,
+    pen_mode: ImmediatePenMode,
+    x_map: Option<(f32, f32)>,
+    y_map: Option<(f32, f32)>,
+    proximity: ProximityKeymapConfig, // 不需要沿用上一个方案，直接转换为最终形态
 }
 impl TryFrom<RawKeymapConfig> for ImmediateKeymapConfig {
     type Error = anyhow::Error;
     fn try_from(value: RawKeymapConfig) -> Result<Self> {
         try_into! { value =>
-            button0, button1, button2, button3, button4, button5, button6, button7,
-            ring0, ring1, ring_button,
+            name, inherit, button0, button1, button2, button3, button4, button5, button6, button7,
+            ring0, ring1, ring_button, tip_mode, pen_mode, x_map, y_map, proximity,
         }
     }
 }
+impl RawScreenConfig {
+    fn resolve_map(&self) -> Result<((f32, f32), (f32, f32))> {
+        let monitors = screen::query_monitors().context("无法查询显示器布局")?;
+        let desktop = screen::desktop_geometry(&monitors).context("无法计算桌面区域")?;
+        let target = match (&self.monitor, &self.geometry) {
+            (Some(name), None) => {
+                monitors
+                    .iter()
+                    .find(|x| &x.name == name)
+                    .with_context(|| format!("找不到名为'{}'的显示器", name))?
+                    .geometry
+            }
+            (None, Some(geometry)) => geometry.parse()?,
+            _ => return Err(anyhow!("screen中必须且只能配置monitor和geometry其中之一")),
+        };
+        screen::compute_map(desktop, target)
+    }
+}
+
 impl ImmediateKeymapConfig {
     fn resolve(&mut self, other: &Self) {
         macro_rules! resolve {
@@ -196,19 +735,323 @@ impl ImmediateKeymapConfig {
             button0, button1, button2, button3, button4, button5, button6, button7,
             ring0, ring1, ring_button,
         }
+        if let ImmediateTipMode::Fallback = self.tip_mode {
+            self.tip_mode = other.tip_mode.clone();
+        }
+        if let ImmediatePenMode::Fallback = self.pen_mode {
+            self.pen_mode = other.pen_mode.clone();
+        }
     }
 }
 
+const DEFAULT_FINE_ADJUST_GAIN: f32 = 0.25;
+const DEFAULT_RELATIVE_SPEED: f32 = 0.1;
+const DEFAULT_PRESSURE_STEP: f32 = 0.1;
+const DEFAULT_SMOOTHING_PRESETS: [f32; 3] = [0.0, 0.5, 0.8];
+
 #[derive(Clone, Default)]
 pub struct Config {
     pub x_max_value: u16,
     pub y_max_value: u16,
+    pub x_resolution: i32, // 单位：设备坐标/毫米；由设备的报告描述符提供
+    pub y_resolution: i32, // 单位：设备坐标/毫米；由设备的报告描述符提供
+    pub area: Option<AreaConfig>,
+    pub area_presets: Vec<AreaPreset>,
+    pub x_gamma: Option<f32>,
+    pub y_gamma: Option<f32>,
+    pub pressure_curve: Option<f32>,
+    pub pressure_step: f32,
+    pub pressure_max_value: u16, // 由设备的报告描述符提供
+    pub smoothing: f32,
+    pub smoothing_presets: Vec<f32>,
+    pub palm_block: Option<Duration>, // 为None时不忽略
+    pub rotation: u16,
     pub x_map: Option<(f32, f32)>,
     pub y_map: Option<(f32, f32)>,
+    pub fine_adjust_gain: f32,
+    pub relative_speed: f32,
+    pub max_report_rate: Option<u32>,
+    pub latency_stats: bool,
+    pub idle_timeout: Option<Duration>, // 为None时不进入空闲状态
+    pub mode_detection: ModeDetection,
+    pub device: DeviceConfig,
+    pub virtual_device_name: Option<String>, // 为None时使用默认名称
+    pub virtual_keyboard_name: Option<String>,
+    pub virtual_mouse_name: Option<String>,
+    pub pad_mode: PadMode,
+    pub virtual_pad_name: Option<String>,
+    pub virtual_axes: VirtualAxesConfig,
+    pub control_socket: Option<PathBuf>, // 为None时使用默认路径；只能在顶层配置
+    pub run_as: Option<RunAsConfig>,     // 只能在顶层配置
+    pub sandbox: bool,                   // 只能在顶层配置
+    pub pause_on_inactive_session: bool, // 只能在顶层配置
+    pub session_user: Option<String>,    // 只能在顶层配置
+    pub log_level: Option<LevelFilter>,  // 为None时使用默认级别；只能在顶层配置
+    pub log_format: LogFormat,           // 只能在顶层配置
+    pub follow_monitor: bool,
+    pub dwell_click: Option<DwellClickConfig>,
+    pub rules: Vec<Rule>, // [[rule]]在前，之后是由[[appProfile]]转换的规则；使用第一个匹配的规则
+    pub notifications: bool,
+    pub osd: bool,
+    pub usage_statistics: bool,
+    pub allow_exec: bool,
+    pub exec_allowlist: Option<Vec<String>>,
+    pub monitor_maps: Vec<MonitorMap>, // 仅在配置了cycleMonitor或followMonitor时查询
     pub keymaps: Vec<KeymapConfig>,
+    pub tablets: Vec<Config>, // 其他绘图板的配置
+    pub source: toml::Table,  // 加载时的原始TOML表（不含tablet），用于重新加载时比较差异
+}
+#[derive(Clone)]
+pub struct RunAsConfig {
+    pub user: String,
+    pub group: Option<String>, // 为None时使用用户的主用户组
+}
+#[derive(Clone, Copy, Default)]
+pub struct VirtualAxesConfig {
+    pub x: AxisConfig,
+    pub y: AxisConfig,
+    pub pressure: AxisConfig,
+}
+impl From<RawVirtualAxesConfig> for VirtualAxesConfig {
+    fn from(value: RawVirtualAxesConfig) -> Self {
+        Self {
+            x: value.x.map(AxisConfig::from).unwrap_or_default(),
+            y: value.y.map(AxisConfig::from).unwrap_or_default(),
+            pressure: value.pressure.map(AxisConfig::from).unwrap_or_default(),
+        }
+    }
+}
+#[derive(Clone, Copy, Default)]
+pub struct AxisConfig {
+    pub fuzz: u16,
+    pub flat: u16,
+    pub resolution: Option<u16>,
+}
+impl From<RawAxisConfig> for AxisConfig {
+    fn from(value: RawAxisConfig) -> Self {
+        Self {
+            fuzz: value.fuzz.unwrap_or(0),
+            flat: value.flat.unwrap_or(0),
+            resolution: value.resolution,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaConfig {
+    pub unit: AreaUnit,
+    pub x: Option<(f32, f32)>,
+    pub y: Option<(f32, f32)>,
+    pub outside: OutsideAreaBehavior,
+}
+#[derive(Clone)]
+pub struct DeviceConfig {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub dongle_product_id: Option<u16>,
+    pub bluetooth_product_id: Option<u16>,
+    pub port: Option<String>,
+    pub name: Option<Regex>, // 匹配设备名称的正则表达式，为None时按设备描述中的名称精确匹配
+    pub path: Option<PathBuf>,
+    pub profile: DeviceProfile,
 }
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        let profile = DeviceProfile::intangbo_m();
+        Self {
+            vendor_id: profile.vendor_id,
+            product_id: profile.product_id,
+            dongle_product_id: profile.dongle_product_id,
+            bluetooth_product_id: profile.bluetooth_product_id,
+            port: None,
+            name: None,
+            path: None,
+            profile,
+        }
+    }
+}
+impl DeviceConfig {
+    // 有线连接与无线接收器对应的(vendor_id, product_id)
+    pub fn ids(&self) -> Vec<(u16, u16)> {
+        let mut ids = vec![(self.vendor_id, self.product_id)];
+        if let Some(product_id) = self.dongle_product_id {
+            ids.push((self.vendor_id, product_id));
+        }
+        ids
+    }
+}
+impl TryFrom<RawDeviceConfig> for DeviceConfig {
+    type Error = Error;
+    fn try_from(value: RawDeviceConfig) -> Result<Self> {
+        if value.name.is_some() && value.path.is_some() {
+            return Err(anyhow!("device.name不能和device.path同时配置"));
+        }
+        let profile = match value.profile {
+            Some(path) => DeviceProfile::load(
+                paths::expand_path(path).map_err(|e| with_field_path(e, "profile"))?,
+            )?,
+            None => DeviceProfile::intangbo_m(),
+        };
+        Ok(Self {
+            vendor_id: value.vendor_id.unwrap_or(profile.vendor_id),
+            product_id: value.product_id.unwrap_or(profile.product_id),
+            dongle_product_id: value.dongle_product_id.or(profile.dongle_product_id),
+            bluetooth_product_id: value.bluetooth_product_id.or(profile.bluetooth_product_id),
+            port: value.port,
+            name: value
+                .name
+                .map(|x| Regex::new(&x).context("device.name不是有效的正则表达式"))
+                .transpose()?,
+            path: value
+                .path
+                .map(paths::expand_path)
+                .transpose()
+                .map_err(|e| with_field_path(e, "path"))?,
+            profile,
+        })
+    }
+}
+// 设备的工作模式（例如Android模式与PC模式）切换后，上报的坐标范围可能发生变化
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum ModeDetection {
+    // 不检测
+    Off,
+    // 坐标超出最大值时输出警告
+    #[default]
+    Warn,
+    // 输出警告，并按观测到的最大值重新计算坐标轴的缩放
+    Rescale,
+}
+// 绘图板按键与转环的输出方式
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum PadMode {
+    // 按按键映射方案转换为键盘按键或特殊行为
+    #[default]
+    Keymap,
+    // 由虚拟的Pad设备上报BTN_0~BTN_9与ABS_WHEEL，交给桌面环境的绘图板设置处理
+    Native,
+}
+#[derive(Clone)]
+pub struct DwellClickConfig {
+    pub delay: Duration,
+    pub radius: u16,
+    pub action: DwellAction,
+}
+#[derive(Clone)]
+pub enum DwellAction {
+    // 由虚拟鼠标单击BTN_LEFT或BTN_RIGHT
+    Click(EV_KEY),
+    // 由虚拟键盘按下并释放按键组合
+    Press(Arc<Vec<EV_KEY>>),
+}
+impl TryFrom<RawDwellClickConfig> for DwellClickConfig {
+    type Error = Error;
+    fn try_from(value: RawDwellClickConfig) -> Result<Self> {
+        let delay = value.delay.unwrap_or(1000);
+        if delay == 0 {
+            return Err(anyhow!("dwellClick.delay必须大于0"));
+        }
+        let action = match value.action.as_deref().map(str::trim) {
+            None | Some("click") => DwellAction::Click(EV_KEY::BTN_LEFT),
+            Some("rightClick") => DwellAction::Click(EV_KEY::BTN_RIGHT),
+            Some(x) => match ImmediateKeymap::try_from(x.to_string())? {
+                ImmediateKeymap::Press(codes) => DwellAction::Press(codes),
+                _ => {
+                    return Err(anyhow!(
+                        "dwellClick.action只能为click、rightClick或按键组合"
+                    ));
+                }
+            },
+        };
+        Ok(Self {
+            delay: Duration::from_millis(delay),
+            radius: value.radius.unwrap_or(100),
+            action,
+        })
+    }
+}
+#[derive(Clone)]
+pub struct AreaPreset {
+    pub name: String,
+    pub area: AreaConfig,
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutsideAreaBehavior {
+    Clamp,
+    Ignore,
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AreaUnit {
+    Raw,
+    Millimeter,
+}
+impl AreaConfig {
+    // 供控制套接字的set命令使用，取值与配置文件中的area相同
+    pub fn new(
+        unit: Option<&str>,
+        x: Option<(f32, f32)>,
+        y: Option<(f32, f32)>,
+        outside: Option<&str>,
+    ) -> Result<Self> {
+        Self::try_from(RawAreaConfig {
+            unit: unit.map(String::from),
+            x,
+            y,
+            outside: outside.map(String::from),
+        })
+    }
+}
+impl TryFrom<RawAreaConfig> for AreaConfig {
+    type Error = Error;
+    fn try_from(value: RawAreaConfig) -> Result<Self> {
+        let unit = match value.unit.as_deref().unwrap_or("raw") {
+            "raw" => AreaUnit::Raw,
+            "mm" => AreaUnit::Millimeter,
+            x => return Err(anyhow!("'{}'不是有效的单位（可选值为raw、mm）", x)),
+        };
+        let outside = match value.outside.as_deref().unwrap_or("clamp") {
+            "clamp" => OutsideAreaBehavior::Clamp,
+            "ignore" => OutsideAreaBehavior::Ignore,
+            x => {
+                return Err(anyhow!(
+                    "'{}'不是有效的区域外行为（可选值为clamp、ignore）",
+                    x
+                ));
+            }
+        };
+        macro_rules! check_range {
+            ($field:ident) => {
+                if let Some((min, max)) = value.$field {
+                    if min < 0f32 {
+                        return Err(anyhow!(concat!(
+                            "area.",
+                            stringify!($field),
+                            "的最小值不能小于0"
+                        )));
+                    }
+                    if min >= max {
+                        return Err(anyhow!(concat!(
+                            "area.",
+                            stringify!($field),
+                            "的最小值必须小于最大值"
+                        )));
+                    }
+                }
+            };
+        }
+        check_range!(x);
+        check_range!(y);
+        Ok(Self {
+            unit,
+            x: value.x,
+            y: value.y,
+            outside,
+        })
+    }
+}
+
 #[derive(Clone, Default)]
 pub struct KeymapConfig {
+    pub name: Option<String>,
     pub button0: Keymap,
     pub button1: Keymap,
     pub button2: Keymap,
@@ -220,13 +1063,186 @@ pub struct KeymapConfig {
     pub ring0: Keymap,
     pub ring1: Keymap,
     pub ring_button: Keymap,
+    pub tip_mode: TipMode,
+    pub pen_mode: PenMode,
+    pub x_map: Option<(f32, f32)>,
+    pub y_map: Option<(f32, f32)>,
+    pub proximity: ProximityKeymapConfig,
 }
+// 画笔在感应范围内时覆盖的按键映射；为None时使用方案中的按键映射
 #[derive(Clone, Default)]
-pub enum Keymap {
-    #[default]
-    None,
-    Press(Arc<Vec<EV_KEY>>),
-    SwitchSchema,
+pub struct ProximityKeymapConfig {
+    pub button0: Option<Keymap>,
+    pub button1: Option<Keymap>,
+    pub button2: Option<Keymap>,
+    pub button3: Option<Keymap>,
+    pub button4: Option<Keymap>,
+    pub button5: Option<Keymap>,
+    pub button6: Option<Keymap>,
+    pub button7: Option<Keymap>,
+    pub ring0: Option<Keymap>,
+    pub ring1: Option<Keymap>,
+    pub ring_button: Option<Keymap>,
+}
+impl TryFrom<RawProximityKeymapConfig> for ProximityKeymapConfig {
+    type Error = Error;
+    fn try_from(value: RawProximityKeymapConfig) -> Result<Self> {
+        // fallback表示使用方案中的按键映射，与不配置相同
+        let convert = |value: Option<String>| -> Result<Option<Keymap>> {
+            match value.map(ImmediateKeymap::try_from).transpose()? {
+                None | Some(ImmediateKeymap::Fallback) => Ok(None),
+                Some(keymap) => Keymap::try_from(keymap).map(Some),
+            }
+        };
+        macro_rules! convert {
+            ($($field:ident),+ $(,)?) => {
+                Ok(Self {
+                    $(
+                        $field: convert(value.$field).map_err(|e| {
+                            with_field_path(e, &camel_case(stringify!($field)))
+                        })?,
+                    )+
+                })
+            };
+        }
+        convert! {
+            button0, button1, button2, button3, button4, button5, button6, button7,
+            ring0, ring1, ring_button,
+        }
+    }
+}
+impl ProximityKeymapConfig {
+    fn pad_key(&self, key: PadKey) -> Option<&Keymap> {
+        match key {
+            PadKey::Button(index) => [
+                &self.button0,
+                &self.button1,
+                &self.button2,
+                &self.button3,
+                &self.button4,
+                &self.button5,
+                &self.button6,
+                &self.button7,
+            ]
+            .get(index)
+            .and_then(|x| x.as_ref()),
+            PadKey::RingCw => self.ring1.as_ref(),
+            PadKey::RingCcw => self.ring0.as_ref(),
+            PadKey::RingButton => self.ring_button.as_ref(),
+        }
+    }
+}
+#[derive(Clone, Default)]
+pub enum Keymap {
+    #[default]
+    None,
+    Press(Arc<Vec<EV_KEY>>),
+    SwitchSchema,
+    FineAdjust,
+    CycleMonitor,
+    // 将绘图板映射到指定名称的显示器
+    MapToMonitor(Arc<String>),
+    CycleArea,
+    Exec(Arc<Vec<String>>),
+    // 按住修饰键并滚动鼠标滚轮；只能用于转环，逆时针缩小、顺时针放大
+    Zoom(Arc<Vec<EV_KEY>>),
+    // 预备修饰键，下一次笔尖按下或按键时一并按下，之后自动释放
+    Latch(Arc<Vec<EV_KEY>>),
+    // 按pressureStep调整当前的压力曲线，使较轻的压力即可画出粗线（Softer）或需要更用力（Harder）
+    PressureSofter,
+    PressureHarder,
+    // 开启或关闭倾斜的上报；关闭时倾斜总是为0
+    ToggleTilt,
+    // 关闭平滑（使用原始坐标）或恢复之前的平滑强度
+    ToggleSmoothing,
+    // 依次切换到smoothingPresets中的下一个平滑强度，最后一个之后恢复为配置的smoothing
+    CycleSmoothing,
+    // 在当前的rotation上再顺时针旋转90度，依次循环
+    RotateMapping,
+}
+// monitor子命令中显示的按键映射
+// 重新加载配置时的一处变化，例如keymap[0].button0："ctrl+z" → "ctrl+y"
+pub struct ConfigChange {
+    pub key: String, // 顶层的配置项，例如keymap
+    path: String,
+    old: Option<String>,
+    new: Option<String>,
+}
+impl Display for ConfigChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let show = |x: &Option<String>| x.clone().unwrap_or_else(|| "（未配置）".to_string());
+        write!(
+            f,
+            "{}：{} → {}",
+            self.path,
+            show(&self.old),
+            show(&self.new)
+        )
+    }
+}
+
+// 递归比较两个TOML值，表按键、数组按下标展开，只记录最内层发生变化的值
+fn diff_values(
+    key: &str,
+    path: &str,
+    old: Option<&toml::Value>,
+    new: Option<&toml::Value>,
+    changes: &mut Vec<ConfigChange>,
+) {
+    match (old, new) {
+        (Some(toml::Value::Table(old)), Some(toml::Value::Table(new))) => {
+            let mut names: Vec<_> = old.keys().chain(new.keys()).collect();
+            names.sort();
+            names.dedup();
+            for name in names {
+                let path = format!("{}.{}", path, name);
+                diff_values(key, &path, old.get(name), new.get(name), changes);
+            }
+        }
+        (Some(toml::Value::Array(old)), Some(toml::Value::Array(new))) => {
+            for i in 0..old.len().max(new.len()) {
+                let path = format!("{}[{}]", path, i);
+                diff_values(key, &path, old.get(i), new.get(i), changes);
+            }
+        }
+        (old, new) if old == new => {}
+        (old, new) => changes.push(ConfigChange {
+            key: key.to_string(),
+            path: path.to_string(),
+            old: old.map(|x| x.to_string()),
+            new: new.map(|x| x.to_string()),
+        }),
+    }
+}
+
+impl Display for Keymap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let join = |codes: &[EV_KEY]| {
+            codes
+                .iter()
+                .map(|x| format!("{:?}", x))
+                .collect::<Vec<_>>()
+                .join("+")
+        };
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Press(codes) => f.write_str(&join(codes)),
+            Self::SwitchSchema => f.write_str("switchSchema"),
+            Self::FineAdjust => f.write_str("fineAdjust"),
+            Self::CycleMonitor => f.write_str("cycleMonitor"),
+            Self::CycleArea => f.write_str("cycleArea"),
+            Self::Exec(argv) => write!(f, "exec({})", argv.join(" ")),
+            Self::Zoom(modifiers) => write!(f, "zoom:{}", join(modifiers)),
+            Self::Latch(modifiers) => write!(f, "latch:{}", join(modifiers)),
+            Self::MapToMonitor(name) => write!(f, "mapToMonitor:{}", name),
+            Self::PressureSofter => f.write_str("pressureSofter"),
+            Self::PressureHarder => f.write_str("pressureHarder"),
+            Self::ToggleTilt => f.write_str("toggleTilt"),
+            Self::ToggleSmoothing => f.write_str("toggleSmoothing"),
+            Self::CycleSmoothing => f.write_str("cycleSmoothing"),
+            Self::RotateMapping => f.write_str("rotateMapping"),
+        }
+    }
 }
 impl TryFrom<ImmediateKeymap> for Keymap {
     type Error = Error;
@@ -234,161 +1250,1392 @@ impl TryFrom<ImmediateKeymap> for Keymap {
         match value {
             ImmediateKeymap::Press(codes) => Ok(Self::Press(codes.clone())),
             ImmediateKeymap::SwitchSchema => Ok(Self::SwitchSchema),
+            ImmediateKeymap::FineAdjust => Ok(Self::FineAdjust),
+            ImmediateKeymap::CycleMonitor => Ok(Self::CycleMonitor),
+            ImmediateKeymap::CycleArea => Ok(Self::CycleArea),
+            ImmediateKeymap::Exec(argv) => Ok(Self::Exec(argv)),
+            ImmediateKeymap::Zoom(modifiers) => Ok(Self::Zoom(modifiers)),
+            ImmediateKeymap::Latch(modifiers) => Ok(Self::Latch(modifiers)),
+            ImmediateKeymap::MapToMonitor(name) => Ok(Self::MapToMonitor(name)),
+            ImmediateKeymap::PressureSofter => Ok(Self::PressureSofter),
+            ImmediateKeymap::PressureHarder => Ok(Self::PressureHarder),
+            ImmediateKeymap::ToggleTilt => Ok(Self::ToggleTilt),
+            ImmediateKeymap::ToggleSmoothing => Ok(Self::ToggleSmoothing),
+            ImmediateKeymap::CycleSmoothing => Ok(Self::CycleSmoothing),
+            ImmediateKeymap::RotateMapping => Ok(Self::RotateMapping),
             ImmediateKeymap::Fallback => Ok(Self::None),
             ImmediateKeymap::None => Ok(Self::None),
         }
     }
 }
+// 笔尖接触绘图板时上报的事件
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum TipMode {
+    // 由虚拟绘图板上报BTN_TOUCH与压力
+    #[default]
+    Touch,
+    // 由虚拟鼠标上报BTN_LEFT，供不支持绘图板的程序使用
+    LeftClick,
+}
+impl TryFrom<ImmediateTipMode> for TipMode {
+    type Error = Error;
+    fn try_from(value: ImmediateTipMode) -> Result<Self> {
+        match value {
+            ImmediateTipMode::Touch => Ok(Self::Touch),
+            ImmediateTipMode::LeftClick => Ok(Self::LeftClick),
+            ImmediateTipMode::Fallback => Ok(Self::Touch),
+        }
+    }
+}
+// 画笔移动的上报方式
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum PenMode {
+    // 由虚拟绘图板上报绝对坐标
+    #[default]
+    Absolute,
+    // 由虚拟鼠标上报相对移动，画笔相当于触控板上的手指
+    Relative,
+}
+impl TryFrom<ImmediatePenMode> for PenMode {
+    type Error = Error;
+    fn try_from(value: ImmediatePenMode) -> Result<Self> {
+        match value {
+            ImmediatePenMode::Absolute => Ok(Self::Absolute),
+            ImmediatePenMode::Relative => Ok(Self::Relative),
+            ImmediatePenMode::Fallback => Ok(Self::Absolute),
+        }
+    }
+}
+impl KeymapConfig {
+    pub fn button(&self, index: usize) -> Option<&Keymap> {
+        match index {
+            0 => Some(&self.button0),
+            1 => Some(&self.button1),
+            2 => Some(&self.button2),
+            3 => Some(&self.button3),
+            4 => Some(&self.button4),
+            5 => Some(&self.button5),
+            6 => Some(&self.button6),
+            7 => Some(&self.button7),
+            _ => None,
+        }
+    }
+
+    // 绘图板上的按键对应的按键映射；画笔在感应范围内时优先使用proximity中的配置
+    pub fn pad_key(&self, key: PadKey, in_proximity: bool) -> Option<&Keymap> {
+        if in_proximity && let Some(keymap) = self.proximity.pad_key(key) {
+            return Some(keymap);
+        }
+        match key {
+            PadKey::Button(index) => self.button(index),
+            PadKey::RingCw => Some(&self.ring1),
+            PadKey::RingCcw => Some(&self.ring0),
+            PadKey::RingButton => Some(&self.ring_button),
+        }
+    }
+
+    // 包括proximity中配置的按键映射
+    pub fn any<F: Fn(&Keymap) -> bool>(&self, f: F) -> bool {
+        macro_rules! any {
+            ($($field:ident),+ $(,)?) => {
+                false $(|| f(&self.$field) || self.proximity.$field.as_ref().is_some_and(&f))+
+            };
+        }
+        any! {
+            button0, button1, button2, button3, button4, button5, button6, button7,
+            ring0, ring1, ring_button,
+        }
+    }
+
+    // press映射的所有按键码，包括proximity中配置的
+    fn press_codes(&self, codes: &mut Vec<EV_KEY>) {
+        let mut push = |keymap: &Keymap| {
+            if let Keymap::Press(x) = keymap {
+                for code in x.iter() {
+                    if !codes.contains(code) {
+                        codes.push(*code);
+                    }
+                }
+            }
+        };
+        macro_rules! push {
+            ($($field:ident),+ $(,)?) => {
+                $(
+                    push(&self.$field);
+                    if let Some(keymap) = &self.proximity.$field {
+                        push(keymap);
+                    }
+                )+
+            };
+        }
+        push! {
+            button0, button1, button2, button3, button4, button5, button6, button7,
+            ring0, ring1, ring_button,
+        }
+    }
+
+    // zoom只能用于转环；返回配置了zoom的其他按键字段
+    fn misplaced_zoom(&self) -> Option<&'static str> {
+        macro_rules! check {
+            ($($field:ident),+ $(,)?) => {
+                $(
+                    if matches!(self.$field, Keymap::Zoom(_)) {
+                        return Some(stringify!($field));
+                    }
+                    if matches!(self.proximity.$field, Some(Keymap::Zoom(_))) {
+                        return Some(concat!("proximity.", stringify!($field)));
+                    }
+                )+
+            };
+        }
+        check! {
+            button0, button1, button2, button3, button4, button5, button6, button7, ring_button,
+        }
+        None
+    }
+
+    // 是否有按键字段配置了exec行为
+    fn has_exec(&self) -> bool {
+        macro_rules! check {
+            ($($field:ident),+ $(,)?) => {
+                $(
+                    if matches!(self.$field, Keymap::Exec(_))
+                        || matches!(self.proximity.$field, Some(Keymap::Exec(_)))
+                    {
+                        return true;
+                    }
+                )+
+            };
+        }
+        check! {
+            button0, button1, button2, button3, button4, button5, button6, button7,
+            ring0, ring1, ring_button,
+        }
+        false
+    }
+}
 impl TryFrom<ImmediateKeymapConfig> for KeymapConfig {
     type Error = Error;
     fn try_from(value: ImmediateKeymapConfig) -> Result<Self> {
         try_into! { value =>
-            button0, button1, button2, button3, button4, button5, button6, button7,
-            ring0, ring1, ring_button,
+            name, button0, button1, button2, button3, button4, button5, button6, button7,
+            ring0, ring1, ring_button, tip_mode, pen_mode, x_map, y_map, proximity,
         }
     }
 }
 impl Config {
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = std::fs::read_to_string(path).context("")?;
-        let raw: RawConfig = toml::from_str(&content).context("TOML解析失败")?;
+        Self::parse(&content, false)
+    }
+
+    // ignore_invalid_keymaps为true时，无论是否配置了ignoreInvalidKeymaps都把无效的按键映射替换为none
+    pub fn parse(content: &str, ignore_invalid_keymaps: bool) -> Result<Self> {
+        Self::parse_content(content, ignore_invalid_keymaps).map_err(|mut e| {
+            if let Some(field) = e.downcast_mut::<FieldPath>() {
+                field.location = locate_field(content, &field.path);
+            }
+            e
+        })
+    }
+
+    fn parse_content(content: &str, ignore_invalid_keymaps: bool) -> Result<Self> {
+        let mut raw: RawConfig = toml::from_str(content).map_err(|e| toml_error(content, e))?;
+        let mut source: toml::Table =
+            toml::from_str(content).map_err(|e| toml_error(content, e))?;
+        let unknown_keys = find_unknown_keys(&source);
+        if raw.deny_unknown_keys.unwrap_or(false) && !unknown_keys.is_empty() {
+            let keys: Vec<_> = unknown_keys.iter().map(|x| x.to_string()).collect();
+            return Err(anyhow!("存在未知的配置项：{}", keys.join("；")));
+        }
+        for key in &unknown_keys {
+            warn!("未知的配置项{}，已忽略", key);
+        }
+        let tablet_sources = match source.remove("tablet") {
+            Some(toml::Value::Array(x)) => x,
+            _ => Vec::new(),
+        };
+        let ignore_invalid_keymaps =
+            ignore_invalid_keymaps || raw.ignore_invalid_keymaps.unwrap_or(false);
+        let mut tablets = Vec::with_capacity(raw.tablets.len());
+        for (i, mut tablet) in std::mem::take(&mut raw.tablets).into_iter().enumerate() {
+            if !tablet.tablets.is_empty() {
+                return Err(anyhow!("tablet[{}]中不能再配置tablet", i));
+            }
+            if tablet.control_socket.is_some() {
+                return Err(anyhow!(
+                    "controlSocket只能在顶层配置，不能在tablet[{}]中配置",
+                    i
+                ));
+            }
+            if tablet.run_as_user.is_some() || tablet.run_as_group.is_some() {
+                return Err(anyhow!(
+                    "runAsUser与runAsGroup只能在顶层配置，不能在tablet[{}]中配置",
+                    i
+                ));
+            }
+            if tablet.pause_on_inactive_session.is_some() || tablet.session_user.is_some() {
+                return Err(anyhow!(
+                    "pauseOnInactiveSession与sessionUser只能在顶层配置，不能在tablet[{}]中配置",
+                    i
+                ));
+            }
+            if tablet.sandbox.is_some() {
+                return Err(anyhow!("sandbox只能在顶层配置，不能在tablet[{}]中配置", i));
+            }
+            if tablet.log_level.is_some() {
+                return Err(anyhow!("logLevel只能在顶层配置，不能在tablet[{}]中配置", i));
+            }
+            if tablet.ignore_invalid_keymaps.is_some() {
+                return Err(anyhow!(
+                    "ignoreInvalidKeymaps只能在顶层配置，不能在tablet[{}]中配置",
+                    i
+                ));
+            }
+            if tablet.deny_unknown_keys.is_some() {
+                return Err(anyhow!(
+                    "denyUnknownKeys只能在顶层配置，不能在tablet[{}]中配置",
+                    i
+                ));
+            }
+            if ignore_invalid_keymaps {
+                for (path, keymap) in tablet.keymaps_mut(&format!("tablet[{}].", i)) {
+                    keymap.replace_invalid(&path);
+                }
+            }
+            let mut tablet = Self::from_raw(tablet)
+                .map_err(|e| with_field_path(e, &format!("tablet[{}]", i)))?;
+            if let Some(toml::Value::Table(x)) = tablet_sources.get(i) {
+                tablet.source = x.clone();
+            }
+            tablets.push(tablet);
+        }
+        if ignore_invalid_keymaps {
+            for (path, keymap) in raw.keymaps_mut("") {
+                keymap.replace_invalid(&path);
+            }
+        }
+        let mut conf = Self::from_raw(raw)?;
+        conf.tablets = tablets;
+        conf.source = source;
+        Ok(conf)
+    }
+
+    // 与旧配置相比的变化，按配置项的路径排序；只比较配置文件中的内容，不包括设备提供的字段
+    pub fn changes_from(&self, old: &Config) -> Vec<ConfigChange> {
+        let mut changes = Vec::new();
+        let mut keys: Vec<_> = old.source.keys().chain(self.source.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            diff_values(
+                key,
+                key,
+                old.source.get(key),
+                self.source.get(key),
+                &mut changes,
+            );
+        }
+        changes
+    }
+
+    // 用于日志的方案描述：有名称时为"下标（名称）"，否则为下标
+    pub fn schema_label(&self, index: usize) -> String {
+        match self.keymaps.get(index).and_then(|x| x.name.as_ref()) {
+            Some(name) => format!("{}（{}）", index, name),
+            None => index.to_string(),
+        }
+    }
+
+    // 所有按键映射方案中由虚拟键盘按下的按键，用于启用虚拟键盘上不在默认列表中的按键
+    pub fn press_codes(&self) -> Vec<EV_KEY> {
+        let mut codes = vec![];
+        for keymap in &self.keymaps {
+            keymap.press_codes(&mut codes);
+        }
+        codes
+    }
+
+    // 是否配置了需要运行外部程序的功能（允许执行的exec行为、桌面通知、OSD、查询显示器布局或窗口焦点），包括所有tablet；
+    // 只看allowExec不够，非root运行时它默认为true，会导致沙箱几乎从不启用
+    pub fn needs_commands(&self) -> bool {
+        std::iter::once(self).chain(&self.tablets).any(|x| {
+            (x.allow_exec && x.keymaps.iter().any(|x| x.has_exec()))
+                || x.notifications
+                || x.osd
+                || x.follow_monitor
+                || !x.monitor_maps.is_empty()
+                || x.source.contains_key("screen")
+                || x.rules.iter().any(|x| x.needs_window())
+        })
+    }
+
+    // 按名称或下标查找按键映射方案；名称优先
+    pub fn find_schema(&self, schema: &str) -> Result<usize> {
+        if let Some(index) = self
+            .keymaps
+            .iter()
+            .position(|x| x.name.as_deref() == Some(schema))
+        {
+            return Ok(index);
+        }
+        match schema.parse::<usize>() {
+            Ok(index) if index < self.keymaps.len() => Ok(index),
+            Ok(index) => Err(anyhow!(
+                "按键映射方案{}不存在（共{}个方案）",
+                index,
+                self.keymaps.len()
+            )),
+            Err(_) => Err(anyhow!("按键映射方案'{}'不存在", schema)),
+        }
+    }
+
+    // 第index个绘图板的配置；0为顶层的配置，其余为tablet中的配置
+    pub fn tablet(&self, index: usize) -> Option<&Config> {
+        match index {
+            0 => Some(self),
+            _ => self.tablets.get(index - 1),
+        }
+    }
+
+    fn from_raw(raw: RawConfig) -> Result<Self> {
         if raw.keymaps.is_empty() {
             return Err(anyhow!("没有配置keymap"));
         }
 
-        let iter = raw.keymaps.into_iter().map(|x| {
+        let defaults = match raw.keymap_defaults {
+            Some(mut defaults) => {
+                let scoped = defaults.name.is_some()
+                    || defaults.inherit.is_some()
+                    || defaults.x_map.is_some()
+                    || defaults.y_map.is_some()
+                    || defaults
+                        .keymap_fields_mut()
+                        .iter()
+                        .any(|(name, _)| name.starts_with("proximity."));
+                if scoped {
+                    return Err(anyhow!(
+                        "keymapDefaults中不能配置name、inherit、xMap、yMap与proximity"
+                    ));
+                }
+                Some(
+                    ImmediateKeymapConfig::try_from(defaults)
+                        .map_err(|e| with_field_path(e, "keymapDefaults"))?,
+                )
+            }
+            None => None,
+        };
+        let iter = raw.keymaps.into_iter().enumerate().map(|(i, x)| {
             ImmediateKeymapConfig::try_from(x)
-                .context("无法把原始按键映射配置转换成中间形态的按键映射配置")
+                .map_err(|e| with_field_path(e, &format!("keymap[{}]", i)))
         });
-        let mut prev = None;
-        let mut immediate_keymaps = vec![];
-        for result in iter {
+        let mut immediate_keymaps: Vec<ImmediateKeymapConfig> = vec![];
+        for (i, result) in iter.enumerate() {
             let mut keymap = result?;
-            if let Some(prev) = prev {
-                keymap.resolve(prev);
+            if let Some(defaults) = &defaults {
+                keymap.resolve(defaults);
+            }
+            // 只能继承之前定义的方案，其配置已经解析完毕，也不会出现循环继承
+            let parent = match &keymap.inherit {
+                Some(name) => Some(
+                    immediate_keymaps
+                        .iter()
+                        .find(|x| x.name.as_ref() == Some(name))
+                        .ok_or_else(|| {
+                            let e = anyhow!("方案'{}'不存在，或者定义在当前方案之后", name);
+                            with_field_path(e, &format!("keymap[{}].inherit", i))
+                        })?,
+                ),
+                None => immediate_keymaps.last(),
+            };
+            if let Some(parent) = parent {
+                keymap.resolve(parent);
             }
             immediate_keymaps.push(keymap);
-            prev = immediate_keymaps.last();
+        }
+
+        for (i, keymap) in immediate_keymaps.iter().enumerate() {
+            let Some(name) = &keymap.name else {
+                continue;
+            };
+            if name.trim().is_empty() {
+                return Err(anyhow!("keymap[{}]的名称不能为空", i));
+            }
+            if immediate_keymaps[..i]
+                .iter()
+                .any(|x| x.name.as_ref() == Some(name))
+            {
+                return Err(anyhow!("存在重复的按键映射方案名称'{}'", name));
+            }
         }
 
         let mut keymaps = vec![];
-        for keymap in immediate_keymaps {
+        for (i, keymap) in immediate_keymaps.into_iter().enumerate() {
             keymaps.push(
                 KeymapConfig::try_from(keymap)
-                    .context("无法把中间形态的按键映射配置转换成最终形态的按键映射配置")?,
+                    .map_err(|e| with_field_path(e, &format!("keymap[{}]", i)))?,
             );
         }
 
+        let (x_map, y_map) = match &raw.screen {
+            Some(screen) => {
+                if raw.x_map.is_some() || raw.y_map.is_some() {
+                    return Err(anyhow!("screen不能和xMap、yMap同时配置"));
+                }
+                let (x_map, y_map) = screen.resolve_map().context("无法计算显示器的映射区域")?;
+                (Some(x_map), Some(y_map))
+            }
+            None => (raw.x_map, raw.y_map),
+        };
+
         macro_rules! check_map_values {
-            ($field:ident) => {
-                if let Some((min, max)) = raw.$field {
-                    if !(0f32..=1f32).contains(&min) {
-                        return Err(anyhow!(concat!(
-                            stringify!($field),
-                            "的最小值必须在0到1之间"
-                        )));
-                    }
-                    if !(0f32..=1f32).contains(&max) {
-                        return Err(anyhow!(concat!(
-                            stringify!($field),
-                            "的最大值必须在0到1之间"
-                        )));
-                    }
-                    if min >= max {
-                        return Err(anyhow!(concat!(
-                            stringify!($field),
-                            "的最小值必须小于最大值"
-                        )));
-                    }
+            ($name:expr, $value:expr) => {
+                if let Some(map) = $value {
+                    check_map(&$name, map)?;
                 }
             };
         }
-        check_map_values!(x_map);
-        check_map_values!(y_map);
+        check_map_values!("x_map", x_map);
+        check_map_values!("y_map", y_map);
+        for (i, keymap) in keymaps.iter().enumerate() {
+            check_map_values!(format!("keymap[{}].x_map", i), keymap.x_map);
+            check_map_values!(format!("keymap[{}].y_map", i), keymap.y_map);
+            if let Some(field) = keymap.misplaced_zoom() {
+                return Err(anyhow!(
+                    "keymap[{}].{}不能配置为zoom（只能用于ring0、ring1）",
+                    i,
+                    field
+                ));
+            }
+        }
+
+        for (name, gamma) in [("x_gamma", raw.x_gamma), ("y_gamma", raw.y_gamma)] {
+            if let Some(gamma) = gamma
+                && !(gamma > 0f32 && gamma.is_finite())
+            {
+                return Err(anyhow!("{}必须大于0", name));
+            }
+        }
+
+        let fine_adjust_gain = raw.fine_adjust_gain.unwrap_or(DEFAULT_FINE_ADJUST_GAIN);
+        if !(fine_adjust_gain > 0f32 && fine_adjust_gain <= 1f32) {
+            return Err(anyhow!("fine_adjust_gain必须在0（不含）到1之间"));
+        }
+
+        let relative_speed = raw.relative_speed.unwrap_or(DEFAULT_RELATIVE_SPEED);
+        if !(relative_speed > 0f32 && relative_speed.is_finite()) {
+            return Err(anyhow!("relativeSpeed必须大于0"));
+        }
+        if raw.max_report_rate == Some(0) {
+            return Err(anyhow!("maxReportRate必须大于0"));
+        }
+        if raw.idle_timeout == Some(0) {
+            return Err(anyhow!("idleTimeout必须大于0"));
+        }
+        if raw.palm_block == Some(0) {
+            return Err(anyhow!("palmBlock必须大于0"));
+        }
+
+        let mode_detection = match raw.mode_detection.as_deref().unwrap_or("warn") {
+            "off" => ModeDetection::Off,
+            "warn" => ModeDetection::Warn,
+            "rescale" => ModeDetection::Rescale,
+            x => {
+                return Err(anyhow!(
+                    "'{}'不是有效的modeDetection配置（可选值为off、warn、rescale）",
+                    x
+                ));
+            }
+        };
+
+        let pad_mode = match raw.pad_mode.as_deref().unwrap_or("keymap") {
+            "keymap" => PadMode::Keymap,
+            "native" => PadMode::Native,
+            x => {
+                return Err(anyhow!(
+                    "'{}'不是有效的padMode配置（可选值为keymap、native）",
+                    x
+                ));
+            }
+        };
+
+        let log_level = match raw.log_level {
+            Some(level) => Some(level.parse::<LevelFilter>().map_err(|_| {
+                anyhow!(
+                    "'{}'不是有效的logLevel配置（可选值为off、error、warn、info、debug、trace）",
+                    level
+                )
+            })?),
+            None => None,
+        };
+        let log_format = match raw.log_format.as_deref().unwrap_or("text") {
+            "text" => LogFormat::Text,
+            "json" => LogFormat::Json,
+            x => {
+                return Err(anyhow!(
+                    "'{}'不是有效的logFormat配置（可选值为text、json）",
+                    x
+                ));
+            }
+        };
+        let run_as = match (raw.run_as_user, raw.run_as_group) {
+            (Some(user), group) => Some(RunAsConfig { user, group }),
+            (None, Some(_)) => return Err(anyhow!("配置了runAsGroup时必须同时配置runAsUser")),
+            (None, None) => None,
+        };
+
+        let device = match raw.device {
+            Some(device) => {
+                DeviceConfig::try_from(device).map_err(|e| with_field_path(e, "device"))?
+            }
+            None => DeviceConfig::default(),
+        };
+
+        for (field, name) in [
+            ("virtualDeviceName", &raw.virtual_device_name),
+            ("virtualKeyboardName", &raw.virtual_keyboard_name),
+            ("virtualMouseName", &raw.virtual_mouse_name),
+            ("virtualPadName", &raw.virtual_pad_name),
+        ] {
+            if name.as_ref().is_some_and(|x| x.trim().is_empty()) {
+                return Err(anyhow!("{}不能为空", field));
+            }
+        }
+
+        let area = match raw.area {
+            Some(area) => Some(AreaConfig::try_from(area).context("area配置有误")?),
+            None => None,
+        };
+        let mut area_presets = Vec::with_capacity(raw.area_presets.len());
+        for preset in raw.area_presets {
+            if area_presets
+                .iter()
+                .any(|x: &AreaPreset| x.name == preset.name)
+            {
+                return Err(anyhow!("存在重复的区域预设名称'{}'", preset.name));
+            }
+            let area = AreaConfig::try_from(preset.area)
+                .with_context(|| format!("区域预设'{}'配置有误", preset.name))?;
+            area_presets.push(AreaPreset {
+                name: preset.name,
+                area,
+            });
+        }
+
+        let resolve_schema = |schema: RawSchemaRef| -> Result<usize> {
+            match schema {
+                RawSchemaRef::Index(index) if index < keymaps.len() => Ok(index),
+                RawSchemaRef::Index(index) => Err(anyhow!(
+                    "按键映射方案{}不存在（共{}个方案）",
+                    index,
+                    keymaps.len()
+                )),
+                RawSchemaRef::Name(name) => keymaps
+                    .iter()
+                    .position(|x| x.name.as_ref() == Some(&name))
+                    .with_context(|| format!("按键映射方案'{}'不存在", name)),
+            }
+        };
+        let check_pressure_curve = |name: &str, curve: Option<f32>| match curve {
+            Some(curve) => check_pressure_curve(name, curve),
+            None => Ok(()),
+        };
+        check_pressure_curve("pressureCurve", raw.pressure_curve)?;
+        let pressure_step = raw.pressure_step.unwrap_or(DEFAULT_PRESSURE_STEP);
+        if !(pressure_step > 0f32 && pressure_step.is_finite()) {
+            return Err(anyhow!("pressureStep必须大于0"));
+        }
+        let smoothing = raw.smoothing.unwrap_or(0f32);
+        check_smoothing(smoothing)?;
+        let smoothing_presets = raw
+            .smoothing_presets
+            .unwrap_or_else(|| DEFAULT_SMOOTHING_PRESETS.to_vec());
+        for smoothing in &smoothing_presets {
+            check_smoothing(*smoothing).context("smoothingPresets配置有误")?;
+        }
+        let rotation = raw.rotation.unwrap_or(0);
+        check_rotation(rotation)?;
+
+        let mut rules = Vec::with_capacity(raw.rules.len() + raw.app_profiles.len());
+        for (i, rule) in raw.rules.into_iter().enumerate() {
+            let compile = |field: &str, pattern: Option<String>| -> Result<Option<Regex>> {
+                pattern
+                    .map(|x| {
+                        Regex::new(&x)
+                            .with_context(|| format!("rule[{}].{}不是有效的正则表达式", i, field))
+                    })
+                    .transpose()
+            };
+            let class = compile("class", rule.class)?;
+            let title = compile("title", rule.title)?;
+            let time = match rule.time {
+                Some(time) => Some(
+                    time.parse::<TimeRange>()
+                        .with_context(|| format!("rule[{}].time配置有误", i))?,
+                ),
+                None => None,
+            };
+            let schema = match rule.schema {
+                Some(schema) => Some(
+                    resolve_schema(schema)
+                        .with_context(|| format!("rule[{}].schema配置有误", i))?,
+                ),
+                None => None,
+            };
+            let area_preset = match rule.area_preset {
+                Some(name) => Some(
+                    area_presets
+                        .iter()
+                        .position(|x| x.name == name)
+                        .with_context(|| format!("rule[{}]的区域预设'{}'不存在", i, name))?,
+                ),
+                None => None,
+            };
+            check_pressure_curve(&format!("rule[{}].pressureCurve", i), rule.pressure_curve)?;
+            if schema.is_none() && area_preset.is_none() && rule.pressure_curve.is_none() {
+                return Err(anyhow!(
+                    "rule[{}]至少需要配置schema、areaPreset、pressureCurve之一",
+                    i
+                ));
+            }
+            rules.push(Rule {
+                name: rule.name,
+                app: None,
+                class,
+                title,
+                monitor: rule.monitor,
+                time,
+                schema,
+                area_preset,
+                pressure_curve: rule.pressure_curve,
+            });
+        }
+        for (i, profile) in raw.app_profiles.into_iter().enumerate() {
+            if profile.app.trim().is_empty() {
+                return Err(anyhow!("appProfile[{}].app不能为空", i));
+            }
+            let schema = resolve_schema(profile.schema)
+                .with_context(|| format!("appProfile[{}].schema配置有误", i))?;
+            rules.push(Rule {
+                name: Some(profile.app.clone()),
+                app: Some(profile.app),
+                class: None,
+                title: None,
+                monitor: None,
+                time: None,
+                schema: Some(schema),
+                area_preset: None,
+                pressure_curve: None,
+            });
+        }
+
+        let dwell_click = match raw.dwell_click {
+            Some(dwell_click) => {
+                Some(DwellClickConfig::try_from(dwell_click).context("dwellClick配置有误")?)
+            }
+            None => None,
+        };
+
+        let follow_monitor = raw.follow_monitor.unwrap_or(false);
+        let mut monitor_maps = vec![];
+        if follow_monitor
+            || keymaps
+                .iter()
+                .any(|x| x.any(|x| matches!(x, Keymap::CycleMonitor | Keymap::MapToMonitor(_))))
+        {
+            match screen::monitor_maps() {
+                Ok(maps) => monitor_maps = maps,
+                Err(e) => {
+                    warn!(
+                        "无法查询显示器布局，cycleMonitor、mapToMonitor与followMonitor将不会生效: {:?}",
+                        e
+                    );
+                }
+            }
+        }
 
         Ok(Self {
-            x_max_value: raw.x_max_value.unwrap_or(0),
-            y_max_value: raw.y_max_value.unwrap_or(0),
-            x_map: raw.x_map,
-            y_map: raw.y_map,
+            x_max_value: raw.x_max_value.unwrap_or(device.profile.x_max_value),
+            y_max_value: raw.y_max_value.unwrap_or(device.profile.y_max_value),
+            x_resolution: 0,
+            y_resolution: 0,
+            area,
+            area_presets,
+            x_gamma: raw.x_gamma,
+            y_gamma: raw.y_gamma,
+            pressure_curve: raw.pressure_curve,
+            pressure_step,
+            pressure_max_value: 0,
+            smoothing,
+            smoothing_presets,
+            palm_block: raw.palm_block.map(Duration::from_millis),
+            rotation,
+            x_map,
+            y_map,
+            fine_adjust_gain,
+            relative_speed,
+            max_report_rate: raw.max_report_rate,
+            latency_stats: raw.latency_stats.unwrap_or(false),
+            idle_timeout: raw.idle_timeout.map(Duration::from_millis),
+            mode_detection,
+            device,
+            virtual_device_name: raw.virtual_device_name,
+            virtual_keyboard_name: raw.virtual_keyboard_name,
+            virtual_mouse_name: raw.virtual_mouse_name,
+            pad_mode,
+            virtual_pad_name: raw.virtual_pad_name,
+            virtual_axes: raw
+                .virtual_axes
+                .map(VirtualAxesConfig::from)
+                .unwrap_or_default(),
+            control_socket: raw
+                .control_socket
+                .map(paths::expand_path)
+                .transpose()
+                .map_err(|e| with_field_path(e, "controlSocket"))?,
+            run_as,
+            sandbox: raw.sandbox.unwrap_or(true),
+            pause_on_inactive_session: raw.pause_on_inactive_session.unwrap_or(false),
+            session_user: raw.session_user,
+            log_level,
+            log_format,
+            follow_monitor,
+            dwell_click,
+            rules,
+            notifications: raw.notifications.unwrap_or(false),
+            osd: raw.osd.unwrap_or(false),
+            usage_statistics: raw.usage_statistics.unwrap_or(false),
+            allow_exec: raw
+                .allow_exec
+                .unwrap_or_else(|| !nix::unistd::geteuid().is_root()),
+            exec_allowlist: raw
+                .exec_allowlist
+                .map(|x| x.iter().map(|x| paths::expand(x)).collect::<Result<_>>())
+                .transpose()
+                .map_err(|e| with_field_path(e, "execAllowlist"))?,
+            monitor_maps,
             keymaps,
+            tablets: vec![],
+            source: toml::Table::new(),
         })
     }
 }
 
+// 命令行中指定的配置项（例如--rotation 180），在本次运行中覆盖配置文件（包括各个tablet）中的值，
+// 重新加载配置文件后仍然生效；用于临时试验而无需修改配置文件
+#[derive(Clone, Default, clap::Args)]
+pub struct ConfigOverrides {
+    // 启动时使用的按键映射方案：方案名称或下标，优先于上次保存的状态
+    #[arg(long, value_name = "方案名称或下标", help = "启动时使用的按键映射方案")]
+    pub schema: Option<String>,
+    #[arg(long, value_name = "最小值,最大值", value_parser = |x: &str| parse_map_override("xMap", x), help = "覆盖xMap")]
+    x_map: Option<(f32, f32)>,
+    #[arg(long, value_name = "最小值,最大值", value_parser = |x: &str| parse_map_override("yMap", x), help = "覆盖yMap")]
+    y_map: Option<(f32, f32)>,
+    #[arg(long, value_name = "角度", value_parser = parse_rotation_override, help = "覆盖rotation")]
+    rotation: Option<u16>,
+    #[arg(long, value_name = "强度", value_parser = parse_smoothing_override, help = "覆盖smoothing")]
+    smoothing: Option<f32>,
+    #[arg(long, value_name = "指数", value_parser = parse_pressure_curve_override, help = "覆盖pressureCurve")]
+    pressure_curve: Option<f32>,
+}
+impl ConfigOverrides {
+    pub fn apply(&self, conf: &mut Config) -> Result<()> {
+        for tablet in &mut conf.tablets {
+            self.apply(tablet)?;
+        }
+        if let Some(schema) = &self.schema {
+            conf.find_schema(schema)?;
+        }
+        if let Some(x_map) = self.x_map {
+            conf.x_map = Some(x_map);
+        }
+        if let Some(y_map) = self.y_map {
+            conf.y_map = Some(y_map);
+        }
+        if let Some(rotation) = self.rotation {
+            conf.rotation = rotation;
+        }
+        if let Some(smoothing) = self.smoothing {
+            conf.smoothing = smoothing;
+        }
+        if let Some(curve) = self.pressure_curve {
+            conf.pressure_curve = Some(curve);
+        }
+        Ok(())
+    }
+}
+
+// 解析形如--x-map 0.1,0.9的参数
+fn parse_map_override(name: &str, value: &str) -> Result<(f32, f32)> {
+    let parts: Vec<_> = value.split(',').map(|x| x.trim().parse::<f32>()).collect();
+    let [Ok(min), Ok(max)] = parts[..] else {
+        return Err(anyhow!("{}的值'{}'必须为<最小值>,<最大值>", name, value));
+    };
+    check_map(name, (min, max))?;
+    Ok((min, max))
+}
+
+fn parse_f32_override(name: &str, value: &str) -> Result<f32> {
+    value
+        .trim()
+        .parse()
+        .with_context(|| format!("{}的值'{}'不是有效的数字", name, value))
+}
+
+fn parse_rotation_override(value: &str) -> Result<u16> {
+    let rotation = value
+        .trim()
+        .parse()
+        .with_context(|| format!("'{}'不是有效的rotation", value))?;
+    check_rotation(rotation)?;
+    Ok(rotation)
+}
+
+fn parse_smoothing_override(value: &str) -> Result<f32> {
+    let smoothing = parse_f32_override("smoothing", value)?;
+    check_smoothing(smoothing)?;
+    Ok(smoothing)
+}
+
+fn parse_pressure_curve_override(value: &str) -> Result<f32> {
+    let curve = parse_f32_override("pressureCurve", value)?;
+    check_pressure_curve("pressureCurve", curve)?;
+    Ok(curve)
+}
+
+// 将xMap、yMap写回配置文件；仅替换（或插入）顶层的xMap、yMap字段，保留其他内容与注释
+// 以下检查同时用于配置文件与控制套接字的set命令
+pub fn check_map(name: &str, (min, max): (f32, f32)) -> Result<()> {
+    if !(0f32..=1f32).contains(&min) {
+        return Err(anyhow!("{}的最小值必须在0到1之间", name));
+    }
+    if !(0f32..=1f32).contains(&max) {
+        return Err(anyhow!("{}的最大值必须在0到1之间", name));
+    }
+    if min >= max {
+        return Err(anyhow!("{}的最小值必须小于最大值", name));
+    }
+    Ok(())
+}
+
+pub fn check_pressure_curve(name: &str, curve: f32) -> Result<()> {
+    if !(curve > 0f32 && curve.is_finite()) {
+        return Err(anyhow!("{}必须大于0", name));
+    }
+    Ok(())
+}
+
+pub fn check_smoothing(smoothing: f32) -> Result<()> {
+    if !(0f32..1f32).contains(&smoothing) {
+        return Err(anyhow!("smoothing必须在0到1（不含）之间"));
+    }
+    Ok(())
+}
+
+pub fn check_rotation(rotation: u16) -> Result<()> {
+    if !matches!(rotation, 0 | 90 | 180 | 270) {
+        return Err(anyhow!(
+            "'{}'不是有效的rotation（可选值为0、90、180、270）",
+            rotation
+        ));
+    }
+    Ok(())
+}
+
+pub fn save_maps<P: AsRef<Path>>(path: P, x_map: (f32, f32), y_map: (f32, f32)) -> Result<()> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).context("无法读取配置文件")?;
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    let is_key = |line: &str, key: &str| {
+        line.trim_start()
+            .strip_prefix(key)
+            .is_some_and(|x| x.trim_start().starts_with('='))
+    };
+    if lines
+        .iter()
+        .any(|x| is_key(x, "screen") || x.trim() == "[screen]")
+    {
+        return Err(anyhow!("配置文件中配置了screen，请先删除screen配置"));
+    }
+    let mut top_level_end = lines
+        .iter()
+        .position(|x| x.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    for (key, (min, max)) in [("xMap", x_map), ("yMap", y_map)] {
+        let line = format!("{} = [{:.4}, {:.4}]", key, min, max);
+        match lines[..top_level_end].iter().position(|x| is_key(x, key)) {
+            Some(i) => lines[i] = line,
+            None => {
+                lines.insert(top_level_end, line);
+                top_level_end += 1;
+            }
+        }
+    }
+    let mut content = lines.join("\n");
+    content.push('\n');
+    std::fs::write(path, content).context("无法写入配置文件")
+}
+
+// 配置有误的字段在配置文件中的路径，例如tablet[0].keymap[1].ringButton；作为错误的上下文附加在最内层，
+// 外层的转换通过with_field_path在前面拼接所在的表，加载失败时据此定位到配置文件中的行与列
+#[derive(Debug)]
+struct FieldPath {
+    path: String,
+    location: Option<String>, // describe_span的结果
+}
+impl Display for FieldPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}配置有误，位于{}", self.path, location),
+            None => write!(f, "{}配置有误", self.path),
+        }
+    }
+}
+
+// 为错误附加所在配置项的路径；已有路径时拼接在其前面，例如keymap[1]与button1拼接为keymap[1].button1
+fn with_field_path(mut e: Error, segment: &str) -> Error {
+    match e.downcast_mut::<FieldPath>() {
+        Some(field) => {
+            field.path = format!("{}.{}", segment, field.path);
+            e
+        }
+        None => e.context(FieldPath {
+            path: segment.to_string(),
+            location: None,
+        }),
+    }
+}
+
+// 结构体字段名称对应的配置项名称，例如ring_button对应ringButton
+fn camel_case(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut upper = false;
+    for c in name.chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                result.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn toml_error(content: &str, e: toml::de::Error) -> Error {
+    match e.span() {
+        Some(span) => anyhow!(
+            "TOML解析失败，位于{}\n{}",
+            describe_span(content, span),
+            e.message()
+        ),
+        None => anyhow!("TOML解析失败: {}", e.message()),
+    }
+}
+
+// 配置项在配置文件中的位置；路径中的某一级不存在时（例如沿用了上一个方案的配置），定位到已找到的最内层
+fn locate_field(content: &str, path: &str) -> Option<String> {
+    let root = toml::de::DeTable::parse(content).ok()?;
+    let mut table = root.get_ref();
+    let mut span = None;
+    'outer: for segment in path.split('.') {
+        let mut indices = segment.split('[');
+        let key = indices.next()?;
+        let Some(mut value) = table.get(key) else {
+            break;
+        };
+        span = Some(value.span());
+        for index in indices {
+            let index: usize = index.trim_end_matches(']').parse().ok()?;
+            let toml::de::DeValue::Array(array) = value.get_ref() else {
+                break 'outer;
+            };
+            let Some(element) = array.get(index) else {
+                break 'outer;
+            };
+            value = element;
+            span = Some(value.span());
+        }
+        match value.get_ref() {
+            toml::de::DeValue::Table(x) => table = x,
+            _ => break,
+        }
+    }
+    span.map(|x| describe_span(content, x))
+}
+
+// span所在的行号、列号（从1开始）以及该行的内容，格式类似于编译器的错误输出
+fn describe_span(content: &str, span: std::ops::Range<usize>) -> String {
+    let start = span.start.min(content.len());
+    let line_start = content[..start].rfind('\n').map_or(0, |x| x + 1);
+    let line_end = content[start..]
+        .find('\n')
+        .map_or(content.len(), |x| start + x);
+    let line_number = content[..start].matches('\n').count() + 1;
+    let column = content[line_start..start].chars().count() + 1;
+    let end = span.end.clamp(start, line_end);
+    let width = content[start..end].chars().count().max(1);
+    let gutter = " ".repeat(line_number.to_string().len());
+    format!(
+        "第{}行第{}列：\n{} |\n{} | {}\n{} | {}{}",
+        line_number,
+        column,
+        gutter,
+        line_number,
+        &content[line_start..line_end],
+        gutter,
+        " ".repeat(column - 1),
+        "^".repeat(width)
+    )
+}
+
+// 配置文件中的一个未知配置项，例如tablet[0].keymap[1].ring_buttonn
+struct UnknownKey {
+    path: String,
+    suggestion: Option<&'static str>, // 名称最接近的有效配置项
+}
+impl Display for UnknownKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.suggestion {
+            Some(suggestion) => write!(f, "'{}'（是否应为'{}'？）", self.path, suggestion),
+            None => write!(f, "'{}'", self.path),
+        }
+    }
+}
+
+// 配置文件中各个表对应的原始配置，用于查找未知的配置项
+#[derive(Clone, Copy)]
+enum Section {
+    Config,
+    Area,
+    AreaPreset,
+    DwellClick,
+    AppProfile,
+    Rule,
+    Device,
+    VirtualAxes,
+    Axis,
+    Screen,
+    Keymap,
+    Proximity,
+}
+impl Section {
+    fn fields(self) -> Vec<&'static str> {
+        match self {
+            Self::Config => field_names::<RawConfig>(),
+            Self::Area => field_names::<RawAreaConfig>(),
+            // 区域预设展开了area的字段，serde不会为其提供字段列表
+            Self::AreaPreset => [&["name"][..], &field_names::<RawAreaConfig>()].concat(),
+            Self::DwellClick => field_names::<RawDwellClickConfig>(),
+            Self::AppProfile => field_names::<RawAppProfileConfig>(),
+            Self::Rule => field_names::<RawRuleConfig>(),
+            Self::Device => field_names::<RawDeviceConfig>(),
+            Self::VirtualAxes => field_names::<RawVirtualAxesConfig>(),
+            Self::Axis => field_names::<RawAxisConfig>(),
+            Self::Screen => field_names::<RawScreenConfig>(),
+            Self::Keymap => field_names::<RawKeymapConfig>(),
+            Self::Proximity => field_names::<RawProximityKeymapConfig>(),
+        }
+    }
+
+    // 值为表（或表数组）的配置项
+    fn child(self, key: &str) -> Option<Self> {
+        match (self, key) {
+            (Self::Config, "tablet") => Some(Self::Config),
+            (Self::Config, "area") => Some(Self::Area),
+            (Self::Config, "areaPreset") => Some(Self::AreaPreset),
+            (Self::Config, "dwellClick") => Some(Self::DwellClick),
+            (Self::Config, "appProfile") => Some(Self::AppProfile),
+            (Self::Config, "rule") => Some(Self::Rule),
+            (Self::Config, "device") => Some(Self::Device),
+            (Self::Config, "virtualAxes") => Some(Self::VirtualAxes),
+            (Self::Config, "screen") => Some(Self::Screen),
+            (Self::Config, "keymap") | (Self::Config, "keymapDefaults") => Some(Self::Keymap),
+            (Self::VirtualAxes, _) => Some(Self::Axis),
+            (Self::Keymap, "proximity") => Some(Self::Proximity),
+            _ => None,
+        }
+    }
+}
+
+// 借助serde为结构体生成的字段列表（重命名之后的名称）获取原始配置中的所有配置项：
+// 只实现deserialize_struct，通过错误带回字段列表，其余的方法都直接返回错误
+fn field_names<'de, T: Deserialize<'de>>() -> Vec<&'static str> {
+    #[derive(Debug)]
+    struct Fields(&'static [&'static str]);
+    impl Display for Fields {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl std::error::Error for Fields {}
+    impl serde::de::Error for Fields {
+        fn custom<T: Display>(_: T) -> Self {
+            Self(&[])
+        }
+    }
+    struct Probe;
+    impl<'de> serde::Deserializer<'de> for Probe {
+        type Error = Fields;
+
+        fn deserialize_any<V: serde::de::Visitor<'de>>(self, _: V) -> Result<V::Value, Fields> {
+            Err(Fields(&[]))
+        }
+
+        fn deserialize_struct<V: serde::de::Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Fields> {
+            Err(Fields(fields))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+    match T::deserialize(Probe) {
+        Ok(_) => vec![],
+        Err(Fields(fields)) => fields.to_vec(),
+    }
+}
+
+fn find_unknown_keys(table: &toml::Table) -> Vec<UnknownKey> {
+    let mut keys = Vec::new();
+    collect_unknown_keys(Section::Config, "", table, &mut keys);
+    keys
+}
+
+fn collect_unknown_keys(
+    section: Section,
+    path: &str,
+    table: &toml::Table,
+    keys: &mut Vec<UnknownKey>,
+) {
+    let fields = section.fields();
+    for (key, value) in table {
+        let path = match path {
+            "" => key.clone(),
+            _ => format!("{}.{}", path, key),
+        };
+        if !fields.contains(&key.as_str()) {
+            keys.push(UnknownKey {
+                suggestion: closest_name(key, &fields),
+                path,
+            });
+            continue;
+        }
+        let Some(child) = section.child(key) else {
+            continue;
+        };
+        match value {
+            toml::Value::Table(x) => collect_unknown_keys(child, &path, x, keys),
+            toml::Value::Array(x) => {
+                for (i, value) in x.iter().enumerate() {
+                    if let toml::Value::Table(x) = value {
+                        collect_unknown_keys(child, &format!("{}[{}]", path, i), x, keys);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// 忽略大小写、下划线与连字符后编辑距离最小的名称；相差过大时不作推荐
+fn closest_name(key: &str, names: &[&'static str]) -> Option<&'static str> {
+    let normalize = |x: &str| -> Vec<char> {
+        x.chars()
+            .filter(|x| *x != '_' && *x != '-')
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let key = normalize(key);
+    names
+        .iter()
+        .map(|name| (edit_distance(&key, &normalize(name)), *name))
+        .filter(|(distance, _)| *distance <= 2.max(key.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.iter().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if x == y {
+                prev
+            } else {
+                prev.min(row[j]).min(current) + 1
+            };
+            prev = current;
+        }
+    }
+    row[b.len()]
+}
+
+// 带有完整注释的示例配置，列出所有按键映射字段与支持的特殊行为，可以作为新配置文件的起点
+pub const DEFAULT_CONFIG: &str = include_str!("../config.example.toml");
+
+// 检查配置文件并列出发现的错误，用于在部署到连接绘图板的机器之前（例如在dotfiles的CI中）验证配置；
+// 无效的按键映射全部列出，之后替换为none继续检查，其余的错误只能列出第一处
+pub fn check(path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("无法读取配置文件{}", path.display()))?;
+    let mut raw: RawConfig = toml::from_str(&content).map_err(|e| toml_error(&content, e))?;
+    let mut keymap_errors = Vec::new();
+    for (path, keymap) in raw.keymaps_mut("") {
+        keymap_errors.extend(keymap.invalid_keymaps(&path));
+    }
+    for (i, tablet) in raw.tablets.iter_mut().enumerate() {
+        for (path, keymap) in tablet.keymaps_mut(&format!("tablet[{}].", i)) {
+            keymap_errors.extend(keymap.invalid_keymaps(&path));
+        }
+    }
+    // 配置了denyUnknownKeys时，未知的配置项由下面的Config::parse作为错误返回
+    if !raw.deny_unknown_keys.unwrap_or(false) {
+        let source: toml::Table = toml::from_str(&content).map_err(|e| toml_error(&content, e))?;
+        for key in find_unknown_keys(&source) {
+            eprintln!("警告：未知的配置项{}，运行时将被忽略", key);
+        }
+    }
+    let mut errors = Vec::new();
+    // 配置了ignoreInvalidKeymaps时，无效的按键映射在运行时只会产生警告
+    if raw.ignore_invalid_keymaps.unwrap_or(false) {
+        for e in keymap_errors {
+            eprintln!("警告：{}，运行时将被替换为none", e);
+        }
+    } else {
+        errors = keymap_errors;
+    }
+    if let Err(e) = Config::parse(&content, true) {
+        errors.push(e);
+    }
+    if errors.is_empty() {
+        println!("配置文件{}检查通过", path.display());
+        return Ok(());
+    }
+    for e in &errors {
+        let mut chain = e.chain();
+        if let Some(e) = chain.next() {
+            eprintln!("错误：{}", e);
+        }
+        for cause in chain {
+            eprintln!("  原因：{}", cause);
+        }
+    }
+    Err(anyhow!(
+        "配置文件{}中发现{}处错误",
+        path.display(),
+        errors.len()
+    ))
+}
+
 type ConfigChangeCallback = Box<dyn FnMut(Arc<Config>) + Send + Sync>;
 
+// 监视配置文件的变动与重新加载的请求；由事件循环驱动
 pub struct WatchConfigChangeTask {
     path: PathBuf,
-    filename: String,
-    epoll: Epoll,
+    target: PathBuf, // 跟随符号链接后的实际文件；无法解析时与path相同
     inotify: Inotify,
+    watches: Vec<(WatchDescriptor, Option<OsString>)>, // 目录中文件名为Some的文件，或None表示配置文件本身
+    reload_eventfd: Arc<EventFd>,
     callbacks: Vec<ConfigChangeCallback>,
+    overrides: ConfigOverrides,
+}
+// 通知配置文件监视任务立即重新加载配置文件，即使文件没有变动
+#[derive(Clone)]
+pub struct ReloadTrigger(Arc<EventFd>);
+impl ReloadTrigger {
+    pub fn trigger(&self) {
+        if let Err(e) = self.0.write(1) {
+            error!(
+                "无法通过写eventfd通知配置文件监视任务重新加载配置文件: {}",
+                e
+            );
+        }
+    }
 }
 impl WatchConfigChangeTask {
-    const EPOLL_CANCEL_EVENT: u64 = 0;
-    const EPOLL_INOTIFY_EVENT: u64 = 1;
     const WATCH_CONFIG_CHANGE_DEBOUNCE: Duration = Duration::from_millis(500);
 
-    pub fn new<P: AsRef<Path>>(path: P, cancel_token: CancelToken) -> Result<Self> {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref().to_path_buf();
-        let filename = path
-            .file_name()
-            .context("无法从配置文件路径中提取文件名")?
-            .to_str()
-            .context("配置文件名称中包含无效字符")?
-            .to_string();
-        let mut parent_dir = path
-            .parent()
-            .context("无法从配置文件路径中提取父目录")?
-            .to_path_buf();
-        if parent_dir.as_os_str().is_empty() {
-            parent_dir = PathBuf::from(".");
-        }
-
-        let cancel_eventfd =
-            EventFd::from_value_and_flags(0, EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_SEMAPHORE)
-                .context("EventFd::from_value_and_flags")?;
-        let cancel_eventfd = Arc::new(cancel_eventfd);
-        {
-            let cancel_eventfd = cancel_eventfd.clone();
-            cancel_token.register_callback(move || {
-                if let Err(e) = cancel_eventfd.write(1) {
-                    error!("无法通过写eventfd通知配置文件监视任务结束执行: {}", e);
-                }
-            });
-        }
-
+        let reload_eventfd = EventFd::from_value_and_flags(0, EfdFlags::EFD_NONBLOCK)
+            .context("EventFd::from_value_and_flags")?;
+        let reload_eventfd = Arc::new(reload_eventfd);
         let inotify = Inotify::init(inotify::InitFlags::all()).context("Inotify::init")?;
-        inotify
-            .add_watch(
-                &parent_dir,
-                inotify::AddWatchFlags::IN_MODIFY
-                    | inotify::AddWatchFlags::IN_CREATE
-                    | inotify::AddWatchFlags::IN_MOVED_TO,
-            )
-            .context("Inotify::add_watch")?;
-
-        let epoll = Epoll::new(EpollCreateFlags::all()).context("Epoll::new")?;
-        epoll
-            .add(
-                &cancel_eventfd,
-                EpollEvent::new(EpollFlags::EPOLLIN, Self::EPOLL_CANCEL_EVENT),
-            )
-            .context("Epoll::add(EventFd)")?;
-        epoll
-            .add(
-                &inotify,
-                EpollEvent::new(EpollFlags::EPOLLIN, Self::EPOLL_INOTIFY_EVENT),
-            )
-            .context("Epoll::add(Inotify)")?;
-        Ok(Self {
+        let mut task = Self {
+            target: path.clone(),
             path,
-            filename,
-            epoll,
             inotify,
+            watches: Vec::new(),
+            reload_eventfd,
             callbacks: Vec::new(),
-        })
+            overrides: ConfigOverrides::default(),
+        };
+        task.watch()?;
+        Ok(task)
+    }
+
+    // 监视配置文件所在的目录（按文件名过滤，覆盖编辑器先写临时文件再重命名的情况），配置文件为符号链接时
+    // 同时监视实际文件所在的目录（例如NixOS、home-manager或dotfiles仓库），以及配置文件本身；
+    // 文件被移动、删除或符号链接指向了其他文件后重新建立监视
+    fn watch(&mut self) -> Result<()> {
+        for (wd, _) in self.watches.drain(..) {
+            // 同一目录可能对应同一个监视，重复移除时忽略错误
+            let _ = self.inotify.rm_watch(wd);
+        }
+        self.target = std::fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        let mut files = vec![self.path.clone()];
+        if self.target != self.path {
+            files.push(self.target.clone());
+        }
+        for file in files {
+            let name = file
+                .file_name()
+                .context("无法从配置文件路径中提取文件名")?
+                .to_os_string();
+            let dir = match file.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let wd = self
+                .inotify
+                .add_watch(
+                    dir,
+                    inotify::AddWatchFlags::IN_MODIFY
+                        | inotify::AddWatchFlags::IN_CLOSE_WRITE
+                        | inotify::AddWatchFlags::IN_CREATE
+                        | inotify::AddWatchFlags::IN_MOVED_TO,
+                )
+                .context("Inotify::add_watch")?;
+            self.watches.push((wd, Some(name)));
+        }
+        // 文件暂时不存在时（例如正在被替换）只监视目录，文件出现后重新建立监视
+        if let Ok(wd) = self.inotify.add_watch(
+            &self.path,
+            inotify::AddWatchFlags::IN_MODIFY
+                | inotify::AddWatchFlags::IN_CLOSE_WRITE
+                | inotify::AddWatchFlags::IN_MOVE_SELF
+                | inotify::AddWatchFlags::IN_DELETE_SELF,
+        ) {
+            self.watches.push((wd, None));
+        }
+        debug!("监视配置文件{}", self.target.display());
+        Ok(())
+    }
+
+    pub fn reload_trigger(&self) -> ReloadTrigger {
+        ReloadTrigger(self.reload_eventfd.clone())
     }
 
     pub fn register_callback<F>(&mut self, f: F)
@@ -398,44 +2645,29 @@ impl WatchConfigChangeTask {
         self.callbacks.push(Box::new(f));
     }
 
-    pub fn run(mut self) -> Result<()> {
-        let mut events = [EpollEvent::empty(); 1];
-        loop {
-            let n = self
-                .epoll
-                .wait(&mut events, EpollTimeout::NONE)
-                .context("Epoll::wait")?;
-            if n == 0 {
-                continue;
-            }
-            match events[0].data() {
-                x if x == Self::EPOLL_CANCEL_EVENT => return Ok(()),
-                x if x == Self::EPOLL_INOTIFY_EVENT => {
-                    let events = self.drain_inotify_events()?;
-                    let mut modified = false;
-                    for event in events {
-                        if event.name.unwrap_or_default() == self.filename.as_str() {
-                            modified = true;
-                        }
-                    }
-                    if !modified {
-                        continue;
-                    }
-                    std::thread::sleep(Self::WATCH_CONFIG_CHANGE_DEBOUNCE);
-                    let _ = self.drain_inotify_events()?;
-                    match Config::load(&self.path) {
-                        Ok(conf) => {
-                            let conf = Arc::new(conf);
-                            for callback in &mut self.callbacks {
-                                callback(conf.clone());
-                            }
-                        }
-                        Err(e) => {
-                            warn!("无法重新加载配置文件，忽略本次配置文件的变动: {e}");
-                        }
-                    }
+    // 重新加载后同样应用命令行中指定的配置项
+    pub fn set_overrides(&mut self, overrides: ConfigOverrides) {
+        self.overrides = overrides;
+    }
+
+    fn reload(&mut self) {
+        let result = Config::load(&self.path).and_then(|mut conf| {
+            self.overrides.apply(&mut conf)?;
+            Ok(conf)
+        });
+        match result {
+            Ok(conf) => {
+                logger::configure(&conf);
+                let conf = Arc::new(conf);
+                for callback in &mut self.callbacks {
+                    callback(conf.clone());
                 }
-                _ => unreachable!(),
+            }
+            Err(e) => {
+                warn!(
+                    event = "config_reload_failed";
+                    "无法重新加载配置文件，忽略本次配置文件的变动: {e}"
+                );
             }
         }
     }
@@ -451,3 +2683,44 @@ impl WatchConfigChangeTask {
         }
     }
 }
+impl EventHandler for WatchConfigChangeTask {
+    fn fds(&self) -> Vec<BorrowedFd<'_>> {
+        vec![self.inotify.as_fd(), self.reload_eventfd.as_fd()]
+    }
+
+    fn handle(&mut self, index: usize) -> Result<()> {
+        if index == 0 {
+            let events = self.drain_inotify_events()?;
+            let mut modified = false;
+            let mut rewatch = false;
+            for event in events {
+                let self_flags =
+                    inotify::AddWatchFlags::IN_MOVE_SELF | inotify::AddWatchFlags::IN_DELETE_SELF;
+                if event.mask.intersects(self_flags) {
+                    modified = true;
+                    rewatch = true;
+                    continue;
+                }
+                modified |= self
+                    .watches
+                    .iter()
+                    .any(|(wd, name)| *wd == event.wd && (name.is_none() || *name == event.name));
+            }
+            if !modified {
+                return Ok(());
+            }
+            std::thread::sleep(Self::WATCH_CONFIG_CHANGE_DEBOUNCE);
+            let _ = self.drain_inotify_events()?;
+            let target = std::fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+            if rewatch || target != self.target || !self.watches.iter().any(|x| x.1.is_none()) {
+                self.watch()?;
+            }
+        } else {
+            // 非信号量模式的eventfd读取一次即清零，合并多次重新加载的请求
+            let _ = self.reload_eventfd.read();
+            debug!("收到重新加载配置文件的请求");
+        }
+        self.reload();
+        Ok(())
+    }
+}
@@ -0,0 +1,841 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
+use parking_lot::Mutex;
+
+use crate::cancel::CancelToken;
+use crate::cli::ClientCommand;
+use crate::config::{self, AreaConfig, AreaUnit, OutsideAreaBehavior, ReloadTrigger};
+use crate::json::Value;
+use crate::latency::LatencySummary;
+use crate::{debug, json_object, warn};
+
+// 转发给驱动任务的运行时命令；status、reload请求由控制任务直接处理
+#[derive(Clone, Copy, Debug)]
+pub enum ControlCommand {
+    // 重新初始化USB设备（保留虚拟设备、当前的按键映射方案与使用统计）
+    Restart,
+    // 切换到指定下标的按键映射方案
+    SwitchSchema(usize),
+    // 切换到下一个按键映射方案（由SIGUSR2触发）
+    NextSchema,
+    // 暂停：释放所有按下的按键与画笔，并关闭设备，使其他程序可以访问设备
+    Pause,
+    Resume,
+    // 在运行时修改属性（由set命令触发）；重新加载配置文件后恢复为配置文件中的值
+    SetProperty(Property),
+}
+
+// 可以在运行时修改的属性；值为None时恢复为配置文件中的值
+#[derive(Clone, Copy, Debug)]
+pub enum Property {
+    PressureCurve(Option<f32>),
+    Smoothing(Option<f32>),
+    Rotation(Option<u16>),
+    XMap(Option<(f32, f32)>),
+    YMap(Option<(f32, f32)>),
+    Area(Option<AreaConfig>),
+}
+impl Property {
+    pub const NAMES: [&str; 6] = [
+        "pressureCurve",
+        "smoothing",
+        "rotation",
+        "xMap",
+        "yMap",
+        "area",
+    ];
+
+    // 解析并校验set命令中的属性值，校验规则与配置文件相同
+    fn parse(name: &str, value: &Value) -> Result<Self> {
+        let number = || -> Result<Option<f32>> {
+            match value {
+                Value::Null => Ok(None),
+                x => Ok(Some(
+                    x.as_f64().context(format!("{}的值必须为数字", name))? as f32
+                )),
+            }
+        };
+        let range = |value: Option<&Value>| -> Result<Option<(f32, f32)>> {
+            match value {
+                None | Some(Value::Null) => Ok(None),
+                Some(Value::Array(x)) => match x.as_slice() {
+                    [min, max] => match (min.as_f64(), max.as_f64()) {
+                        (Some(min), Some(max)) => Ok(Some((min as f32, max as f32))),
+                        _ => Err(anyhow!("{}的值必须为两个数字组成的数组", name)),
+                    },
+                    _ => Err(anyhow!("{}的值必须为两个数字组成的数组", name)),
+                },
+                Some(_) => Err(anyhow!("{}的值必须为两个数字组成的数组", name)),
+            }
+        };
+        let property = match name {
+            "pressureCurve" => {
+                let curve = number()?;
+                if let Some(curve) = curve {
+                    config::check_pressure_curve(name, curve)?;
+                }
+                Self::PressureCurve(curve)
+            }
+            "smoothing" => {
+                let smoothing = number()?;
+                if let Some(smoothing) = smoothing {
+                    config::check_smoothing(smoothing)?;
+                }
+                Self::Smoothing(smoothing)
+            }
+            "rotation" => {
+                let rotation = match value {
+                    Value::Null => None,
+                    x => Some(
+                        x.as_u64()
+                            .filter(|x| *x <= u16::MAX as u64)
+                            .context("rotation的值必须为0、90、180或270")?
+                            as u16,
+                    ),
+                };
+                if let Some(rotation) = rotation {
+                    config::check_rotation(rotation)?;
+                }
+                Self::Rotation(rotation)
+            }
+            "xMap" | "yMap" => {
+                let map = range(Some(value))?;
+                if let Some(map) = map {
+                    config::check_map(name, map)?;
+                }
+                match name {
+                    "xMap" => Self::XMap(map),
+                    _ => Self::YMap(map),
+                }
+            }
+            // 例：{"unit": "mm", "x": [10, 150], "y": [10, 90], "outside": "ignore"}；
+            // 与配置文件中的area相同，省略x、y时不限制对应的坐标轴
+            "area" => match value {
+                Value::Null => Self::Area(None),
+                Value::Object(_) => {
+                    let field = |key| value.get(key).and_then(Value::as_str);
+                    let area = AreaConfig::new(
+                        field("unit"),
+                        range(value.get("x"))?,
+                        range(value.get("y"))?,
+                        field("outside"),
+                    )?;
+                    Self::Area(Some(area))
+                }
+                _ => return Err(anyhow!("area的值必须为对象")),
+            },
+            x => {
+                return Err(anyhow!(
+                    "'{}'不是有效的属性（可选值为{}）",
+                    x,
+                    Self::NAMES.join("、")
+                ));
+            }
+        };
+        Ok(property)
+    }
+}
+
+// 各属性当前生效的值，供status请求查询
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Properties {
+    pub pressure_curve: Option<f32>,
+    pub smoothing: f32,
+    pub rotation: u16,
+    pub x_map: Option<(f32, f32)>,
+    pub y_map: Option<(f32, f32)>,
+    pub area: Option<AreaConfig>,
+}
+impl Properties {
+    fn to_json(self) -> Value {
+        let range = |x: Option<(f32, f32)>| {
+            x.map(|(min, max)| Value::Array(vec![Value::from(min), Value::from(max)]))
+        };
+        let area = self.area.map(|area| {
+            json_object! {
+                "unit" => match area.unit {
+                    AreaUnit::Raw => "raw",
+                    AreaUnit::Millimeter => "mm",
+                },
+                "x" => range(area.x),
+                "y" => range(area.y),
+                "outside" => match area.outside {
+                    OutsideAreaBehavior::Clamp => "clamp",
+                    OutsideAreaBehavior::Ignore => "ignore",
+                },
+            }
+        });
+        json_object! {
+            "pressureCurve" => self.pressure_curve,
+            "smoothing" => self.smoothing,
+            "rotation" => self.rotation as usize,
+            "xMap" => range(self.x_map),
+            "yMap" => range(self.y_map),
+            "area" => area,
+        }
+    }
+}
+
+// 驱动任务启动以来的运行统计，用于判断驱动是否正常工作
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct RuntimeStats {
+    pub reports: u64,         // 从设备读取的报告数
+    pub events: u64,          // 写入虚拟设备的事件数
+    pub reconnects: u64,      // 设备断开（或暂停）后重新连接的次数
+    pub ignored_reports: u64, // 无法识别或没有对应映射而被忽略的报告数
+    pub dropped_reports: u64, // 被maxReportRate合并而未上报的报告数
+    pub reloads: u64,         // 重新加载配置文件的次数
+    pub recoveries: u64,      // 出错后重新初始化设备的次数
+}
+impl RuntimeStats {
+    fn to_json(self) -> Value {
+        json_object! {
+            "reports" => self.reports as usize,
+            "events" => self.events as usize,
+            "reconnects" => self.reconnects as usize,
+            "ignoredReports" => self.ignored_reports as usize,
+            "droppedReports" => self.dropped_reports as usize,
+            "reloads" => self.reloads as usize,
+            "recoveries" => self.recoveries as usize,
+        }
+    }
+}
+
+// 驱动任务发布的状态快照，供status请求查询
+#[derive(Clone, Default, PartialEq)]
+pub struct TabletStatus {
+    pub connection: Option<&'static str>, // "usb"、"dongle"或"bluetooth"；设备未连接时为None
+    pub paused: bool,
+    pub schema: usize,
+    pub schema_names: Vec<Option<String>>, // 各按键映射方案的名称；仅在配置变化时更新
+    pub monitor: Option<String>,           // 通过cycleMonitor或followMonitor切换到的显示器
+    pub area: Option<String>,              // 通过cycleArea切换到的区域预设
+    pub pen_mode: &'static str,
+    pub properties: Properties,
+    pub stats: RuntimeStats,
+    pub latency: Option<LatencySummary>, // 仅在配置了latencyStats = true且已有记录时存在
+}
+impl TabletStatus {
+    fn to_json(&self, tablet_index: usize) -> Value {
+        json_object! {
+            "tablet" => tablet_index,
+            "connected" => self.connection.is_some(),
+            "connection" => self.connection,
+            "paused" => self.paused,
+            "schema" => self.schema,
+            "schemaName" => self.schema_names.get(self.schema).cloned().flatten(),
+            "schemas" => Value::Array(self.schema_names.iter().cloned().map(Value::from).collect()),
+            "monitor" => self.monitor.clone(),
+            "area" => self.area.clone(),
+            "penMode" => self.pen_mode,
+            "properties" => self.properties.to_json(),
+            "stats" => self.stats.to_json(),
+            "latency" => self.latency.map(|x| json_object! {
+                "count" => x.count as usize,
+                "p50" => x.p50 as usize,
+                "p90" => x.p90 as usize,
+                "p99" => x.p99 as usize,
+                "max" => x.max as usize,
+            }),
+        }
+    }
+}
+
+// monitor、record请求的连接：驱动任务把解码后的输入事件（或原始报告）逐行写入；写入失败（客户端已断开或读取过慢）时移除该连接
+#[derive(Clone, Default)]
+pub struct MonitorClients(Arc<MonitorClientsInner>);
+#[derive(Default)]
+struct MonitorClientsInner {
+    streams: Mutex<Vec<UnixStream>>,
+    count: AtomicUsize, // 驱动任务处理每个报告时都会检查是否有连接，因此不加锁读取
+}
+impl MonitorClients {
+    pub fn is_empty(&self) -> bool {
+        self.0.count.load(Ordering::Relaxed) == 0
+    }
+
+    pub fn send(&self, event: Value) {
+        let line = format!("{}\n", event);
+        let mut streams = self.0.streams.lock();
+        streams.retain(|stream| {
+            let mut stream = stream;
+            stream.write_all(line.as_bytes()).is_ok()
+        });
+        self.0.count.store(streams.len(), Ordering::Relaxed);
+    }
+
+    fn add(&self, stream: UnixStream) {
+        let mut streams = self.0.streams.lock();
+        streams.push(stream);
+        self.0.count.store(streams.len(), Ordering::Relaxed);
+    }
+}
+
+type ControlCommandCallback = Box<dyn FnMut(ControlCommand) + Send + Sync>;
+
+struct Tablet {
+    index: usize,
+    status: Arc<Mutex<TabletStatus>>,
+    monitors: MonitorClients,
+    recorders: MonitorClients,
+    callback: ControlCommandCallback,
+}
+
+// 监听控制套接字，处理按行分隔的JSON请求：status请求直接回复各驱动任务发布的状态，其余命令转发给驱动任务
+pub struct ControlTask {
+    path: PathBuf,
+    listener: UnixListener,
+    epoll: Epoll,
+    tablets: Vec<Tablet>,
+    reload_trigger: Option<ReloadTrigger>, // 未指定配置文件时为None
+}
+impl ControlTask {
+    const EPOLL_CANCEL_EVENT: u64 = 0;
+    const EPOLL_LISTENER_EVENT: u64 = 1;
+    const CONNECTION_TIMEOUT: Duration = Duration::from_secs(1);
+
+    pub fn new(cancel_token: CancelToken, path: Option<&Path>) -> Result<Self> {
+        let path = socket_path(path);
+        if UnixStream::connect(&path).is_ok() {
+            return Err(anyhow!("控制套接字{}正在被其他进程使用", path.display()));
+        }
+        // 清理上次运行残留的套接字文件
+        let _ = std::fs::remove_file(&path);
+        let listener =
+            UnixListener::bind(&path).context(format!("无法绑定控制套接字{}", path.display()))?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .context("无法设置控制套接字的权限")?;
+        listener
+            .set_nonblocking(true)
+            .context("UnixListener::set_nonblocking")?;
+
+        let epoll = Epoll::new(EpollCreateFlags::all()).context("Epoll::new")?;
+        epoll
+            .add(
+                cancel_token.as_fd()?,
+                EpollEvent::new(EpollFlags::EPOLLIN, Self::EPOLL_CANCEL_EVENT),
+            )
+            .context("Epoll::add(CancelToken)")?;
+        epoll
+            .add(
+                &listener,
+                EpollEvent::new(EpollFlags::EPOLLIN, Self::EPOLL_LISTENER_EVENT),
+            )
+            .context("Epoll::add(UnixListener)")?;
+        Ok(Self {
+            path,
+            listener,
+            epoll,
+            tablets: Vec::new(),
+            reload_trigger: None,
+        })
+    }
+
+    // 注册第tablet_index个绘图板的命令回调；返回的状态由驱动任务更新
+    pub fn register_callback<F>(&mut self, tablet_index: usize, f: F) -> Arc<Mutex<TabletStatus>>
+    where
+        F: FnMut(ControlCommand) + Send + Sync + 'static,
+    {
+        let status = Arc::new(Mutex::new(TabletStatus::default()));
+        self.tablets.push(Tablet {
+            index: tablet_index,
+            status: status.clone(),
+            monitors: MonitorClients::default(),
+            recorders: MonitorClients::default(),
+            callback: Box::new(f),
+        });
+        status
+    }
+
+    // 第tablet_index个绘图板的monitor连接；需要先调用register_callback
+    pub fn monitor_clients(&self, tablet_index: usize) -> MonitorClients {
+        self.tablets
+            .iter()
+            .find(|x| x.index == tablet_index)
+            .map(|x| x.monitors.clone())
+            .unwrap_or_default()
+    }
+
+    // 第tablet_index个绘图板的record连接；需要先调用register_callback
+    pub fn record_clients(&self, tablet_index: usize) -> MonitorClients {
+        self.tablets
+            .iter()
+            .find(|x| x.index == tablet_index)
+            .map(|x| x.recorders.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn set_reload_trigger(&mut self, trigger: ReloadTrigger) {
+        self.reload_trigger.replace(trigger);
+    }
+
+    pub fn run(mut self) -> Result<()> {
+        let mut events = [EpollEvent::empty(); 1];
+        loop {
+            let n = self
+                .epoll
+                .wait(&mut events, EpollTimeout::NONE)
+                .context("Epoll::wait")?;
+            if n == 0 {
+                continue;
+            }
+            match events[0].data() {
+                x if x == Self::EPOLL_CANCEL_EVENT => return Ok(()),
+                x if x == Self::EPOLL_LISTENER_EVENT => loop {
+                    match self.listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Err(e) = self.handle_connection(stream) {
+                                warn!("处理控制命令时发生错误: {:?}", e);
+                            }
+                        }
+                        Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                        Err(e) => Err(e).context("UnixListener::accept")?,
+                    }
+                },
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // 每个连接可以发送多个请求，每个请求占一行，并收到一行回复；空闲超过CONNECTION_TIMEOUT后断开
+    fn handle_connection(&mut self, stream: UnixStream) -> Result<()> {
+        stream
+            .set_nonblocking(false)
+            .context("UnixStream::set_nonblocking")?;
+        stream
+            .set_read_timeout(Some(Self::CONNECTION_TIMEOUT))
+            .context("UnixStream::set_read_timeout")?;
+        let mut reader = BufReader::new(&stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) => return Ok(()),
+                Ok(_) => {}
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(());
+                }
+                Err(e) => return Err(e).context("无法读取控制请求"),
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match self.handle_request(&line, &stream) {
+                Ok(reply) => reply,
+                Err(e) => json_object! {
+                    "ok" => false,
+                    "error" => format!("{:#}", e),
+                },
+            };
+            writeln!(&stream, "{}", reply).context("无法回复控制请求")?;
+        }
+    }
+
+    // 请求格式：{"command": "<命令>", "tablet": <绘图板序号>}；省略tablet时作用于所有绘图板
+    fn handle_request(&mut self, line: &str, stream: &UnixStream) -> Result<Value> {
+        let request = Value::parse(line).context("请求不是有效的JSON")?;
+        let command = request
+            .get("command")
+            .and_then(Value::as_str)
+            .context("请求中缺少command字段")?;
+        let tablet_index = match request.get("tablet") {
+            None | Some(Value::Null) => None,
+            Some(x) => Some(x.as_u64().context("tablet字段必须为非负整数")? as usize),
+        };
+        if let Some(index) = tablet_index
+            && !self.tablets.iter().any(|x| x.index == index)
+        {
+            return Err(anyhow!("绘图板{}不存在", index));
+        }
+        let tablets = self
+            .tablets
+            .iter_mut()
+            .filter(|x| tablet_index.is_none_or(|index| x.index == index));
+        let command = match command {
+            "status" => {
+                let statuses = tablets.map(|x| x.status.lock().to_json(x.index)).collect();
+                return Ok(json_object! {
+                    "ok" => true,
+                    "tablets" => Value::Array(statuses),
+                });
+            }
+            // 回复之后，连接转交给驱动任务，用于持续接收输入事件（record为原始报告）；同一连接上不再处理其他请求
+            "monitor" | "record" => {
+                for tablet in tablets {
+                    let stream = stream.try_clone().context("UnixStream::try_clone")?;
+                    // 非阻塞写入，避免读取过慢的客户端阻塞驱动任务
+                    stream
+                        .set_nonblocking(true)
+                        .context("UnixStream::set_nonblocking")?;
+                    match command {
+                        "monitor" => tablet.monitors.add(stream),
+                        _ => tablet.recorders.add(stream),
+                    }
+                }
+                return Ok(json_object! { "ok" => true });
+            }
+            "reload" => {
+                let trigger = self
+                    .reload_trigger
+                    .as_ref()
+                    .context("启动时未指定配置文件，无法重新加载")?;
+                trigger.trigger();
+                return Ok(json_object! { "ok" => true });
+            }
+            "switchSchema" => {
+                let schema = request.get("schema").context("请求中缺少schema字段")?;
+                // 先为每个绘图板解析方案，全部成功后再转发，避免只有部分绘图板切换
+                let commands = tablets
+                    .map(|x| {
+                        let index = resolve_schema(&x.status.lock(), schema)
+                            .with_context(|| format!("绘图板{}", x.index))?;
+                        Ok((x, ControlCommand::SwitchSchema(index)))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                for (tablet, command) in commands {
+                    debug!("收到控制命令{:?}", command);
+                    (tablet.callback)(command);
+                }
+                return Ok(json_object! { "ok" => true });
+            }
+            // 请求格式：{"command": "set", "property": "<属性>", "value": <值>}；值为null时恢复为配置文件中的值
+            "set" => {
+                let property = request
+                    .get("property")
+                    .and_then(Value::as_str)
+                    .context("请求中缺少property字段")?;
+                let value = request.get("value").context("请求中缺少value字段")?;
+                ControlCommand::SetProperty(Property::parse(property, value)?)
+            }
+            "restart" => ControlCommand::Restart,
+            "pause" => ControlCommand::Pause,
+            "resume" => ControlCommand::Resume,
+            x => return Err(anyhow!("'{}'不是有效的控制命令", x)),
+        };
+        debug!("收到控制命令{:?}", command);
+        for tablet in tablets {
+            (tablet.callback)(command);
+        }
+        Ok(json_object! { "ok" => true })
+    }
+}
+// schema可以是方案的名称，也可以是方案的下标
+fn resolve_schema(status: &TabletStatus, schema: &Value) -> Result<usize> {
+    let len = status.schema_names.len();
+    if let Some(index) = schema.as_u64() {
+        let index = index as usize;
+        if index >= len {
+            return Err(anyhow!("按键映射方案{}不存在（共{}个方案）", index, len));
+        }
+        return Ok(index);
+    }
+    let name = schema.as_str().context("schema字段必须为方案名称或下标")?;
+    if let Some(index) = status
+        .schema_names
+        .iter()
+        .position(|x| x.as_deref() == Some(name))
+    {
+        return Ok(index);
+    }
+    match name.parse::<usize>() {
+        Ok(index) if index < len => Ok(index),
+        _ => Err(anyhow!("找不到名为'{}'的按键映射方案", name)),
+    }
+}
+
+impl Drop for ControlTask {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// 控制套接字的路径：未配置controlSocket时为$XDG_RUNTIME_DIR/parblo-intangbo-m-driver.sock，
+// 未设置XDG_RUNTIME_DIR时使用临时目录
+fn socket_path(path: Option<&Path>) -> PathBuf {
+    if let Some(path) = path {
+        return path.to_path_buf();
+    }
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::temp_dir(),
+    };
+    dir.join(format!("{}.sock", env!("CARGO_PKG_NAME")))
+}
+
+// 把请求发送给正在运行的驱动程序，并返回回复；回复中ok为false时返回其中的错误信息
+pub fn send_request(path: Option<&Path>, request: &Value) -> Result<Value> {
+    let path = socket_path(path);
+    let stream = UnixStream::connect(&path).context(format!(
+        "无法连接控制套接字{}，驱动程序是否正在运行？",
+        path.display()
+    ))?;
+    writeln!(&stream, "{}", request).context("无法发送控制请求")?;
+    let mut reply = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut reply)
+        .context("无法读取回复")?;
+    let reply = Value::parse(&reply).context("回复不是有效的JSON")?;
+    if reply.get("ok").and_then(Value::as_bool) != Some(true) {
+        let e = reply
+            .get("error")
+            .and_then(Value::as_str)
+            .unwrap_or("未知错误");
+        return Err(anyhow!("{}", e));
+    }
+    Ok(reply)
+}
+
+// 客户端子命令：把命令发送给正在运行的驱动程序，并打印回复；
+// 支持--socket <路径>（配置了controlSocket时）与--tablet <绘图板序号>（省略时作用于所有绘图板）
+pub fn client(command: ClientCommand) -> Result<()> {
+    // 附加在请求中的其他字段
+    let mut fields = vec![];
+    let (name, target) = match &command {
+        ClientCommand::Ctl { command, target } => (command.as_str(), target),
+        ClientCommand::Status(target) => ("status", target),
+        ClientCommand::SwitchSchema { schema, target } => {
+            fields.push(("schema", Value::from(schema.as_str())));
+            ("switchSchema", target)
+        }
+        ClientCommand::Reload(target) => ("reload", target),
+        ClientCommand::Pause(target) => ("pause", target),
+        ClientCommand::Resume(target) => ("resume", target),
+        // 值按JSON解析，解析失败时作为字符串，例如：set pressureCurve 1.5、set xMap "[0, 0.5]"
+        ClientCommand::Set {
+            property,
+            value,
+            target,
+        } => {
+            fields.push(("property", Value::from(property.as_str())));
+            fields.push((
+                "value",
+                Value::parse(value).unwrap_or(Value::from(value.as_str())),
+            ));
+            ("set", target)
+        }
+        ClientCommand::Get { property, target } => {
+            if let Some(property) = property
+                && !Property::NAMES.contains(&property.as_str())
+            {
+                return Err(anyhow!(
+                    "'{}'不是有效的属性（可选值为{}）",
+                    property,
+                    Property::NAMES.join("、")
+                ));
+            }
+            ("status", target)
+        }
+        ClientCommand::Monitor(target) => ("monitor", target),
+        ClientCommand::Record { target, .. } => ("record", target),
+    };
+    let mut tablet = target.tablet;
+    // 原始报告中不包含绘图板序号，因此只记录一个绘图板；省略--tablet时记录第一个绘图板
+    if let ClientCommand::Record { .. } = command {
+        tablet.get_or_insert(0);
+    }
+    let mut request = vec![("command".to_string(), Value::from(name))];
+    if let Some(tablet) = tablet {
+        request.push(("tablet".to_string(), Value::from(tablet)));
+    }
+    request.extend(fields.into_iter().map(|(k, v)| (k.to_string(), v)));
+    let socket = target.socket.as_deref();
+    match &command {
+        ClientCommand::Monitor(_) => return monitor(socket, &Value::Object(request)),
+        ClientCommand::Record { output, .. } => {
+            return record(socket, &Value::Object(request), output);
+        }
+        _ => {}
+    }
+    let reply = send_request(socket, &Value::Object(request))?;
+    match &command {
+        ClientCommand::Ctl { .. } => println!("{}", reply),
+        ClientCommand::Status(_) => print_status(&reply),
+        ClientCommand::Get { property, .. } => print_properties(&reply, property.as_deref()),
+        _ => println!("ok"),
+    }
+    Ok(())
+}
+
+// 发送monitor或record请求，并逐个处理之后收到的事件，直到驱动程序退出或按下Ctrl+C
+fn stream_events<F>(path: Option<&Path>, request: &Value, connected: &str, mut f: F) -> Result<()>
+where
+    F: FnMut(&Value) -> Result<()>,
+{
+    let path = socket_path(path);
+    let stream = UnixStream::connect(&path).context(format!(
+        "无法连接控制套接字{}，驱动程序是否正在运行？",
+        path.display()
+    ))?;
+    writeln!(&stream, "{}", request).context("无法发送控制请求")?;
+    for line in BufReader::new(&stream).lines() {
+        let line = line.context("无法读取事件")?;
+        let event = Value::parse(&line).context("事件不是有效的JSON")?;
+        match event.get("ok").and_then(Value::as_bool) {
+            Some(true) => println!("{}", connected),
+            Some(false) => {
+                let e = event
+                    .get("error")
+                    .and_then(Value::as_str)
+                    .unwrap_or("未知错误");
+                return Err(anyhow!("{}", e));
+            }
+            None => f(&event)?,
+        }
+    }
+    println!("驱动程序已关闭连接");
+    Ok(())
+}
+
+// 持续打印驱动程序解码后的输入事件；不会打开设备，也不影响驱动程序的运行
+fn monitor(path: Option<&Path>, request: &Value) -> Result<()> {
+    let connected = "已连接驱动程序，等待输入事件（按Ctrl+C退出）";
+    stream_events(path, request, connected, |event| {
+        print_monitor_event(event);
+        Ok(())
+    })
+}
+
+// 把驱动程序收到的原始报告写入文件，格式同capture-profile生成的fixture.log：
+// 每行为「毫秒时间戳 原始报告的十六进制」，时间戳从第一个报告开始计算；以#开头的行为注释
+fn record(path: Option<&Path>, request: &Value, output: &Path) -> Result<()> {
+    let mut file = File::create(output).context(format!("无法创建文件{}", output.display()))?;
+    writeln!(
+        file,
+        "# {} {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )
+    .context("无法写入记录文件")?;
+    let connected = format!("正在把原始报告记录到{}（按Ctrl+C结束）", output.display());
+    let mut started_at = None;
+    stream_events(path, request, &connected, |event| {
+        let (Some(time), Some(report)) = (
+            event.get("time").and_then(Value::as_f64),
+            event.get("report").and_then(Value::as_str),
+        ) else {
+            return Ok(());
+        };
+        let started_at = *started_at.get_or_insert(time);
+        // 逐行写入，按下Ctrl+C结束时不会丢失已收到的报告
+        writeln!(file, "{} {}", (time - started_at) as u64, report).context("无法写入记录文件")
+    })
+}
+
+fn print_monitor_event(event: &Value) {
+    let field = |key| event.get(key).and_then(Value::as_str).unwrap_or_default();
+    let number = |key| event.get(key).and_then(Value::as_f64).unwrap_or_default();
+    let flag = |key| event.get(key).and_then(Value::as_bool).unwrap_or_default();
+    let index = number("tablet");
+    match field("type") {
+        "pen" => {
+            println!(
+                "绘图板{}：画笔 感应区域={} 笔尖={} 笔侧键=({},{}) 坐标=({},{}) 压力={} 倾斜=({},{})",
+                index,
+                flag("inArea") as u8,
+                flag("touching") as u8,
+                flag("button0") as u8,
+                flag("button1") as u8,
+                number("x"),
+                number("y"),
+                number("pressure"),
+                number("tiltX"),
+                number("tiltY"),
+            );
+        }
+        "pad" => {
+            println!(
+                "绘图板{}：按下{} -> {}",
+                index,
+                field("key"),
+                field("action")
+            );
+        }
+        "release" => println!("绘图板{}：释放按键", index),
+        // 已有的客户端遇到新版本的驱动程序增加的事件类型时，原样输出
+        _ => println!("{}", event),
+    }
+}
+
+// 每个绘图板输出一行；指定了属性时只输出该属性的值
+fn print_properties(reply: &Value, property: Option<&str>) {
+    let Some(Value::Array(tablets)) = reply.get("tablets") else {
+        return;
+    };
+    for tablet in tablets {
+        let index = tablet.get("tablet").and_then(Value::as_u64).unwrap_or(0);
+        let Some(properties) = tablet.get("properties") else {
+            continue;
+        };
+        match property {
+            Some(property) => {
+                let value = properties.get(property).unwrap_or(&Value::Null);
+                println!("绘图板{}：{}", index, value);
+            }
+            None => println!("绘图板{}：{}", index, properties),
+        }
+    }
+}
+
+fn print_status(reply: &Value) {
+    let Some(Value::Array(tablets)) = reply.get("tablets") else {
+        return;
+    };
+    for tablet in tablets {
+        let field = |key| tablet.get(key).and_then(Value::as_str);
+        let index = tablet.get("tablet").and_then(Value::as_u64).unwrap_or(0);
+        let connection = match field("connection") {
+            Some(x) => format!("已连接（{}）", x),
+            None => "未连接".to_string(),
+        };
+        let paused = match tablet.get("paused").and_then(Value::as_bool) {
+            Some(true) => "，已暂停",
+            _ => "",
+        };
+        println!("绘图板{}：{}{}", index, connection, paused);
+        let schema = tablet.get("schema").and_then(Value::as_u64).unwrap_or(0);
+        match field("schemaName") {
+            Some(name) => println!("  按键映射方案：{}（{}）", schema, name),
+            None => println!("  按键映射方案：{}", schema),
+        }
+        println!("  画笔模式：{}", field("penMode").unwrap_or_default());
+        if let Some(monitor) = field("monitor") {
+            println!("  显示器：{}", monitor);
+        }
+        if let Some(area) = field("area") {
+            println!("  区域预设：{}", area);
+        }
+        if let Some(stats) = tablet.get("stats") {
+            let field = |key| stats.get(key).and_then(Value::as_u64).unwrap_or(0);
+            println!(
+                "  统计：已处理{}个报告，写入{}个事件，重新连接{}次，忽略{}个报告，合并丢弃{}个报告，重新加载配置{}次，出错后恢复{}次",
+                field("reports"),
+                field("events"),
+                field("reconnects"),
+                field("ignoredReports"),
+                field("droppedReports"),
+                field("reloads"),
+                field("recoveries")
+            );
+        }
+        if let Some(latency) = tablet.get("latency").filter(|x| **x != Value::Null) {
+            let field = |key| latency.get(key).and_then(Value::as_u64).unwrap_or(0);
+            println!(
+                "  延迟：{}个报告，p50={}µs p90={}µs p99={}µs max={}µs",
+                field("count"),
+                field("p50"),
+                field("p90"),
+                field("p99"),
+                field("max")
+            );
+        }
+    }
+}
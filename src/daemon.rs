@@ -0,0 +1,118 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use nix::fcntl::{Flock, FlockArg};
+use nix::unistd::{ForkResult, dup2_stderr, dup2_stdin, dup2_stdout, fork, pipe, setsid};
+
+use crate::{logger, warn};
+
+// 以--daemon运行时后台进程就绪后写入管道的字节；管道关闭前没有收到时说明后台进程初始化失败
+const READY: u8 = 1;
+
+// PID文件：持有文件锁直到进程退出，用于发现已经在运行的实例；退出时删除
+pub struct PidFile {
+    path: PathBuf,
+    _lock: Flock<File>,
+}
+impl PidFile {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o644)
+            .open(path)
+            .with_context(|| format!("无法打开PID文件{}", path.display()))?;
+        let mut lock = match Flock::lock(file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => lock,
+            Err((mut file, _)) => {
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                return Err(anyhow!(
+                    "PID文件{}已被进程{}锁定，可能已有实例在运行",
+                    path.display(),
+                    pid.trim()
+                ));
+            }
+        };
+        lock.set_len(0).context("无法清空PID文件")?;
+        writeln!(lock, "{}", std::process::id()).context("无法写入PID文件")?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            _lock: lock,
+        })
+    }
+}
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!("无法删除PID文件{}: {}", self.path.display(), e);
+        }
+    }
+}
+
+// 两次fork脱离终端与会话，切换工作目录到/，标准输入重定向到/dev/null；
+// 日志写入log_file，未指定时写入journald（不可用时丢弃）。原进程等待后台进程创建PID文件后以0退出，
+// 失败时输出原因并以1退出。必须在创建任何线程之前调用
+pub fn daemonize(log_file: Option<&Path>, pid_file: Option<&Path>) -> Result<Option<PidFile>> {
+    // 先在前台打开日志文件，路径有误时可以直接报告
+    let log = match log_file {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("无法打开日志文件{}", path.display()))?,
+        ),
+        None => None,
+    };
+    let (reader, writer) = pipe().context("pipe")?;
+    // SAFETY: 此时还没有创建其他线程
+    if let ForkResult::Parent { .. } = unsafe { fork() }.context("fork")? {
+        drop(writer);
+        let mut buf = [0u8; 1];
+        let code = match File::from(reader).read(&mut buf) {
+            Ok(1) if buf[0] == READY => 0,
+            _ => {
+                eprintln!("后台进程启动失败");
+                1
+            }
+        };
+        std::process::exit(code);
+    }
+    drop(reader);
+    setsid().context("setsid")?;
+    // SAFETY: 同上；第二次fork使进程不再是会话首进程，不会重新获得控制终端
+    if let ForkResult::Parent { .. } = unsafe { fork() }.context("fork")? {
+        std::process::exit(0);
+    }
+    std::env::set_current_dir("/").context("无法切换工作目录")?;
+    let pid_file = pid_file.map(PidFile::create).transpose()?;
+
+    let null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .context("无法打开/dev/null")?;
+    dup2_stdin(&null).context("dup2")?;
+    match &log {
+        Some(log) => {
+            dup2_stdout(log).context("dup2")?;
+            dup2_stderr(log).context("dup2")?;
+        }
+        None => {
+            if !logger::use_journal() {
+                warn!("journald不可用，后台运行时的日志将被丢弃；可以通过--log-file指定日志文件");
+            }
+            dup2_stdout(&null).context("dup2")?;
+            dup2_stderr(&null).context("dup2")?;
+        }
+    }
+    // 前台进程收到后以0退出；写入失败时前台进程已经退出，无需处理
+    let _ = File::from(writer).write_all(&[READY]);
+    Ok(pid_file)
+}
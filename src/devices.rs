@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use evdev_rs::enums::{EV_ABS, EventCode};
+use evdev_rs::{Device as EventDevice, DeviceWrapper};
+use rusb::UsbContext;
+
+use crate::config::Config;
+use crate::{driver, hidraw};
+
+// list-devices子命令：列出候选的事件设备、hidraw节点与USB设备，并标出各绘图板的驱动任务将会使用的设备
+pub fn list(conf: &Config) -> Result<()> {
+    let mut tablets = vec![conf];
+    tablets.extend(&conf.tablets);
+
+    // 各绘图板将会选择的事件设备与蓝牙hidraw节点
+    let mut evdev_picks: Vec<(usize, PathBuf)> = vec![];
+    let mut hidraw_picks: Vec<(usize, PathBuf)> = vec![];
+    for (i, tablet) in tablets.iter().enumerate() {
+        match driver::find_evdev(&tablet.device) {
+            Ok(path) => evdev_picks.push((i, path)),
+            Err(e) => println!("绘图板{}：找不到对应的事件设备（{:#}）", i, e),
+        }
+        if let Some(product_id) = tablet.device.bluetooth_product_id
+            && let Ok(path) = hidraw::find_bluetooth(tablet.device.vendor_id, product_id)
+        {
+            hidraw_picks.push((i, path));
+        }
+    }
+
+    println!("事件设备：");
+    if let Err(e) = print_evdevs(&evdev_picks) {
+        println!("  （{:#}）", e);
+    }
+
+    println!("hidraw设备：");
+    match hidraw::list() {
+        Ok(infos) => {
+            for info in infos {
+                println!(
+                    "  {}  {:04x}:{:04x}:{:04x}  「{}」{}{}",
+                    info.path.display(),
+                    info.bus,
+                    info.vendor_id,
+                    info.product_id,
+                    info.name,
+                    match info.is_bluetooth() {
+                        true => "  蓝牙",
+                        false => "",
+                    },
+                    describe_picks(&hidraw_picks, &info.path),
+                );
+            }
+        }
+        Err(e) => println!("  （{:#}）", e),
+    }
+
+    println!("USB设备：");
+    if let Err(e) = print_usb_devices(&tablets) {
+        println!("  （{:#}）", e);
+    }
+    Ok(())
+}
+
+fn print_evdevs(picks: &[(usize, PathBuf)]) -> Result<()> {
+    let mut entries: Vec<_> = std::fs::read_dir("/dev/input")
+        .context("无法读取目录/dev/input")?
+        .flatten()
+        .filter(|x| x.file_name().to_string_lossy().starts_with("event"))
+        .map(|x| x.path())
+        .collect();
+    entries.sort_by_key(|x| {
+        let name = x.file_name().unwrap_or_default().to_string_lossy();
+        name["event".len()..].parse::<u32>().unwrap_or(u32::MAX)
+    });
+    for path in entries {
+        let evdev = match EventDevice::new_from_path(&path) {
+            Ok(evdev) => evdev,
+            Err(e) => {
+                println!("  {}  （无法打开: {}）", path.display(), e);
+                continue;
+            }
+        };
+        println!(
+            "  {}  {:04x}:{:04x}:{:04x}  「{}」{}{}",
+            path.display(),
+            evdev.bustype(),
+            evdev.vendor_id(),
+            evdev.product_id(),
+            evdev.name().unwrap_or_default(),
+            match evdev.has(EventCode::EV_ABS(EV_ABS::ABS_PRESSURE)) {
+                true => "  支持压力轴",
+                false => "",
+            },
+            describe_picks(picks, &path),
+        );
+    }
+    Ok(())
+}
+
+fn print_usb_devices(tablets: &[&Config]) -> Result<()> {
+    // 先创建上下文：libusb初始化失败时，rusb::devices会直接panic
+    let context = rusb::Context::new().context("rusb::Context::new")?;
+    for usb_device in context.devices().context("rusb::Context::devices")?.iter() {
+        let Ok(descriptor) = usb_device.device_descriptor() else {
+            continue;
+        };
+        let id = (descriptor.vendor_id(), descriptor.product_id());
+        let port = driver::usb_port_path(&usb_device);
+        let matched: Vec<_> = tablets
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.device.ids().contains(&id))
+            .filter(|(_, x)| x.device.port.as_ref().is_none_or(|x| *x == port))
+            .map(|(i, _)| i.to_string())
+            .collect();
+        if matched.is_empty() {
+            continue;
+        }
+        println!(
+            "  {}  {:04x}:{:04x}  <- 匹配绘图板{}",
+            port,
+            id.0,
+            id.1,
+            matched.join("、")
+        );
+    }
+    Ok(())
+}
+
+fn describe_picks(picks: &[(usize, PathBuf)], path: &PathBuf) -> String {
+    let tablets: Vec<_> = picks
+        .iter()
+        .filter(|(_, x)| x == path)
+        .map(|(i, _)| i.to_string())
+        .collect();
+    match tablets.is_empty() {
+        true => String::new(),
+        false => format!("  <- 绘图板{}将使用该设备", tablets.join("、")),
+    }
+}
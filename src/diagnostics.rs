@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use evdev_rs::enums::EventCode;
+use parking_lot::Mutex;
+
+use crate::cli;
+use crate::config::{Config, Keymap};
+
+const LOG_BUFFER_CAPACITY: usize = 256;
+const EVENT_BUFFER_CAPACITY: usize = 512;
+
+// 崩溃时写入诊断信息包所需的状态（最近的日志与事件）；不记录按键映射的具体按键，避免泄露用户的配置内容
+struct DiagnosticsState {
+    logs: VecDeque<String>,
+    events: VecDeque<(Duration, EventRecord)>,
+    device_info: Option<String>,
+    config_summary: Option<String>,
+}
+static STATE: Mutex<DiagnosticsState> = Mutex::new(DiagnosticsState {
+    logs: VecDeque::new(),
+    events: VecDeque::new(),
+    device_info: None,
+    config_summary: None,
+});
+
+// 记录日志后端输出的一行日志
+pub fn record_log(line: String) {
+    let mut state = STATE.lock();
+    if state.logs.len() >= LOG_BUFFER_CAPACITY {
+        state.logs.pop_front();
+    }
+    state.logs.push_back(line);
+}
+
+// 驱动任务转换后的事件
+pub enum EventRecord {
+    // 写入虚拟设备的事件
+    Output {
+        device: &'static str,
+        code: EventCode,
+        value: i32,
+    },
+    // 触发的特殊行为，例如switchSchema
+    Action(&'static str),
+}
+
+pub fn record_event(event: EventRecord) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut state = STATE.lock();
+    if state.events.len() >= EVENT_BUFFER_CAPACITY {
+        state.events.pop_front();
+    }
+    state.events.push_back((timestamp, event));
+}
+
+pub fn set_device_info(info: String) {
+    STATE.lock().device_info.replace(info);
+}
+
+pub fn set_config(conf: &Config) {
+    let mut summary = String::new();
+    let _ = writeln!(
+        summary,
+        "xMaxValue={} yMaxValue={} xMap={:?} yMap={:?} area={} areaPresets={} followMonitor={} fineAdjustGain={} relativeSpeed={} monitors={}",
+        conf.x_max_value,
+        conf.y_max_value,
+        conf.x_map,
+        conf.y_map,
+        conf.area.is_some(),
+        conf.area_presets.len(),
+        conf.follow_monitor,
+        conf.fine_adjust_gain,
+        conf.relative_speed,
+        conf.monitor_maps.len(),
+    );
+    for (i, keymap) in conf.keymaps.iter().enumerate() {
+        macro_rules! describe {
+            ($($field:ident),+ $(,)?) => {
+                let _ = write!(summary, "keymap[{}]:", i);
+                $(
+                    let _ = write!(summary, " {}={}", stringify!($field), describe_keymap(&keymap.$field));
+                )+
+                $(
+                    if let Some(x) = &keymap.proximity.$field {
+                        let _ = write!(summary, " proximity.{}={}", stringify!($field), describe_keymap(x));
+                    }
+                )+
+                let _ = writeln!(summary);
+            };
+        }
+        describe! {
+            button0, button1, button2, button3, button4, button5, button6, button7,
+            ring0, ring1, ring_button,
+        }
+    }
+    STATE.lock().config_summary.replace(summary);
+}
+
+fn describe_keymap(keymap: &Keymap) -> String {
+    match keymap {
+        Keymap::None => "none".to_string(),
+        Keymap::Press(codes) => format!("press({})", codes.len()),
+        Keymap::SwitchSchema => "switchSchema".to_string(),
+        Keymap::FineAdjust => "fineAdjust".to_string(),
+        Keymap::CycleMonitor => "cycleMonitor".to_string(),
+        Keymap::CycleArea => "cycleArea".to_string(),
+        Keymap::Exec(_) => "exec".to_string(),
+        Keymap::Zoom(modifiers) => format!("zoom({})", modifiers.len()),
+        Keymap::Latch(modifiers) => format!("latch({})", modifiers.len()),
+        Keymap::MapToMonitor(_) => "mapToMonitor".to_string(),
+        Keymap::PressureSofter => "pressureSofter".to_string(),
+        Keymap::PressureHarder => "pressureHarder".to_string(),
+        Keymap::ToggleTilt => "toggleTilt".to_string(),
+        Keymap::ToggleSmoothing => "toggleSmoothing".to_string(),
+        Keymap::CycleSmoothing => "cycleSmoothing".to_string(),
+        Keymap::RotateMapping => "rotateMapping".to_string(),
+    }
+}
+
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        report(&info.to_string());
+    }));
+}
+
+// 写入诊断信息包，并打印其路径；发生panic时日志缓冲区的锁可能正被持有，因此这里不使用error!
+pub fn report(reason: &str) {
+    match write_bundle(reason) {
+        Ok(path) => {
+            println!(
+                "[ERROR] 已将诊断信息写入{}，反馈问题时请附上该文件",
+                path.display()
+            );
+        }
+        Err(e) => {
+            println!("[ERROR] 无法写入诊断信息: {:?}", e);
+        }
+    }
+}
+
+fn write_bundle(reason: &str) -> Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let path = std::env::temp_dir().join(format!(
+        "{}-crash-{}.txt",
+        env!("CARGO_PKG_NAME"),
+        timestamp
+    ));
+
+    // 发生panic时锁可能正被持有，此时放弃读取状态
+    let mut content = String::new();
+    let _ = writeln!(content, "version: {}", cli::VERSION);
+    let _ = writeln!(content, "timestamp: {}", timestamp);
+    let _ = writeln!(content, "reason: {}", reason);
+    match STATE.try_lock() {
+        Some(state) => {
+            let _ = writeln!(
+                content,
+                "\n# device\n{}",
+                state.device_info.as_deref().unwrap_or("unknown")
+            );
+            let _ = writeln!(
+                content,
+                "\n# config\n{}",
+                state.config_summary.as_deref().unwrap_or("unknown")
+            );
+            let _ = writeln!(content, "\n# recent logs");
+            for line in &state.logs {
+                let _ = writeln!(content, "{}", line);
+            }
+            let _ = writeln!(content, "\n# recent events");
+            for (timestamp, event) in &state.events {
+                let _ = write!(
+                    content,
+                    "{}.{:03} ",
+                    timestamp.as_secs(),
+                    timestamp.subsec_millis()
+                );
+                let _ = match event {
+                    EventRecord::Output {
+                        device,
+                        code,
+                        value,
+                    } => writeln!(content, "{} {} {}", device, code, value),
+                    EventRecord::Action(action) => writeln!(content, "action {}", action),
+                };
+            }
+        }
+        None => {
+            let _ = writeln!(content, "\n(diagnostics state is locked)");
+        }
+    }
+    std::fs::write(&path, content).context(format!("无法写入文件{}", path.display()))?;
+    Ok(path)
+}
@@ -1,30 +1,163 @@
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result, anyhow};
-use evdev_rs::enums::{EV_ABS, EV_KEY, EV_SYN, EventCode, EventType, InputProp};
-use evdev_rs::{
-    AbsInfo, Device as EventDevice, DeviceWrapper, InputEvent, TimeVal, UInputDevice, UninitDevice,
-};
+use evdev_rs::enums::{EV_ABS, EV_KEY, EV_MSC, EV_REL, EV_SYN, EventCode, EventType, InputProp};
+use evdev_rs::{AbsInfo, Device as EventDevice, DeviceWrapper, UInputDevice, UninitDevice};
 use parking_lot::Mutex;
-use rusb::{DeviceHandle as UsbDeviceHandle, Error as UsbError, UsbContext};
+use rusb::{DeviceHandle as UsbDeviceHandle, Error as UsbError};
 
+use crate::bus::{EventBus, StateEvent};
 use crate::cancel::CancelToken;
-use crate::config::{Config, Keymap, WatchConfigChangeTask};
-use crate::{debug, info, warn};
+use crate::config::{
+    self, AreaConfig, AreaUnit, Config, DeviceConfig, DwellAction, Keymap, ModeDetection,
+    OutsideAreaBehavior, PadMode, PenMode, TipMode, WatchConfigChangeTask,
+};
+use crate::control::{
+    ControlCommand, ControlTask, MonitorClients, Properties, Property, RuntimeStats, TabletStatus,
+};
+use crate::diagnostics::EventRecord;
+use crate::event_loop::EventLoop;
+use crate::focus::FocusTrackTask;
+use crate::follow::FollowMonitorTask;
+use crate::hidraw::HidrawDevice;
+use crate::hotplug::{HotplugEvent, HotplugTask};
+use crate::identity::DeviceIdentity;
+use crate::io::{InputSource, OutputSink, PrintSink};
+use crate::latency::LatencyStats;
+use crate::latest::Latest;
+use crate::logger;
+use crate::notify::Notifier;
+use crate::osd::Osd;
+use crate::profile::PadKey;
+use crate::replay::{RecordedReport, ReplaySource};
+use crate::rule::{self, Environment};
+use crate::sandbox;
+use crate::screen::FocusedWindow;
+use crate::session::SessionWatchTask;
+use crate::signal::ExitSignal;
+use crate::sleep::SleepDetector;
+use crate::state::RuntimeState;
+use crate::systemd::{self, Watchdog};
+use crate::uinput::VirtualDevice;
+use crate::usage::UsageStatistics;
+use crate::usb;
+use crate::wakeup::Wakeup;
+use crate::{debug, diagnostics, error, info, json_object, warn};
 
-const VENDOR_ID: u16 = 0x0483;
-const PRODUCT_ID: u16 = 0xa013;
 const INTERFACE_NUM: u8 = 0x02;
-const IN_ENDPOINT: u8 = 0x83;
+pub const IN_ENDPOINT: u8 = 0x83;
 const OUT_ENDPOINT: u8 = 0x03;
 const HANDSHAKE_USAGE_BUF_SIZE: usize = 1101;
-const INPUT_USAGE_BUF_SIZE: usize = 10;
-const READ_INTERRUPT_TIMEOUT: Duration = Duration::from_millis(1000);
-const EVENT_DEVICE_NAME: &str = "  Parblo Intangbo  M(F7)";
+pub const INPUT_USAGE_BUF_SIZE: usize = 10;
+pub const READ_INTERRUPT_TIMEOUT: Duration = Duration::from_millis(1000);
+// 通过无线接收器或蓝牙连接时，画笔离开感应范围（或绘图板休眠）时可能不会上报离开事件；
+// 因此缩短读取的超时时间，并在一段时间内没有收到画笔事件时视为画笔离开感应区域
+const WIRELESS_READ_INTERRUPT_TIMEOUT: Duration = Duration::from_millis(50);
+const WIRELESS_STYLUS_IDLE_TIMEOUT: Duration = Duration::from_millis(200);
+const ACQUIRE_DEVICE_MIN_BACKOFF: Duration = Duration::from_millis(250);
+const ACQUIRE_DEVICE_MAX_BACKOFF: Duration = Duration::from_secs(8);
+// 蓝牙连接在空闲时会断开，且重新连接时没有USB热插拔通知；缩短重试间隔的上限，使绘图板唤醒后尽快恢复
+const BLUETOOTH_ACQUIRE_DEVICE_MAX_BACKOFF: Duration = Duration::from_secs(1);
+const WAIT_FOR_DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+// pressureSofter、pressureHarder调整后压力曲线的范围
+const MIN_PRESSURE_CURVE: f32 = 0.1;
+const MAX_PRESSURE_CURVE: f32 = 10.0;
+// 驱动任务出错后重新初始化设备的次数上限：RECOVERY_WINDOW内超过MAX_RECOVERIES次时视为无法恢复的错误
+const MAX_RECOVERIES: usize = 5;
+const RECOVERY_WINDOW: Duration = Duration::from_secs(60);
+// 配置了包含时间段条件的规则时，定期重新匹配规则的间隔
+const RULE_TIME_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+// 未配置virtualDeviceName、virtualKeyboardName、virtualMouseName时使用的虚拟设备名称
 const VIRTUAL_DIGITIZER_NAME: &str = "Parblo Intangbo M (Digitizer)";
 const VIRTUAL_KEYBOARD_NAME: &str = "Parblo Intangbo M (Keyboard)";
+const VIRTUAL_MOUSE_NAME: &str = "Parblo Intangbo M (Mouse)";
+const VIRTUAL_PAD_NAME: &str = "Parblo Intangbo M (Pad)";
+// 虚拟键盘默认启用的按键，与按键映射中的按键名称对应；按键映射中使用的其他按键（例如KEY_VOLUMEUP）在创建时额外启用
+const KEYBOARD_KEYS: &[EV_KEY] = &[
+    EV_KEY::KEY_A,
+    EV_KEY::KEY_B,
+    EV_KEY::KEY_C,
+    EV_KEY::KEY_D,
+    EV_KEY::KEY_E,
+    EV_KEY::KEY_F,
+    EV_KEY::KEY_G,
+    EV_KEY::KEY_H,
+    EV_KEY::KEY_I,
+    EV_KEY::KEY_J,
+    EV_KEY::KEY_K,
+    EV_KEY::KEY_L,
+    EV_KEY::KEY_M,
+    EV_KEY::KEY_N,
+    EV_KEY::KEY_O,
+    EV_KEY::KEY_P,
+    EV_KEY::KEY_Q,
+    EV_KEY::KEY_R,
+    EV_KEY::KEY_S,
+    EV_KEY::KEY_T,
+    EV_KEY::KEY_U,
+    EV_KEY::KEY_V,
+    EV_KEY::KEY_W,
+    EV_KEY::KEY_X,
+    EV_KEY::KEY_Y,
+    EV_KEY::KEY_Z,
+    EV_KEY::KEY_0,
+    EV_KEY::KEY_1,
+    EV_KEY::KEY_2,
+    EV_KEY::KEY_3,
+    EV_KEY::KEY_4,
+    EV_KEY::KEY_5,
+    EV_KEY::KEY_6,
+    EV_KEY::KEY_7,
+    EV_KEY::KEY_8,
+    EV_KEY::KEY_9,
+    EV_KEY::KEY_MINUS,
+    EV_KEY::KEY_EQUAL,
+    EV_KEY::KEY_BACKSLASH,
+    EV_KEY::KEY_GRAVE,
+    EV_KEY::KEY_LEFTBRACE,
+    EV_KEY::KEY_RIGHTBRACE,
+    EV_KEY::KEY_SEMICOLON,
+    EV_KEY::KEY_APOSTROPHE,
+    EV_KEY::KEY_COMMA,
+    EV_KEY::KEY_DOT,
+    EV_KEY::KEY_SLASH,
+    EV_KEY::KEY_ESC,
+    EV_KEY::KEY_TAB,
+    EV_KEY::KEY_BACKSPACE,
+    EV_KEY::KEY_SPACE,
+    EV_KEY::KEY_ENTER,
+    EV_KEY::KEY_HOME,
+    EV_KEY::KEY_END,
+    EV_KEY::KEY_PAGEUP,
+    EV_KEY::KEY_PAGEDOWN,
+    EV_KEY::KEY_INSERT,
+    EV_KEY::KEY_DELETE,
+    EV_KEY::KEY_LEFTCTRL,
+    EV_KEY::KEY_LEFTSHIFT,
+    EV_KEY::KEY_LEFTALT,
+    EV_KEY::KEY_LEFTMETA,
+];
+// padMode为native时，各按钮按编号依次对应BTN_0~BTN_9，转环中心按钮对应最后一个按钮之后的编号
+const PAD_BUTTON_CODES: [EV_KEY; 10] = [
+    EV_KEY::BTN_0,
+    EV_KEY::BTN_1,
+    EV_KEY::BTN_2,
+    EV_KEY::BTN_3,
+    EV_KEY::BTN_4,
+    EV_KEY::BTN_5,
+    EV_KEY::BTN_6,
+    EV_KEY::BTN_7,
+    EV_KEY::BTN_8,
+    EV_KEY::BTN_9,
+];
+// 与Wacom的转环相同，ABS_WHEEL的范围为0~71；libinput把0视为手指离开转环，因此转动时只使用1~71
+const PAD_RING_MAX: i32 = 71;
 const DEVICE_HANDSHAKE_DATA_LIST: &[&[u8]] = &[
     &[
         0xfd, 0x89, 0xff, 0xff, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x03, 0x01, 0x01, 0x01, 0x91,
@@ -41,20 +174,151 @@ const DEVICE_HANDSHAKE_DATA_LIST: &[&[u8]] = &[
     &[0x02, 0xb0, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00],
 ];
 
+// 与设备之间的连接
+enum DeviceHandle {
+    Usb {
+        handle: UsbDeviceHandle<rusb::GlobalContext>,
+        dongle: bool, // 是否通过无线接收器连接
+    },
+    Bluetooth(HidrawDevice),
+}
+impl InputSource for DeviceHandle {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Usb { dongle: false, .. } => "usb",
+            Self::Usb { dongle: true, .. } => "dongle",
+            Self::Bluetooth(_) => "bluetooth",
+        }
+    }
+
+    fn wireless(&self) -> bool {
+        match self {
+            Self::Usb { dongle, .. } => *dongle,
+            Self::Bluetooth(_) => true,
+        }
+    }
+
+    // 为统一处理，蓝牙连接的错误也转换为rusb::Error
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, UsbError> {
+        match self {
+            Self::Usb { handle, .. } => handle.read_interrupt(IN_ENDPOINT, buf, timeout),
+            Self::Bluetooth(hidraw) => match hidraw.read(buf, timeout) {
+                Ok(Some(len)) => Ok(len),
+                Ok(None) => Err(UsbError::Timeout),
+                Err(e) => Err(hidraw_error(e)),
+            },
+        }
+    }
+
+    fn read_idle(
+        &self,
+        buf: &mut [u8],
+        event_loop: &mut EventLoop,
+        timeout: Option<Duration>,
+    ) -> Result<Option<usize>, UsbError> {
+        match self {
+            Self::Usb { handle, .. } => {
+                usb::read_interrupt_until(handle, IN_ENDPOINT, buf, event_loop, timeout)
+            }
+            Self::Bluetooth(hidraw) => hidraw
+                .read_until(buf, event_loop, timeout)
+                .map_err(hidraw_error),
+        }
+    }
+
+    fn release(&self) {
+        if let Self::Usb { handle, .. } = self
+            && let Err(e) = handle.release_interface(INTERFACE_NUM)
+        {
+            warn!("无法释放USB设备的接口: {}", e);
+        }
+    }
+}
+
+fn hidraw_error(e: std::io::Error) -> UsbError {
+    match e.raw_os_error() {
+        Some(nix::libc::ENODEV) => UsbError::NoDevice,
+        _ => UsbError::Io,
+    }
+}
+
+type AreaMap = (Option<(f32, f32)>, Option<(f32, f32)>); // (X轴, Y轴)
+
 pub struct DriverTask {
     cancel_token: CancelToken,
-    digitizer_uinput: UInputDevice,
-    keyboard_uinput: UInputDevice,
-    handle: UsbDeviceHandle<rusb::GlobalContext>,
+    tablet_index: usize, // 在配置文件中的绘图板序号；0为顶层配置
+
+    // 首次连接设备时创建，之后设备断开时也一直保留；各设备分别只声明画笔、键盘按键、鼠标按键与Pad的能力，
+    // 避免混合能力的设备被libinput错误分类（例如影响防误触）
+    digitizer_sink: Option<Box<dyn OutputSink>>,
+    keyboard_sink: Option<Box<dyn OutputSink>>,
+    keyboard_keys: Vec<EV_KEY>, // 虚拟键盘创建时启用的按键
+    mouse_sink: Option<Box<dyn OutputSink>>,
+    pad_sink: Option<Box<dyn OutputSink>>, // 仅在padMode为native时创建
+    handle: Option<Box<dyn InputSource>>,  // 设备未连接时为None
+    latest_hotplug: Arc<Latest<HotplugEvent>>,
+    sleep_detector: SleepDetector,
+    wakeup: Wakeup, // 其他任务放入新的配置、控制命令等之后唤醒阻塞在事件循环上的驱动任务
+    event_loop: EventLoop, // 监视取消、唤醒以及空闲时的设备
+    last_input: Instant, // 最近一次收到报告的时间，用于判断是否进入空闲状态
+    last_pad_input: Option<Instant>, // 最近一次收到按键或转环报告的时间，用于palmBlock
+    idle: bool,
+    last_acquire_attempt: Option<Instant>,
+    acquire_backoff: Duration, // 打开设备失败后，距离下次尝试的间隔；每次失败后加倍
     conf: Config,
-    latest_conf: Arc<Mutex<Option<Config>>>,
+    latest_conf: Arc<Latest<Config>>,
+    pending_conf: Option<Config>, // 笔画或按键尚未结束时收到的新配置，结束后再应用
+    latest_monitor: Arc<Latest<String>>,
+    latest_focus: Arc<Latest<FocusedWindow>>,
+    latest_session: Arc<Latest<bool>>,
+    pending_commands: Arc<Mutex<Vec<ControlCommand>>>, // 按收到的顺序依次处理，不能只保留最新的一个
+    status: Arc<Mutex<TabletStatus>>,                  // 供控制套接字的status请求查询
+    monitor: MonitorClients,                           // 接收解码后输入事件的monitor连接
+    recorder: MonitorClients,                          // 接收原始报告的record连接
     keymap_index: usize,
+    paused: bool,
+    session_inactive: bool, // 配置了pauseOnInactiveSession且登录会话处于非活动状态；与pause命令相互独立
+    monitor_index: Option<usize>, // 通过cycleMonitor切换到的显示器；为None时使用配置中的xMap、yMap
+    area_index: Option<usize>, // 通过cycleArea切换到的区域预设；为None时使用配置中的area
+    tilt_enabled: bool,     // 通过toggleTilt关闭后上报的倾斜总是为0；不写入状态文件
+    smoothing_off: bool,    // 通过toggleSmoothing关闭平滑；不写入状态文件
+    smoothing_index: Option<usize>, // cycleSmoothing切换到的smoothingPresets下标；None时使用配置的smoothing
+    rotation_offset: u16, // 通过rotateMapping叠加在rotation上的角度（顺时针）；不写入状态文件
+    pressure_adjust: f32, // 通过pressureSofter、pressureHarder对压力曲线的调整，叠加在当前生效的压力曲线上
+    saved_state: (usize, Option<usize>, Option<usize>, f32), // 最近一次写入状态文件的keymap_index、area_index、monitor_index与pressure_adjust
     pressed_keys: HashSet<EV_KEY>, // 设备本身不支持同时按下多个键，因此可直接用集合记录某个键的按键码组合
     stylus: StylusStatus,
+    relative: RelativeStatus,
+    pad: PadStatus,
+    latch: LatchStatus,
+    dwell: DwellStatus,
+    rate_limit: RateLimitStatus,
+    // 当前正在处理的报告的接收时间，用于上报MSC_TIMESTAMP；为None时使用写入时的时间
+    report_received_at: Option<Instant>,
+    timestamp_origin: Instant,
+    input_range: InputRange,
+    fine_adjust: Option<FineAdjustStatus>,
+    rule: RuleStatus,
+    overrides: PropertyOverrides,
+    usage: Option<UsageStatistics>,
+    bus: EventBus, // 状态变化的订阅者：桌面通知与OSD
+    watchdog: Watchdog,
+    stats: RefCell<RuntimeStats>,
+    ever_connected: bool,
+    latency: RefCell<LatencyStats>, // 仅在配置了latencyStats = true时记录
+    calibration: Option<Calibration>,
+    dry_run: bool, // 不写入虚拟设备，而是打印将要写入的事件
+}
+// 校准模式：记录笔尖点击的位置，据此计算xMap、yMap并写回配置文件
+struct Calibration {
+    conf_path: PathBuf,
+    points: Vec<(u16, u16)>,
 }
 struct StylusStatus {
     in_area: bool,
+    in_proximity: bool, // 设备上报的画笔是否在感应范围内；不受有效区域与画笔模式的影响，用于选择proximity中的按键映射
     tip_pressed: bool,
+    tip_mode: TipMode, // 笔尖按下时所使用的笔尖模式
     pressure: u16,
     button0_pressed: bool,
     button1_pressed: bool,
@@ -62,37 +326,252 @@ struct StylusStatus {
     y: u16,
     tilt_x: i8,
     tilt_y: i8,
+    last_report: Option<Instant>, // 最近一次收到画笔事件的时间
+    smoothed: Option<(f32, f32)>, // 平滑后的坐标；画笔离开感应范围后为None，重新进入时不产生拖尾
+}
+// 相对模式：画笔在感应区域内移动时，按与上一次坐标的差值由虚拟鼠标上报相对移动
+#[derive(Default)]
+struct RelativeStatus {
+    last: Option<(u16, u16)>, // 画笔离开感应区域后为None，重新进入时不产生跳变
+    remainder: (f32, f32),    // 缩放后不足一个单位的移动量，累计到下一次上报
+    pressed_buttons: Vec<EV_KEY>, // 虚拟鼠标上按下的按键
+}
+// padMode为native时虚拟Pad设备的状态
+#[derive(Default)]
+struct PadStatus {
+    pressed: Option<EV_KEY>, // 设备本身不支持同时按下多个键
+    ring: i32,               // 转环的位置（1~PAD_RING_MAX）
+    ring_touched: bool,      // 转动转环后尚未上报手指离开
+}
+// latch行为预备的修饰键：下一次笔尖按下时按下、笔尖抬起后释放；或与下一次按键一起按下、一起释放
+#[derive(Default)]
+struct LatchStatus {
+    armed: Vec<EV_KEY>, // 已预备、尚未使用的修饰键
+    held: Vec<EV_KEY>,  // 随笔尖按下的修饰键，笔尖抬起后释放
+}
+// 悬停点击：画笔悬停且在radius范围内保持不动达到delay后触发一次；移动超出范围后重新计时
+#[derive(Default)]
+struct DwellStatus {
+    anchor: Option<(u16, u16, Instant)>, // 开始保持不动时的位置与时间；画笔没有悬停时为None
+    fired: bool,                         // 本次保持不动期间已经触发
+}
+// 配置了maxReportRate时，合并高频的画笔移动报告：间隔不足时只保留最新的一个报告，
+// 间隔到达后再处理；画笔状态（感应区域、笔尖、笔侧键）变化的报告总是立即处理
+#[derive(Default)]
+struct RateLimitStatus {
+    last_handled: Option<Instant>,
+    last_status: u8,                     // 最近一次处理的报告的状态字节
+    pending: Option<(Vec<u8>, Instant)>, // 被合并、尚未处理的最新报告及其接收时间
+}
+// 自动切换规则：环境（获得焦点的窗口、显示器、时间）变化时重新匹配，生效的规则变化时切换状态
+#[derive(Default)]
+struct RuleStatus {
+    window: Option<FocusedWindow>, // 最近一次获得焦点的窗口
+    dirty: bool,                   // 环境已变化，需要重新匹配
+    checked_at: Option<Instant>,   // 最近一次匹配的时间
+    active: Option<usize>,         // 当前生效的规则
+    // 规则生效前的keymap_index与area_index；没有规则匹配时恢复；期间手动切换过方案或区域预设时为None
+    base: Option<(usize, Option<usize>)>,
+}
+// 通过控制套接字的set命令修改的属性；优先于配置文件与规则，重新加载配置文件后清除
+#[derive(Default)]
+struct PropertyOverrides {
+    pressure_curve: Option<f32>,
+    smoothing: Option<f32>,
+    rotation: Option<u16>,
+    x_map: Option<(f32, f32)>,
+    y_map: Option<(f32, f32)>,
+    area: Option<AreaConfig>,
+}
+// 本次连接中观测到的原始坐标的最大值，用于检测设备工作模式的切换
+#[derive(Default)]
+struct InputRange {
+    x_max: u16,
+    y_max: u16,
+    warned: bool,
+}
+// 微调模式：冻结画笔当前的绝对位置作为锚点，之后画笔的移动按增益缩小后相对锚点上报
+#[derive(Default)]
+struct FineAdjustStatus {
+    x: FineAdjustAxis,
+    y: FineAdjustAxis,
+}
+#[derive(Default)]
+struct FineAdjustAxis {
+    anchor: Option<(u16, u16)>, // (锚点的上报值, 锚点对应的输入值)
+    last: Option<u16>,
+}
+impl FineAdjustAxis {
+    fn apply(&mut self, value: u16, gain: f32, max_value: u16) -> u16 {
+        let (anchor_output, anchor_input) = *self
+            .anchor
+            .get_or_insert((self.last.unwrap_or(value), value));
+        let value = (anchor_output as f32 + (value as f32 - anchor_input as f32) * gain)
+            .round()
+            .clamp(0f32, max_value as f32) as u16;
+        self.last = Some(value);
+        value
+    }
+
+    // 类似于抬起鼠标：画笔重新进入感应区域时，从上次上报的位置继续相对移动
+    fn lift(&mut self) {
+        self.anchor = None;
+    }
 }
 impl DriverTask {
     pub fn new(
         cancel_token: CancelToken,
-        mut conf: Config,
+        tablet_index: usize,
+        conf: Config,
         watch_config_change_task: Option<&mut WatchConfigChangeTask>,
+        follow_monitor_task: Option<&mut FollowMonitorTask>,
+        control_task: Option<&mut ControlTask>,
+        hotplug_task: Option<&mut HotplugTask>,
     ) -> Result<Self> {
-        let (digitizer_uinput, keyboard_uinput) =
-            create_uinput_device(&mut conf).context("无法创建虚拟设备")?;
-        let handle = open_usb_device_handle().context("无法打开USB设备句柄")?;
+        diagnostics::set_config(&conf);
 
-        let latest_conf = Arc::new(Mutex::new(None));
+        // 暂停、等待设备与空闲时都阻塞在事件循环上，取消或被其他任务唤醒时立即返回
+        let wakeup = Wakeup::new().context("无法创建用于唤醒驱动任务的eventfd")?;
+        let mut event_loop =
+            EventLoop::new(cancel_token.clone()).context("无法创建驱动任务的事件循环")?;
+        event_loop.add("驱动任务的唤醒", wakeup.clone())?;
+
+        let latest_conf = Arc::new(Latest::default());
         if let Some(task) = watch_config_change_task {
             let latest_conf = latest_conf.clone();
-            task.register_callback(move |conf| {
-                latest_conf.lock().replace(conf.as_ref().clone());
+            let wakeup = wakeup.clone();
+            task.register_callback(move |conf| match conf.tablet(tablet_index) {
+                Some(conf) => {
+                    let mut conf = conf.clone();
+                    conf.tablets.clear();
+                    latest_conf.put(conf);
+                    wakeup.wake();
+                }
+                None => {
+                    warn!(
+                        "新的配置文件中没有第{}个绘图板的配置，忽略本次变动",
+                        tablet_index
+                    );
+                }
+            });
+        }
+
+        let latest_monitor = Arc::new(Latest::default());
+        if let Some(task) = follow_monitor_task {
+            let latest_monitor = latest_monitor.clone();
+            let wakeup = wakeup.clone();
+            task.register_callback(move |name| {
+                latest_monitor.put(name);
+                wakeup.wake();
             });
         }
 
+        let latest_hotplug = Arc::new(Latest::default());
+        if let Some(task) = hotplug_task {
+            let latest_hotplug = latest_hotplug.clone();
+            let wakeup = wakeup.clone();
+            task.register_callback(move |event| {
+                latest_hotplug.put(event);
+                wakeup.wake();
+            });
+        }
+
+        let pending_commands = Arc::new(Mutex::new(Vec::new()));
+        let mut status = Arc::new(Mutex::new(TabletStatus::default()));
+        let mut monitor = MonitorClients::default();
+        let mut recorder = MonitorClients::default();
+        if let Some(task) = control_task {
+            let pending_commands = pending_commands.clone();
+            let wakeup = wakeup.clone();
+            status = task.register_callback(tablet_index, move |command| {
+                pending_commands.lock().push(command);
+                wakeup.wake();
+            });
+            monitor = task.monitor_clients(tablet_index);
+            recorder = task.record_clients(tablet_index);
+        }
+
+        // 微调模式只在按住按键时生效，频繁切换，因此不发送桌面通知
+        let mut bus = EventBus::default();
+        Notifier::spawn(bus.subscribe(|conf, event| {
+            conf.notifications && !matches!(event, StateEvent::FineAdjust(_))
+        }));
+        Osd::spawn(bus.subscribe(|conf, _| conf.osd));
+
+        let state = RuntimeState::load(tablet_index);
+        let (keymap_index, area_index, monitor_index) = match &state {
+            Some(state) => state.resolve(&conf),
+            None => (0, None, None),
+        };
+        if keymap_index != 0 {
+            info!(
+                "已恢复上次使用的按键映射方案{}",
+                conf.schema_label(keymap_index)
+            );
+        }
+        let pressure_adjust = state.as_ref().map_or(0f32, RuntimeState::pressure_adjust);
+        if pressure_adjust != 0f32 {
+            info!("已恢复上次对压力曲线的调整（{:+.2}）", pressure_adjust);
+        }
+
+        let usage = conf.usage_statistics.then(|| {
+            UsageStatistics::new(
+                &conf,
+                format!(
+                    "{} ({:04x}:{:04x})",
+                    conf.device.profile.name.trim(),
+                    conf.device.vendor_id,
+                    conf.device.product_id
+                ),
+                tablet_index,
+            )
+        });
+
         Ok(Self {
             cancel_token,
-            digitizer_uinput,
-            keyboard_uinput,
-            handle,
+            tablet_index,
+            digitizer_sink: None,
+            keyboard_sink: None,
+            keyboard_keys: vec![],
+            mouse_sink: None,
+            pad_sink: None,
+            handle: None,
+            latest_hotplug,
+            sleep_detector: SleepDetector::new(),
+            wakeup,
+            event_loop,
+            last_input: Instant::now(),
+            last_pad_input: None,
+            idle: false,
+            last_acquire_attempt: None,
+            acquire_backoff: ACQUIRE_DEVICE_MIN_BACKOFF,
             conf,
             latest_conf,
-            keymap_index: 0,
+            pending_conf: None,
+            latest_monitor,
+            latest_focus: Arc::new(Latest::default()),
+            latest_session: Arc::new(Latest::default()),
+            pending_commands,
+            status,
+            monitor,
+            recorder,
+            keymap_index,
+            paused: false,
+            session_inactive: false,
+            monitor_index,
+            area_index,
+            tilt_enabled: true,
+            smoothing_off: false,
+            smoothing_index: None,
+            rotation_offset: 0,
+            pressure_adjust,
+            saved_state: (keymap_index, area_index, monitor_index, pressure_adjust),
             pressed_keys: HashSet::new(),
             stylus: StylusStatus {
                 in_area: false,
+                in_proximity: false,
                 tip_pressed: false,
+                tip_mode: TipMode::Touch,
                 pressure: 0,
                 button0_pressed: false,
                 button1_pressed: false,
@@ -100,177 +579,1730 @@ impl DriverTask {
                 y: 0,
                 tilt_x: 0,
                 tilt_y: 0,
+                last_report: None,
+                smoothed: None,
             },
+            relative: RelativeStatus::default(),
+            pad: PadStatus::default(),
+            latch: LatchStatus::default(),
+            dwell: DwellStatus::default(),
+            rate_limit: RateLimitStatus::default(),
+            report_received_at: None,
+            timestamp_origin: Instant::now(),
+            input_range: InputRange::default(),
+            fine_adjust: None,
+            // 启动时匹配一次，使只配置了显示器或时间段条件的规则立即生效
+            rule: RuleStatus {
+                dirty: true,
+                ..Default::default()
+            },
+            overrides: PropertyOverrides::default(),
+            usage,
+            bus,
+            watchdog: Watchdog::new(),
+            stats: RefCell::default(),
+            ever_connected: false,
+            latency: RefCell::default(),
+            calibration: None,
+            dry_run: false,
         })
     }
 
+    // 收到SIGUSR2时切换到下一个按键映射方案
+    pub fn watch_signals(&self, exit_signal: &mut ExitSignal) {
+        let pending_commands = self.pending_commands.clone();
+        let wakeup = self.wakeup.clone();
+        exit_signal.register_switch_schema_callback(move || {
+            pending_commands.lock().push(ControlCommand::NextSchema);
+            wakeup.wake();
+        });
+    }
+
+    // 获得焦点的窗口变化时按appProfile切换按键映射方案
+    pub fn watch_focus(&self, focus_track_task: &mut FocusTrackTask) {
+        let latest_focus = self.latest_focus.clone();
+        let wakeup = self.wakeup.clone();
+        focus_track_task.register_callback(move |window| {
+            latest_focus.put(window);
+            wakeup.wake();
+        });
+    }
+
+    // 登录会话变为非活动状态时暂停，恢复活动状态后重新打开设备
+    pub fn watch_session(&self, session_watch_task: &mut SessionWatchTask) {
+        let latest_session = self.latest_session.clone();
+        let wakeup = self.wakeup.clone();
+        session_watch_task.register_callback(move |active| {
+            latest_session.put(active);
+            wakeup.wake();
+        });
+    }
+
+    // 命令行中通过--schema指定的按键映射方案，优先于上次保存的状态
+    pub fn select_schema(&mut self, index: usize) {
+        info!(
+            "使用命令行指定的按键映射方案{}",
+            self.conf.schema_label(index)
+        );
+        self.keymap_index = index;
+        self.update_schema_log_field();
+    }
+
+    // 不创建虚拟设备，而是打印将要写入的事件；用于在没有uinput权限的机器上验证配置
+    pub fn enable_dry_run(&mut self) {
+        self.dry_run = true;
+    }
+
+    // 配置了runAsUser时，在放弃root权限之前打开已连接的绘图板：读取事件设备、创建虚拟设备并声明USB接口。
+    // 切换用户之后不再支持以root权限重新打开设备：设备断开后重新连接、启动时未连接的绘图板连接后，
+    // 都需要切换到的用户能够访问设备节点与uinput（例如通过install-udev-rules安装的规则），否则每次尝试都会输出警告
+    pub fn acquire_before_privilege_drop(&mut self) {
+        self.last_acquire_attempt = Some(Instant::now());
+        match self.acquire_device() {
+            Ok(()) => self.device_connected(),
+            Err(e) => info!("设备未连接，切换用户后等待设备插入: {:#}", e),
+        }
+    }
+
+    // 进入校准模式；校准期间将整个绘图板映射到整个桌面，并忽略配置文件的变动
+    pub fn enable_calibration(&mut self, conf_path: PathBuf) {
+        self.conf.x_map = None;
+        self.conf.y_map = None;
+        self.conf.area = None;
+        self.conf.area_presets.clear();
+        self.area_index = None;
+        self.monitor_index = None;
+        self.conf.x_gamma = None;
+        self.conf.y_gamma = None;
+        self.conf.monitor_maps.clear();
+        for keymap in &mut self.conf.keymaps {
+            keymap.x_map = None;
+            keymap.y_map = None;
+        }
+        self.calibration.replace(Calibration {
+            conf_path,
+            points: Vec::with_capacity(4),
+        });
+        info!("校准模式：请用画笔依次点击目标区域的四个角（左上、右上、右下、左下）");
+    }
+
     pub fn run(mut self) -> Result<()> {
+        logger::set_field("TABLET", Some(self.tablet_index.to_string()));
+        self.update_schema_log_field();
         info!("驱动任务开始运行");
+        // 切换用户之前已经打开了设备
+        if self.handle.is_some() {
+            systemd::notify_ready();
+        }
+        let result = self.supervise();
+        // 退出前释放所有按下的按键与画笔，避免按键在虚拟设备销毁前一直处于按下状态
+        if let Err(e) = self.release_all() {
+            warn!("退出时无法释放按下的按键: {:?}", e);
+        }
+        self.log_latency_summary();
+        if let Some(usage) = &self.usage {
+            match usage.save() {
+                Ok(path) => {
+                    info!("已将使用统计写入{}", path.display());
+                }
+                Err(e) => {
+                    warn!("无法保存使用统计: {:?}", e);
+                }
+            }
+        }
+        result
+    }
+
+    // 运行中的错误（例如读取设备时的偶发错误、uinput写入失败）通常是暂时的：关闭设备并释放按下的按键与画笔后
+    // 重新打开设备继续运行，虚拟设备保持不变；短时间内反复出错时才视为无法恢复，返回错误使该绘图板的驱动任务退出
+    fn supervise(&mut self) -> Result<()> {
+        let mut recoveries = VecDeque::with_capacity(MAX_RECOVERIES);
+        loop {
+            let e = match self.run_loop() {
+                Ok(()) => return Ok(()),
+                Err(e) if self.cancel_token.cancelled() => return Err(e),
+                Err(e) => e,
+            };
+            while recoveries
+                .front()
+                .is_some_and(|x: &Instant| x.elapsed() >= RECOVERY_WINDOW)
+            {
+                recoveries.pop_front();
+            }
+            if recoveries.len() >= MAX_RECOVERIES {
+                return Err(e.context(format!(
+                    "{}秒内连续出错超过{}次，无法恢复",
+                    RECOVERY_WINDOW.as_secs(),
+                    MAX_RECOVERIES
+                )));
+            }
+            recoveries.push_back(Instant::now());
+            error!(
+                event = "driver_recovering";
+                "驱动任务发生错误，将重新初始化设备: {:?}",
+                e
+            );
+            if let Some(handle) = &self.handle {
+                handle.release();
+            }
+            if let Err(e) = self.release_device() {
+                warn!("无法释放按下的按键: {:?}", e);
+            }
+            self.stats.borrow_mut().recoveries += 1;
+        }
+    }
+
+    fn run_loop(&mut self) -> Result<()> {
         loop {
             if self.cancel_token.cancelled() {
                 return Ok(());
             }
-            self.check_config_change();
+            self.check_changes()?;
+            self.publish_status();
+            self.persist_state();
+            self.watchdog.ping();
+            if self.paused || self.session_inactive {
+                let timeout = self.poll_interval();
+                self.event_loop.wait(timeout)?;
+                continue;
+            }
+            if self.handle.is_none() {
+                self.wait_for_device()?;
+                continue;
+            }
             self.read_and_handle_device_input()?;
         }
     }
 
-    fn check_config_change(&mut self) {
-        let mut latest_conf = match self.latest_conf.lock().take() {
-            Some(keymaps) => keymaps,
-            None => return,
+    // 其他任务放入的新配置、显示器、窗口焦点、会话状态与控制命令，以及规则与系统挂起的检查
+    fn check_changes(&mut self) -> Result<()> {
+        self.check_config_change()?;
+        self.check_monitor_change();
+        self.check_focus_change();
+        self.check_session_change()?;
+        self.check_system_resume()?;
+        self.check_rules()?;
+        self.check_control_commands()
+    }
+
+    // 配置了idleTimeout时，超过该时长没有收到报告，且画笔已离开感应范围、没有按下的按键、
+    // 没有等待处理的报告与悬停点击时进入空闲状态
+    fn update_idle(&mut self) -> bool {
+        let idle = self.conf.idle_timeout.is_some_and(|x| {
+            self.last_input.elapsed() >= x
+                && !self.stylus.in_proximity
+                && !self.stylus.in_area
+                && !self.input_in_progress()
+                && self.rate_limit.pending.is_none()
+                && self.dwell_remaining().is_none()
+        });
+        if idle != self.idle {
+            self.idle = idle;
+            debug!("{}空闲状态", if idle { "进入" } else { "退出" });
+        }
+        idle
+    }
+
+    // 暂停、等待设备或空闲时每次阻塞的最长时间：空闲状态下不设超时，启用了看门狗时不超过其超时时间的一半
+    fn poll_interval(&mut self) -> Option<Duration> {
+        if !self.update_idle() {
+            return Some(WAIT_FOR_DEVICE_POLL_INTERVAL);
+        }
+        self.watchdog.interval().map(|x| x / 2)
+    }
+
+    // 设备未连接时，按指数退避的间隔尝试打开设备；收到设备插入的热插拔通知时立即尝试
+    fn wait_for_device(&mut self) -> Result<()> {
+        let arrived = matches!(self.latest_hotplug.take(), Some(HotplugEvent::Arrived));
+        if arrived {
+            self.acquire_backoff = ACQUIRE_DEVICE_MIN_BACKOFF;
+        }
+        let remaining = match self.last_acquire_attempt {
+            None => Duration::ZERO,
+            Some(_) if arrived => Duration::ZERO,
+            Some(instant) => self.acquire_backoff.saturating_sub(instant.elapsed()),
         };
+        if !remaining.is_zero() {
+            let timeout = self.poll_interval().map_or(remaining, |x| remaining.min(x));
+            self.event_loop.wait(Some(timeout))?;
+            return Ok(());
+        }
+        let first_attempt = self.last_acquire_attempt.is_none();
+        self.last_acquire_attempt = Some(Instant::now());
+        match self.acquire_device() {
+            Ok(()) => {
+                self.device_connected();
+                systemd::notify_ready();
+                Ok(())
+            }
+            Err(e) => {
+                if self.conf.run_as.is_some() && permission_denied(&e) {
+                    warn!(
+                        "已放弃root权限，无法打开设备；需要runAsUser能够访问设备节点与uinput（例如通过install-udev-rules --group安装的规则）: {:?}",
+                        e
+                    );
+                } else if first_attempt {
+                    info!("设备未连接，等待设备插入: {:?}", e);
+                } else {
+                    debug!(
+                        "无法打开设备，{}毫秒后重试: {:?}",
+                        self.acquire_backoff.as_millis(),
+                        e
+                    );
+                }
+                let max_backoff = match self.conf.device.bluetooth_product_id {
+                    Some(_) => BLUETOOTH_ACQUIRE_DEVICE_MAX_BACKOFF,
+                    None => ACQUIRE_DEVICE_MAX_BACKOFF,
+                };
+                self.acquire_backoff = (self.acquire_backoff * 2).min(max_backoff);
+                Ok(())
+            }
+        }
+    }
+
+    fn device_connected(&mut self) {
+        info!(event = "device_connected"; "已连接设备");
+        if self.ever_connected {
+            self.stats.borrow_mut().reconnects += 1;
+        }
+        self.ever_connected = true;
+        self.acquire_backoff = ACQUIRE_DEVICE_MIN_BACKOFF;
+    }
+
+    fn acquire_device(&mut self) -> Result<()> {
+        self.ensure_output_sinks()?;
+        self.handle
+            .replace(Box::new(open_device_handle(&self.conf.device)?));
+        // 设备重新连接后可能切换了工作模式，重新观测坐标范围
+        self.input_range = InputRange {
+            x_max: self.conf.x_max_value,
+            y_max: self.conf.y_max_value,
+            warned: false,
+        };
+        Ok(())
+    }
+
+    fn ensure_output_sinks(&mut self) -> Result<()> {
+        if self.digitizer_sink.is_some() {
+            return Ok(());
+        }
+        if self.dry_run {
+            // 不创建虚拟设备，但仍需按事件设备补全坐标范围等数值
+            create_uninit_devices(&mut self.conf, self.tablet_index)
+                .context("无法读取事件设备的信息")?;
+            diagnostics::set_config(&self.conf);
+        }
+        self.create_output_sinks()
+    }
+
+    // 创建虚拟设备；指定了--dry-run时改为打印将要写入的事件
+    fn create_output_sinks(&mut self) -> Result<()> {
+        self.keyboard_keys = KEYBOARD_KEYS
+            .iter()
+            .copied()
+            .chain(self.conf.press_codes())
+            .collect();
+        if self.dry_run {
+            self.digitizer_sink = Some(Box::new(PrintSink("digitizer")));
+            self.keyboard_sink = Some(Box::new(PrintSink("keyboard")));
+            self.mouse_sink = Some(Box::new(PrintSink("mouse")));
+            self.pad_sink = match self.conf.pad_mode {
+                PadMode::Native => Some(Box::new(PrintSink("pad"))),
+                PadMode::Keymap => None,
+            };
+            return Ok(());
+        }
+        let (digitizer_uinput, keyboard_uinput, mouse_uinput, pad_uinput) =
+            create_uinput_device(&mut self.conf, self.tablet_index).context("无法创建虚拟设备")?;
+        diagnostics::set_config(&self.conf);
+        self.digitizer_sink = Some(Box::new(VirtualDevice::new(digitizer_uinput)));
+        self.keyboard_sink = Some(Box::new(VirtualDevice::new(keyboard_uinput)));
+        self.mouse_sink = Some(Box::new(VirtualDevice::new(mouse_uinput)));
+        self.pad_sink = pad_uinput.map(|x| Box::new(VirtualDevice::new(x)) as Box<dyn OutputSink>);
+        Ok(())
+    }
+
+    // 设备断开时释放所有按下的按键与画笔，保留虚拟设备，等待设备重新插入
+    fn release_device(&mut self) -> Result<()> {
+        self.handle = None;
+        logger::set_field("DEVICE", None);
+        self.rate_limit = RateLimitStatus::default();
+        self.last_acquire_attempt = Some(Instant::now());
+        self.fine_adjust = None;
+        self.stylus.in_proximity = false;
+        self.stylus.smoothed = None;
+        self.dwell = DwellStatus::default();
+        self.release_all()
+    }
+
+    fn release_all(&mut self) -> Result<()> {
+        self.release_pressed_keys()?;
+        self.release_relative()?;
+        self.lift_stylus()?;
+        self.release_latch()
+    }
+
+    // 笔尖、笔侧键、虚拟鼠标按键或映射的键盘按键仍然按下时，视为一次操作尚未结束
+    fn input_in_progress(&self) -> bool {
+        self.stylus.tip_pressed
+            || self.stylus.button0_pressed
+            || self.stylus.button1_pressed
+            || !self.pressed_keys.is_empty()
+            || !self.relative.pressed_buttons.is_empty()
+            || self.pad.pressed.is_some()
+    }
+
+    // 在处理报告的间隙调用；没有新配置时只进行一次原子交换
+    fn check_config_change(&mut self) -> Result<()> {
+        if let Some(conf) = self.latest_conf.take() {
+            if self.input_in_progress() {
+                debug!("笔画或按键尚未结束，结束后再应用新的配置");
+            }
+            self.pending_conf = Some(conf);
+        }
+        if self.pending_conf.is_none() {
+            return Ok(());
+        }
+        if self.calibration.is_some() {
+            self.pending_conf = None;
+            return Ok(());
+        }
+        // 让当前的笔画按旧的配置完成，按住的按键（例如修饰键）也按旧的按键映射释放，之后再切换
+        if self.input_in_progress() {
+            return Ok(());
+        }
+        let Some(mut latest_conf) = self.pending_conf.take() else {
+            return Ok(());
+        };
+        // 只重建发生变化的部分，例如只修改了按键映射时保留平滑与规则匹配的状态
+        let changes = latest_conf.changes_from(&self.conf);
+        let changed = |key: &str| changes.iter().any(|x| x.key == key);
+        if changes.is_empty() {
+            info!("配置文件的内容没有变化");
+        }
+        for change in &changes {
+            info!("配置变化：{}", change);
+        }
+        // 转环等没有释放事件的状态在新的按键映射下无法再被正确复位
+        if changed("keymap") {
+            self.release_pressed_keys()?;
+        }
+        // 虚拟键盘只能在创建时启用按键
+        if self.keyboard_sink.is_some() {
+            let missing = latest_conf
+                .press_codes()
+                .into_iter()
+                .filter(|x| !self.keyboard_keys.contains(x))
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                warn!(
+                    "虚拟键盘没有启用{:?}，需要重新启动驱动程序后才能生效",
+                    missing
+                );
+            }
+        }
         {
             // 修正不支持热更的字段
             latest_conf.x_max_value = self.conf.x_max_value;
             latest_conf.y_max_value = self.conf.y_max_value;
+            latest_conf.x_resolution = self.conf.x_resolution;
+            latest_conf.y_resolution = self.conf.y_resolution;
+            latest_conf.pressure_max_value = self.conf.pressure_max_value;
+            latest_conf.follow_monitor = self.conf.follow_monitor;
+            latest_conf.usage_statistics = self.conf.usage_statistics;
+            latest_conf.allow_exec = self.conf.allow_exec;
+            latest_conf.exec_allowlist = self.conf.exec_allowlist.clone();
+            latest_conf.device = self.conf.device.clone();
+            latest_conf.virtual_device_name = self.conf.virtual_device_name.clone();
+            latest_conf.virtual_keyboard_name = self.conf.virtual_keyboard_name.clone();
+            latest_conf.virtual_mouse_name = self.conf.virtual_mouse_name.clone();
+            latest_conf.pad_mode = self.conf.pad_mode;
+            latest_conf.virtual_pad_name = self.conf.virtual_pad_name.clone();
+            latest_conf.virtual_axes = self.conf.virtual_axes;
+            latest_conf.control_socket = self.conf.control_socket.clone();
+            latest_conf.run_as = self.conf.run_as.clone();
+            latest_conf.sandbox = self.conf.sandbox;
+            latest_conf.pause_on_inactive_session = self.conf.pause_on_inactive_session;
+            latest_conf.session_user = self.conf.session_user.clone();
+        }
+        if sandbox::installed() && latest_conf.needs_commands() {
+            warn!(
+                "已启用seccomp沙箱，无法运行外部程序；新增的通知、OSD等功能需要重新启动驱动程序才能生效"
+            );
         }
         if latest_conf.keymaps.len() >= self.conf.keymaps.len() {
             info!(
+                event = "config_reloaded";
                 "已重新加载配置文件；继续使用按键映射方案{}",
-                self.keymap_index
+                latest_conf.schema_label(self.keymap_index)
             );
         } else {
-            info!("已重新加载配置文件；切换到按键映射方案0");
+            info!(
+                event = "config_reloaded";
+                "已重新加载配置文件；切换到按键映射方案{}",
+                latest_conf.schema_label(0)
+            );
             self.keymap_index = 0;
         }
+        if let Some(index) = self.monitor_index
+            && index >= latest_conf.monitor_maps.len()
+        {
+            self.monitor_index = None;
+        }
+        if let Some(index) = self.area_index
+            && index >= latest_conf.area_presets.len()
+        {
+            info!("区域预设{}已不存在，切换回默认区域", index);
+            self.area_index = None;
+        }
+        // 规则的下标可能已经改变，重新匹配
+        if changed("rule") || changed("appProfile") {
+            self.rule.active = None;
+            self.rule.dirty = true;
+        }
+        self.overrides = PropertyOverrides::default();
+        if changed("smoothing") {
+            self.stylus.smoothed = None;
+        }
         self.conf = latest_conf;
+        self.stats.borrow_mut().reloads += 1;
+        diagnostics::set_config(&self.conf);
+        self.update_schema_log_field();
+        Ok(())
+    }
+
+    // 当前的按键映射方案作为驱动任务线程的日志字段（journald中的SCHEMA）
+    fn update_schema_log_field(&self) {
+        let schema = self
+            .conf
+            .keymaps
+            .get(self.keymap_index)
+            .and_then(|x| x.name.clone())
+            .unwrap_or_else(|| self.keymap_index.to_string());
+        logger::set_field("SCHEMA", Some(schema));
+    }
+
+    fn check_monitor_change(&mut self) {
+        let name = match self.latest_monitor.take() {
+            Some(name) => name,
+            None => return,
+        };
+        match self.conf.monitor_maps.iter().position(|x| x.name == name) {
+            Some(index) => {
+                self.monitor_index = Some(index);
+                self.rule.dirty = true;
+                info!("鼠标指针移动到了显示器{}，已重新映射绘图板", name);
+            }
+            None => {
+                warn!("找不到显示器{}的映射区域，忽略本次变化", name);
+            }
+        }
+    }
+
+    fn check_focus_change(&mut self) {
+        if let Some(window) = self.latest_focus.take() {
+            self.rule.window = Some(window);
+            self.rule.dirty = true;
+        }
+    }
+
+    // 使用第一个匹配的规则；生效的规则不变时不重复切换，避免覆盖期间的手动切换
+    fn check_rules(&mut self) -> Result<()> {
+        if self.calibration.is_some() {
+            return Ok(());
+        }
+        let time_due = self.conf.rules.iter().any(|x| x.time.is_some())
+            && self
+                .rule
+                .checked_at
+                .is_none_or(|x| x.elapsed() >= RULE_TIME_CHECK_INTERVAL);
+        if !self.rule.dirty && !time_due {
+            return Ok(());
+        }
+        self.rule.dirty = false;
+        self.rule.checked_at = Some(Instant::now());
+        let env = Environment {
+            window: self.rule.window.as_ref(),
+            monitor: self
+                .monitor_index
+                .and_then(|x| self.conf.monitor_maps.get(x))
+                .map(|x| x.name.as_str()),
+            minute: rule::local_minute_of_day(),
+        };
+        let matched = self.conf.rules.iter().position(|x| x.matches(&env));
+        if matched == self.rule.active {
+            return Ok(());
+        }
+        self.rule.active = matched;
+        match matched {
+            Some(index) => {
+                let rule = &self.conf.rules[index];
+                let (schema, area_preset) = (rule.schema, rule.area_preset);
+                info!("{}已生效", rule.describe(index));
+                let (keymap_index, area_index) = *self
+                    .rule
+                    .base
+                    .get_or_insert((self.keymap_index, self.area_index));
+                self.switch_schema_to(schema.unwrap_or(keymap_index))?;
+                self.switch_area_to(area_preset.or(area_index));
+            }
+            None => {
+                if let Some((keymap_index, area_index)) = self.rule.base.take() {
+                    info!("没有匹配的规则，恢复规则生效前的状态");
+                    self.switch_schema_to(keymap_index)?;
+                    self.switch_area_to(area_index);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 各字段只在变化时更新，避免在处理每个报告时都复制字符串
+    fn publish_status(&self) {
+        let latency = match self.conf.latency_stats {
+            true => self.latency.borrow_mut().summary(),
+            false => None,
+        };
+        let monitor = self
+            .monitor_index
+            .and_then(|x| self.conf.monitor_maps.get(x))
+            .map(|x| x.name.as_str());
+        let area = self
+            .area_index
+            .and_then(|x| self.conf.area_presets.get(x))
+            .map(|x| x.name.as_str());
+        let mut status = self.status.lock();
+        status.connection = self.handle.as_ref().map(|x| x.kind());
+        status.paused = self.paused;
+        status.stats = *self.stats.borrow();
+        status.latency = latency;
+        status.schema = self.keymap_index;
+        status.pen_mode = match self.current_pen_mode() {
+            PenMode::Absolute => "absolute",
+            PenMode::Relative => "relative",
+        };
+        status.properties = Properties {
+            pressure_curve: self.pressure_curve(),
+            smoothing: self.smoothing(),
+            rotation: self.rotation(),
+            x_map: self.area_map().0,
+            y_map: self.area_map().1,
+            area: self.area().copied(),
+        };
+        if status.monitor.as_deref() != monitor {
+            status.monitor = monitor.map(String::from);
+        }
+        if status.area.as_deref() != area {
+            status.area = area.map(String::from);
+        }
+        if status.schema_names.len() != self.conf.keymaps.len()
+            || status
+                .schema_names
+                .iter()
+                .zip(&self.conf.keymaps)
+                .any(|(x, y)| *x != y.name)
+        {
+            status.schema_names = self.conf.keymaps.iter().map(|x| x.name.clone()).collect();
+        }
+    }
+
+    // 切换到的按键映射方案、区域预设或显示器变化时写入状态文件；校准期间的临时状态不保存
+    fn persist_state(&mut self) {
+        let current = (
+            self.keymap_index,
+            self.area_index,
+            self.monitor_index,
+            self.pressure_adjust,
+        );
+        if current == self.saved_state || self.calibration.is_some() {
+            return;
+        }
+        self.saved_state = current;
+        let state = RuntimeState::new(
+            &self.conf,
+            self.keymap_index,
+            self.area_index,
+            self.monitor_index,
+            self.pressure_adjust,
+        );
+        if let Err(e) = state.save(self.tablet_index) {
+            warn!("无法保存运行时状态: {:?}", e);
+        }
+    }
+
+    fn check_control_commands(&mut self) -> Result<()> {
+        let commands = std::mem::take(&mut *self.pending_commands.lock());
+        for command in commands {
+            self.handle_control_command(command)?;
+        }
+        Ok(())
+    }
+
+    fn handle_control_command(&mut self, command: ControlCommand) -> Result<()> {
+        match command {
+            ControlCommand::Restart => self.restart_device(),
+            ControlCommand::SwitchSchema(index) => {
+                self.rule.base = None;
+                self.switch_schema_to(index)
+            }
+            ControlCommand::NextSchema => self.switch_schema(),
+            ControlCommand::Pause => self.pause(),
+            ControlCommand::Resume => {
+                self.resume();
+                Ok(())
+            }
+            ControlCommand::SetProperty(property) => {
+                self.set_property(property);
+                Ok(())
+            }
+        }
+    }
+
+    fn set_property(&mut self, property: Property) {
+        let overrides = &mut self.overrides;
+        match property {
+            Property::PressureCurve(x) => overrides.pressure_curve = x,
+            Property::Smoothing(x) => overrides.smoothing = x,
+            Property::Rotation(x) => overrides.rotation = x,
+            Property::XMap(x) => overrides.x_map = x,
+            Property::YMap(x) => overrides.y_map = x,
+            Property::Area(x) => overrides.area = x,
+        }
+        // 旋转或平滑强度变化后，之前的平滑坐标已经没有意义
+        self.stylus.smoothed = None;
+        info!("已在运行时修改属性：{:?}", property);
+    }
+
+    // 重新打开USB设备并重新握手；虚拟设备、当前的按键映射方案与使用统计保持不变
+    fn restart_device(&mut self) -> Result<()> {
+        let Some(handle) = &self.handle else {
+            warn!("设备未连接或已暂停，忽略restart命令");
+            return Ok(());
+        };
+        info!("正在重新初始化设备");
+        handle.release();
+        self.release_device()?;
+        self.handle.replace(Box::new(
+            open_device_handle(&self.conf.device).context("无法重新打开设备")?,
+        ));
+        info!("已重新初始化设备");
+        Ok(())
+    }
+
+    // 让虚拟绘图板上的画笔离开感应区域，释放笔尖与笔侧键
+    fn lift_stylus(&mut self) -> Result<()> {
+        if !self.stylus.in_area {
+            return Ok(());
+        }
+        self.write_digitizer_tip_released()?;
+        self.write_digitizer_button0_released()?;
+        self.write_digitizer_button1_released()?;
+        self.write_digitizer_event(EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN), 0)?;
+        self.stylus.in_area = false;
+        self.write_digitizer_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
     }
 
     fn read_and_handle_device_input(&mut self) -> Result<()> {
         let mut buf = [0u8; INPUT_USAGE_BUF_SIZE];
-        loop {
-            match self
-                .handle
-                .read_interrupt(IN_ENDPOINT, &mut buf, READ_INTERRUPT_TIMEOUT)
-            {
+        let wireless = self.handle.as_ref().is_some_and(|x| x.wireless());
+        let mut timeout = match wireless {
+            true => WIRELESS_READ_INTERRUPT_TIMEOUT,
+            false => READ_INTERRUPT_TIMEOUT,
+        };
+        // 存在被合并的报告时，最迟在下一个上报时刻处理它
+        if self.rate_limit.pending.is_some()
+            && let Some(remaining) = self.rate_limit_remaining()
+        {
+            timeout = timeout.min(remaining.max(Duration::from_millis(1)));
+        }
+        // 等待悬停点击的时刻到达，即使画笔保持不动时设备不再上报
+        if let Some(remaining) = self.dwell_remaining() {
+            timeout = timeout.min(remaining.max(Duration::from_millis(1)));
+        }
+        // 空闲状态下没有需要定时处理的事情：不设超时，阻塞到收到报告或被其他任务唤醒
+        let idle = self.update_idle();
+        let idle_timeout = self.poll_interval();
+        while let Some(handle) = &self.handle
+            && !self.cancel_token.cancelled()
+        {
+            let result = match idle {
+                true => match handle.read_idle(&mut buf, &mut self.event_loop, idle_timeout) {
+                    Ok(Some(len)) => Ok(len),
+                    // 被唤醒、取消或到达看门狗的期限：回到run_loop处理
+                    Ok(None) => return Ok(()),
+                    Err(e) => Err(e),
+                },
+                false => handle.read(&mut buf, timeout),
+            };
+            match result {
                 Ok(len) => {
-                    self.handle_device_input(&buf[..len])?;
+                    self.last_input = Instant::now();
+                    // 空闲期间收到的控制命令与配置变动可能尚未处理，在处理报告之前先处理，
+                    // 例如切换方案后按下的按键应当使用新的方案
+                    if idle {
+                        self.update_idle();
+                        self.check_changes()?;
+                        if self.handle.is_none() || self.paused || self.session_inactive {
+                            return Ok(());
+                        }
+                    }
+                    self.record_report(&buf[..len]);
+                    self.report_received_at = Some(Instant::now());
+                    let result = self.handle_device_input(&buf[..len]);
+                    self.report_received_at = None;
+                    result?;
+                    // 持续有输入时不会回到run_loop，因此在报告之间检查新的配置
+                    self.check_config_change()?;
+                    // 回到run_loop，按退出空闲状态后的超时时间继续读取
+                    if idle {
+                        return Ok(());
+                    }
                 }
-                Err(UsbError::Timeout) => {
-                    return Ok(());
+                Err(UsbError::Timeout) => return self.handle_read_timeout(wireless),
+                // 蓝牙连接在绘图板空闲休眠时断开属于正常情况
+                Err(UsbError::NoDevice | UsbError::Io) if handle.kind() == "bluetooth" => {
+                    info!(
+                        event = "device_disconnected";
+                        "蓝牙连接已断开（绘图板可能进入了休眠），等待设备重新连接"
+                    );
+                    return self.release_device();
+                }
+                // ENODEV：设备被拔出；EIO：连接异常（例如线缆接触不良）
+                Err(e @ (UsbError::NoDevice | UsbError::Io)) => {
+                    warn!(
+                        event = "device_disconnected";
+                        "设备已断开（{}），等待设备重新连接",
+                        e
+                    );
+                    return self.release_device();
                 }
                 Err(e) => {
                     return Err(anyhow!("读取USB设备的中断端点时发生错误: {}", e));
                 }
             }
         }
+        Ok(())
+    }
+
+    // 等待报告超时：处理被合并的报告、无线连接下长时间未收到画笔事件与悬停点击
+    fn handle_read_timeout(&mut self, wireless: bool) -> Result<()> {
+        if let Some((pending, received_at)) = self.rate_limit.pending.take() {
+            self.rate_limit.last_handled = Some(Instant::now());
+            self.report_received_at = Some(received_at);
+            let result = self.handle_digitizer_event(&pending);
+            self.report_received_at = None;
+            result?;
+        }
+        if wireless
+            && self.stylus.in_area
+            && self
+                .stylus
+                .last_report
+                .is_some_and(|x| x.elapsed() >= WIRELESS_STYLUS_IDLE_TIMEOUT)
+        {
+            debug!("长时间未收到画笔事件，视为画笔离开感应区域");
+            self.stylus.in_proximity = false;
+            self.stylus.smoothed = None;
+            self.dwell = DwellStatus::default();
+            self.lift_stylus()?;
+        }
+        self.check_dwell()
+    }
+
+    // 回放记录的原始报告：按记录中的时间间隔依次经过与设备输入相同的处理
+    pub fn replay(mut self, reports: Vec<RecordedReport>) -> Result<()> {
+        if self.digitizer_sink.is_none() {
+            self.create_output_sinks()?;
+        }
+        info!("开始回放{}个报告", reports.len());
+        self.handle = Some(Box::new(ReplaySource::new(
+            self.cancel_token.clone(),
+            reports,
+        )));
+        while let Some(handle) = &self.handle
+            && !handle.finished()
+        {
+            if self.cancel_token.cancelled() {
+                return self.release_all();
+            }
+            self.read_and_handle_device_input()?;
+        }
+        self.handle_read_timeout(false)?;
+        self.release_all()?;
+        info!("回放完成");
+        self.log_latency_summary();
+        Ok(())
+    }
+
+    fn log_latency_summary(&self) {
+        if let Some(summary) = self.latency.borrow().summarize() {
+            info!("延迟统计：{}", summary);
+        }
+    }
+
+    fn record_report(&self, buf: &[u8]) {
+        if self.recorder.is_empty() {
+            return;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let report: Vec<_> = buf.iter().map(|x| format!("{:02x}", x)).collect();
+        self.recorder.send(json_object! {
+            "time" => time.as_millis() as usize,
+            "report" => report.join(" "),
+        });
     }
 
     fn handle_device_input(&mut self, buf: &[u8]) -> Result<()> {
+        self.stats.borrow_mut().reports += 1;
         if buf.is_empty() {
             return Ok(());
         }
         if buf[0] != 0x02 {
             warn!("收到非0x02用途的中断输入：{:02x?}", buf);
+            self.stats.borrow_mut().ignored_reports += 1;
             return Ok(());
         }
         let buf = &buf[1..];
+        // 按键报告至少包含按键码，画笔报告至少包含状态位、坐标、压力与倾斜；截断的报告（例如记录不完整）直接忽略
+        let min_len = match buf.first() {
+            Some(0xf0..=0xff) => 3,
+            _ => 9,
+        };
+        if buf.len() < min_len {
+            warn!("收到了过短的报告：{:02x?}", buf);
+            self.stats.borrow_mut().ignored_reports += 1;
+            return Ok(());
+        }
         match buf[0] & 0xf0 {
             0xf0 => {
                 self.handle_button_event(buf)?;
             }
             _ => {
+                if self.should_coalesce(buf) {
+                    let received_at = self.report_received_at.unwrap_or_else(Instant::now);
+                    let previous = self.rate_limit.pending.replace((buf.to_vec(), received_at));
+                    if previous.is_some() {
+                        self.stats.borrow_mut().dropped_reports += 1;
+                    }
+                    return Ok(());
+                }
                 self.handle_digitizer_event(buf)?;
+                // 在笔尖抬起的事件上报之后再释放随笔尖按下的修饰键
+                if !self.stylus.tip_pressed
+                    && !self.relative.pressed_buttons.contains(&EV_KEY::BTN_LEFT)
+                {
+                    self.release_latch()?;
+                }
             }
         }
         Ok(())
     }
 
+    // 距离下一个允许上报的时刻还有多久；未配置maxReportRate时为None
+    fn rate_limit_remaining(&self) -> Option<Duration> {
+        let interval = Duration::from_secs(1) / self.conf.max_report_rate?;
+        let elapsed = self.rate_limit.last_handled?.elapsed();
+        Some(interval.saturating_sub(elapsed))
+    }
+
+    fn should_coalesce(&mut self, buf: &[u8]) -> bool {
+        if self.conf.max_report_rate.is_none() {
+            return false;
+        }
+        let status = buf[0];
+        if status == self.rate_limit.last_status
+            && self.rate_limit_remaining().is_some_and(|x| !x.is_zero())
+        {
+            return true;
+        }
+        // 状态变化的报告立即上报，被合并的报告不再上报
+        if self.rate_limit.pending.take().is_some() {
+            self.stats.borrow_mut().dropped_reports += 1;
+        }
+        self.rate_limit.last_status = status;
+        self.rate_limit.last_handled = Some(Instant::now());
+        false
+    }
+
     fn handle_button_event(&mut self, buf: &[u8]) -> Result<()> {
         let code = ((buf[1] as u16) << 8) | (buf[2] as u16);
-        macro_rules! handle {
-            ($desc:literal, $field:ident) => {
-                debug!($desc);
-                match &self
-                    .conf
-                    .keymaps
-                    .get(self.keymap_index)
-                    .context("按键映射方案下标越界")?
-                    .$field
-                {
-                    Keymap::Press(codes) => {
-                        for code in codes.iter() {
-                            debug!("虚拟键盘 - 按下{:?}", code);
-                            self.write_keyboard_event(EventCode::EV_KEY(*code), 1)?;
-                            self.pressed_keys.insert(*code);
-                        }
-                        self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
-                    }
-                    Keymap::SwitchSchema => {
-                        self.switch_schema();
-                    }
-                    _ => {}
-                }
-            };
+        // 松开按键时手掌同样可能移动，也重新开始计时
+        self.last_pad_input = Some(Instant::now());
+        if code == 0x0000 {
+            debug!("收到释放按键事件");
+            if !self.monitor.is_empty() {
+                self.monitor.send(json_object! {
+                    "tablet" => self.tablet_index,
+                    "type" => "release",
+                });
+            }
+            self.release_pressed_keys()?;
+            if self.fine_adjust.take().is_some() {
+                debug!("虚拟绘图板 - 退出微调模式");
+                self.publish(StateEvent::FineAdjust(false));
+            }
+            return Ok(());
         }
-        match code {
-            0x0000 => {
-                debug!("收到释放按键事件");
-                if !self.pressed_keys.is_empty() {
-                    for code in self.pressed_keys.iter() {
-                        debug!("虚拟键盘 - 释放{:?}", code);
-                        self.write_keyboard_event(EventCode::EV_KEY(*code), 0)?;
-                    }
-                    self.pressed_keys.clear();
-                    self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+        let Some(key) = self.conf.device.profile.lookup(code) else {
+            warn!("收到了未知的按键事件：{:02x?}", buf);
+            self.stats.borrow_mut().ignored_reports += 1;
+            return Ok(());
+        };
+        debug!("收到按下{:?}事件", key);
+        if self.conf.pad_mode == PadMode::Native {
+            if !self.monitor.is_empty() {
+                let action = match key {
+                    PadKey::Button(index) => PAD_BUTTON_CODES
+                        .get(index)
+                        .map(|x| format!("{:?}", x))
+                        .unwrap_or_default(),
+                    PadKey::RingCw | PadKey::RingCcw => "ABS_WHEEL".to_string(),
+                    PadKey::RingButton => "none".to_string(),
+                };
+                self.send_monitor_pad_event(key, action);
+            }
+            return self.handle_native_pad_event(key);
+        }
+        let keymap = self
+            .conf
+            .keymaps
+            .get(self.keymap_index)
+            .context("按键映射方案下标越界")?;
+        let Some(keymap) = keymap.pad_key(key, self.stylus.in_proximity) else {
+            warn!("{:?}没有对应的按键映射字段，忽略", key);
+            self.stats.borrow_mut().ignored_reports += 1;
+            return Ok(());
+        };
+        if !self.monitor.is_empty() {
+            self.send_monitor_pad_event(key, keymap.to_string());
+        }
+        match keymap.clone() {
+            Keymap::Press(codes) => {
+                // 预备的修饰键随本次按键一起按下，松开按键时一起释放
+                for code in std::mem::take(&mut self.latch.armed) {
+                    debug!("虚拟键盘 - 按下预备的{:?}", code);
+                    self.write_keyboard_event(EventCode::EV_KEY(code), 1)?;
+                    self.pressed_keys.insert(code);
+                }
+                for code in codes.iter() {
+                    debug!("虚拟键盘 - 按下{:?}", code);
+                    self.write_keyboard_event(EventCode::EV_KEY(*code), 1)?;
+                    self.pressed_keys.insert(*code);
                 }
+                self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
             }
-            0x0100 => {
-                handle!("收到按下按钮0事件", button0);
+            Keymap::SwitchSchema => {
+                self.record_action("switchSchema");
+                self.switch_schema()?;
             }
-            0x0200 => {
-                handle!("收到按下按钮1事件", button1);
+            Keymap::FineAdjust => {
+                self.record_action("fineAdjust");
+                self.enter_fine_adjust();
             }
-            0x0400 => {
-                handle!("收到按下按钮2事件", button2);
+            Keymap::CycleMonitor => {
+                self.record_action("cycleMonitor");
+                self.cycle_monitor();
             }
-            0x0800 => {
-                handle!("收到按下按钮3事件", button3);
+            Keymap::MapToMonitor(name) => {
+                self.record_action("mapToMonitor");
+                self.map_to_monitor(&name);
             }
-            0x0801 => {
-                handle!("收到顺时针转动转环事件", ring1);
+            Keymap::CycleArea => {
+                self.record_action("cycleArea");
+                self.cycle_area();
             }
-            0x0802 => {
-                handle!("收到逆时针转动转环事件", ring0);
+            Keymap::Exec(argv) => {
+                self.record_action("exec");
+                self.exec(&argv);
             }
-            0x0803 => {
-                handle!("收到按下转环中心按钮事件", ring_button);
+            Keymap::PressureSofter => {
+                self.record_action("pressureSofter");
+                self.adjust_pressure(-1f32);
             }
-            0x1000 => {
-                handle!("收到按下按钮4事件", button4);
+            Keymap::PressureHarder => {
+                self.record_action("pressureHarder");
+                self.adjust_pressure(1f32);
             }
-            0x2000 => {
-                handle!("收到按下按钮5事件", button5);
+            Keymap::ToggleTilt => {
+                self.record_action("toggleTilt");
+                self.toggle_tilt();
             }
-            0x4000 => {
-                handle!("收到按下按钮6事件", button6);
+            Keymap::RotateMapping => {
+                self.record_action("rotateMapping");
+                self.rotate_mapping();
             }
-            0x8000 => {
-                handle!("收到按下按钮7事件", button7);
+            Keymap::ToggleSmoothing => {
+                self.record_action("toggleSmoothing");
+                self.toggle_smoothing();
             }
-            _ => {
-                warn!("收到了未知的按键事件：{:02x?}", buf);
+            Keymap::CycleSmoothing => {
+                self.record_action("cycleSmoothing");
+                self.cycle_smoothing();
             }
+            Keymap::Latch(modifiers) => {
+                self.record_action("latch");
+                self.toggle_latch(&modifiers);
+            }
+            Keymap::Zoom(modifiers) => {
+                // 配置中已确保zoom只用于转环
+                let direction = match key {
+                    PadKey::RingCw => 1,
+                    _ => -1,
+                };
+                self.record_action("zoom");
+                self.zoom(&modifiers, direction)?;
+            }
+            Keymap::None => {}
         }
         Ok(())
     }
 
-    fn switch_schema(&mut self) {
+    fn send_monitor_pad_event(&self, key: PadKey, action: String) {
+        self.monitor.send(json_object! {
+            "tablet" => self.tablet_index,
+            "type" => "pad",
+            "key" => key.field_name(),
+            "action" => action,
+        });
+    }
+
+    // padMode为native时，按键与转环的事件原样由虚拟Pad设备上报，不经过按键映射
+    fn handle_native_pad_event(&mut self, key: PadKey) -> Result<()> {
+        match key {
+            PadKey::RingCw | PadKey::RingCcw => {
+                let step = if matches!(key, PadKey::RingCw) { 1 } else { -1 };
+                // 在1~PAD_RING_MAX之间循环
+                self.pad.ring = (self.pad.ring - 1 + step).rem_euclid(PAD_RING_MAX) + 1;
+                self.pad.ring_touched = true;
+                debug!("虚拟Pad - 转环位置({})", self.pad.ring);
+                self.write_pad_event(EventCode::EV_ABS(EV_ABS::ABS_WHEEL), self.pad.ring)?;
+            }
+            PadKey::Button(_) | PadKey::RingButton => {
+                let index = match key {
+                    PadKey::Button(index) => index,
+                    _ => self.conf.device.profile.buttons.len(),
+                };
+                let Some(code) = PAD_BUTTON_CODES.get(index) else {
+                    warn!("{:?}超出了虚拟Pad设备支持的按钮数量，忽略", key);
+                    self.stats.borrow_mut().ignored_reports += 1;
+                    return Ok(());
+                };
+                debug!("虚拟Pad - 按下{:?}", code);
+                self.write_pad_event(EventCode::EV_KEY(*code), 1)?;
+                self.pad.pressed = Some(*code);
+            }
+        }
+        self.write_pad_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
+    }
+
+    fn release_pad(&mut self) -> Result<()> {
+        if self.pad.pressed.is_none() && !self.pad.ring_touched {
+            return Ok(());
+        }
+        if let Some(code) = self.pad.pressed.take() {
+            debug!("虚拟Pad - 释放{:?}", code);
+            self.write_pad_event(EventCode::EV_KEY(code), 0)?;
+        }
+        if self.pad.ring_touched {
+            self.pad.ring_touched = false;
+            self.write_pad_event(EventCode::EV_ABS(EV_ABS::ABS_WHEEL), 0)?;
+        }
+        self.write_pad_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
+    }
+
+    // 按住修饰键并滚动一格鼠标滚轮（direction为1时向上，即放大），随后立即释放修饰键；
+    // 同时上报REL_WHEEL与高精度的REL_WHEEL_HI_RES（每格120）
+    fn zoom(&mut self, modifiers: &[EV_KEY], direction: i32) -> Result<()> {
+        debug!("虚拟鼠标 - 按住{:?}滚动滚轮({})", modifiers, direction);
+        for code in modifiers {
+            self.write_keyboard_event(EventCode::EV_KEY(*code), 1)?;
+        }
+        self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+        self.write_mouse_event(EventCode::EV_REL(EV_REL::REL_WHEEL), direction)?;
+        self.write_mouse_event(EventCode::EV_REL(EV_REL::REL_WHEEL_HI_RES), direction * 120)?;
+        self.write_mouse_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+        for code in modifiers.iter().rev() {
+            self.write_keyboard_event(EventCode::EV_KEY(*code), 0)?;
+        }
+        self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
+    }
+
+    // 再次按下预备了相同修饰键的按键时取消预备
+    fn toggle_latch(&mut self, modifiers: &[EV_KEY]) {
+        if self.latch.armed == modifiers {
+            self.latch.armed.clear();
+            info!("已取消预备的修饰键");
+        } else {
+            self.latch.armed = modifiers.to_vec();
+            info!("已预备修饰键{:?}，将应用于下一次笔尖按下或按键", modifiers);
+        }
+    }
+
+    // 笔尖按下时按下预备的修饰键，在笔尖事件之前上报
+    fn apply_latch(&mut self) -> Result<()> {
+        if self.latch.armed.is_empty() {
+            return Ok(());
+        }
+        for code in std::mem::take(&mut self.latch.armed) {
+            debug!("虚拟键盘 - 随笔尖按下预备的{:?}", code);
+            self.write_keyboard_event(EventCode::EV_KEY(code), 1)?;
+            self.latch.held.push(code);
+        }
+        self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
+    }
+
+    fn release_latch(&mut self) -> Result<()> {
+        if self.latch.held.is_empty() {
+            return Ok(());
+        }
+        for code in std::mem::take(&mut self.latch.held) {
+            debug!("虚拟键盘 - 释放预备的{:?}", code);
+            self.write_keyboard_event(EventCode::EV_KEY(code), 0)?;
+        }
+        self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
+    }
+
+    fn release_pressed_keys(&mut self) -> Result<()> {
+        self.release_pad()?;
+        if self.pressed_keys.is_empty() {
+            return Ok(());
+        }
+        for code in self.pressed_keys.iter() {
+            debug!("虚拟键盘 - 释放{:?}", code);
+            self.write_keyboard_event(EventCode::EV_KEY(*code), 0)?;
+        }
+        self.pressed_keys.clear();
+        self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
+    }
+
+    fn record_action(&mut self, action: &'static str) {
+        diagnostics::record_event(EventRecord::Action(action));
+        if let Some(usage) = &mut self.usage {
+            usage.record_action(action);
+        }
+    }
+
+    // 手动切换方案后，没有规则匹配时不再恢复规则生效前的状态
+    fn switch_schema(&mut self) -> Result<()> {
+        self.rule.base = None;
         let len = self.conf.keymaps.len();
-        let current_index = self.keymap_index;
-        let new_index = (current_index + 1) % len;
-        if new_index != current_index {
-            self.keymap_index = new_index;
-            info!("已切换到按键映射方案{}", new_index);
+        self.switch_schema_to((self.keymap_index + 1) % len)
+    }
+
+    fn switch_schema_to(&mut self, new_index: usize) -> Result<()> {
+        if new_index == self.keymap_index {
+            return Ok(());
+        }
+        // 控制命令发出后配置文件可能已被重新加载
+        let Some(keymap) = self.conf.keymaps.get(new_index) else {
+            warn!("按键映射方案{}不存在，忽略切换", new_index);
+            return Ok(());
+        };
+        let event = StateEvent::SchemaSwitched {
+            index: new_index,
+            name: keymap.name.clone(),
+        };
+        // 释放旧方案中按下的按键，避免其在切换后一直处于按下状态
+        self.release_pressed_keys()?;
+        self.keymap_index = new_index;
+        self.update_schema_log_field();
+        info!("{}", event);
+        self.publish(event);
+        if let Some(usage) = &mut self.usage {
+            usage.record_schema(new_index);
+        }
+        Ok(())
+    }
+
+    // 暂停：释放所有按下的按键与画笔，并关闭设备（USB设备会重新挂载内核驱动），
+    // 使固件升级工具等其他程序可以访问设备；虚拟设备保持不变，直到收到resume命令
+    fn pause(&mut self) -> Result<()> {
+        if self.paused {
+            return Ok(());
+        }
+        self.release_device()?;
+        self.paused = true;
+        info!("已暂停");
+        self.publish(StateEvent::Paused(true));
+        Ok(())
+    }
+
+    fn resume(&mut self) {
+        if !self.paused {
+            return;
+        }
+        self.paused = false;
+        // 立即尝试重新打开设备
+        self.last_acquire_attempt = None;
+        self.acquire_backoff = ACQUIRE_DEVICE_MIN_BACKOFF;
+        info!("已恢复");
+        self.publish(StateEvent::Paused(false));
+    }
+
+    fn check_session_change(&mut self) -> Result<()> {
+        let Some(active) = self.latest_session.take() else {
+            return Ok(());
+        };
+        if active != self.session_inactive {
+            return Ok(());
+        }
+        self.session_inactive = !active;
+        if active {
+            // 已通过pause命令暂停时保持暂停
+            self.last_acquire_attempt = None;
+            self.acquire_backoff = ACQUIRE_DEVICE_MIN_BACKOFF;
+            info!("登录会话已恢复活动状态，继续注入事件");
+        } else {
+            self.release_device()?;
+            info!("登录会话处于非活动状态，暂停注入事件");
+        }
+        Ok(())
+    }
+
+    // 系统从挂起中恢复后，USB设备可能被重置或重新枚举，已打开的句柄读取时一直超时，或者设备退出了握手后的工作模式；
+    // 因此关闭设备（释放按下的按键与画笔）并立即重新打开、重新握手，设备尚未重新出现时按退避间隔重试
+    fn check_system_resume(&mut self) -> Result<()> {
+        let Some(slept) = self.sleep_detector.check() else {
+            return Ok(());
+        };
+        self.acquire_backoff = ACQUIRE_DEVICE_MIN_BACKOFF;
+        let Some(handle) = &self.handle else {
+            info!("系统已从挂起中恢复（挂起了{}秒）", slept.as_secs());
+            self.last_acquire_attempt = None;
+            return Ok(());
+        };
+        info!(
+            event = "system_resumed";
+            "系统已从挂起中恢复（挂起了{}秒），重新初始化设备",
+            slept.as_secs()
+        );
+        handle.release();
+        self.release_device()?;
+        self.last_acquire_attempt = None;
+        Ok(())
+    }
+
+    fn exec(&self, argv: &[String]) {
+        if !self.conf.allow_exec {
+            warn!(
+                "未允许exec行为（以root身份运行时需要配置allowExec = true），忽略执行{}",
+                argv[0]
+            );
+            return;
+        }
+        if let Some(allowlist) = &self.conf.exec_allowlist
+            && !allowlist.contains(&argv[0])
+        {
+            warn!("{}不在execAllowlist中，忽略执行", argv[0]);
+            return;
+        }
+        if self.dry_run {
+            println!("exec      {:?}", argv);
+            return;
+        }
+        debug!("执行命令{:?}", argv);
+        // 避免子进程继承NOTIFY_SOCKET后冒充本程序向systemd发送通知
+        match Command::new(&argv[0])
+            .args(&argv[1..])
+            .env_remove("NOTIFY_SOCKET")
+            .spawn()
+        {
+            Ok(mut child) => {
+                // 在后台回收子进程，避免产生僵尸进程
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => {
+                warn!("无法执行{}: {}", argv[0], e);
+            }
+        }
+    }
+
+    fn cycle_monitor(&mut self) {
+        let len = self.conf.monitor_maps.len();
+        if len == 0 {
+            warn!("没有可用的显示器布局，忽略cycleMonitor");
+            return;
+        }
+        let new_index = match self.monitor_index {
+            Some(index) => (index + 1) % len,
+            None => 0,
+        };
+        self.switch_monitor_to(new_index);
+    }
+
+    // 显示器布局在加载配置文件时查询，之后接入的显示器需要重新加载配置文件
+    fn map_to_monitor(&mut self, name: &str) {
+        match self.conf.monitor_maps.iter().position(|x| x.name == name) {
+            Some(index) => self.switch_monitor_to(index),
+            None => warn!("找不到显示器{}，忽略mapToMonitor", name),
+        }
+    }
+
+    fn switch_monitor_to(&mut self, new_index: usize) {
+        self.monitor_index = Some(new_index);
+        self.rule.dirty = true;
+        let event = StateEvent::MonitorChanged(self.conf.monitor_maps[new_index].name.clone());
+        info!("{}", event);
+        self.publish(event);
+    }
+
+    // 依次切换区域预设，最后一个预设之后回到配置中的area
+    fn cycle_area(&mut self) {
+        let len = self.conf.area_presets.len();
+        if len == 0 {
+            warn!("没有配置区域预设，忽略cycleArea");
+            return;
+        }
+        let new_index = match self.area_index {
+            Some(index) if index + 1 < len => Some(index + 1),
+            Some(_) => None,
+            None => Some(0),
+        };
+        self.rule.base = None;
+        self.switch_area_to(new_index);
+    }
+
+    fn switch_area_to(&mut self, new_index: Option<usize>) {
+        // 规则生效前的区域预设可能已在重新加载配置时被删除
+        let new_index = new_index.filter(|x| *x < self.conf.area_presets.len());
+        if new_index == self.area_index {
+            return;
+        }
+        self.area_index = new_index;
+        let event = StateEvent::AreaChanged(
+            new_index.map(|index| self.conf.area_presets[index].name.clone()),
+        );
+        info!("{}", event);
+        self.publish(event);
+    }
+
+    // 当前生效的压力曲线；优先级：set命令 > 生效的规则 > 配置
+    fn pressure_curve(&self) -> Option<f32> {
+        let curve = self.base_pressure_curve();
+        if self.pressure_adjust == 0f32 {
+            return curve;
+        }
+        Some(
+            (curve.unwrap_or(1f32) + self.pressure_adjust)
+                .clamp(MIN_PRESSURE_CURVE, MAX_PRESSURE_CURVE),
+        )
+    }
+
+    // 未叠加pressureSofter、pressureHarder的调整时的压力曲线
+    fn base_pressure_curve(&self) -> Option<f32> {
+        self.overrides.pressure_curve.or_else(|| {
+            self.rule
+                .active
+                .and_then(|x| self.conf.rules.get(x))
+                .and_then(|x| x.pressure_curve)
+                .or(self.conf.pressure_curve)
+        })
+    }
+
+    // 关闭时从下一个报告起上报的倾斜为0
+    fn toggle_tilt(&mut self) {
+        self.tilt_enabled = !self.tilt_enabled;
+        info!(
+            "{}倾斜",
+            if self.tilt_enabled {
+                "已开启"
+            } else {
+                "已关闭"
+            }
+        );
+        self.publish(StateEvent::TiltEnabled(self.tilt_enabled));
+    }
+
+    // 按pressureStep调整压力曲线；direction为-1时更轻的压力即可画出粗线，为1时需要更用力。
+    // 调整后的曲线限制在MIN_PRESSURE_CURVE到MAX_PRESSURE_CURVE之间，超出的部分不计入调整
+    fn adjust_pressure(&mut self, direction: f32) {
+        let base = self.base_pressure_curve().unwrap_or(1f32);
+        let curve = (base + self.pressure_adjust + direction * self.conf.pressure_step)
+            .clamp(MIN_PRESSURE_CURVE, MAX_PRESSURE_CURVE);
+        // 避免反复调整后累积浮点误差
+        self.pressure_adjust = ((curve - base) * 100f32).round() / 100f32;
+        let curve = self.pressure_curve().unwrap_or(1f32);
+        info!(
+            "压力曲线已调整为{:.2}（{:+.2}）",
+            curve, self.pressure_adjust
+        );
+        self.publish(StateEvent::PressureAdjusted(curve));
+    }
+
+    // 优先级：toggleSmoothing关闭 > cycleSmoothing切换到的强度 > set命令 > 配置；
+    // 重新加载配置后下标可能超出smoothingPresets，此时视为未切换
+    fn smoothing(&self) -> f32 {
+        if self.smoothing_off {
+            return 0f32;
+        }
+        self.smoothing_index
+            .and_then(|x| self.conf.smoothing_presets.get(x).copied())
+            .or(self.overrides.smoothing)
+            .unwrap_or(self.conf.smoothing)
+    }
+
+    // 在原始坐标与当前的平滑强度之间切换；切换后从下一个报告起重新开始平滑，不产生拖尾
+    fn toggle_smoothing(&mut self) {
+        self.smoothing_off = !self.smoothing_off;
+        self.stylus.smoothed = None;
+        self.report_smoothing();
+    }
+
+    // 依次切换到smoothingPresets中的下一个强度，最后一个之后恢复为配置的smoothing；同时取消toggleSmoothing的关闭
+    fn cycle_smoothing(&mut self) {
+        let len = self.conf.smoothing_presets.len();
+        if len == 0 {
+            warn!("smoothingPresets为空，忽略cycleSmoothing");
+            return;
+        }
+        self.smoothing_index = match self.smoothing_index {
+            Some(x) if x + 1 < len => Some(x + 1),
+            Some(_) => None,
+            None => Some(0),
+        };
+        self.smoothing_off = false;
+        self.stylus.smoothed = None;
+        self.report_smoothing();
+    }
+
+    fn report_smoothing(&mut self) {
+        let smoothing = self.smoothing();
+        match smoothing > 0f32 {
+            true => info!("平滑强度已切换为{:.2}", smoothing),
+            false => info!("已关闭平滑"),
+        }
+        self.publish(StateEvent::SmoothingChanged(smoothing));
+    }
+
+    // 绘图板的宽高比（按物理尺寸）；分辨率未知时视为两轴的设备坐标单位长度相同
+    fn aspect_ratio(&self) -> f32 {
+        let (x_resolution, y_resolution) = match (self.conf.x_resolution, self.conf.y_resolution) {
+            (x, y) if x > 0 && y > 0 => (x as f32, y as f32),
+            _ => (1f32, 1f32),
+        };
+        (self.conf.x_max_value as f32 / x_resolution)
+            / (self.conf.y_max_value as f32 / y_resolution)
+    }
+
+    fn rotation(&self) -> u16 {
+        (self.overrides.rotation.unwrap_or(self.conf.rotation) + self.rotation_offset) % 360
+    }
+
+    // 在当前的旋转角度上再顺时针旋转90度；虚拟绘图板的X、Y轴范围保持不变，
+    // 旋转90、270度时原始的X、Y轴互相调换，并在apply_rotation中保持绘图板的宽高比
+    fn rotate_mapping(&mut self) {
+        self.rotation_offset = (self.rotation_offset + 90) % 360;
+        // 旋转前的平滑坐标与微调的锚点已经没有意义
+        self.stylus.smoothed = None;
+        if let Some(fine_adjust) = &mut self.fine_adjust {
+            fine_adjust.x.lift();
+            fine_adjust.y.lift();
+        }
+        let rotation = self.rotation();
+        info!("旋转角度已切换为{}", rotation);
+        self.publish(StateEvent::RotationChanged(rotation));
+    }
+
+    fn publish(&self, event: StateEvent) {
+        self.bus.publish(&self.conf, event);
+    }
+
+    // 当前生效的有效区域；优先级：set命令 > 切换到的区域预设 > 配置
+    fn area(&self) -> Option<&AreaConfig> {
+        if let Some(area) = &self.overrides.area {
+            return Some(area);
+        }
+        match self.area_index.and_then(|x| self.conf.area_presets.get(x)) {
+            Some(preset) => Some(&preset.area),
+            None => self.conf.area.as_ref(),
+        }
+    }
+
+    // 当前生效的X、Y轴比例映射；优先级：set命令 > 切换到的显示器 > 按键映射方案中的配置 > 全局配置
+    fn area_map(&self) -> AreaMap {
+        let (x_map, y_map) = if let Some(map) = self
+            .monitor_index
+            .and_then(|x| self.conf.monitor_maps.get(x))
+        {
+            (Some(map.x_map), Some(map.y_map))
+        } else {
+            match self.conf.keymaps.get(self.keymap_index) {
+                Some(keymap) => (
+                    keymap.x_map.or(self.conf.x_map),
+                    keymap.y_map.or(self.conf.y_map),
+                ),
+                None => (self.conf.x_map, self.conf.y_map),
+            }
+        };
+        (
+            self.overrides.x_map.or(x_map),
+            self.overrides.y_map.or(y_map),
+        )
+    }
+
+    // 当前生效的X、Y轴有效区域，单位为设备坐标
+    fn area_range(&self) -> AreaMap {
+        let Some(area) = self.area() else {
+            return (None, None);
+        };
+        let to_raw = |range: Option<(f32, f32)>, resolution: i32| {
+            let (min, max) = range?;
+            match area.unit {
+                AreaUnit::Raw => Some((min, max)),
+                AreaUnit::Millimeter if resolution > 0 => {
+                    Some((min * resolution as f32, max * resolution as f32))
+                }
+                AreaUnit::Millimeter => None,
+            }
+        };
+        (
+            to_raw(area.x, self.conf.x_resolution),
+            to_raw(area.y, self.conf.y_resolution),
+        )
+    }
+
+    fn should_ignore_outside_area(&self, x: u16, y: u16) -> bool {
+        match self.area() {
+            Some(area) if area.outside == OutsideAreaBehavior::Ignore => {}
+            _ => return false,
+        }
+        let (x_range, y_range) = self.area_range();
+        let outside = |value: f32, range: Option<(f32, f32)>| match range {
+            Some((min, max)) => value < min || value > max,
+            None => false,
+        };
+        // 有效区域的Y轴范围是从上往下的，而原始输入的Y轴是从下往上的
+        let y = self.conf.y_max_value as f32 - y as f32;
+        outside(x as f32, x_range) || outside(y, y_range)
+    }
+
+    fn current_pen_mode(&self) -> PenMode {
+        self.conf
+            .keymaps
+            .get(self.keymap_index)
+            .map(|x| x.pen_mode)
+            .unwrap_or_default()
+    }
+
+    fn current_tip_mode(&self) -> TipMode {
+        self.conf
+            .keymaps
+            .get(self.keymap_index)
+            .map(|x| x.tip_mode)
+            .unwrap_or_default()
+    }
+
+    fn enter_fine_adjust(&mut self) {
+        if self.fine_adjust.is_none() {
+            debug!("虚拟绘图板 - 进入微调模式");
+            self.fine_adjust.replace(FineAdjustStatus::default());
+            self.publish(StateEvent::FineAdjust(true));
         }
     }
 
     fn write_keyboard_event(&self, code: EventCode, value: i32) -> Result<()> {
-        let dummy_timeval = TimeVal::new(0, 0);
-        self.keyboard_uinput
-            .write_event(&InputEvent::new(&dummy_timeval, &code, value))
-            .context("UInputDevice::write_event(keyboard)")
+        diagnostics::record_event(EventRecord::Output {
+            device: "keyboard",
+            code,
+            value,
+        });
+        self.stats.borrow_mut().events += 1;
+        self.keyboard_sink
+            .as_ref()
+            .context("虚拟键盘尚未创建")?
+            .write_event(code, value)
+            .context("OutputSink::write_event(keyboard)")
+    }
+
+    fn write_pad_event(&self, code: EventCode, value: i32) -> Result<()> {
+        diagnostics::record_event(EventRecord::Output {
+            device: "pad",
+            code,
+            value,
+        });
+        self.stats.borrow_mut().events += 1;
+        self.pad_sink
+            .as_ref()
+            .context("虚拟Pad设备尚未创建")?
+            .write_event(code, value)
+            .context("OutputSink::write_event(pad)")
+    }
+
+    fn write_mouse_event(&self, code: EventCode, value: i32) -> Result<()> {
+        diagnostics::record_event(EventRecord::Output {
+            device: "mouse",
+            code,
+            value,
+        });
+        self.stats.borrow_mut().events += 1;
+        self.mouse_sink
+            .as_ref()
+            .context("虚拟鼠标尚未创建")?
+            .write_event(code, value)
+            .context("OutputSink::write_event(mouse)")
+    }
+
+    // 固件的工作模式切换后，坐标范围可能与设备描述符中的最大值不一致，导致只能映射到屏幕的一部分；
+    // 观测到超出最大值的坐标时输出警告，并按配置以观测到的最大值重新缩放
+    fn check_input_range(&mut self, x: u16, y: u16) -> (u16, u16) {
+        if self.conf.mode_detection == ModeDetection::Off {
+            return (x, y);
+        }
+        let range = &mut self.input_range;
+        if x > range.x_max || y > range.y_max {
+            range.x_max = range.x_max.max(x);
+            range.y_max = range.y_max.max(y);
+            if !range.warned {
+                range.warned = true;
+                warn!(
+                    "收到的坐标({},{})超出了最大值({},{})，设备可能工作在其他模式下（例如PC模式）；{}",
+                    x,
+                    y,
+                    self.conf.x_max_value,
+                    self.conf.y_max_value,
+                    match self.conf.mode_detection {
+                        ModeDetection::Rescale => "将按观测到的最大值重新缩放坐标轴",
+                        _ => "可尝试配置xMaxValue、yMaxValue或modeDetection = \"rescale\"",
+                    }
+                );
+            } else {
+                debug!("观测到的坐标最大值更新为({},{})", range.x_max, range.y_max);
+            }
+        }
+        if self.conf.mode_detection != ModeDetection::Rescale {
+            return (x, y);
+        }
+        let rescale = |value: u16, input_max: u16, max_value: u16| {
+            if input_max <= max_value {
+                return value;
+            }
+            (value as f32 * max_value as f32 / input_max as f32).round() as u16
+        };
+        (
+            rescale(x, range.x_max, self.conf.x_max_value),
+            rescale(y, range.y_max, self.conf.y_max_value),
+        )
+    }
+
+    // 对坐标进行指数移动平均；画笔离开感应范围时重置，避免重新进入时从上次的位置拖尾
+    fn smooth(&mut self, x: u16, y: u16, in_proximity: bool) -> (u16, u16) {
+        let smoothing = self.smoothing();
+        if !in_proximity || smoothing <= 0f32 {
+            self.stylus.smoothed = None;
+            return (x, y);
+        }
+        let (x, y) = (x as f32, y as f32);
+        let (sx, sy) = match self.stylus.smoothed {
+            Some((sx, sy)) => (
+                sx + (x - sx) * (1f32 - smoothing),
+                sy + (y - sy) * (1f32 - smoothing),
+            ),
+            None => (x, y),
+        };
+        self.stylus.smoothed = Some((sx, sy));
+        (sx.round() as u16, sy.round() as u16)
     }
 
     fn handle_digitizer_event(&mut self, buf: &[u8]) -> Result<()> {
@@ -279,6 +2311,7 @@ impl DriverTask {
             0xc0 => false,
             _ => {
                 warn!("收到了未知的绘图板事件：{:02x?}", buf);
+                self.stats.borrow_mut().ignored_reports += 1;
                 return Ok(());
             }
         };
@@ -287,6 +2320,18 @@ impl DriverTask {
         let stylus1_pressed = buf[0] & (0x01 << 2) != 0;
         let y = u16::from_le_bytes([buf[1], buf[2]]); // 调换原始输入的X、Y坐标
         let x = u16::from_le_bytes([buf[3], buf[4]]);
+        let (x, y) = self.check_input_range(x, y);
+        let (x, y) = apply_rotation(
+            x,
+            y,
+            self.rotation(),
+            self.conf.x_max_value,
+            self.conf.y_max_value,
+            self.aspect_ratio(),
+        );
+        let (x, y) = self.smooth(x, y, stylus_in_area);
+        self.stylus.last_report = Some(Instant::now());
+        self.stylus.in_proximity = stylus_in_area;
         let pressure = u16::from_le_bytes([buf[5], buf[6]]);
         let x_tilt = i8::from_le_bytes([buf[7]]);
         let y_tilt = i8::from_le_bytes([buf[8]]);
@@ -302,6 +2347,53 @@ impl DriverTask {
             x_tilt,
             y_tilt
         );
+        if !self.monitor.is_empty() {
+            self.monitor.send(json_object! {
+                "tablet" => self.tablet_index,
+                "type" => "pen",
+                "inArea" => stylus_in_area,
+                "touching" => stylus_touching,
+                "button0" => stylus0_pressed,
+                "button1" => stylus1_pressed,
+                "x" => x as usize,
+                "y" => y as usize,
+                "pressure" => pressure as usize,
+                "tiltX" => x_tilt as f32,
+                "tiltY" => y_tilt as f32,
+            });
+        }
+
+        let (touching, stylus0_pressed, stylus1_pressed) =
+            self.apply_palm_block(stylus_touching, stylus0_pressed, stylus1_pressed);
+        // 被忽略的落笔同样不上报压力
+        let pressure = match touching == stylus_touching {
+            true => pressure,
+            false => 0,
+        };
+        let stylus_touching = touching;
+
+        if stylus_touching && !self.stylus.tip_pressed {
+            self.calibrate(x, y)?;
+        }
+        if self.calibration.is_none() {
+            self.update_dwell(stylus_in_area && !stylus_touching, x, y)?;
+        }
+
+        if self.calibration.is_none() && self.current_pen_mode() == PenMode::Relative {
+            return self.handle_relative_stylus(
+                stylus_in_area,
+                stylus_touching,
+                stylus0_pressed,
+                stylus1_pressed,
+                x,
+                y,
+            );
+        }
+        // 从相对模式切换回绝对模式时，释放虚拟鼠标上按下的按键
+        self.release_relative()?;
+
+        // 画笔在有效区域外时，视为离开感应区域
+        let stylus_in_area = stylus_in_area && !self.should_ignore_outside_area(x, y);
 
         // 进入/离开感应区域
         {
@@ -309,6 +2401,10 @@ impl DriverTask {
                 if !self.stylus.in_area {
                     debug!("虚拟绘图板 - 笔尖进入感应区域");
                     self.stylus.in_area = true;
+                    if let Some(fine_adjust) = &mut self.fine_adjust {
+                        fine_adjust.x.lift();
+                        fine_adjust.y.lift();
+                    }
                     self.write_digitizer_event(EventCode::EV_KEY(EV_KEY::BTN_TOOL_PEN), 1)?;
                     {
                         self.write_digitizer_x(x, true)?;
@@ -368,9 +2464,19 @@ impl DriverTask {
         } else {
             report |= self.write_digitizer_button1_released()?;
         }
-        report |= self.write_digitizer_tip_pressure(pressure, false)?;
+        if self.stylus.tip_mode == TipMode::Touch {
+            let pressure = match self.pressure_curve() {
+                Some(curve) => apply_pressure_curve(pressure, curve, self.conf.pressure_max_value),
+                None => pressure,
+            };
+            report |= self.write_digitizer_tip_pressure(pressure, false)?;
+        }
         report |= self.write_digitizer_x(x, false)?;
         report |= self.write_digitizer_y(y, false)?;
+        let (x_tilt, y_tilt) = match self.tilt_enabled {
+            true => (x_tilt, y_tilt),
+            false => (0, 0),
+        };
         report |= self.write_digitizer_tilt_x(x_tilt)?;
         report |= self.write_digitizer_tilt_y(y_tilt)?;
         if report {
@@ -379,21 +2485,288 @@ impl DriverTask {
         Ok(())
     }
 
+    // 配置了palmBlock时，按键或转环操作后的一段时间内忽略新的落笔与笔侧键按下（相对模式下为鼠标按键），
+    // 悬停与移动照常上报；已经按下的保持按下，不会打断当前的笔画
+    fn apply_palm_block(
+        &self,
+        touching: bool,
+        button0_pressed: bool,
+        button1_pressed: bool,
+    ) -> (bool, bool, bool) {
+        let blocked = self
+            .conf
+            .palm_block
+            .zip(self.last_pad_input)
+            .is_some_and(|(window, last)| last.elapsed() < window);
+        if !blocked {
+            return (touching, button0_pressed, button1_pressed);
+        }
+        let held =
+            |code: EV_KEY, pressed: bool| pressed || self.relative.pressed_buttons.contains(&code);
+        let result = (
+            touching && held(EV_KEY::BTN_LEFT, self.stylus.tip_pressed),
+            button0_pressed && held(EV_KEY::BTN_RIGHT, self.stylus.button0_pressed),
+            button1_pressed && held(EV_KEY::BTN_MIDDLE, self.stylus.button1_pressed),
+        );
+        if result != (touching, button0_pressed, button1_pressed) {
+            debug!("按键操作后不久，忽略画笔的按下");
+        }
+        result
+    }
+
+    fn update_dwell(&mut self, hovering: bool, x: u16, y: u16) -> Result<()> {
+        let Some(dwell_click) = &self.conf.dwell_click else {
+            return Ok(());
+        };
+        if !hovering {
+            self.dwell = DwellStatus::default();
+            return Ok(());
+        }
+        let radius = dwell_click.radius as f32;
+        let moved = self.dwell.anchor.is_none_or(|(anchor_x, anchor_y, _)| {
+            let dx = x as f32 - anchor_x as f32;
+            let dy = y as f32 - anchor_y as f32;
+            dx.hypot(dy) > radius
+        });
+        if moved {
+            self.dwell.anchor = Some((x, y, Instant::now()));
+            self.dwell.fired = false;
+        }
+        self.check_dwell()
+    }
+
+    // 距离悬停点击触发的剩余时间；未配置dwellClick、画笔没有悬停或本次已经触发时为None
+    fn dwell_remaining(&self) -> Option<Duration> {
+        let dwell_click = self.conf.dwell_click.as_ref()?;
+        let (_, _, since) = self.dwell.anchor?;
+        if self.dwell.fired {
+            return None;
+        }
+        Some(dwell_click.delay.saturating_sub(since.elapsed()))
+    }
+
+    fn check_dwell(&mut self) -> Result<()> {
+        if self.dwell_remaining() != Some(Duration::ZERO) {
+            return Ok(());
+        }
+        let Some(action) = self.conf.dwell_click.as_ref().map(|x| x.action.clone()) else {
+            return Ok(());
+        };
+        self.dwell.fired = true;
+        debug!("画笔保持不动，触发悬停点击");
+        self.record_action("dwellClick");
+        match action {
+            DwellAction::Click(code) => {
+                for value in [1, 0] {
+                    self.write_mouse_event(EventCode::EV_KEY(code), value)?;
+                    self.write_mouse_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+                }
+            }
+            DwellAction::Press(codes) => {
+                for code in codes.iter() {
+                    self.write_keyboard_event(EventCode::EV_KEY(*code), 1)?;
+                }
+                self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+                for code in codes.iter().rev() {
+                    self.write_keyboard_event(EventCode::EV_KEY(*code), 0)?;
+                }
+                self.write_keyboard_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 相对模式下的画笔事件：笔尖对应鼠标左键，笔侧键依次对应右键与中键；不使用有效区域与映射区域
+    fn handle_relative_stylus(
+        &mut self,
+        in_area: bool,
+        touching: bool,
+        button0_pressed: bool,
+        button1_pressed: bool,
+        x: u16,
+        y: u16,
+    ) -> Result<()> {
+        // 从绝对模式切换到相对模式时，让虚拟绘图板上的画笔离开感应区域
+        self.lift_stylus()?;
+        if !in_area {
+            return self.release_relative();
+        }
+
+        let mut report = false;
+        let mut speed = self.conf.relative_speed;
+        if self.fine_adjust.is_some() {
+            speed *= self.conf.fine_adjust_gain;
+        }
+        if let Some((last_x, last_y)) = self.relative.last {
+            let dx = (x as f32 - last_x as f32) * speed + self.relative.remainder.0;
+            let dy = (y as f32 - last_y as f32) * speed + self.relative.remainder.1;
+            self.relative.remainder = (dx.fract(), dy.fract());
+            if dx.trunc() != 0f32 {
+                self.write_mouse_event(EventCode::EV_REL(EV_REL::REL_X), dx.trunc() as i32)?;
+                report = true;
+            }
+            if dy.trunc() != 0f32 {
+                self.write_mouse_event(EventCode::EV_REL(EV_REL::REL_Y), dy.trunc() as i32)?;
+                report = true;
+            }
+        }
+        self.relative.last = Some((x, y));
+
+        for (pressed, code) in [
+            (touching, EV_KEY::BTN_LEFT),
+            (button0_pressed, EV_KEY::BTN_RIGHT),
+            (button1_pressed, EV_KEY::BTN_MIDDLE),
+        ] {
+            let was_pressed = self.relative.pressed_buttons.contains(&code);
+            if pressed == was_pressed {
+                continue;
+            }
+            debug!(
+                "虚拟鼠标 - {}{:?}",
+                if pressed { "按下" } else { "释放" },
+                code
+            );
+            if pressed && code == EV_KEY::BTN_LEFT {
+                self.apply_latch()?;
+            }
+            self.write_mouse_event(EventCode::EV_KEY(code), pressed as i32)?;
+            if pressed {
+                self.relative.pressed_buttons.push(code);
+            } else {
+                self.relative.pressed_buttons.retain(|x| *x != code);
+            }
+            report = true;
+        }
+        if report {
+            self.write_mouse_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+        }
+        Ok(())
+    }
+
+    // 画笔离开感应区域（或退出相对模式）时，释放虚拟鼠标上按下的按键
+    fn release_relative(&mut self) -> Result<()> {
+        self.relative.last = None;
+        self.relative.remainder = (0f32, 0f32);
+        if self.relative.pressed_buttons.is_empty() {
+            return Ok(());
+        }
+        for code in std::mem::take(&mut self.relative.pressed_buttons) {
+            debug!("虚拟鼠标 - 释放{:?}", code);
+            self.write_mouse_event(EventCode::EV_KEY(code), 0)?;
+        }
+        self.write_mouse_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)
+    }
+
+    fn calibrate(&mut self, x: u16, y: u16) -> Result<()> {
+        let Some(calibration) = &mut self.calibration else {
+            return Ok(());
+        };
+        let x = std::cmp::min(x, self.conf.x_max_value);
+        let y = std::cmp::min(y, self.conf.y_max_value);
+        calibration.points.push((x, y));
+        info!("校准模式：已记录第{}个点", calibration.points.len());
+        if calibration.points.len() < 4 {
+            return Ok(());
+        }
+
+        // 原始输入的Y轴是从下往上的，而yMap也是作用于翻转前的Y轴，因此可直接使用原始输入的比例
+        let ratio = |value: u16, max_value: u16| value as f32 / max_value.max(1) as f32;
+        let x_map = (
+            ratio(
+                calibration.points.iter().map(|p| p.0).min().unwrap_or(0),
+                self.conf.x_max_value,
+            ),
+            ratio(
+                calibration.points.iter().map(|p| p.0).max().unwrap_or(0),
+                self.conf.x_max_value,
+            ),
+        );
+        let y_map = (
+            ratio(
+                calibration.points.iter().map(|p| p.1).min().unwrap_or(0),
+                self.conf.y_max_value,
+            ),
+            ratio(
+                calibration.points.iter().map(|p| p.1).max().unwrap_or(0),
+                self.conf.y_max_value,
+            ),
+        );
+        if x_map.0 >= x_map.1 || y_map.0 >= y_map.1 {
+            warn!("校准模式：点击的区域过小，请重新点击四个角");
+            calibration.points.clear();
+            return Ok(());
+        }
+        config::save_maps(&calibration.conf_path, x_map, y_map)
+            .context("无法将校准结果写回配置文件")?;
+        info!(
+            "校准完成：xMap = [{:.4}, {:.4}]，yMap = [{:.4}, {:.4}]，已写回配置文件",
+            x_map.0, x_map.1, y_map.0, y_map.1
+        );
+        self.cancel_token.cancel();
+        Ok(())
+    }
+
     fn write_digitizer_event(&self, code: EventCode, value: i32) -> Result<()> {
-        let dummy_timeval = TimeVal::new(0, 0);
-        self.digitizer_uinput
-            .write_event(&InputEvent::new(&dummy_timeval, &code, value))
-            .context("UInputDevice::write_event(digitizer)")
+        // libusb读取的报告不带时间戳，且uinput会以写入时的时间作为事件时间；
+        // 因此在每组事件末尾附加报告的接收时间（微秒，允许回绕），供按速度平滑的程序使用
+        if code == EventCode::EV_SYN(EV_SYN::SYN_REPORT) {
+            let received_at = self.report_received_at.unwrap_or_else(Instant::now);
+            let timestamp = received_at
+                .saturating_duration_since(self.timestamp_origin)
+                .as_micros() as u32;
+            self.write_digitizer_event_unchecked(
+                EventCode::EV_MSC(EV_MSC::MSC_TIMESTAMP),
+                timestamp as i32,
+            )?;
+            self.write_digitizer_event_unchecked(code, value)?;
+            // 写入SYN_REPORT时整组事件才真正写入uinput
+            if self.conf.latency_stats
+                && let Some(received_at) = self.report_received_at
+            {
+                self.latency.borrow_mut().record(received_at.elapsed());
+            }
+            return Ok(());
+        }
+        self.write_digitizer_event_unchecked(code, value)
+    }
+
+    fn write_digitizer_event_unchecked(&self, code: EventCode, value: i32) -> Result<()> {
+        diagnostics::record_event(EventRecord::Output {
+            device: "digitizer",
+            code,
+            value,
+        });
+        self.stats.borrow_mut().events += 1;
+        self.digitizer_sink
+            .as_ref()
+            .context("虚拟绘图板尚未创建")?
+            .write_event(code, value)
+            .context("OutputSink::write_event(digitizer)")
     }
 
     fn write_digitizer_x(&mut self, x: u16, force: bool) -> Result<bool> {
-        let x = std::cmp::min(x, self.conf.x_max_value);
-        let x = match self.conf.x_map {
+        let x = match self.area_range().0 {
+            Some(range) => crop_to_area(x, range, self.conf.x_max_value),
+            None => std::cmp::min(x, self.conf.x_max_value),
+        };
+        let x = match self.conf.x_gamma {
+            Some(gamma) => apply_gamma(x, gamma, self.conf.x_max_value),
+            None => x,
+        };
+        let x = match self.area_map().0 {
             Some((min_ratio, max_ratio)) => (self.conf.x_max_value as f32 * min_ratio
                 + (x as f32 * (max_ratio - min_ratio)))
                 .round() as u16,
             None => x,
         };
+        let x = match &mut self.fine_adjust {
+            Some(fine_adjust) => {
+                fine_adjust
+                    .x
+                    .apply(x, self.conf.fine_adjust_gain, self.conf.x_max_value)
+            }
+            None => x,
+        };
         if !force && x == self.stylus.x {
             return Ok(false);
         }
@@ -404,13 +2777,32 @@ impl DriverTask {
     }
 
     fn write_digitizer_y(&mut self, y: u16, force: bool) -> Result<bool> {
-        let y = std::cmp::min(y, self.conf.y_max_value);
-        let y = match self.conf.y_map {
+        let y = match self.area_range().1 {
+            Some(range) => {
+                // 有效区域的Y轴范围是从上往下的，而原始输入的Y轴是从下往上的
+                let y = self.conf.y_max_value.saturating_sub(y);
+                self.conf.y_max_value - crop_to_area(y, range, self.conf.y_max_value)
+            }
+            None => std::cmp::min(y, self.conf.y_max_value),
+        };
+        let y = match self.conf.y_gamma {
+            Some(gamma) => apply_gamma(y, gamma, self.conf.y_max_value),
+            None => y,
+        };
+        let y = match self.area_map().1 {
             Some((min_ratio, max_ratio)) => (self.conf.y_max_value as f32 * min_ratio
                 + (y as f32 * (max_ratio - min_ratio)))
                 .round() as u16,
             None => y,
         };
+        let y = match &mut self.fine_adjust {
+            Some(fine_adjust) => {
+                fine_adjust
+                    .y
+                    .apply(y, self.conf.fine_adjust_gain, self.conf.y_max_value)
+            }
+            None => y,
+        };
         let y = self.conf.y_max_value - y; // 需要再翻转一次
         if !force && y == self.stylus.y {
             return Ok(false);
@@ -425,10 +2817,21 @@ impl DriverTask {
         if self.stylus.tip_pressed {
             return Ok(false);
         }
-        debug!("虚拟绘图板 - 笔尖按下");
+        self.apply_latch()?;
         self.stylus.tip_pressed = true;
-        self.write_digitizer_event(EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 1)?;
-        self.write_digitizer_tip_pressure(1, true)?;
+        self.stylus.tip_mode = self.current_tip_mode();
+        match self.stylus.tip_mode {
+            TipMode::Touch => {
+                debug!("虚拟绘图板 - 笔尖按下");
+                self.write_digitizer_event(EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 1)?;
+                self.write_digitizer_tip_pressure(1, true)?;
+            }
+            TipMode::LeftClick => {
+                debug!("虚拟鼠标 - 笔尖按下，按下BTN_LEFT");
+                self.write_mouse_event(EventCode::EV_KEY(EV_KEY::BTN_LEFT), 1)?;
+                self.write_mouse_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+            }
+        }
         Ok(true)
     }
 
@@ -436,10 +2839,19 @@ impl DriverTask {
         if !self.stylus.tip_pressed {
             return Ok(false);
         }
-        debug!("虚拟绘图板 - 笔尖释放");
         self.stylus.tip_pressed = false;
-        self.write_digitizer_event(EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 0)?;
-        self.write_digitizer_tip_pressure(0, true)?;
+        match self.stylus.tip_mode {
+            TipMode::Touch => {
+                debug!("虚拟绘图板 - 笔尖释放");
+                self.write_digitizer_event(EventCode::EV_KEY(EV_KEY::BTN_TOUCH), 0)?;
+                self.write_digitizer_tip_pressure(0, true)?;
+            }
+            TipMode::LeftClick => {
+                debug!("虚拟鼠标 - 笔尖释放，释放BTN_LEFT");
+                self.write_mouse_event(EventCode::EV_KEY(EV_KEY::BTN_LEFT), 0)?;
+                self.write_mouse_event(EventCode::EV_SYN(EV_SYN::SYN_REPORT), 0)?;
+            }
+        }
         Ok(true)
     }
 
@@ -514,34 +2926,208 @@ impl DriverTask {
     }
 }
 
-fn create_uinput_device(conf: &mut Config) -> Result<(UInputDevice, UInputDevice)> {
-    let evdev = open_evdev().context("open_evdev")?;
-    let digitizer = create_uninit_digitizer_from_evdev(conf, &evdev)
-        .context("create_uninit_digitizer_from_evdev")?;
-    let keyboard =
-        create_uninit_keyboard_from_evdev(&evdev).context("create_uninit_keyboard_from_evdev")?;
+// 驱动任务panic时的清理：正常退出时run已释放按键与画笔，这里只处理panic时的栈展开；
+// 之后USB句柄被销毁时重新挂载内核驱动，虚拟设备被销毁时由内核释放其余按下的按键
+impl Drop for DriverTask {
+    fn drop(&mut self) {
+        if !std::thread::panicking() {
+            return;
+        }
+        if let Err(e) = self.release_all() {
+            error!("驱动任务panic后无法释放按下的按键: {:?}", e);
+        }
+    }
+}
+
+// 将有效区域拉伸到整个坐标轴
+fn crop_to_area(value: u16, (min, max): (f32, f32), max_value: u16) -> u16 {
+    ((value as f32 - min) / (max - min) * max_value as f32)
+        .round()
+        .clamp(0f32, max_value as f32) as u16
+}
+
+// 以坐标轴中心为原点应用幂函数曲线，两侧对称，端点保持不变
+fn apply_gamma(value: u16, gamma: f32, max_value: u16) -> u16 {
+    if max_value == 0 {
+        return value;
+    }
+    let half = max_value as f32 / 2f32;
+    let offset = (value as f32 - half) / half;
+    let offset = offset.signum() * offset.abs().powf(gamma);
+    (half + offset * half).round().clamp(0f32, max_value as f32) as u16
+}
+
+// 按绘图板的旋转角度（顺时针）变换原始坐标；aspect为绘图板的宽高比。
+// 旋转90、270度时交换X、Y轴，虚拟绘图板的范围不变，旋转后的绘图板映射到其中央宽高比相同的区域（两侧或上下留空），
+// 避免图形被拉伸。原始输入的Y轴是从下往上的，因此先转换为从上往下的比例再旋转
+fn apply_rotation(
+    x: u16,
+    y: u16,
+    rotation: u16,
+    x_max_value: u16,
+    y_max_value: u16,
+    aspect: f32,
+) -> (u16, u16) {
+    if rotation == 0 || x_max_value == 0 || y_max_value == 0 {
+        return (x, y);
+    }
+    let u = (x as f32 / x_max_value as f32).clamp(0f32, 1f32);
+    let v = 1f32 - (y as f32 / y_max_value as f32).clamp(0f32, 1f32);
+    let (u, v) = match rotation {
+        90 => (1f32 - v, u),
+        180 => (1f32 - u, 1f32 - v),
+        270 => (v, 1f32 - u),
+        _ => (u, v),
+    };
+    // 旋转后的宽高比为1/aspect，在宽高比为aspect的范围内按比例缩小
+    let (u, v) = match rotation {
+        90 | 270 if aspect.is_normal() => {
+            let ratio = 1f32 / (aspect * aspect);
+            (
+                0.5 + (u - 0.5) * ratio.min(1f32),
+                0.5 + (v - 0.5) * (1f32 / ratio).min(1f32),
+            )
+        }
+        _ => (u, v),
+    };
+    (
+        (u * x_max_value as f32).round() as u16,
+        ((1f32 - v) * y_max_value as f32).round() as u16,
+    )
+}
+
+// 对归一化的压力应用幂函数曲线，0与最大值保持不变
+fn apply_pressure_curve(value: u16, curve: f32, max_value: u16) -> u16 {
+    if max_value == 0 {
+        return value;
+    }
+    let ratio = (value as f32 / max_value as f32).clamp(0f32, 1f32);
+    (ratio.powf(curve) * max_value as f32).round() as u16
+}
+
+// 绘图板、键盘、鼠标与Pad（仅在padMode为native时）
+type Devices<T> = (T, T, T, Option<T>);
+
+fn create_uinput_device(conf: &mut Config, tablet_index: usize) -> Result<Devices<UInputDevice>> {
+    let (digitizer, keyboard, mouse, pad) = create_uninit_devices(conf, tablet_index)?;
     let digitizer_uinput =
         UInputDevice::create_from_device(&digitizer).context("UInputDevice::create_from_device")?;
     let keyboard_uinput =
         UInputDevice::create_from_device(&keyboard).context("UInputDevice::create_from_device")?;
-    Ok((digitizer_uinput, keyboard_uinput))
+    let mouse_uinput =
+        UInputDevice::create_from_device(&mouse).context("UInputDevice::create_from_device")?;
+    let pad_uinput = match pad {
+        Some(pad) => Some(
+            UInputDevice::create_from_device(&pad).context("UInputDevice::create_from_device")?,
+        ),
+        None => None,
+    };
+    Ok((digitizer_uinput, keyboard_uinput, mouse_uinput, pad_uinput))
+}
+
+// 按事件设备生成各虚拟设备的描述，同时补全conf中未配置的坐标范围、分辨率与压力最大值；
+// 只有创建uinput设备时才需要相应的权限
+fn create_uninit_devices(conf: &mut Config, tablet_index: usize) -> Result<Devices<UninitDevice>> {
+    let evdev = open_evdev(&conf.device).context("open_evdev")?;
+    logger::set_field("DEVICE", Some(evdev.name().unwrap_or_default().to_string()));
+    diagnostics::set_device_info(format!(
+        "name={:?} bustype={:#06x} vendor_id={:#06x} product_id={:#06x} version={:#06x}",
+        evdev.name().unwrap_or_default(),
+        evdev.bustype(),
+        evdev.vendor_id(),
+        evdev.product_id(),
+        evdev.version(),
+    ));
+    let identity = DeviceIdentity::load_or_create(&evdev, tablet_index);
+    let digitizer = create_uninit_digitizer_from_evdev(conf, &evdev, &identity)
+        .context("create_uninit_digitizer_from_evdev")?;
+    let keyboard = create_uninit_keyboard_from_evdev(conf, &identity)
+        .context("create_uninit_keyboard_from_evdev")?;
+    let mouse = create_uninit_mouse(conf, &identity).context("create_uninit_mouse")?;
+    let pad = match conf.pad_mode {
+        PadMode::Native => Some(create_uninit_pad(conf, &identity).context("create_uninit_pad")?),
+        PadMode::Keymap => None,
+    };
+    Ok((digitizer, keyboard, mouse, pad))
+}
+
+// 按device配置查找绘图板对应的事件设备：优先使用path；其次按name匹配设备名称；
+// 均未配置时按设备描述中的名称匹配，找不到时再选择vendor_id、product_id（包括无线接收器、蓝牙的product_id）一致且支持压力轴的事件设备。
+// uinput创建的虚拟设备总是被跳过：本程序的虚拟设备名称与绘图板相近且复制了原始设备的vendor_id、product_id，
+// 重新连接时虚拟设备仍然存在，不跳过的话可能会读取到自己的输出
+pub fn open_evdev(device: &DeviceConfig) -> Result<EventDevice> {
+    let path = find_evdev(device)?;
+    EventDevice::new_from_path(&path).context(format!("无法打开事件设备{}", path.display()))
 }
 
-fn open_evdev() -> Result<EventDevice> {
+// 返回open_evdev将会打开的事件设备的路径
+pub fn find_evdev(device: &DeviceConfig) -> Result<PathBuf> {
+    if let Some(path) = &device.path {
+        return Ok(path.clone());
+    }
     let entries = std::fs::read_dir("/dev/input").context("无法读取目录/dev/input")?;
+    let mut fallback = None;
     for entry in entries {
         let entry = entry.context("无法读取目录/dev/input中的信息")?;
         let filename = entry.file_name();
-        if !filename.to_string_lossy().starts_with("event") {
+        if !filename.to_string_lossy().starts_with("event") || is_uinput_device(&filename) {
             continue;
         }
         let path = entry.path();
-        let device = EventDevice::new_from_path(&path).context("EventDevice::new_from_path")?;
-        if device.name().unwrap_or_default() == EVENT_DEVICE_NAME {
-            return Ok(device);
+        let evdev = EventDevice::new_from_path(&path).context("EventDevice::new_from_path")?;
+        let name = evdev.name().unwrap_or_default();
+        match &device.name {
+            Some(pattern) => {
+                if pattern.is_match(name) {
+                    return Ok(path);
+                }
+            }
+            None => {
+                if name == device.profile.name {
+                    return Ok(path);
+                }
+                if fallback.is_none()
+                    && (device
+                        .ids()
+                        .contains(&(evdev.vendor_id(), evdev.product_id()))
+                        || (evdev.vendor_id() == device.vendor_id
+                            && Some(evdev.product_id()) == device.bluetooth_product_id))
+                    && evdev.has(EventCode::EV_ABS(EV_ABS::ABS_PRESSURE))
+                {
+                    fallback = Some((path, evdev.name().unwrap_or_default().to_string()));
+                }
+            }
         }
     }
-    Err(anyhow!("找不到「Parblo Intangbo M」对应的EventDevice"))
+    if let Some((path, name)) = fallback {
+        info!(
+            "找不到名称为「{}」的事件设备，使用{:04x}:{:04x}对应的事件设备「{}」",
+            device.profile.name.trim(),
+            device.vendor_id,
+            device.product_id,
+            name
+        );
+        return Ok(path);
+    }
+    match &device.name {
+        Some(pattern) => Err(anyhow!(
+            "找不到名称匹配「{}」的EventDevice",
+            pattern.as_str()
+        )),
+        None => Err(anyhow!(
+            "找不到「{}」对应的EventDevice",
+            device.profile.name.trim()
+        )),
+    }
+}
+
+// uinput创建的设备位于/sys/devices/virtual/input下；通过蓝牙（uhid）连接的设备位于/sys/devices/virtual/misc下，不受影响
+fn is_uinput_device(filename: &OsStr) -> bool {
+    Path::new("/sys/class/input")
+        .join(filename)
+        .join("device")
+        .canonicalize()
+        .is_ok_and(|x| x.starts_with("/sys/devices/virtual/input"))
 }
 
 macro_rules! enable_key_code {
@@ -555,13 +3141,18 @@ macro_rules! enable_key_code {
 fn create_uninit_digitizer_from_evdev(
     conf: &mut Config,
     evdev: &EventDevice,
+    identity: &DeviceIdentity,
 ) -> Result<UninitDevice> {
     let ud = UninitDevice::new().context("UninitDevice::new")?;
-    ud.set_name(VIRTUAL_DIGITIZER_NAME);
-    ud.set_bustype(evdev.bustype());
-    ud.set_vendor_id(evdev.vendor_id());
-    ud.set_product_id(evdev.product_id());
-    ud.set_version(evdev.version());
+    ud.set_name(
+        conf.virtual_device_name
+            .as_deref()
+            .unwrap_or(VIRTUAL_DIGITIZER_NAME),
+    );
+    ud.set_bustype(identity.bustype);
+    ud.set_vendor_id(identity.vendor_id);
+    ud.set_product_id(identity.product_id);
+    ud.set_version(identity.version);
 
     macro_rules! read_abs_info {
         ($name:ident) => {{
@@ -584,12 +3175,60 @@ fn create_uninit_digitizer_from_evdev(
     } else {
         conf.x_max_value = abs_y.maximum as u16;
     }
-    let abs_pressure = read_abs_info!(ABS_PRESSURE);
+    if conf.device.profile.x_resolution > 0 {
+        abs_y.resolution = conf.device.profile.x_resolution as i32; // ABS_X与ABS_Y需要互相调换
+    }
+    if conf.device.profile.y_resolution > 0 {
+        abs_x.resolution = conf.device.profile.y_resolution as i32; // ABS_X与ABS_Y需要互相调换
+    }
+    if let Some(resolution) = conf.virtual_axes.x.resolution {
+        abs_y.resolution = resolution as i32; // ABS_X与ABS_Y需要互相调换
+    }
+    if let Some(resolution) = conf.virtual_axes.y.resolution {
+        abs_x.resolution = resolution as i32; // ABS_X与ABS_Y需要互相调换
+    }
+    conf.x_resolution = abs_y.resolution; // ABS_X与ABS_Y需要互相调换
+    conf.y_resolution = abs_x.resolution; // ABS_X与ABS_Y需要互相调换
+    // libinput依据坐标轴的分辨率计算绘图板的物理尺寸，缺少分辨率时可能不会将其识别为绘图板
+    if conf.x_resolution <= 0 || conf.y_resolution <= 0 {
+        warn!(
+            "设备没有提供坐标轴的分辨率，虚拟绘图板可能不会被识别为绘图板；可在设备描述中配置xResolution、yResolution"
+        );
+    }
+    if conf
+        .area
+        .iter()
+        .chain(conf.area_presets.iter().map(|x| &x.area))
+        .any(|x| x.unit == AreaUnit::Millimeter)
+        && (conf.x_resolution <= 0 || conf.y_resolution <= 0)
+    {
+        warn!("缺少坐标轴的分辨率，以毫米为单位的area配置将不会生效");
+    }
+    let mut abs_pressure = read_abs_info!(ABS_PRESSURE);
+    if conf.device.profile.pressure_max_value > 0 {
+        abs_pressure.maximum = conf.device.profile.pressure_max_value as i32;
+    }
+    if let Some(resolution) = conf.virtual_axes.pressure.resolution {
+        abs_pressure.resolution = resolution as i32;
+    }
+    if abs_pressure.maximum <= abs_pressure.minimum {
+        warn!(
+            "压力轴的范围[{}, {}]无效，应用程序可能无法使用压力；可在设备描述中配置pressureMaxValue",
+            abs_pressure.minimum, abs_pressure.maximum
+        );
+    } else {
+        conf.pressure_max_value = abs_pressure.maximum as u16;
+    }
     let abs_tilt_x = read_abs_info!(ABS_TILT_X);
     let abs_tilt_y = read_abs_info!(ABS_TILT_Y);
 
+    // fuzz、flat影响libinput的滞后与加速处理；未配置时为0
     macro_rules! copy_abs_info {
         ($dst:ident, $src:expr) => {
+            copy_abs_info!($dst, $src, Default::default())
+        };
+        ($dst:ident, $src:expr, $axis:expr) => {
+            let axis: config::AxisConfig = $axis;
             ud.enable_event_code(
                 &EventCode::EV_ABS(EV_ABS::$dst),
                 Some(evdev_rs::EnableCodeData::AbsInfo(AbsInfo {
@@ -597,8 +3236,8 @@ fn create_uninit_digitizer_from_evdev(
                     maximum: $src.maximum,
                     resolution: $src.resolution,
                     value: 0,
-                    fuzz: 0,
-                    flat: 0,
+                    fuzz: axis.fuzz as i32,
+                    flat: axis.flat as i32,
                 })),
             )
             .context(concat!(
@@ -608,58 +3247,233 @@ fn create_uninit_digitizer_from_evdev(
             ))?;
         };
     }
-    copy_abs_info!(ABS_X, &abs_y); // ABS_X与ABS_Y需要互相调换
-    copy_abs_info!(ABS_Y, &abs_x); // ABS_X与ABS_Y需要互相调换
-    copy_abs_info!(ABS_PRESSURE, &abs_pressure);
+    copy_abs_info!(ABS_X, &abs_y, conf.virtual_axes.x); // ABS_X与ABS_Y需要互相调换
+    copy_abs_info!(ABS_Y, &abs_x, conf.virtual_axes.y); // ABS_X与ABS_Y需要互相调换
+    copy_abs_info!(ABS_PRESSURE, &abs_pressure, conf.virtual_axes.pressure);
     copy_abs_info!(ABS_TILT_X, &abs_tilt_x);
     copy_abs_info!(ABS_TILT_Y, &abs_tilt_y);
 
     ud.enable_event_type(&EventType::EV_SYN)
         .context("UninitDevice::enable_event_type(EV_SYN)")?;
+    ud.enable_event_code(&EventCode::EV_MSC(EV_MSC::MSC_TIMESTAMP), None)
+        .context("UninitDevice::enable_event_code(MSC_TIMESTAMP)")?;
+    // 绘图板不带屏幕，画笔间接控制指针，因此声明INPUT_PROP_POINTER而不是INPUT_PROP_DIRECT
     ud.enable_property(&InputProp::INPUT_PROP_POINTER)
         .context("UninitDevice::enable_property(INPUT_PROP_POINTER)")?;
-    enable_key_code! { ud => BTN_TOOL_PEN, BTN_TOOL_RUBBER, BTN_TOUCH, BTN_STYLUS, BTN_STYLUS2 };
+    // 画笔没有橡皮擦端，不声明BTN_TOOL_RUBBER，避免应用程序显示不存在的橡皮擦工具
+    enable_key_code! { ud => BTN_TOOL_PEN, BTN_TOUCH, BTN_STYLUS, BTN_STYLUS2 };
     Ok(ud)
 }
 
-fn create_uninit_keyboard_from_evdev(evdev: &EventDevice) -> Result<UninitDevice> {
+fn create_uninit_keyboard_from_evdev(
+    conf: &Config,
+    identity: &DeviceIdentity,
+) -> Result<UninitDevice> {
     let ud = UninitDevice::new().context("UninitDevice::new")?;
-    ud.set_name(VIRTUAL_KEYBOARD_NAME);
-    ud.set_bustype(evdev.bustype());
-    ud.set_vendor_id(evdev.vendor_id());
-    ud.set_product_id(evdev.product_id());
-    ud.set_version(evdev.version());
+    ud.set_name(
+        conf.virtual_keyboard_name
+            .as_deref()
+            .unwrap_or(VIRTUAL_KEYBOARD_NAME),
+    );
+    ud.set_bustype(identity.bustype);
+    ud.set_vendor_id(identity.vendor_id);
+    ud.set_product_id(identity.product_id);
+    ud.set_version(identity.version);
 
     ud.enable_event_type(&EventType::EV_SYN)
         .context("UninitDevice::enable_event_type(EV_SYN)")?;
     ud.enable_event_type(&EventType::EV_REP)
         .context("UninitDevice::enable_event_type(EV_REP)")?;
-    enable_key_code! { ud =>
-        KEY_A, KEY_B, KEY_C, KEY_D, KEY_E, KEY_F, KEY_G, KEY_H, KEY_I, KEY_J, KEY_K, KEY_L, KEY_M,
-        KEY_N, KEY_O, KEY_P, KEY_Q, KEY_R, KEY_S, KEY_T, KEY_U, KEY_V, KEY_W, KEY_X, KEY_Y, KEY_Z,
+    for code in KEYBOARD_KEYS.iter().chain(&conf.press_codes()) {
+        ud.enable_event_code(&EventCode::EV_KEY(*code), None)
+            .with_context(|| format!("UninitDevice::enable_event_code({:?})", code))?;
+    }
+    Ok(ud)
+}
 
-        KEY_0, KEY_1, KEY_2, KEY_3, KEY_4, KEY_5, KEY_6, KEY_7, KEY_8, KEY_9,
+// 笔尖模式为leftClick时上报鼠标按键，画笔模式为relative时上报相对移动与鼠标按键，zoom时上报滚轮；
+// 相对移动与绝对坐标分别由不同的设备上报，使桌面环境对其使用普通的指针加速
+fn create_uninit_mouse(conf: &Config, identity: &DeviceIdentity) -> Result<UninitDevice> {
+    let ud = UninitDevice::new().context("UninitDevice::new")?;
+    ud.set_name(
+        conf.virtual_mouse_name
+            .as_deref()
+            .unwrap_or(VIRTUAL_MOUSE_NAME),
+    );
+    ud.set_bustype(identity.bustype);
+    ud.set_vendor_id(identity.vendor_id);
+    ud.set_product_id(identity.product_id);
+    ud.set_version(identity.version);
 
-        KEY_MINUS, KEY_EQUAL, KEY_BACKSLASH, KEY_GRAVE, KEY_LEFTBRACE, KEY_RIGHTBRACE,
-        KEY_SEMICOLON, KEY_APOSTROPHE, KEY_COMMA, KEY_DOT, KEY_SLASH,
+    ud.enable_event_type(&EventType::EV_SYN)
+        .context("UninitDevice::enable_event_type(EV_SYN)")?;
+    for code in [
+        EV_REL::REL_X,
+        EV_REL::REL_Y,
+        EV_REL::REL_WHEEL,
+        EV_REL::REL_WHEEL_HI_RES,
+    ] {
+        ud.enable_event_code(&EventCode::EV_REL(code), None)
+            .context(format!("UninitDevice::enable_event_code({:?})", code))?;
+    }
+    enable_key_code! { ud => BTN_LEFT, BTN_RIGHT, BTN_MIDDLE };
+    Ok(ud)
+}
 
-        KEY_ESC, KEY_TAB, KEY_BACKSPACE, KEY_SPACE, KEY_ENTER,
-        KEY_HOME, KEY_END, KEY_PAGEUP, KEY_PAGEDOWN, KEY_INSERT, KEY_DELETE,
+// padMode为native时上报按钮与转环的原始事件；udev按BTN_0与ABS_X、ABS_Y（且没有画笔按键）将其识别为绘图板的Pad，
+// 从而由GNOME、KDE的绘图板设置为按钮分配功能。ABS_X、ABS_Y不会被使用，只是为了满足识别条件
+fn create_uninit_pad(conf: &Config, identity: &DeviceIdentity) -> Result<UninitDevice> {
+    let ud = UninitDevice::new().context("UninitDevice::new")?;
+    ud.set_name(conf.virtual_pad_name.as_deref().unwrap_or(VIRTUAL_PAD_NAME));
+    ud.set_bustype(identity.bustype);
+    ud.set_vendor_id(identity.vendor_id);
+    ud.set_product_id(identity.product_id);
+    ud.set_version(identity.version);
 
-        KEY_LEFTCTRL, KEY_LEFTSHIFT, KEY_LEFTALT, KEY_LEFTMETA,
+    ud.enable_event_type(&EventType::EV_SYN)
+        .context("UninitDevice::enable_event_type(EV_SYN)")?;
+    let profile = &conf.device.profile;
+    let button_count = profile.buttons.len() + profile.ring_button.is_some() as usize;
+    if button_count > PAD_BUTTON_CODES.len() {
+        warn!(
+            "设备有{}个按钮，虚拟Pad设备最多支持{}个，多余的按钮将被忽略",
+            button_count,
+            PAD_BUTTON_CODES.len()
+        );
+    }
+    for code in PAD_BUTTON_CODES.iter().take(button_count.max(1)) {
+        ud.enable_event_code(&EventCode::EV_KEY(*code), None)
+            .context(format!("UninitDevice::enable_event_code({:?})", code))?;
+    }
+    let abs_info = |maximum| {
+        Some(evdev_rs::EnableCodeData::AbsInfo(AbsInfo {
+            minimum: 0,
+            maximum,
+            resolution: 0,
+            value: 0,
+            fuzz: 0,
+            flat: 0,
+        }))
     };
+    for code in [EV_ABS::ABS_X, EV_ABS::ABS_Y] {
+        ud.enable_event_code(&EventCode::EV_ABS(code), abs_info(1))
+            .context(format!("UninitDevice::enable_event_code({:?})", code))?;
+    }
+    if profile.ring_cw.is_some() || profile.ring_ccw.is_some() {
+        ud.enable_event_code(
+            &EventCode::EV_ABS(EV_ABS::ABS_WHEEL),
+            abs_info(PAD_RING_MAX),
+        )
+        .context("UninitDevice::enable_event_code(ABS_WHEEL)")?;
+    }
     Ok(ud)
 }
 
-fn open_usb_device_handle() -> Result<UsbDeviceHandle<rusb::GlobalContext>> {
-    let ctx = rusb::GlobalContext {};
-    let handle = ctx
-        .open_device_with_vid_pid(VENDOR_ID, PRODUCT_ID)
-        .context("UsbDeviceHandle::open_device_with_vid_pid")?;
+// 优先通过USB（有线或无线接收器）打开设备；找不到USB设备且配置了蓝牙的product_id时，再尝试蓝牙连接
+// 错误是否由权限不足引起，例如放弃root权限后打开设备节点或uinput
+fn permission_denied(e: &anyhow::Error) -> bool {
+    e.chain().any(|x| {
+        x.downcast_ref::<UsbError>() == Some(&UsbError::Access)
+            || x.downcast_ref::<std::io::Error>()
+                .is_some_and(|x| x.kind() == std::io::ErrorKind::PermissionDenied)
+    })
+}
+
+fn open_device_handle(device: &DeviceConfig) -> Result<DeviceHandle> {
+    let usb_error = match open_usb_device_handle(device) {
+        Ok(handle) => {
+            let dongle = device.dongle_product_id.is_some()
+                && handle
+                    .device()
+                    .device_descriptor()
+                    .is_ok_and(|x| Some(x.product_id()) == device.dongle_product_id);
+            if dongle {
+                info!("设备通过无线接收器连接");
+            }
+            return Ok(DeviceHandle::Usb { handle, dongle });
+        }
+        Err(e) => e.context("无法打开USB设备句柄"),
+    };
+    let Some(product_id) = device.bluetooth_product_id else {
+        return Err(usb_error);
+    };
+    match HidrawDevice::open_bluetooth(device.vendor_id, product_id) {
+        Ok(hidraw) => {
+            info!("设备通过蓝牙连接（{}）", hidraw.path().display());
+            Ok(DeviceHandle::Bluetooth(hidraw))
+        }
+        Err(e) => {
+            debug!("{:?}", usb_error);
+            Err(e.context("无法打开USB设备，也无法打开蓝牙HID设备"))
+        }
+    }
+}
+
+// 打开vendor_id、product_id（以及端口）匹配的USB设备并完成握手；存在多个匹配的设备时，
+// 跳过已被占用的设备（例如已由其他绘图板的驱动任务打开）
+pub fn open_usb_device_handle(
+    device: &DeviceConfig,
+) -> Result<UsbDeviceHandle<rusb::GlobalContext>> {
+    let devices = rusb::devices().context("rusb::devices")?;
+    let mut last_error = None;
+    for usb_device in devices.iter() {
+        let Ok(descriptor) = usb_device.device_descriptor() else {
+            continue;
+        };
+        if !device
+            .ids()
+            .contains(&(descriptor.vendor_id(), descriptor.product_id()))
+        {
+            continue;
+        }
+        if let Some(port) = &device.port
+            && &usb_port_path(&usb_device) != port
+        {
+            continue;
+        }
+        match open_and_handshake(&usb_device) {
+            Ok(handle) => return Ok(handle),
+            Err(e) => {
+                debug!(
+                    "无法打开端口{}上的USB设备: {:?}",
+                    usb_port_path(&usb_device),
+                    e
+                );
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        anyhow!(
+            "找不到{:04x}:{:04x}对应的USB设备",
+            device.vendor_id,
+            device.product_id
+        )
+    }))
+}
+
+// USB设备所在的端口，格式同sysfs中的设备名称，例如"1-2.3"
+pub fn usb_port_path<T: rusb::UsbContext>(usb_device: &rusb::Device<T>) -> String {
+    let ports: Vec<_> = usb_device
+        .port_numbers()
+        .unwrap_or_default()
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    format!("{}-{}", usb_device.bus_number(), ports.join("."))
+}
+
+fn open_and_handshake(
+    usb_device: &rusb::Device<rusb::GlobalContext>,
+) -> Result<UsbDeviceHandle<rusb::GlobalContext>> {
+    let handle = usb_device.open().context("rusb::Device::open")?;
 
-    if handle
-        .kernel_driver_active(INTERFACE_NUM)
-        .context("UsbDeviceHandle::kernel_driver_active")?
+    // 优先由libusb自动卸载内核驱动：句柄被销毁时（包括驱动任务panic时）释放接口并重新挂载内核驱动，
+    // 使原始设备恢复可用；不支持时手动卸载
+    if handle.set_auto_detach_kernel_driver(true).is_err()
+        && handle
+            .kernel_driver_active(INTERFACE_NUM)
+            .context("UsbDeviceHandle::kernel_driver_active")?
     {
         handle
             .detach_kernel_driver(INTERFACE_NUM)
@@ -693,3 +3507,258 @@ fn open_usb_device_handle() -> Result<UsbDeviceHandle<rusb::GlobalContext>> {
     }
     Ok(handle)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::{CaptureSink, CapturedEvents, MemorySource};
+
+    const CONFIG: &str = r#"
+xMaxValue = 16000
+yMaxValue = 10000
+
+[[keymap]]
+button0 = "ctrl+z"
+ring0 = "["
+ring1 = "]"
+"#;
+
+    // 以内存中的报告代替设备、以记录事件的输出代替虚拟设备的驱动任务
+    fn driver_task() -> (DriverTask, CapturedEvents) {
+        let mut conf = Config::parse(CONFIG, false).unwrap();
+        conf.pressure_max_value = 8191;
+        let mut task =
+            DriverTask::new(CancelToken::new(), 0, conf, None, None, None, None).unwrap();
+        let events = CapturedEvents::default();
+        task.digitizer_sink = Some(Box::new(CaptureSink("digitizer", events.clone())));
+        task.keyboard_sink = Some(Box::new(CaptureSink("keyboard", events.clone())));
+        task.mouse_sink = Some(Box::new(CaptureSink("mouse", events.clone())));
+        (task, events)
+    }
+
+    // 依次处理报告，返回期间写入的事件
+    fn feed(
+        task: &mut DriverTask,
+        events: &CapturedEvents,
+        reports: &[&[u8]],
+    ) -> Vec<(&'static str, EventCode, i32)> {
+        let reports = reports.iter().map(|x| x.to_vec()).collect();
+        task.handle = Some(Box::new(MemorySource::new(reports)));
+        task.read_and_handle_device_input().unwrap();
+        assert!(task.handle.as_ref().unwrap().finished());
+        std::mem::take(&mut *events.lock())
+    }
+
+    fn contains(
+        events: &[(&'static str, EventCode, i32)],
+        device: &str,
+        code: EventCode,
+        value: i32,
+    ) -> bool {
+        events
+            .iter()
+            .any(|x| x.0 == device && x.1 == code && x.2 == value)
+    }
+
+    fn key(code: EV_KEY) -> EventCode {
+        EventCode::EV_KEY(code)
+    }
+
+    // 画笔报告：状态位、Y坐标、X坐标（原始输入中X、Y坐标是调换的）、压力与倾斜
+    const HOVER: &[u8] = &[0x02, 0xa0, 0x88, 0x13, 0x40, 0x1f, 0x00, 0x00, 0x00, 0x00];
+    const TOUCH: &[u8] = &[0x02, 0xa1, 0x88, 0x13, 0x40, 0x1f, 0x00, 0x10, 0x00, 0x00];
+    const LEAVE: &[u8] = &[0x02, 0xc0, 0x88, 0x13, 0x40, 0x1f, 0x00, 0x00, 0x00, 0x00];
+    const RELEASE: &[u8] = &[0x02, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+
+    #[test]
+    fn rotation_keeps_aspect_ratio() {
+        // 16000x10000的绘图板旋转90度后为10000x16000，映射到虚拟绘图板中央宽度为10000*10000/16000的区域
+        let aspect = 1.6;
+        assert_eq!(
+            apply_rotation(0, 0, 90, 16000, 10000, aspect),
+            (4875, 10000)
+        );
+        assert_eq!(
+            apply_rotation(16000, 10000, 90, 16000, 10000, aspect),
+            (11125, 0)
+        );
+        assert_eq!(
+            apply_rotation(0, 10000, 270, 16000, 10000, aspect),
+            (4875, 0)
+        );
+        // 旋转180度时宽高比不变
+        assert_eq!(
+            apply_rotation(0, 0, 180, 16000, 10000, aspect),
+            (16000, 10000)
+        );
+    }
+
+    #[test]
+    fn short_reports_are_ignored() {
+        let (mut task, events) = driver_task();
+        let written = feed(
+            &mut task,
+            &events,
+            &[&[0x02], &[0x02, 0xa1, 0x88], &[0x02, 0xf0]],
+        );
+        assert!(written.is_empty());
+        assert_eq!(task.stats.borrow().ignored_reports, 3);
+    }
+
+    #[test]
+    fn replay_fixture() {
+        let reports = crate::replay::load(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/stroke.log"
+        ))
+        .unwrap();
+        let (task, events) = driver_task();
+        task.replay(reports).unwrap();
+        let written = std::mem::take(&mut *events.lock());
+        let pressures: Vec<_> = written
+            .iter()
+            .filter(|x| x.1 == EventCode::EV_ABS(EV_ABS::ABS_PRESSURE))
+            .map(|x| x.2)
+            .collect();
+        // 笔尖按下时先上报最小的压力，之后的报告再上报实际的压力
+        assert_eq!(pressures, [1, 0x1000, 0x1800, 0x0800, 0]);
+        let mut keys: Vec<_> = written
+            .iter()
+            .filter(|x| matches!(x.1, EventCode::EV_KEY(_)))
+            .map(|x| (x.0, x.1, x.2))
+            .collect();
+        assert_eq!(
+            keys.pop(),
+            Some(("digitizer", key(EV_KEY::BTN_TOOL_PEN), 0))
+        );
+        // 按下的键记录在集合中，组合键释放的先后顺序不固定
+        let released = keys.split_off(5);
+        assert_eq!(released.len(), 2);
+        assert!(released.contains(&("keyboard", key(EV_KEY::KEY_LEFTCTRL), 0)));
+        assert!(released.contains(&("keyboard", key(EV_KEY::KEY_Z), 0)));
+        assert_eq!(
+            keys,
+            [
+                ("digitizer", key(EV_KEY::BTN_TOOL_PEN), 1),
+                ("digitizer", key(EV_KEY::BTN_TOUCH), 1),
+                ("digitizer", key(EV_KEY::BTN_TOUCH), 0),
+                ("keyboard", key(EV_KEY::KEY_LEFTCTRL), 1),
+                ("keyboard", key(EV_KEY::KEY_Z), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn pen_enters_and_leaves_area() {
+        let (mut task, events) = driver_task();
+        let written = feed(&mut task, &events, &[HOVER]);
+        assert!(contains(
+            &written,
+            "digitizer",
+            key(EV_KEY::BTN_TOOL_PEN),
+            1
+        ));
+        assert!(
+            written
+                .iter()
+                .any(|x| x.1 == EventCode::EV_ABS(EV_ABS::ABS_X))
+        );
+        assert!(task.stylus.in_area);
+
+        let written = feed(&mut task, &events, &[LEAVE]);
+        assert!(contains(
+            &written,
+            "digitizer",
+            key(EV_KEY::BTN_TOOL_PEN),
+            0
+        ));
+        assert!(!task.stylus.in_area);
+    }
+
+    #[test]
+    fn tip_reports_touch_and_pressure() {
+        let (mut task, events) = driver_task();
+        feed(&mut task, &events, &[HOVER]);
+        let written = feed(&mut task, &events, &[TOUCH, TOUCH]);
+        assert!(contains(&written, "digitizer", key(EV_KEY::BTN_TOUCH), 1));
+        assert!(contains(
+            &written,
+            "digitizer",
+            EventCode::EV_ABS(EV_ABS::ABS_PRESSURE),
+            0x1000
+        ));
+
+        let written = feed(&mut task, &events, &[HOVER]);
+        assert!(contains(&written, "digitizer", key(EV_KEY::BTN_TOUCH), 0));
+        assert!(!task.stylus.tip_pressed);
+    }
+
+    #[test]
+    fn pad_button_presses_and_releases_keys() {
+        let (mut task, events) = driver_task();
+        let written = feed(
+            &mut task,
+            &events,
+            &[&[0x02, 0xf0, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]],
+        );
+        assert!(contains(&written, "keyboard", key(EV_KEY::KEY_LEFTCTRL), 1));
+        assert!(contains(&written, "keyboard", key(EV_KEY::KEY_Z), 1));
+
+        let written = feed(&mut task, &events, &[RELEASE]);
+        assert!(contains(&written, "keyboard", key(EV_KEY::KEY_LEFTCTRL), 0));
+        assert!(contains(&written, "keyboard", key(EV_KEY::KEY_Z), 0));
+        assert!(task.pressed_keys.is_empty());
+    }
+
+    #[test]
+    fn ring_maps_direction_to_keys() {
+        let (mut task, events) = driver_task();
+        let written = feed(
+            &mut task,
+            &events,
+            &[
+                &[0x02, 0xf0, 0x08, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+                RELEASE,
+            ],
+        );
+        assert!(contains(
+            &written,
+            "keyboard",
+            key(EV_KEY::KEY_RIGHTBRACE),
+            1
+        ));
+        assert!(contains(
+            &written,
+            "keyboard",
+            key(EV_KEY::KEY_RIGHTBRACE),
+            0
+        ));
+        assert!(!contains(
+            &written,
+            "keyboard",
+            key(EV_KEY::KEY_LEFTBRACE),
+            1
+        ));
+
+        let written = feed(
+            &mut task,
+            &events,
+            &[
+                &[0x02, 0xf0, 0x08, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+                RELEASE,
+            ],
+        );
+        assert!(contains(
+            &written,
+            "keyboard",
+            key(EV_KEY::KEY_LEFTBRACE),
+            1
+        ));
+        assert!(contains(
+            &written,
+            "keyboard",
+            key(EV_KEY::KEY_LEFTBRACE),
+            0
+        ));
+    }
+}
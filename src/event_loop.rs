@@ -0,0 +1,138 @@
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
+
+use crate::cancel::CancelToken;
+use crate::error;
+
+// 由事件循环驱动的任务：提供需要监视的文件描述符，并在其可读时处理
+pub trait EventHandler: Send {
+    fn fds(&self) -> Vec<BorrowedFd<'_>>;
+
+    // fds()中第index个文件描述符需要监视的事件
+    fn flags(&self, _index: usize) -> EpollFlags {
+        EpollFlags::EPOLLIN
+    }
+
+    // fds()中第index个文件描述符可读时调用；返回错误时该任务退出，不影响其他任务
+    fn handle(&mut self, index: usize) -> Result<()>;
+}
+
+// 只用于唤醒事件循环的文件描述符（例如空闲时等待的设备），由调用方在wait返回后自行处理
+pub struct Watch(pub Vec<(OwnedFd, EpollFlags)>);
+impl EventHandler for Watch {
+    fn fds(&self) -> Vec<BorrowedFd<'_>> {
+        self.0.iter().map(|(fd, _)| fd.as_fd()).collect()
+    }
+
+    fn flags(&self, index: usize) -> EpollFlags {
+        self.0[index].1
+    }
+
+    fn handle(&mut self, _: usize) -> Result<()> {
+        Ok(())
+    }
+}
+
+// 在同一个线程中通过一个epoll驱动多个任务（例如退出信号监视与配置文件监视），
+// 代替每个任务各自占用一个线程、一个epoll与一个用于取消的eventfd；取消时结束
+pub struct EventLoop {
+    cancel_token: CancelToken,
+    epoll: Epoll,
+    handlers: Vec<Option<(&'static str, Box<dyn EventHandler>)>>, // 已出错退出或已移除的任务为None
+}
+impl EventLoop {
+    const EPOLL_CANCEL_EVENT: u64 = u64::MAX;
+
+    pub fn new(cancel_token: CancelToken) -> Result<Self> {
+        let epoll = Epoll::new(EpollCreateFlags::all()).context("Epoll::new")?;
+        epoll
+            .add(
+                cancel_token.as_fd()?,
+                EpollEvent::new(EpollFlags::EPOLLIN, Self::EPOLL_CANCEL_EVENT),
+            )
+            .context("Epoll::add(CancelToken)")?;
+        Ok(Self {
+            cancel_token,
+            epoll,
+            handlers: Vec::new(),
+        })
+    }
+
+    // name用于在任务出错时输出日志，例如"配置文件监控任务"；返回的下标用于remove
+    pub fn add<H>(&mut self, name: &'static str, handler: H) -> Result<usize>
+    where
+        H: EventHandler + 'static,
+    {
+        let index = self
+            .handlers
+            .iter()
+            .position(|x| x.is_none())
+            .unwrap_or(self.handlers.len());
+        // epoll事件的数据：高32位为任务的下标，低32位为文件描述符在fds()中的下标
+        for (i, fd) in handler.fds().into_iter().enumerate() {
+            self.epoll
+                .add(
+                    fd,
+                    EpollEvent::new(handler.flags(i), (index as u64) << 32 | i as u64),
+                )
+                .context(format!("Epoll::add({})", name))?;
+        }
+        let handler: Box<dyn EventHandler> = Box::new(handler);
+        match self.handlers.get_mut(index) {
+            Some(slot) => *slot = Some((name, handler)),
+            None => self.handlers.push(Some((name, handler))),
+        }
+        Ok(index)
+    }
+
+    // 移除任务并停止监视其文件描述符；任务已出错退出时什么也不做
+    pub fn remove(&mut self, index: usize) {
+        if let Some(Some((_, handler))) = self.handlers.get_mut(index).map(Option::take) {
+            for fd in handler.fds() {
+                let _ = self.epoll.delete(fd);
+            }
+        }
+    }
+
+    // 等待并处理一轮事件，返回本轮处理了事件的任务的下标；超时或取消时返回的列表为空
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Vec<usize>> {
+        let timeout = match timeout {
+            Some(x) => EpollTimeout::try_from(x).unwrap_or(EpollTimeout::MAX),
+            None => EpollTimeout::NONE,
+        };
+        let mut events = [EpollEvent::empty(); 8];
+        let n = self
+            .epoll
+            .wait(&mut events, timeout)
+            .context("Epoll::wait")?;
+        let mut handled = Vec::with_capacity(n);
+        for event in &events[..n] {
+            if event.data() == Self::EPOLL_CANCEL_EVENT {
+                continue;
+            }
+            let index = (event.data() >> 32) as usize;
+            let Some((name, handler)) = &mut self.handlers[index] else {
+                continue;
+            };
+            if let Err(e) = handler.handle(event.data() as u32 as usize) {
+                error!("{}发生错误并退出: {:?}", name, e);
+                self.remove(index);
+                continue;
+            }
+            if !handled.contains(&index) {
+                handled.push(index);
+            }
+        }
+        Ok(handled)
+    }
+
+    pub fn run(mut self) -> Result<()> {
+        while !self.cancel_token.cancelled() {
+            self.wait(None)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::cancel::CancelToken;
+use crate::screen::{self, FocusedWindow};
+use crate::{debug, warn};
+
+type FocusChangeCallback = Box<dyn FnMut(FocusedWindow) + Send + Sync>;
+
+// 定期查询获得焦点的窗口，在其WM_CLASS或标题发生变化时通知驱动任务重新匹配自动切换规则
+pub struct FocusTrackTask {
+    cancel_token: CancelToken,
+    callbacks: Vec<FocusChangeCallback>,
+}
+impl FocusTrackTask {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new(cancel_token: CancelToken) -> Self {
+        Self {
+            cancel_token,
+            callbacks: Vec::new(),
+        }
+    }
+
+    pub fn register_callback<F>(&mut self, f: F)
+    where
+        F: FnMut(FocusedWindow) + Send + Sync + 'static,
+    {
+        self.callbacks.push(Box::new(f));
+    }
+
+    pub fn run(mut self) -> Result<()> {
+        // Wayland下总是无法查询，不必每隔POLL_INTERVAL醒来一次，只等待退出
+        if screen::is_wayland() {
+            warn!("Wayland下不支持查询获得焦点的窗口，自动切换规则中的窗口条件不会生效");
+            self.cancel_token.wait();
+            return Ok(());
+        }
+        let mut current: Option<FocusedWindow> = None;
+        let mut warned = false;
+        loop {
+            if self.cancel_token.wait_timeout(Self::POLL_INTERVAL) {
+                return Ok(());
+            }
+
+            // 查询失败（例如窗口恰好被关闭）时忽略本次查询；持续失败时只输出一次警告
+            let window = match screen::query_focused_window() {
+                Ok(x) => x,
+                Err(e) => {
+                    if !warned {
+                        warn!("无法查询获得焦点的窗口: {:?}", e);
+                        warned = true;
+                    }
+                    continue;
+                }
+            };
+            // 焦点移到桌面等没有WM_CLASS的窗口时保持当前的状态
+            let Some(window) = window else {
+                continue;
+            };
+            if current.as_ref() == Some(&window) {
+                continue;
+            }
+            debug!(
+                "获得焦点的窗口变为{}（{}）：{}",
+                window.instance, window.class, window.title
+            );
+            current.replace(window.clone());
+            for callback in &mut self.callbacks {
+                callback(window.clone());
+            }
+        }
+    }
+}
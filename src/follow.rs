@@ -0,0 +1,73 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::cancel::CancelToken;
+use crate::screen::{self, Monitor};
+use crate::warn;
+
+type MonitorChangeCallback = Box<dyn FnMut(String) + Send + Sync>;
+
+// 定期查询鼠标指针所在的显示器，在其发生变化时通知驱动任务重新映射绘图板
+pub struct FollowMonitorTask {
+    cancel_token: CancelToken,
+    callbacks: Vec<MonitorChangeCallback>,
+}
+impl FollowMonitorTask {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new(cancel_token: CancelToken) -> Self {
+        Self {
+            cancel_token,
+            callbacks: Vec::new(),
+        }
+    }
+
+    pub fn register_callback<F>(&mut self, f: F)
+    where
+        F: FnMut(String) + Send + Sync + 'static,
+    {
+        self.callbacks.push(Box::new(f));
+    }
+
+    pub fn run(mut self) -> Result<()> {
+        let mut monitors: Vec<Monitor> = vec![];
+        let mut current: Option<String> = None;
+        loop {
+            if self.cancel_token.wait_timeout(Self::POLL_INTERVAL) {
+                return Ok(());
+            }
+
+            let (x, y) = screen::query_pointer().context("无法查询鼠标指针的位置")?;
+            let mut found = find_monitor(&monitors, x, y);
+            if found.is_none() {
+                // 显示器布局可能发生了变化，重新查询
+                monitors = match screen::query_monitors() {
+                    Ok(x) => x,
+                    Err(e) => {
+                        warn!("无法查询显示器布局: {:?}", e);
+                        continue;
+                    }
+                };
+                found = find_monitor(&monitors, x, y);
+            }
+            let Some(monitor) = found else {
+                continue;
+            };
+            if current.as_deref() == Some(monitor.name.as_str()) {
+                continue;
+            }
+            current.replace(monitor.name.clone());
+            for callback in &mut self.callbacks {
+                callback(monitor.name.clone());
+            }
+        }
+    }
+}
+
+fn find_monitor(monitors: &[Monitor], x: i32, y: i32) -> Option<&Monitor> {
+    monitors.iter().find(|m| {
+        let g = &m.geometry;
+        (g.x..g.x + g.width as i32).contains(&x) && (g.y..g.y + g.height as i32).contains(&y)
+    })
+}
@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::epoll::EpollFlags;
+
+use crate::debug;
+use crate::event_loop::{EventLoop, Watch};
+
+// HID设备所在的总线类型，见linux/input.h
+const BUS_BLUETOOTH: u16 = 0x0005;
+
+// 通过hidraw读取蓝牙连接的绘图板的HID报告；报告的格式与USB中断端点上读到的一致（首字节为报告ID）
+pub struct HidrawDevice {
+    path: PathBuf,
+    file: File,
+}
+impl HidrawDevice {
+    pub fn open_bluetooth(vendor_id: u16, product_id: u16) -> Result<Self> {
+        let path = find_bluetooth(vendor_id, product_id)?;
+        let file = File::open(&path).context(format!("无法打开{}", path.display()))?;
+        debug!("已打开蓝牙HID设备{}", path.display());
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // 读取一个HID报告；超时时返回Ok(None)，连接断开时返回错误
+    pub fn read(&self, buf: &mut [u8], timeout: Duration) -> std::io::Result<Option<usize>> {
+        let timeout = PollTimeout::try_from(timeout).unwrap_or(PollTimeout::MAX);
+        let mut fds = [PollFd::new(self.file.as_fd(), PollFlags::POLLIN)];
+        if poll(&mut fds, timeout)? == 0 {
+            return Ok(None);
+        }
+        let revents = fds[0].revents().unwrap_or(PollFlags::empty());
+        if revents.intersects(PollFlags::POLLHUP | PollFlags::POLLERR) {
+            return Err(Errno::ENODEV.into());
+        }
+        (&self.file).read(buf).map(Some)
+    }
+
+    // 与read相同，但同时等待事件循环中的其他事件；事件循环处理了其他事件或超过timeout时返回Ok(None)
+    pub fn read_until(
+        &self,
+        buf: &mut [u8],
+        event_loop: &mut EventLoop,
+        timeout: Option<Duration>,
+    ) -> std::io::Result<Option<usize>> {
+        let fd = self.file.as_fd().try_clone_to_owned()?;
+        let index = event_loop
+            .add("蓝牙HID设备", Watch(vec![(fd, EpollFlags::EPOLLIN)]))
+            .map_err(std::io::Error::other)?;
+        let result = event_loop.wait(timeout);
+        event_loop.remove(index);
+        if !result.map_err(std::io::Error::other)?.contains(&index) {
+            return Ok(None);
+        }
+        self.read(buf, Duration::ZERO)
+    }
+}
+
+// 系统中的一个hidraw节点
+pub struct HidrawInfo {
+    pub path: PathBuf,
+    pub name: String,
+    pub bus: u16,
+    pub vendor_id: u16,
+    pub product_id: u16,
+}
+impl HidrawInfo {
+    pub fn is_bluetooth(&self) -> bool {
+        self.bus == BUS_BLUETOOTH
+    }
+}
+
+// 列出所有hidraw节点，按节点名称排序
+pub fn list() -> Result<Vec<HidrawInfo>> {
+    let entries = std::fs::read_dir("/sys/class/hidraw").context("无法读取/sys/class/hidraw")?;
+    let mut infos = Vec::new();
+    for entry in entries.flatten() {
+        let sys_path = entry.path();
+        let Some((bus, vendor_id, product_id)) = read_hid_id(&sys_path) else {
+            continue;
+        };
+        infos.push(HidrawInfo {
+            path: Path::new("/dev").join(entry.file_name()),
+            name: read_uevent(&sys_path, "HID_NAME").unwrap_or_default(),
+            bus,
+            vendor_id,
+            product_id,
+        });
+    }
+    infos.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(infos)
+}
+
+// 查找vendor_id、product_id匹配的蓝牙HID设备；设备存在多个hidraw节点时，
+// 选择报告描述符中声明了报告ID 0x02（画笔与按键事件）的节点
+pub fn find_bluetooth(vendor_id: u16, product_id: u16) -> Result<PathBuf> {
+    let mut candidates = Vec::new();
+    let entries = std::fs::read_dir("/sys/class/hidraw").context("无法读取/sys/class/hidraw")?;
+    for entry in entries.flatten() {
+        let sys_path = entry.path();
+        let Some(hid_id) = read_hid_id(&sys_path) else {
+            continue;
+        };
+        if hid_id != (BUS_BLUETOOTH, vendor_id, product_id) {
+            continue;
+        }
+        candidates.push((entry.file_name(), declares_report_id(&sys_path, 0x02)));
+    }
+    candidates.sort();
+    candidates
+        .iter()
+        .find(|(_, x)| *x)
+        .or(candidates.first())
+        .map(|(name, _)| Path::new("/dev").join(name))
+        .ok_or_else(|| {
+            anyhow!(
+                "找不到{:04x}:{:04x}对应的蓝牙HID设备",
+                vendor_id,
+                product_id
+            )
+        })
+}
+
+fn read_uevent(sys_path: &Path, key: &str) -> Option<String> {
+    let uevent = std::fs::read_to_string(sys_path.join("device/uevent")).ok()?;
+    uevent
+        .lines()
+        .find_map(|x| x.strip_prefix(key)?.strip_prefix('='))
+        .map(|x| x.to_string())
+}
+
+// 解析uevent中的HID_ID，格式为"BBBB:VVVVVVVV:PPPPPPPP"
+fn read_hid_id(sys_path: &Path) -> Option<(u16, u16, u16)> {
+    let value = read_uevent(sys_path, "HID_ID")?;
+    let mut parts = value.split(':').map(|x| u32::from_str_radix(x, 16).ok());
+    let bus = parts.next()??;
+    let vendor_id = parts.next()??;
+    let product_id = parts.next()??;
+    Some((bus as u16, vendor_id as u16, product_id as u16))
+}
+
+// 粗略检查报告描述符中是否包含Report ID项（0x85）声明的指定报告ID
+fn declares_report_id(sys_path: &Path, report_id: u8) -> bool {
+    std::fs::read(sys_path.join("device/report_descriptor"))
+        .map(|x| x.windows(2).any(|x| x == [0x85, report_id]))
+        .unwrap_or(false)
+}
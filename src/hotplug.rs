@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result, anyhow};
+use parking_lot::Mutex;
+use rusb::{Device as UsbDevice, HotplugBuilder, UsbContext};
+
+use crate::cancel::CancelToken;
+use crate::debug;
+
+#[derive(Clone, Copy, Debug)]
+pub enum HotplugEvent {
+    Arrived,
+    Left,
+}
+
+type HotplugCallback = Box<dyn FnMut(HotplugEvent) + Send + Sync>;
+
+// 通过libusb的热插拔通知（Linux下基于udev/netlink）监视绘图板的插入与拔出
+pub struct HotplugTask {
+    cancel_token: CancelToken,
+    context: rusb::Context,
+    device_ids: Vec<(u16, u16)>, // (vendor_id, product_id)
+    callbacks: Arc<Mutex<Vec<HotplugCallback>>>,
+}
+impl HotplugTask {
+    pub fn new(cancel_token: CancelToken, device_ids: Vec<(u16, u16)>) -> Result<Self> {
+        // 先创建上下文：libusb初始化失败时，rusb::has_hotplug会直接panic
+        let context = rusb::Context::new().context("rusb::Context::new")?;
+        if !rusb::has_hotplug() {
+            return Err(anyhow!("libusb不支持热插拔通知"));
+        }
+        Ok(Self {
+            cancel_token,
+            context,
+            device_ids,
+            callbacks: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    pub fn register_callback<F>(&mut self, f: F)
+    where
+        F: FnMut(HotplugEvent) + Send + Sync + 'static,
+    {
+        self.callbacks.lock().push(Box::new(f));
+    }
+
+    pub fn run(self) -> Result<()> {
+        // 注册在离开作用域时自动注销
+        let mut registrations = Vec::with_capacity(self.device_ids.len());
+        for (vendor_id, product_id) in &self.device_ids {
+            let mut builder = HotplugBuilder::new();
+            builder.vendor_id(*vendor_id).product_id(*product_id);
+            registrations.push(
+                builder
+                    .register(
+                        &self.context,
+                        Box::new(HotplugHandler {
+                            callbacks: self.callbacks.clone(),
+                        }),
+                    )
+                    .context("HotplugBuilder::register")?,
+            );
+        }
+        // 不设超时，避免空闲时定期醒来；取消时中断事件处理
+        let context = self.context.clone();
+        let id = self
+            .cancel_token
+            .register_callback(move || context.interrupt_handle_events());
+        let result = loop {
+            if self.cancel_token.cancelled() {
+                break Ok(());
+            }
+            match self.context.handle_events(None) {
+                Ok(()) | Err(rusb::Error::Interrupted) => {}
+                Err(e) => break Err(e).context("rusb::Context::handle_events"),
+            }
+        };
+        self.cancel_token.unregister_callback(id);
+        result
+    }
+}
+
+struct HotplugHandler {
+    callbacks: Arc<Mutex<Vec<HotplugCallback>>>,
+}
+impl HotplugHandler {
+    fn notify(&self, event: HotplugEvent) {
+        debug!("收到热插拔事件{:?}", event);
+        for callback in self.callbacks.lock().iter_mut() {
+            callback(event);
+        }
+    }
+}
+impl rusb::Hotplug<rusb::Context> for HotplugHandler {
+    fn device_arrived(&mut self, _device: UsbDevice<rusb::Context>) {
+        self.notify(HotplugEvent::Arrived);
+    }
+
+    fn device_left(&mut self, _device: UsbDevice<rusb::Context>) {
+        self.notify(HotplugEvent::Left);
+    }
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use evdev_rs::{Device as EventDevice, DeviceWrapper};
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+use crate::warn;
+
+// 虚拟设备的标识；首次运行时从原始设备复制并持久化，之后一直复用，
+// 使混成器、应用程序按设备保存的设置（速度、映射等）在重启驱动后依然有效。
+// 注意：libevdev创建uinput设备时不支持设置uniq，因此这里只记录bustype、vendor、product与version
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceIdentity {
+    pub bustype: u16,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub version: u16,
+}
+impl DeviceIdentity {
+    pub fn load_or_create(evdev: &EventDevice, tablet_index: usize) -> Self {
+        let path = paths::tablet_state_file("identity", tablet_index);
+        if let Some(identity) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|x| toml::from_str(&x).ok())
+        {
+            return identity;
+        }
+        let identity = Self {
+            bustype: evdev.bustype(),
+            vendor_id: evdev.vendor_id(),
+            product_id: evdev.product_id(),
+            version: evdev.version(),
+        };
+        if let Err(e) = identity.save(&path) {
+            warn!("无法保存虚拟设备的标识，下次运行时将重新生成: {:?}", e);
+        }
+        identity
+    }
+
+    fn save(&self, path: &PathBuf) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(format!("无法创建目录{}", parent.display()))?;
+        }
+        let content = toml::to_string(self).context("无法序列化虚拟设备的标识")?;
+        std::fs::write(path, content).context(format!("无法写入文件{}", path.display()))
+    }
+}
@@ -0,0 +1,108 @@
+#[cfg(test)]
+use std::cell::RefCell;
+#[cfg(test)]
+use std::collections::VecDeque;
+#[cfg(test)]
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use evdev_rs::enums::EventCode;
+#[cfg(test)]
+use parking_lot::Mutex;
+use rusb::Error as UsbError;
+
+use crate::event_loop::EventLoop;
+
+// 驱动任务读取原始报告的来源：与设备之间的连接，或回放的记录
+pub trait InputSource: Send {
+    // "usb"、"dongle"、"bluetooth"、"replay"或"memory"（测试）
+    fn kind(&self) -> &'static str;
+
+    // 无线连接时画笔离开感应范围可能不会上报离开事件
+    fn wireless(&self) -> bool {
+        false
+    }
+
+    // 读取一个输入报告；超时返回UsbError::Timeout，设备断开返回UsbError::NoDevice或UsbError::Io
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, UsbError>;
+
+    // 空闲时读取一个输入报告：不设超时，通过事件循环同时等待报告与其他事件（唤醒、取消等），
+    // 事件循环处理了其他事件或超过timeout时返回Ok(None)；默认退化为带超时的读取，用于回放与测试
+    fn read_idle(
+        &self,
+        buf: &mut [u8],
+        _event_loop: &mut EventLoop,
+        timeout: Option<Duration>,
+    ) -> Result<Option<usize>, UsbError> {
+        match self.read(buf, timeout.unwrap_or(Duration::MAX)) {
+            Ok(len) => Ok(Some(len)),
+            Err(UsbError::Timeout) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // 重新初始化设备前释放占用的资源
+    fn release(&self) {}
+
+    // 是否已没有更多的报告（例如回放的记录已全部读取）；设备连接总是返回false
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+// 驱动任务写入输出事件的目标：uinput虚拟设备，或--dry-run时的标准输出
+pub trait OutputSink: Send {
+    fn write_event(&self, code: EventCode, value: i32) -> Result<()>;
+}
+
+// 把将要写入的事件逐行打印到标准输出，例如"digitizer ABS_X 100"
+pub struct PrintSink(pub &'static str);
+impl OutputSink for PrintSink {
+    fn write_event(&self, code: EventCode, value: i32) -> Result<()> {
+        println!("{:<9} {} {}", self.0, code, value);
+        Ok(())
+    }
+}
+
+// 测试用：按顺序立即返回给定的报告，全部读取后返回超时
+#[cfg(test)]
+pub struct MemorySource(RefCell<VecDeque<Vec<u8>>>);
+#[cfg(test)]
+impl MemorySource {
+    pub fn new(reports: Vec<Vec<u8>>) -> Self {
+        Self(RefCell::new(reports.into()))
+    }
+}
+#[cfg(test)]
+impl InputSource for MemorySource {
+    fn kind(&self) -> &'static str {
+        "memory"
+    }
+
+    fn read(&self, buf: &mut [u8], _: Duration) -> Result<usize, UsbError> {
+        let report = self.0.borrow_mut().pop_front().ok_or(UsbError::Timeout)?;
+        let len = report.len().min(buf.len());
+        buf[..len].copy_from_slice(&report[..len]);
+        Ok(len)
+    }
+
+    fn finished(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+}
+
+// 测试用：写入的事件，例如("digitizer", ABS_X, 100)
+#[cfg(test)]
+pub type CapturedEvents = Arc<Mutex<Vec<(&'static str, EventCode, i32)>>>;
+
+// 测试用：把写入的事件记录到共享的列表中；各虚拟设备共用一个列表，保留事件之间的先后顺序
+#[cfg(test)]
+pub struct CaptureSink(pub &'static str, pub CapturedEvents);
+#[cfg(test)]
+impl OutputSink for CaptureSink {
+    fn write_event(&self, code: EventCode, value: i32) -> Result<()> {
+        self.1.lock().push((self.0, code, value));
+        Ok(())
+    }
+}
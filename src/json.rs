@@ -0,0 +1,449 @@
+use std::fmt::{Display, Formatter, Write};
+
+use anyhow::{Result, anyhow};
+
+// 控制协议使用的最小JSON实现：仅支持解析与输出单行的JSON值，对象保留字段的顺序
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+impl Value {
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            chars: s.chars().collect(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(anyhow!("JSON值之后存在多余的内容"));
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Self::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::Number(n) if *n >= 0f64 && n.fract() == 0f64 && *n <= u64::MAX as f64 => {
+                Some(*n as u64)
+            }
+            _ => None,
+        }
+    }
+}
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Self::Number(value as f64)
+    }
+}
+impl From<f32> for Value {
+    // 按f32的最短表示转换，避免输出1.2000000476837158这样的值
+    fn from(value: f32) -> Self {
+        Self::Number(value.to_string().parse().unwrap_or(f64::NAN))
+    }
+}
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Self::Null)
+    }
+}
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{}", b),
+            Self::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Self::Number(_) => f.write_str("null"),
+            Self::String(s) => write_string(f, s),
+            Self::Array(values) => {
+                f.write_char('[')?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            Self::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, s: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+// 构造JSON对象，字段按书写顺序输出
+#[macro_export]
+macro_rules! json_object {
+    ($($key:expr => $value:expr),* $(,)?) => {
+        $crate::json::Value::Object(vec![
+            $(($key.to_string(), $crate::json::Value::from($value))),*
+        ])
+    };
+}
+
+// 数组与对象的最大嵌套层数，避免恶意的输入耗尽栈空间
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|x| matches!(x, ' ' | '\t' | '\n' | '\r'))
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char> {
+        let c = *self.chars.get(self.pos).ok_or(anyhow!("JSON不完整"))?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, s: &str) -> Result<()> {
+        for expected in s.chars() {
+            if self.next()? != expected {
+                return Err(anyhow!("JSON第{}个字符处应为'{}'", self.pos, s));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            Some('n') => self.expect("null").map(|_| Value::Null),
+            Some('t') => self.expect("true").map(|_| Value::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some('"') => self.parse_string().map(Value::String),
+            Some('[') | Some('{') => {
+                if self.depth >= MAX_DEPTH {
+                    return Err(anyhow!("JSON的嵌套层数超过{}", MAX_DEPTH));
+                }
+                self.depth += 1;
+                let value = if self.chars[self.pos] == '[' {
+                    self.parse_array()
+                } else {
+                    self.parse_object()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) => Err(anyhow!("JSON第{}个字符'{}'无效", self.pos + 1, c)),
+            None => Err(anyhow!("JSON不完整")),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        while self
+            .chars
+            .get(self.pos)
+            .is_some_and(|x| matches!(x, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+        {
+            self.pos += 1;
+        }
+        let s: String = self.chars[start..self.pos].iter().collect();
+        s.parse()
+            .map(Value::Number)
+            .map_err(|_| anyhow!("'{}'不是有效的JSON数字", s))
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => match self.next()? {
+                    '"' => s.push('"'),
+                    '\\' => s.push('\\'),
+                    '/' => s.push('/'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => {
+                        let mut code = self.parse_hex4()?;
+                        // UTF-16代理对
+                        if (0xd800..0xdc00).contains(&code) {
+                            self.expect("\\u")?;
+                            let low = self.parse_hex4()?;
+                            if !(0xdc00..0xe000).contains(&low) {
+                                return Err(anyhow!("JSON字符串中的UTF-16代理对无效"));
+                            }
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                        }
+                        s.push(char::from_u32(code).ok_or(anyhow!("JSON字符串中的\\u转义无效"))?);
+                    }
+                    c => return Err(anyhow!("JSON字符串中的转义字符'\\{}'无效", c)),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .next()?
+                .to_digit(16)
+                .ok_or(anyhow!("JSON字符串中的\\u转义无效"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn parse_array(&mut self) -> Result<Value> {
+        self.expect("[")?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                ']' => return Ok(Value::Array(values)),
+                c => return Err(anyhow!("JSON数组中出现了意外的字符'{}'", c)),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Value> {
+        self.expect("{")?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&'}') {
+            self.pos += 1;
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            let value = self.parse_value()?;
+            fields.push((key, value));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                '}' => return Ok(Value::Object(fields)),
+                c => return Err(anyhow!("JSON对象中出现了意外的字符'{}'", c)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strings() {
+        let parse = |s| Value::parse(s).unwrap();
+        assert_eq!(
+            parse(r#""a\"b\\c\/d\b\f\n\r\t""#),
+            Value::from("a\"b\\c/d\u{8}\u{c}\n\r\t")
+        );
+        assert_eq!(parse(r#""\u4e2d\u6587""#), Value::from("中文"));
+        assert_eq!(parse(r#""\ud83d\ude00""#), Value::from("😀"));
+        assert_eq!(parse(r#""中文😀""#), Value::from("中文😀"));
+
+        // 输出之后能解析回相同的值
+        let value = Value::from("\"\\\n\r\t\u{1}中文");
+        assert_eq!(value.to_string(), r#""\"\\\n\r\t\u0001中文""#);
+        assert_eq!(parse(&value.to_string()), value);
+    }
+
+    #[test]
+    fn numbers() {
+        let parse = |s| Value::parse(s).unwrap().as_f64().unwrap();
+        assert_eq!(parse("0"), 0f64);
+        assert_eq!(parse("-12"), -12f64);
+        assert_eq!(parse("1.5"), 1.5);
+        assert_eq!(parse("2.5e3"), 2500f64);
+        assert_eq!(parse("1E-2"), 0.01);
+        assert_eq!(Value::parse("42").unwrap().as_u64(), Some(42));
+        assert_eq!(Value::parse("4.2").unwrap().as_u64(), None);
+        assert_eq!(Value::parse("-1").unwrap().as_u64(), None);
+        assert_eq!(Value::from(1.2f32).to_string(), "1.2");
+        assert_eq!(Value::Number(f64::NAN).to_string(), "null");
+    }
+
+    #[test]
+    fn nesting() {
+        let value =
+            Value::parse(r#" {"a": [1, true, null, {"b": []}], "c": {}, "a": "x"} "#).unwrap();
+        assert_eq!(
+            value,
+            Value::Object(vec![
+                (
+                    "a".to_string(),
+                    Value::Array(vec![
+                        Value::Number(1f64),
+                        Value::Bool(true),
+                        Value::Null,
+                        Value::Object(vec![("b".to_string(), Value::Array(vec![]))]),
+                    ])
+                ),
+                ("c".to_string(), Value::Object(vec![])),
+                ("a".to_string(), Value::from("x")),
+            ])
+        );
+        // 字段保持书写顺序，重复的字段取第一个
+        assert_eq!(value.get("a").and_then(|x| x.as_str()), None);
+        assert_eq!(
+            value.to_string(),
+            r#"{"a":[1,true,null,{"b":[]}],"c":{},"a":"x"}"#
+        );
+        assert_eq!(
+            json_object! { "b" => 1usize, "a" => None::<bool> }.to_string(),
+            r#"{"b":1,"a":null}"#
+        );
+
+        let nested = |depth| "[".repeat(depth) + &"]".repeat(depth);
+        assert!(Value::parse(&nested(MAX_DEPTH)).is_ok());
+        assert!(Value::parse(&nested(MAX_DEPTH + 1)).is_err());
+        assert!(Value::parse(&"[".repeat(100000)).is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        let inputs = [
+            "",
+            "   ",
+            "nul",
+            "tru",
+            "falsy",
+            "1 2",
+            "{} x",
+            "[1],",
+            "\"abc",
+            "\"abc\\",
+            "\"\\x\"",
+            "\"\\u12\"",
+            "\"\\u12g4\"",
+            "\"\\ud83d\"",
+            "\"\\ud83dx\"",
+            "\"\\ud83d\\u0041\"",
+            "\"\\ud800\\u0000\"",
+            "\"\\ude00\"",
+            "-",
+            "1e",
+            "1.2.3",
+            "--1",
+            "+1",
+            ".5",
+            "[",
+            "[1",
+            "[1,",
+            "[1 2]",
+            "[,]",
+            "{",
+            "{\"a\"",
+            "{\"a\":",
+            "{\"a\":1",
+            "{\"a\" 1}",
+            "{a:1}",
+            "{\"a\":1,}",
+            "{\"a\":1;\"b\":2}",
+        ];
+        for input in inputs {
+            assert!(Value::parse(input).is_err(), "{:?}", input);
+        }
+        // 截断合法输入的每一个前缀都不应导致panic
+        let valid = r#"{"a":[1.5e2,"\ud83d\ude00\n",{"b":null}],"c":false}"#;
+        let chars: Vec<char> = valid.chars().collect();
+        for i in 0..chars.len() {
+            let prefix: String = chars[..i].iter().collect();
+            assert!(Value::parse(&prefix).is_err(), "{:?}", prefix);
+        }
+        assert!(Value::parse(valid).is_ok());
+    }
+}
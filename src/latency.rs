@@ -0,0 +1,72 @@
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, Instant};
+
+const SAMPLE_CAPACITY: usize = 4096;
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(1);
+
+// 延迟统计：画笔报告从读取到写入虚拟绘图板的耗时；百分位数按最近SAMPLE_CAPACITY个报告计算
+#[derive(Default)]
+pub struct LatencyStats {
+    samples: VecDeque<u32>, // 单位：微秒
+    count: u64,
+    cached: Option<(Instant, LatencySummary)>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LatencySummary {
+    pub count: u64, // 启用统计以来记录的报告数
+    pub p50: u32,
+    pub p90: u32,
+    pub p99: u32,
+    pub max: u32,
+}
+
+impl LatencyStats {
+    pub fn record(&mut self, latency: Duration) {
+        if self.samples.len() >= SAMPLE_CAPACITY {
+            self.samples.pop_front();
+        }
+        self.samples
+            .push_back(latency.as_micros().min(u32::MAX as u128) as u32);
+        self.count += 1;
+    }
+
+    // 计算百分位数需要排序，因此最多每SUMMARY_INTERVAL重新计算一次
+    pub fn summary(&mut self) -> Option<LatencySummary> {
+        if let Some((at, summary)) = self.cached
+            && at.elapsed() < SUMMARY_INTERVAL
+        {
+            return Some(summary);
+        }
+        let summary = self.summarize()?;
+        self.cached = Some((Instant::now(), summary));
+        Some(summary)
+    }
+
+    pub fn summarize(&self) -> Option<LatencySummary> {
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<_> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+        Some(LatencySummary {
+            count: self.count,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+impl Display for LatencySummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}个报告，p50={}µs p90={}µs p99={}µs max={}µs",
+            self.count, self.p50, self.p90, self.p99, self.max
+        )
+    }
+}
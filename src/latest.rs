@@ -0,0 +1,44 @@
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// 只保留最新值的单槽信箱：其他任务（例如配置文件监视任务）放入新值时替换尚未取走的旧值，
+// 驱动任务在处理报告的间隙取走；基于原子指针交换所有权，取值时不加锁、不分配内存
+pub struct Latest<T> {
+    ptr: AtomicPtr<T>,
+    _marker: PhantomData<Mutex<T>>, // 与Mutex<T>相同的Send、Sync约束
+}
+impl<T> Default for Latest<T> {
+    fn default() -> Self {
+        Self {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+}
+impl<T> Latest<T> {
+    pub fn put(&self, value: T) {
+        let old = self
+            .ptr
+            .swap(Box::into_raw(Box::new(value)), Ordering::AcqRel);
+        if !old.is_null() {
+            // SAFETY: 非空指针都由put中的Box::into_raw得到，交换后只有当前线程持有
+            drop(unsafe { Box::from_raw(old) });
+        }
+    }
+
+    pub fn take(&self) -> Option<T> {
+        let ptr = self.ptr.swap(ptr::null_mut(), Ordering::AcqRel);
+        if ptr.is_null() {
+            return None;
+        }
+        // SAFETY: 同put
+        Some(*unsafe { Box::from_raw(ptr) })
+    }
+}
+impl<T> Drop for Latest<T> {
+    fn drop(&mut self) {
+        let _ = self.take();
+    }
+}
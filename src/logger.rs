@@ -0,0 +1,288 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::kv::{Error, Key, Value, VisitSource};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use parking_lot::RwLock;
+
+use crate::config::Config;
+use crate::diagnostics;
+use crate::json;
+
+// 日志的输出格式
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    // 带级别前缀的文本；由systemd启动时写入journald
+    #[default]
+    Text,
+    // 每行一个JSON对象，便于导入日志收集系统
+    Json,
+}
+
+// 日志级别的过滤规则：默认级别，以及按模块（例如driver、config）覆盖的级别
+struct Filter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+    from_env: bool, // 由RUST_LOG指定时，忽略配置文件中的logLevel
+}
+// target形如parblo_intangbo_m_driver::driver
+fn module_name(target: &str) -> &str {
+    target.split("::").nth(1).unwrap_or(target)
+}
+
+impl Filter {
+    fn level(&self, target: &str) -> LevelFilter {
+        let module = module_name(target);
+        self.modules
+            .iter()
+            .rev()
+            .find(|(name, _)| name == module)
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .chain([self.default])
+            .max()
+            .unwrap_or(LevelFilter::Off)
+    }
+}
+
+static FILTER: RwLock<Filter> = RwLock::new(Filter {
+    default: DEFAULT_LEVEL,
+    modules: Vec::new(),
+    from_env: false,
+});
+
+static JSON_FORMAT: AtomicBool = AtomicBool::new(false);
+
+// 调试构建默认输出DEBUG级别的日志，发布构建默认为INFO
+const DEFAULT_LEVEL: LevelFilter = if cfg!(debug_assertions) {
+    LevelFilter::Debug
+} else {
+    LevelFilter::Info
+};
+
+const JOURNAL_SOCKET_PATH: &str = "/run/systemd/journal/socket";
+
+// 标准输出连接到journald（由systemd启动）时，改为通过原生协议写入日志，附带优先级与结构化字段
+static JOURNAL: LazyLock<Option<UnixDatagram>> = LazyLock::new(|| {
+    if !stdout_is_journal() {
+        return None;
+    }
+    UnixDatagram::unbound()
+        .and_then(|socket| socket.connect(JOURNAL_SOCKET_PATH).map(|_| socket))
+        .ok()
+});
+
+// 以--daemon运行且未指定--log-file时，标准输出被重定向到/dev/null，日志改为写入journald
+static DAEMON_JOURNAL: OnceLock<UnixDatagram> = OnceLock::new();
+
+// 之后的日志写入journald；journald不可用时返回false
+pub fn use_journal() -> bool {
+    let Ok(socket) = UnixDatagram::unbound()
+        .and_then(|socket| socket.connect(JOURNAL_SOCKET_PATH).map(|_| socket))
+    else {
+        return false;
+    };
+    let _ = DAEMON_JOURNAL.set(socket);
+    true
+}
+
+// systemd将服务的标准输出连接到journald时，会在JOURNAL_STREAM中设置该流的"<设备号>:<inode>"
+fn stdout_is_journal() -> bool {
+    let Ok(stream) = std::env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Ok(metadata) = std::fs::metadata("/proc/self/fd/1") else {
+        return false;
+    };
+    stream == format!("{}:{}", metadata.dev(), metadata.ino())
+}
+
+thread_local! {
+    // 附加到当前线程日志中的结构化字段，例如驱动任务的设备名称与当前的按键映射方案
+    static FIELDS: RefCell<Vec<(&'static str, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+// 设置当前线程的日志字段；字段名需由大写字母、数字与下划线组成，value为None时移除该字段；
+// 单条日志的字段可以通过info!(event = "..."; "...")的形式附加
+pub fn set_field(name: &'static str, value: Option<String>) {
+    FIELDS.with_borrow_mut(|fields| {
+        fields.retain(|(x, _)| *x != name);
+        if let Some(value) = value {
+            fields.push((name, value));
+        }
+    });
+}
+
+struct CollectFields(Vec<(String, String)>);
+impl<'kvs> VisitSource<'kvs> for CollectFields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.push((key.as_str().to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+// 当前线程的日志字段与该条日志附加的字段
+fn collect_fields(record: &Record) -> Vec<(String, String)> {
+    let mut fields = CollectFields(FIELDS.with_borrow(|fields| {
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }));
+    let _ = record.key_values().visit(&mut fields);
+    fields.0
+}
+
+fn journal_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+// 按journald原生协议编码一个字段；值中包含换行时使用带长度的二进制格式
+fn append_journal_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    buf.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        buf.push(b'\n');
+        buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        buf.push(b'=');
+    }
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(b'\n');
+}
+
+fn write_journal(socket: &UnixDatagram, record: &Record) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    append_journal_field(&mut buf, "MESSAGE", &record.args().to_string());
+    append_journal_field(
+        &mut buf,
+        "PRIORITY",
+        &journal_priority(record.level()).to_string(),
+    );
+    append_journal_field(&mut buf, "SYSLOG_IDENTIFIER", env!("CARGO_PKG_NAME"));
+    append_journal_field(&mut buf, "CODE_MODULE", record.target());
+    for (name, value) in collect_fields(record) {
+        append_journal_field(&mut buf, &name.to_ascii_uppercase(), &value);
+    }
+    socket.send(&buf).map(|_| ())
+}
+
+// 例如{"timestamp":1760000000.123,"level":"INFO","module":"driver","message":"已连接设备","fields":{"tablet":"0","event":"device_connected"}}
+fn format_json(record: &Record) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let fields = collect_fields(record)
+        .into_iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), json::Value::from(value)))
+        .collect();
+    json::Value::Object(vec![
+        (
+            "timestamp".to_string(),
+            json::Value::Number(timestamp.as_millis() as f64 / 1000.0),
+        ),
+        ("level".to_string(), record.level().as_str().into()),
+        ("module".to_string(), module_name(record.target()).into()),
+        ("message".to_string(), record.args().to_string().into()),
+        ("fields".to_string(), json::Value::Object(fields)),
+    ])
+    .to_string()
+}
+
+struct Logger;
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= FILTER.read().level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let prefix = match record.level() {
+            Level::Error => "[ERROR] ",
+            Level::Warn => " [WARN] ",
+            Level::Info => " [INFO] ",
+            Level::Debug => "[DEBUG] ",
+            Level::Trace => "[TRACE] ",
+        };
+        let line = format!("{}{}", prefix, record.args());
+        match JOURNAL.as_ref().or(DAEMON_JOURNAL.get()) {
+            _ if JSON_FORMAT.load(Ordering::Relaxed) => {
+                let _ = writeln!(std::io::stdout().lock(), "{}", format_json(record));
+            }
+            Some(socket) => {
+                // 写入失败（例如消息过长）时退回到标准输出，并以<优先级>前缀让journald识别优先级
+                if write_journal(socket, record).is_err() {
+                    let priority = journal_priority(record.level());
+                    let _ = writeln!(std::io::stdout().lock(), "<{}>{}", priority, line);
+                }
+            }
+            None => {
+                let _ = writeln!(std::io::stdout().lock(), "{}", line);
+            }
+        }
+        diagnostics::record_log(line);
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+// 安装日志后端；设置了RUST_LOG时按其过滤，格式为逗号分隔的级别或<模块>=<级别>，例如"info,driver=debug"
+pub fn init() {
+    if let Ok(spec) = std::env::var("RUST_LOG")
+        && !spec.trim().is_empty()
+    {
+        let mut filter = FILTER.write();
+        filter.from_env = true;
+        for directive in spec.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (module, level) = match directive.split_once('=') {
+                Some((module, level)) => (Some(module.trim()), level),
+                None => (None, directive),
+            };
+            let Ok(level) = level.trim().parse::<LevelFilter>() else {
+                eprintln!("忽略RUST_LOG中无效的日志级别'{}'", directive);
+                continue;
+            };
+            // 同时接受完整的target，例如parblo_intangbo_m_driver::driver
+            match module.map(|x| x.rsplit("::").next().unwrap_or(x)) {
+                Some(module) if module != env!("CARGO_CRATE_NAME") => {
+                    filter.modules.push((module.to_string(), level));
+                }
+                _ => filter.default = level,
+            }
+        }
+    }
+    log::set_max_level(FILTER.read().max_level());
+    if log::set_logger(&Logger).is_err() {
+        eprintln!("日志后端已被安装");
+    }
+}
+
+// 应用配置文件中的logFormat与logLevel；设置了RUST_LOG时以RUST_LOG中的级别为准，未配置logLevel时恢复默认级别
+pub fn configure(conf: &Config) {
+    JSON_FORMAT.store(conf.log_format == LogFormat::Json, Ordering::Relaxed);
+    let mut filter = FILTER.write();
+    if filter.from_env {
+        return;
+    }
+    filter.default = conf.log_level.unwrap_or(DEFAULT_LEVEL);
+    log::set_max_level(filter.max_level());
+}
@@ -1,33 +1,28 @@
+// 日志宏转发到log crate，由logger模块中的日志后端按级别过滤并输出
 #[macro_export]
 macro_rules! debug {
     ($($arg:tt)*) => {
-        #[cfg(debug_assertions)]
-        print!("[DEBUG] ");
-        #[cfg(debug_assertions)]
-        println!($($arg)*);
+        ::log::debug!($($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! info {
     ($($arg:tt)*) => {
-        print!(" [INFO] ");
-        println!($($arg)*);
+        ::log::info!($($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! warn {
     ($($arg:tt)*) => {
-        print!(" [WARN] ");
-        println!($($arg)*);
+        ::log::warn!($($arg)*)
     };
 }
 
 #[macro_export]
 macro_rules! error {
     ($($arg:tt)*) => {
-        print!("[ERROR] ");
-        println!($($arg)*);
+        ::log::error!($($arg)*)
     };
 }
@@ -1,58 +1,427 @@
-use std::thread::spawn;
+use std::path::{Path, PathBuf};
+use std::thread::{JoinHandle, spawn};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, anyhow};
 
 use crate::{
     cancel::CancelToken,
+    cli::{Cli, Command, DaemonOptions, DriverOptions, RunArgs},
     config::{Config, WatchConfigChangeTask},
+    control::ControlTask,
+    daemon::PidFile,
     driver::DriverTask,
+    event_loop::EventLoop,
+    focus::FocusTrackTask,
+    follow::FollowMonitorTask,
+    hotplug::HotplugTask,
+    privilege::PrivilegeDrop,
+    session::SessionWatchTask,
     signal::ExitSignal,
 };
 
+mod bus;
 mod cancel;
+mod capture;
+mod cli;
 mod config;
+mod control;
+mod daemon;
+mod devices;
+mod diagnostics;
 mod driver;
+mod event_loop;
+mod focus;
+mod follow;
+mod hidraw;
+mod hotplug;
+mod identity;
+mod io;
+mod json;
+mod latency;
+mod latest;
+mod logger;
 mod macros;
+mod notify;
+mod osd;
+mod paths;
+mod privilege;
+mod probe;
+mod profile;
+mod regex;
+mod replay;
+mod rule;
+mod sandbox;
+mod screen;
+mod service;
+mod session;
 mod signal;
+mod sleep;
+mod state;
+mod systemd;
+mod udev;
+mod uinput;
+mod usage;
+mod usb;
+mod wacom;
+mod wakeup;
+
+// 驱动程序本身以及需要加载配置、打开设备的子命令
+enum Mode {
+    Driver,
+    Calibrate,
+    Replay(PathBuf),
+    CaptureProfile,
+    ListDevices,
+    Probe,
+}
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+    if cli.print_default_config {
+        print!("{}", config::DEFAULT_CONFIG);
+        return Ok(());
+    }
+    // 不需要日志与诊断信息的子命令直接执行
+    let (mode, args) = match cli.command {
+        None => (Mode::Driver, cli.run),
+        Some(Command::Calibrate(args)) => (
+            Mode::Calibrate,
+            RunArgs {
+                options: args.options,
+                config: args.config,
+                ..Default::default()
+            },
+        ),
+        Some(Command::Replay(args)) => (
+            Mode::Replay(args.record),
+            RunArgs {
+                options: args.options,
+                config: args.config,
+                ..Default::default()
+            },
+        ),
+        Some(Command::CaptureProfile(config)) => (
+            Mode::CaptureProfile,
+            RunArgs {
+                config,
+                ..Default::default()
+            },
+        ),
+        Some(Command::ListDevices(config)) => (
+            Mode::ListDevices,
+            RunArgs {
+                config,
+                ..Default::default()
+            },
+        ),
+        Some(Command::Probe(config)) => (
+            Mode::Probe,
+            RunArgs {
+                config,
+                ..Default::default()
+            },
+        ),
+        Some(Command::Check(args)) => return config::check(&args.config),
+        Some(Command::ImportXsetwacom(args)) => return wacom::import(&args),
+        Some(Command::InstallUdevRules(args)) => return udev::install(args),
+        Some(Command::InstallService(args)) => return service::install(args),
+        Some(Command::Client(command)) => return control::client(command),
+    };
+
+    logger::init();
+    diagnostics::install_panic_hook();
+    let result = run(mode, args);
+    if let Err(e) = &result {
+        diagnostics::report(&format!("{:?}", e));
+    }
+    result
+}
+
+fn run(mode: Mode, args: RunArgs) -> Result<()> {
+    info!("{} {}", env!("CARGO_PKG_NAME"), cli::VERSION);
     let ct = CancelToken::new();
 
-    let conf_path = std::env::args().nth(1);
-    let conf = match &conf_path {
+    let RunArgs {
+        options: DriverOptions { dry_run, overrides },
+        daemon:
+            DaemonOptions {
+                daemon,
+                pid_file,
+                log_file,
+            },
+        config,
+    } = args;
+    let calibrate = matches!(mode, Mode::Calibrate);
+    let pid_file = pid_file
+        .map(std::path::absolute)
+        .transpose()
+        .context("无法解析PID文件的路径")?;
+    let log_file = log_file
+        .map(std::path::absolute)
+        .transpose()
+        .context("无法解析日志文件的路径")?;
+    let mut conf_path = config.path().or_else(|| {
+        let path = paths::find_config()?;
+        info!("未指定配置文件，使用{}", path.display());
+        Some(path)
+    });
+    // 后台运行时工作目录会切换到/
+    if daemon && let Some(path) = &mut conf_path {
+        *path = std::path::absolute(&*path).context("无法解析配置文件的路径")?;
+    }
+    if calibrate && conf_path.is_none() {
+        return Err(anyhow!("校准模式需要指定配置文件的路径"));
+    }
+    let mut conf = match &conf_path {
         Some(path) => Config::load(path).context("加载配置文件失败")?,
         None => Config::default(),
     };
+    overrides.apply(&mut conf).context("命令行参数有误")?;
+    logger::configure(&conf);
+
+    match mode {
+        Mode::ListDevices => return devices::list(&conf),
+        Mode::Probe => return probe::run(&conf),
+        _ => {}
+    }
+
+    // fork之前不能创建任何线程；PID文件在函数返回时删除
+    let _pid_file = match daemon {
+        true => daemon::daemonize(log_file.as_deref(), pid_file.as_deref())?,
+        false => pid_file.as_deref().map(PidFile::create).transpose()?,
+    };
+
+    let mut exit_signal = ExitSignal::new(ct.clone())?;
+    let mut event_loop = EventLoop::new(ct.clone()).context("初始化事件循环时发生错误")?;
+
+    if let Mode::CaptureProfile = mode {
+        event_loop.add("退出信号监控任务", exit_signal)?;
+        let exit_signal_task = spawn_event_loop(event_loop);
+        let result = capture::run(ct.clone(), &conf.device, Path::new("."));
+        ct.cancel();
+        if exit_signal_task.join().is_err() {
+            error!("任务意外退出");
+        }
+        return result.context("采集设备描述时发生错误");
+    }
 
-    let exit_signal = ExitSignal::new(ct.clone())?;
+    // 回放只驱动第一个绘图板，且不监控配置文件、不响应控制命令，保证结果可以复现
+    if let Mode::Replay(record_path) = mode {
+        let reports = replay::load(&record_path)?;
+        conf.tablets.clear();
+        conf.usage_statistics = false;
+        if dry_run {
+            replay::fill_missing_ranges(&mut conf, &reports);
+        }
+        let schema = overrides.schema.as_ref().map(|x| conf.find_schema(x));
+        let mut driver_task = DriverTask::new(ct.clone(), 0, conf, None, None, None, None)
+            .context("初始化驱动任务时发生错误")?;
+        if let Some(schema) = schema {
+            driver_task.select_schema(schema?);
+        }
+        if dry_run {
+            driver_task.enable_dry_run();
+        }
+        event_loop.add("退出信号监控任务", exit_signal)?;
+        let exit_signal_task = spawn_event_loop(event_loop);
+        let result = driver_task.replay(reports);
+        ct.cancel();
+        if exit_signal_task.join().is_err() {
+            error!("任务意外退出");
+        }
+        return result.context("回放记录时发生错误");
+    }
 
     let mut watch_config_change_task = None;
-    if let Some(conf_path) = conf_path {
+    if let Some(conf_path) = &conf_path {
         watch_config_change_task.replace(
-            WatchConfigChangeTask::new(conf_path, ct.clone())
-                .context("初始化配置文件监控任务时发生错误")?,
+            WatchConfigChangeTask::new(conf_path).context("初始化配置文件监控任务时发生错误")?,
         );
     }
-    let driver_task = DriverTask::new(ct.clone(), conf, watch_config_change_task.as_mut())
-        .context("初始化驱动任务时发生错误")?;
+    if let Some(task) = &mut watch_config_change_task {
+        exit_signal.set_reload_trigger(task.reload_trigger());
+        task.set_overrides(overrides.clone());
+    }
+    // 第一个绘图板使用顶层配置，其余绘图板使用tablet中的配置；校准模式只驱动第一个绘图板
+    let mut tablet_confs = std::mem::take(&mut conf.tablets);
+    tablet_confs.insert(0, conf);
+    if calibrate {
+        tablet_confs.truncate(1);
+    }
 
-    let mut tasks = Vec::with_capacity(2);
-    tasks.push(spawn(move || {
-        if let Err(e) = exit_signal.wait() {
-            error!("退出信号监控任务发生错误并退出: {:?}", e);
+    let mut follow_monitor_task = None;
+    if tablet_confs.iter().any(|x| x.follow_monitor) {
+        follow_monitor_task.replace(FollowMonitorTask::new(ct.clone()));
+    }
+    let mut focus_track_task = None;
+    if tablet_confs
+        .iter()
+        .any(|x| x.rules.iter().any(|x| x.needs_window()))
+    {
+        focus_track_task.replace(FocusTrackTask::new(ct.clone()));
+    }
+    // 无法确定要跟随的登录会话时仅输出警告，不暂停注入事件
+    let mut session_watch_task = None;
+    if tablet_confs[0].pause_on_inactive_session {
+        match SessionWatchTask::new(tablet_confs[0].session_user.as_deref()) {
+            Ok(task) => session_watch_task = Some(task),
+            Err(e) => warn!(
+                "初始化登录会话监视任务时发生错误，会话切换时不会暂停: {:?}",
+                e
+            ),
+        }
+    }
+    // 控制套接字不可用时（例如已有其他实例在运行）仅输出警告，不影响驱动运行
+    let mut control_task =
+        match ControlTask::new(ct.clone(), tablet_confs[0].control_socket.as_deref()) {
+            Ok(mut task) => {
+                if let Some(watch_task) = &watch_config_change_task {
+                    task.set_reload_trigger(watch_task.reload_trigger());
+                }
+                Some(task)
+            }
+            Err(e) => {
+                warn!("初始化控制任务时发生错误，控制命令将不可用: {:?}", e);
+                None
+            }
+        };
+    // 不支持热插拔通知时，驱动任务会定期尝试打开设备
+    let mut device_ids = vec![];
+    for id in tablet_confs.iter().flat_map(|x| x.device.ids()) {
+        if !device_ids.contains(&id) {
+            device_ids.push(id);
+        }
+    }
+    let mut hotplug_task = match HotplugTask::new(ct.clone(), device_ids) {
+        Ok(task) => Some(task),
+        Err(e) => {
+            warn!(
+                "初始化热插拔监视任务时发生错误，将定期尝试打开设备: {:?}",
+                e
+            );
+            None
+        }
+    };
+    // 在进入事件循环之前切换用户，不等待绘图板连接
+    let privilege_drop = match &tablet_confs[0].run_as {
+        Some(run_as) => PrivilegeDrop::new(run_as).context("无法准备切换用户")?,
+        None => None,
+    };
+    // 过滤器会被子进程继承，配置了需要运行外部程序的功能时不启用
+    let sandbox = match tablet_confs[0].sandbox {
+        true if tablet_confs.iter().any(|x| x.needs_commands()) => {
+            info!("配置中的功能需要运行外部程序，不启用seccomp沙箱");
+            false
         }
-    }));
+        x => x,
+    };
+    let mut driver_tasks = Vec::with_capacity(tablet_confs.len());
+    for (i, conf) in tablet_confs.into_iter().enumerate() {
+        let follow_monitor = conf.follow_monitor;
+        let schema = overrides.schema.as_ref().map(|x| conf.find_schema(x));
+        // 每个绘图板的驱动任务使用各自的子令牌：收到退出信号时随之取消，也可以只停止其中一个绘图板
+        let mut driver_task = DriverTask::new(
+            ct.child(),
+            i,
+            conf,
+            watch_config_change_task.as_mut(),
+            follow_monitor_task.as_mut().filter(|_| follow_monitor),
+            control_task.as_mut(),
+            hotplug_task.as_mut(),
+        )
+        .context(format!("初始化第{}个绘图板的驱动任务时发生错误", i))?;
+        if let Some(schema) = schema {
+            driver_task.select_schema(schema?);
+        }
+        driver_task.watch_signals(&mut exit_signal);
+        if let Some(task) = &mut focus_track_task {
+            driver_task.watch_focus(task);
+        }
+        if let Some(task) = &mut session_watch_task {
+            driver_task.watch_session(task);
+        }
+        if dry_run {
+            driver_task.enable_dry_run();
+        }
+        if privilege_drop.is_some() {
+            driver_task.acquire_before_privilege_drop();
+        }
+        if calibrate && let Some(conf_path) = &conf_path {
+            driver_task.enable_calibration(conf_path.into());
+        }
+        driver_tasks.push(driver_task);
+    }
+
+    // 切换用户时查询用户组可能需要连接sssd、nscd等服务，因此在切换完成后再安装过滤器
+    if let Some(privilege_drop) = privilege_drop {
+        privilege_drop.switch().context("无法放弃root权限")?;
+    }
+    // 所有任务都已初始化，之后只需要事件循环用到的系统调用
+    if sandbox {
+        match sandbox::install() {
+            Ok(()) => info!("已启用seccomp沙箱"),
+            Err(e) => warn!("无法启用seccomp沙箱，将继续运行: {:?}", e),
+        }
+    }
+
+    // 退出信号、配置文件与登录会话的监视共用一个事件循环线程
+    let mut tasks = Vec::with_capacity(5);
+    event_loop.add("退出信号监控任务", exit_signal)?;
     if let Some(task) = watch_config_change_task {
+        event_loop.add("配置文件监控任务", task)?;
+    }
+    if let Some(task) = session_watch_task {
+        event_loop.add("登录会话监视任务", task)?;
+    }
+    tasks.push(spawn_event_loop(event_loop));
+    if let Some(task) = follow_monitor_task {
         tasks.push(spawn(move || {
             if let Err(e) = task.run() {
-                error!("配置文件监控任务发生错误并退出: {:?}", e);
+                error!("显示器跟随任务发生错误并退出: {:?}", e);
+            }
+        }));
+    }
+    if let Some(task) = focus_track_task {
+        tasks.push(spawn(move || {
+            if let Err(e) = task.run() {
+                error!("窗口焦点跟踪任务发生错误并退出: {:?}", e);
+            }
+        }));
+    }
+    if let Some(task) = hotplug_task {
+        tasks.push(spawn(move || {
+            if let Err(e) = task.run() {
+                error!("热插拔监视任务发生错误并退出: {:?}", e);
+            }
+        }));
+    }
+    if let Some(task) = control_task {
+        tasks.push(spawn(move || {
+            if let Err(e) = task.run() {
+                error!("控制任务发生错误并退出: {:?}", e);
             }
         }));
     }
 
-    if let Err(e) = driver_task.run() {
-        error!("驱动任务发生错误并退出: {:?}", e);
+    // 某个绘图板的驱动任务出错退出时，其他绘图板继续运行；所有驱动任务都结束后再通知其他任务退出
+    let driver_tasks: Vec<_> = driver_tasks
+        .into_iter()
+        .enumerate()
+        .map(|(i, task)| {
+            spawn(move || {
+                if let Err(e) = task.run() {
+                    error!("第{}个绘图板的驱动任务发生错误并退出: {:?}", i, e);
+                    diagnostics::report(&format!("{:?}", e));
+                }
+            })
+        })
+        .collect();
+    for task in driver_tasks {
+        if let Err(e) = task.join() {
+            error!("驱动任务意外退出: {:?}", e);
+        }
     }
+    systemd::notify("STOPPING=1");
     ct.cancel();
     for task in tasks {
         if let Err(e) = task.join() {
@@ -61,3 +430,11 @@ fn main() -> Result<()> {
     }
     Ok(())
 }
+
+fn spawn_event_loop(event_loop: EventLoop) -> JoinHandle<()> {
+    spawn(move || {
+        if let Err(e) = event_loop.run() {
+            error!("事件循环发生错误并退出: {:?}", e);
+        }
+    })
+}
@@ -0,0 +1,62 @@
+use std::process::Command;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::bus::StateEvent;
+use crate::{debug, warn};
+
+// 通过notify-send发送桌面通知；在后台线程中执行，避免阻塞驱动任务
+pub struct Notifier;
+impl Notifier {
+    // 短时间内的多条通知只显示最后一条，例如连续切换按键映射方案时
+    const COALESCE_INTERVAL: Duration = Duration::from_millis(300);
+    // 在该时间内再次发送通知时，替换上一条通知而不是堆叠显示
+    const REPLACE_WINDOW: Duration = Duration::from_secs(5);
+
+    // 驱动任务结束时事件总线被销毁，后台线程随之退出
+    pub fn spawn(receiver: Receiver<StateEvent>) {
+        std::thread::spawn(move || Self::run(receiver));
+    }
+
+    fn run(receiver: Receiver<StateEvent>) {
+        let mut last: Option<(u32, Instant)> = None; // (通知ID, 发送时间)
+        while let Ok(event) = receiver.recv() {
+            let mut body = event.to_string();
+            loop {
+                match receiver.recv_timeout(Self::COALESCE_INTERVAL) {
+                    Ok(x) => body = x.to_string(),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            let replace_id = last
+                .filter(|(_, sent_at)| sent_at.elapsed() < Self::REPLACE_WINDOW)
+                .map(|(id, _)| id);
+            match send(&body, replace_id) {
+                Ok(id) => last = id.map(|x| (x, Instant::now())),
+                Err(e) => {
+                    warn!("无法发送桌面通知: {}", e);
+                    last = None;
+                }
+            }
+        }
+    }
+}
+
+// 返回通知服务器分配的通知ID；较旧版本的notify-send不支持打印通知ID，此时无法替换通知
+fn send(body: &str, replace_id: Option<u32>) -> std::io::Result<Option<u32>> {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name", env!("CARGO_PKG_NAME"), "--print-id"]);
+    if let Some(id) = replace_id {
+        command.arg(format!("--replace-id={}", id));
+    }
+    command.args(["Parblo Intangbo M", body]);
+    debug!("发送桌面通知: {}", body);
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().parse().ok())
+}
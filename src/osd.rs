@@ -0,0 +1,92 @@
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::bus::StateEvent;
+use crate::{debug, warn};
+
+// 屏幕显示（OSD）：通过xosd的osd_cat在屏幕下方短暂显示状态变化；
+// osd_cat使用override-redirect窗口，在X11与XWayland下可用；在后台线程中执行，避免阻塞驱动任务
+pub struct Osd;
+impl Osd {
+    const DELAY_SECS: u32 = 1;
+    // 定期回收已经结束显示的osd_cat进程
+    const REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+    // 驱动任务结束时事件总线被销毁，后台线程随之退出
+    pub fn spawn(receiver: Receiver<StateEvent>) {
+        std::thread::spawn(move || Self::run(receiver));
+    }
+
+    fn run(receiver: Receiver<StateEvent>) {
+        let mut current: Option<Child> = None;
+        let mut warned = false;
+        loop {
+            // 没有正在显示的内容时一直等待，避免OSD未启用时后台线程被定期唤醒
+            let result = match current {
+                Some(_) => receiver.recv_timeout(Self::REAP_INTERVAL),
+                None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let event = match result {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(child) = &mut current
+                        && !matches!(child.try_wait(), Ok(None))
+                    {
+                        current = None;
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if !warned && std::env::var_os("DISPLAY").is_none() {
+                warn!("未设置DISPLAY环境变量，OSD需要X11或XWayland");
+                warned = true;
+            }
+            // 新的状态替换正在显示的内容，而不是堆叠显示
+            if let Some(child) = current.take() {
+                dismiss(child);
+            }
+            match show(&event.to_string()) {
+                Ok(child) => current = Some(child),
+                Err(e) => {
+                    warn!("无法通过osd_cat显示状态，将不再尝试: {}", e);
+                    break;
+                }
+            }
+        }
+        if let Some(child) = current.take() {
+            dismiss(child);
+        }
+        // 出错后继续接收并丢弃事件，直到驱动任务结束，避免事件在通道中堆积
+        while receiver.recv().is_ok() {}
+    }
+}
+
+fn show(text: &str) -> std::io::Result<Child> {
+    debug!("显示OSD: {}", text);
+    let mut child = Command::new("osd_cat")
+        .args([
+            "--pos=bottom",
+            "--align=center",
+            "--offset=80",
+            "--outline=2",
+            "--colour=white",
+            "--font=-*-*-bold-r-*-*-34-*-*-*-*-*-*-*",
+        ])
+        .arg(format!("--delay={}", Osd::DELAY_SECS))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        writeln!(stdin, "{}", text)?;
+    }
+    Ok(child)
+}
+
+fn dismiss(mut child: Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow};
+
+// 保存程序状态的目录：$XDG_STATE_HOME/parblo-intangbo-m-driver，或~/.local/state/parblo-intangbo-m-driver
+pub fn state_dir() -> PathBuf {
+    let state_home = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir().join(".local").join("state"),
+    };
+    state_home.join(env!("CARGO_PKG_NAME"))
+}
+
+// 每个绘图板各自的状态文件名：第一个绘图板为<name>.toml，其余为<name>-<index>.toml
+pub fn tablet_state_file(name: &str, tablet_index: usize) -> PathBuf {
+    match tablet_index {
+        0 => state_dir().join(format!("{}.toml", name)),
+        _ => state_dir().join(format!("{}-{}.toml", name, tablet_index)),
+    }
+}
+
+// 未指定配置文件时依次查找$XDG_CONFIG_HOME/parblo-intangbo-m/config.toml（未设置XDG_CONFIG_HOME时为
+// ~/.config/...）与/etc/parblo-intangbo-m/config.toml，返回第一个存在的文件
+pub fn find_config() -> Option<PathBuf> {
+    [config_home(), PathBuf::from("/etc")]
+        .into_iter()
+        .map(|dir| dir.join("parblo-intangbo-m").join("config.toml"))
+        .find(|path| path.is_file())
+}
+
+// systemd用户服务的目录：$XDG_CONFIG_HOME/systemd/user，或~/.config/systemd/user
+pub fn user_unit_dir() -> PathBuf {
+    config_home().join("systemd").join("user")
+}
+
+fn config_home() -> PathBuf {
+    match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => home_dir().join(".config"),
+    }
+}
+
+fn home_dir() -> PathBuf {
+    PathBuf::from(std::env::var_os("HOME").unwrap_or_else(|| "/root".into()))
+}
+
+// 展开开头的~（当前用户的主目录）以及其中的$VAR、${VAR}（环境变量），用于配置文件中的路径与exec的命令行，
+// 使同一份配置可以在主目录不同的机器之间共享；环境变量未设置、${}或缺少}时返回错误，单独的$保持不变
+pub fn expand(value: &str) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let rest = match value.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            result.push_str(&home_dir().to_string_lossy());
+            rest
+        }
+        _ => value,
+    };
+    let mut chars = rest.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            result.push(c);
+            continue;
+        }
+        let mut name = String::new();
+        if chars.next_if_eq(&'{').is_some() {
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(anyhow!("'{}'中的${{缺少对应的}}", value)),
+                }
+            }
+            if name.is_empty() {
+                return Err(anyhow!("'{}'中的${{}}缺少环境变量名", value));
+            }
+        } else {
+            while let Some(c) = chars.next_if(|x| x.is_ascii_alphanumeric() || *x == '_') {
+                name.push(c);
+            }
+        }
+        if name.is_empty() {
+            result.push('$');
+            continue;
+        }
+        let value = std::env::var(&name).with_context(|| format!("环境变量{}未设置", name))?;
+        result.push_str(&value);
+    }
+    Ok(result)
+}
+
+pub fn expand_path(path: PathBuf) -> Result<PathBuf> {
+    match path.to_str() {
+        Some(x) => Ok(PathBuf::from(expand(x)?)),
+        None => Ok(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(value: &str) -> String {
+        expand(value).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn home() {
+        let home = home_dir().to_string_lossy().into_owned();
+        assert_eq!(expand("~").unwrap(), home);
+        assert_eq!(expand("~/bin").unwrap(), format!("{}/bin", home));
+        // 不支持~user，也不展开中间的~
+        assert_eq!(expand("~user/bin").unwrap(), "~user/bin");
+        assert_eq!(expand("a/~/b").unwrap(), "a/~/b");
+    }
+
+    #[test]
+    fn variables() {
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(expand("$HOME/a").unwrap(), format!("{}/a", home));
+        assert_eq!(expand("${HOME}a").unwrap(), format!("{}a", home));
+        assert_eq!(expand("x$HOME.y").unwrap(), format!("x{}.y", home));
+        // 单独的$保持不变
+        assert_eq!(expand("$").unwrap(), "$");
+        assert_eq!(expand("a$ b$/c$").unwrap(), "a$ b$/c$");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("a${}b"), "'a${}b'中的${}缺少环境变量名");
+        assert_eq!(error("a${HOME"), "'a${HOME'中的${缺少对应的}");
+        assert_eq!(error("${"), "'${'中的${缺少对应的}");
+        assert_eq!(
+            error("$PARBLO_INTANGBO_M_UNSET/a"),
+            "环境变量PARBLO_INTANGBO_M_UNSET未设置"
+        );
+        assert_eq!(
+            error("${PARBLO_INTANGBO_M_UNSET}"),
+            "环境变量PARBLO_INTANGBO_M_UNSET未设置"
+        );
+    }
+}
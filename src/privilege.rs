@@ -0,0 +1,61 @@
+use std::ffi::CString;
+
+use anyhow::{Context, Result, anyhow};
+use nix::unistd::{Gid, Group, Uid, User, geteuid, initgroups, setgid, setuid};
+
+use crate::config::RunAsConfig;
+use crate::info;
+
+// 以root身份启动并配置了runAsUser时，在进入事件循环之前切换到指定的用户
+pub struct PrivilegeDrop {
+    name: CString,
+    uid: Uid,
+    gid: Gid,
+}
+impl PrivilegeDrop {
+    // 未以root身份运行时无需切换，返回None；用户或用户组不存在时返回错误，避免运行到一半才发现
+    pub fn new(conf: &RunAsConfig) -> Result<Option<Self>> {
+        if !geteuid().is_root() {
+            info!("未以root身份运行，忽略runAsUser配置");
+            return Ok(None);
+        }
+        let user = User::from_name(&conf.user)
+            .context("无法查询用户信息")?
+            .ok_or_else(|| anyhow!("用户'{}'不存在", conf.user))?;
+        let gid = match &conf.group {
+            Some(name) => {
+                Group::from_name(name)
+                    .context("无法查询用户组信息")?
+                    .ok_or_else(|| anyhow!("用户组'{}'不存在", name))?
+                    .gid
+            }
+            None => user.gid,
+        };
+        if user.uid.is_root() {
+            return Err(anyhow!("runAsUser不能是root用户"));
+        }
+        Ok(Some(Self {
+            name: CString::new(user.name).context("用户名中包含空字符")?,
+            uid: user.uid,
+            gid,
+        }))
+    }
+
+    // 无法确认已经放弃root权限时返回错误，调用者不应继续运行
+    pub fn switch(self) -> Result<()> {
+        // 先设置附加用户组与用户组，放弃root之后就无法再修改
+        initgroups(&self.name, self.gid).context("无法设置附加用户组")?;
+        setgid(self.gid).context("无法切换用户组")?;
+        setuid(self.uid).context("无法切换用户")?;
+        if setuid(Uid::from_raw(0)).is_ok() {
+            return Err(anyhow!("切换用户后仍能恢复root权限"));
+        }
+        info!(
+            "已切换到用户{}（uid={}，gid={}）",
+            self.name.to_string_lossy(),
+            self.uid,
+            self.gid
+        );
+        Ok(())
+    }
+}
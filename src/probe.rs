@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use evdev_rs::enums::{EV_ABS, EV_KEY, EV_MSC, EventCode, EventType, InputProp};
+use evdev_rs::{Device as EventDevice, DeviceWrapper};
+
+use crate::config::Config;
+use crate::driver;
+
+// probe子命令：打开各绘图板对应的事件设备，打印其声明的全部能力，以及驱动程序将会使用的数值
+pub fn run(conf: &Config) -> Result<()> {
+    let mut tablets = vec![conf];
+    tablets.extend(&conf.tablets);
+    for (i, tablet) in tablets.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("绘图板{}：", i);
+        let path = driver::find_evdev(&tablet.device).context("找不到对应的事件设备")?;
+        let evdev = EventDevice::new_from_path(&path)
+            .context(format!("无法打开事件设备{}", path.display()))?;
+        print_device(&path.display().to_string(), &evdev);
+        print_driver_values(tablet, &evdev);
+    }
+    Ok(())
+}
+
+fn print_device(path: &str, evdev: &EventDevice) {
+    println!("  路径: {}", path);
+    println!("  名称: 「{}」", evdev.name().unwrap_or_default());
+    println!("  物理位置: {}", evdev.phys().unwrap_or_default());
+    println!(
+        "  ID: bustype={:#06x} vendor_id={:#06x} product_id={:#06x} version={:#06x}",
+        evdev.bustype(),
+        evdev.vendor_id(),
+        evdev.product_id(),
+        evdev.version(),
+    );
+
+    let props: Vec<_> = InputProp::INPUT_PROP_POINTER
+        .iter()
+        .filter(|x| evdev.has_property(x))
+        .map(|x| x.to_string())
+        .collect();
+    println!("  属性: {}", props.join(" "));
+
+    println!("  坐标轴:");
+    for code in EventCode::EV_ABS(EV_ABS::ABS_X).iter() {
+        if let Some(info) = evdev.abs_info(&code) {
+            println!(
+                "    {:<16} 范围=[{}, {}] 分辨率={} fuzz={} flat={} 当前值={}",
+                code.to_string(),
+                info.minimum,
+                info.maximum,
+                info.resolution,
+                info.fuzz,
+                info.flat,
+                info.value,
+            );
+        }
+    }
+
+    let keys: Vec<_> = EventCode::EV_KEY(EV_KEY::KEY_RESERVED)
+        .iter()
+        .filter(|x| evdev.has(*x))
+        .map(|x| x.to_string())
+        .collect();
+    println!("  按键: {}", keys.join(" "));
+
+    let msc: Vec<_> = EventCode::EV_MSC(EV_MSC::MSC_SERIAL)
+        .iter()
+        .filter(|x| evdev.has(*x))
+        .map(|x| x.to_string())
+        .collect();
+    println!("  MSC: {}", msc.join(" "));
+
+    let types: Vec<_> = EventType::EV_SYN
+        .iter()
+        .filter(|x| evdev.has(*x))
+        .map(|x| x.to_string())
+        .collect();
+    println!("  事件类型: {}", types.join(" "));
+}
+
+// 与创建虚拟设备时的计算方式一致：原始设备的ABS_X、ABS_Y需要互相调换；配置文件、设备描述中的值依次优先
+fn print_driver_values(conf: &Config, evdev: &EventDevice) {
+    let abs_max = |code| {
+        evdev
+            .abs_info(&EventCode::EV_ABS(code))
+            .map(|x| x.maximum)
+            .unwrap_or_default()
+    };
+    let abs_resolution = |code| {
+        evdev
+            .abs_info(&EventCode::EV_ABS(code))
+            .map(|x| x.resolution)
+            .unwrap_or_default()
+    };
+    let x_max = match conf.x_max_value {
+        0 => abs_max(EV_ABS::ABS_Y),
+        x => x as i32,
+    };
+    let y_max = match conf.y_max_value {
+        0 => abs_max(EV_ABS::ABS_X),
+        x => x as i32,
+    };
+    let x_resolution = match (
+        conf.virtual_axes.x.resolution,
+        conf.device.profile.x_resolution,
+    ) {
+        (Some(x), _) => x as i32,
+        (None, 0) => abs_resolution(EV_ABS::ABS_Y),
+        (None, x) => x as i32,
+    };
+    let y_resolution = match (
+        conf.virtual_axes.y.resolution,
+        conf.device.profile.y_resolution,
+    ) {
+        (Some(x), _) => x as i32,
+        (None, 0) => abs_resolution(EV_ABS::ABS_X),
+        (None, x) => x as i32,
+    };
+    let pressure_max = match conf.device.profile.pressure_max_value {
+        0 => abs_max(EV_ABS::ABS_PRESSURE),
+        x => x as i32,
+    };
+    println!("  驱动程序将使用的数值:");
+    println!("    xMaxValue={} yMaxValue={}", x_max, y_max);
+    println!(
+        "    X轴分辨率={} Y轴分辨率={}（单位/毫米；为0表示设备未提供）",
+        x_resolution, y_resolution,
+    );
+    if x_resolution <= 0 || y_resolution <= 0 {
+        println!("    警告: 缺少坐标轴的分辨率，虚拟绘图板可能不会被识别为绘图板");
+    }
+    println!("    压力最大值={}", pressure_max);
+    if pressure_max <= 0 {
+        println!("    警告: 压力最大值无效，虚拟设备将无法报告压力");
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+// 设备型号的描述：坐标范围与各按键、转环对应的按键码；可由capture-profile子命令生成候选描述
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DeviceProfile {
+    // 事件设备的名称
+    pub name: String,
+
+    pub vendor_id: u16,
+    pub product_id: u16,
+
+    // 通过2.4G无线接收器连接时的product_id；没有无线版本（或尚未确认）时为None
+    pub dongle_product_id: Option<u16>,
+
+    // 通过蓝牙连接时的product_id；没有蓝牙版本（或尚未确认）时为None
+    pub bluetooth_product_id: Option<u16>,
+
+    // 调换X、Y轴后的坐标最大值；为0时使用事件设备提供的最大值
+    pub x_max_value: u16,
+    pub y_max_value: u16,
+
+    // 调换X、Y轴后的分辨率（单位：设备坐标/毫米）；为0时使用事件设备提供的分辨率
+    pub x_resolution: u16,
+    pub y_resolution: u16,
+
+    // 压力最大值；为0时使用事件设备提供的最大值
+    pub pressure_max_value: u16,
+
+    // 各按钮的按键码，按按钮编号排列
+    pub buttons: Vec<u16>,
+
+    // 顺时针、逆时针转动转环与按下转环中心按钮的按键码；没有转环时为None
+    pub ring_cw: Option<u16>,
+    pub ring_ccw: Option<u16>,
+    pub ring_button: Option<u16>,
+}
+
+// 绘图板上的按键
+#[derive(Clone, Copy, Debug)]
+pub enum PadKey {
+    Button(usize),
+    RingCw,
+    RingCcw,
+    RingButton,
+}
+impl PadKey {
+    // 对应的按键映射字段名称
+    pub fn field_name(&self) -> String {
+        match self {
+            Self::Button(index) => format!("button{}", index),
+            Self::RingCw => "ring1".to_string(),
+            Self::RingCcw => "ring0".to_string(),
+            Self::RingButton => "ringButton".to_string(),
+        }
+    }
+}
+
+impl DeviceProfile {
+    // 内置的Parblo Intangbo M的描述
+    pub fn intangbo_m() -> Self {
+        Self {
+            name: "  Parblo Intangbo  M(F7)".to_string(),
+            vendor_id: 0x0483,
+            product_id: 0xa013,
+            dongle_product_id: None,
+            bluetooth_product_id: None,
+            x_max_value: 0,
+            y_max_value: 0,
+            x_resolution: 0,
+            y_resolution: 0,
+            pressure_max_value: 0,
+            buttons: vec![
+                0x0100, 0x0200, 0x0400, 0x0800, 0x1000, 0x2000, 0x4000, 0x8000,
+            ],
+            ring_cw: Some(0x0801),
+            ring_ccw: Some(0x0802),
+            ring_button: Some(0x0803),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .context(format!("无法读取设备描述文件{}", path.display()))?;
+        toml::from_str(&content).context("设备描述文件的TOML解析失败")
+    }
+
+    pub fn lookup(&self, code: u16) -> Option<PadKey> {
+        if let Some(index) = self.buttons.iter().position(|x| *x == code) {
+            return Some(PadKey::Button(index));
+        }
+        match Some(code) {
+            x if x == self.ring_cw => Some(PadKey::RingCw),
+            x if x == self.ring_ccw => Some(PadKey::RingCcw),
+            x if x == self.ring_button => Some(PadKey::RingButton),
+            _ => None,
+        }
+    }
+}
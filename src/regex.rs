@@ -0,0 +1,394 @@
+use anyhow::{Result, anyhow};
+
+// 规则匹配使用的最小正则表达式实现（回溯匹配），支持：
+// 字符、.、字符类[a-z]与[^...]、\d \w \s及其大写形式、^ $、分组(...)与(?:...)、|、* + ?与{n} {n,} {n,m}量词；
+// 以(?i)开头时不区分大小写。与regex crate的is_match相同，只要输入中的某一部分匹配即可
+#[derive(Clone, Debug)]
+pub struct Regex {
+    pattern: String,
+    root: Node,
+    case_insensitive: bool,
+}
+#[derive(Clone, Debug)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        ranges: Vec<(char, char)>,
+        negated: bool,
+    },
+    Start,
+    End,
+    Group(Vec<Vec<Node>>), // 各个分支
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+    },
+}
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let (case_insensitive, rest) = match pattern.strip_prefix("(?i)") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+        let mut parser = Parser {
+            chars: rest.chars().collect(),
+            pos: 0,
+        };
+        let root = parser.parse_alternatives()?;
+        if parser.pos < parser.chars.len() {
+            return Err(anyhow!("正则表达式'{}'中存在多余的')'", pattern));
+        }
+        Ok(Self {
+            pattern: pattern.to_string(),
+            root,
+            case_insensitive,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, input: &str) -> bool {
+        let input: Vec<char> = input.chars().collect();
+        let matcher = Matcher {
+            input: &input,
+            case_insensitive: self.case_insensitive,
+        };
+        let root = std::slice::from_ref(&self.root);
+        (0..=input.len()).any(|start| matcher.match_here(root, start, &mut |_| true))
+    }
+}
+
+struct Matcher<'a> {
+    input: &'a [char],
+    case_insensitive: bool,
+}
+impl Matcher<'_> {
+    // 匹配nodes后，以匹配结束的位置调用k，由k决定是否继续回溯
+    fn match_here(&self, nodes: &[Node], pos: usize, k: &mut dyn FnMut(usize) -> bool) -> bool {
+        let Some((first, rest)) = nodes.split_first() else {
+            return k(pos);
+        };
+        match first {
+            Node::Start => pos == 0 && self.match_here(rest, pos, k),
+            Node::End => pos == self.input.len() && self.match_here(rest, pos, k),
+            Node::Group(alternatives) => alternatives
+                .iter()
+                .any(|nodes| self.match_here(nodes, pos, &mut |p| self.match_here(rest, p, k))),
+            Node::Repeat { .. } => self.match_repeat(first, 0, rest, pos, k),
+            node => match self.input.get(pos) {
+                Some(c) if self.match_char(node, *c) => self.match_here(rest, pos + 1, k),
+                _ => false,
+            },
+        }
+    }
+
+    // 贪婪匹配：优先尝试多匹配一次；count为已经匹配的次数
+    fn match_repeat(
+        &self,
+        repeat: &Node,
+        count: usize,
+        rest: &[Node],
+        pos: usize,
+        k: &mut dyn FnMut(usize) -> bool,
+    ) -> bool {
+        let Node::Repeat { node, min, max } = repeat else {
+            return false;
+        };
+        if max.is_none_or(|max| count < max)
+            && self.match_here(std::slice::from_ref(node), pos, &mut |p| {
+                // 不允许在达到最小次数后继续匹配空串，避免无限递归
+                (p != pos || count < *min) && self.match_repeat(repeat, count + 1, rest, p, k)
+            })
+        {
+            return true;
+        }
+        count >= *min && self.match_here(rest, pos, k)
+    }
+
+    fn match_char(&self, node: &Node, c: char) -> bool {
+        if !self.case_insensitive {
+            return match_char(node, c);
+        }
+        c.to_lowercase()
+            .chain(c.to_uppercase())
+            .any(|x| match_char(node, x))
+            || match_char(node, c)
+    }
+}
+
+fn match_char(node: &Node, c: char) -> bool {
+    match node {
+        Node::Char(x) => *x == c,
+        Node::Any => c != '\n',
+        Node::Class { ranges, negated } => {
+            ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated
+        }
+        _ => false,
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Result<char> {
+        let c = self.peek().ok_or(anyhow!("正则表达式不完整"))?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn parse_alternatives(&mut self) -> Result<Node> {
+        let mut alternatives = vec![self.parse_sequence()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.parse_sequence()?);
+        }
+        Ok(Node::Group(alternatives))
+    }
+
+    fn parse_sequence(&mut self) -> Result<Vec<Node>> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            let Some((min, max)) = self.parse_quantifier()? else {
+                nodes.push(atom);
+                continue;
+            };
+            if matches!(atom, Node::Start | Node::End) {
+                return Err(anyhow!("正则表达式第{}个字符处的量词无效", self.pos));
+            }
+            nodes.push(Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+            });
+        }
+        Ok(nodes)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node> {
+        match self.next()? {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '(' => {
+                if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                }
+                let group = self.parse_alternatives()?;
+                if self.next().ok() != Some(')') {
+                    return Err(anyhow!("正则表达式中缺少')'"));
+                }
+                Ok(group)
+            }
+            '[' => self.parse_class(),
+            '\\' => self.parse_escape(),
+            c @ ('*' | '+' | '?' | '{') => Err(anyhow!(
+                "正则表达式第{}个字符'{}'之前没有可重复的内容",
+                self.pos,
+                c
+            )),
+            '}' => Err(anyhow!(
+                "正则表达式第{}个字符'}}'没有对应的'{{'，作为普通字符时需要写成'\\}}'",
+                self.pos
+            )),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    // 解析原子之后的量词，返回(最小次数, 最大次数)
+    fn parse_quantifier(&mut self) -> Result<Option<(usize, Option<usize>)>> {
+        let bounds = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => return self.parse_bounds().map(Some),
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        Ok(Some(bounds))
+    }
+
+    // 解析{n}、{n,}与{n,m}
+    fn parse_bounds(&mut self) -> Result<(usize, Option<usize>)> {
+        let start = self.pos + 1;
+        let end = self.chars[start..]
+            .iter()
+            .position(|x| *x == '}')
+            .map(|x| start + x)
+            .ok_or(anyhow!("正则表达式第{}个字符处的'{{'缺少对应的'}}'", start))?;
+        let content: String = self.chars[start..end].iter().collect();
+        let parse = |x: &str| {
+            x.parse::<usize>()
+                .map_err(|_| anyhow!("正则表达式中的重复次数{{{}}}无效", content))
+        };
+        let bounds = match content.split_once(',') {
+            None => parse(&content).map(|n| (n, Some(n)))?,
+            Some((min, "")) => (parse(min)?, None),
+            Some((min, max)) => (parse(min)?, Some(parse(max)?)),
+        };
+        if bounds.1.is_some_and(|max| max < bounds.0) {
+            return Err(anyhow!("正则表达式中的重复次数{{{}}}无效", content));
+        }
+        self.pos = end + 1;
+        Ok(bounds)
+    }
+
+    fn parse_escape(&mut self) -> Result<Node> {
+        let c = self.next()?;
+        let (ranges, negated) = match c {
+            'd' | 'D' => (DIGIT.to_vec(), c == 'D'),
+            'w' | 'W' => (WORD.to_vec(), c == 'W'),
+            's' | 'S' => (SPACE.to_vec(), c == 'S'),
+            'n' => return Ok(Node::Char('\n')),
+            't' => return Ok(Node::Char('\t')),
+            c if c.is_ascii_alphanumeric() => {
+                return Err(anyhow!("正则表达式中的转义字符'\\{}'不受支持", c));
+            }
+            c => return Ok(Node::Char(c)),
+        };
+        Ok(Node::Class { ranges, negated })
+    }
+
+    fn parse_class(&mut self) -> Result<Node> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = vec![];
+        let mut first = true;
+        loop {
+            let c = self.next().map_err(|_| anyhow!("正则表达式中缺少']'"))?;
+            // 紧跟在[或[^之后的]作为普通字符
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = match c {
+                '\\' => match self.parse_escape()? {
+                    Node::Char(c) => c,
+                    Node::Class {
+                        ranges: escaped,
+                        negated: false,
+                    } => {
+                        ranges.extend(escaped);
+                        continue;
+                    }
+                    _ => return Err(anyhow!("字符类中不支持取反的转义字符")),
+                },
+                c => c,
+            };
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|x| *x != ']') {
+                self.pos += 1;
+                let hi = match self.next()? {
+                    '\\' => match self.parse_escape()? {
+                        Node::Char(c) => c,
+                        _ => return Err(anyhow!("字符类的范围无效")),
+                    },
+                    c => c,
+                };
+                if hi < lo {
+                    return Err(anyhow!("字符类的范围{}-{}无效", lo, hi));
+                }
+                ranges.push((lo, hi));
+            } else {
+                ranges.push((lo, lo));
+            }
+        }
+        Ok(Node::Class { ranges, negated })
+    }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[(' ', ' '), ('\t', '\r')];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, input: &str) -> bool {
+        Regex::new(pattern).unwrap().is_match(input)
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(is_match("Intangbo", "Parblo Intangbo M"));
+        assert!(is_match("^Parblo Intangbo M$", "Parblo Intangbo M"));
+        assert!(!is_match(
+            "^Parblo Intangbo M$",
+            "Parblo Intangbo M (Digitizer)"
+        ));
+        assert!(!is_match("^Intangbo", "Parblo Intangbo M"));
+        assert!(is_match("^$", ""));
+        assert!(Regex::new("^*").is_err());
+    }
+
+    #[test]
+    fn classes() {
+        assert!(is_match("^[a-c]+$", "abcab"));
+        assert!(!is_match("^[a-c]+$", "abd"));
+        assert!(is_match("^[^0-9]$", "x"));
+        assert!(!is_match("^[^0-9]$", "5"));
+        assert!(is_match("^[]x]$", "]"));
+        assert!(is_match(r"^[\d_]+$", "12_3"));
+        assert!(is_match(r"^\w+\s\d$", "abc 5"));
+        assert!(!is_match(r"^\S+$", "a b"));
+        assert!(is_match("^a.c$", "abc"));
+        assert!(!is_match("^a.c$", "a\nc"));
+    }
+
+    #[test]
+    fn alternation_and_groups() {
+        assert!(is_match("^(krita|gimp)$", "gimp"));
+        assert!(!is_match("^(krita|gimp)$", "inkscape"));
+        assert!(is_match("^(?:ab)+$", "ababab"));
+        assert!(is_match("^a|b$", "ax"));
+        assert!(is_match("^(a|ab)c$", "abc"));
+    }
+
+    #[test]
+    fn quantifiers() {
+        assert!(is_match("^ab*c$", "ac"));
+        assert!(is_match("^ab+c$", "abbc"));
+        assert!(!is_match("^ab+c$", "ac"));
+        assert!(is_match("^ab?c$", "abc"));
+        assert!(!is_match("^ab?c$", "abbc"));
+        assert!(is_match(r"^\d{4}$", "2024"));
+        assert!(!is_match(r"^\d{4}$", "202"));
+        assert!(is_match(r"^\d{2,}$", "123456"));
+        assert!(is_match(r"^\d{1,3}$", "12"));
+        assert!(!is_match(r"^\d{1,3}$", "1234"));
+        assert!(is_match(r"^\{\}$", "{}"));
+        assert!(is_match("^(a*)*$", "aaa"));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert!(is_match("(?i)^krita$", "Krita"));
+        assert!(!is_match("^krita$", "Krita"));
+        assert!(is_match("(?i)^[a-z]+$", "ABC"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in [
+            "(abc", "abc)", "[abc", "*a", "a|+", r"\q", "[z-a]", r"[\D]", "\\", "a{", "a{x}",
+            "a{3,2}", "{2}", "a}",
+        ] {
+            assert!(Regex::new(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::cell::Cell;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow};
+use rusb::Error as UsbError;
+
+use crate::cancel::CancelToken;
+use crate::config::Config;
+use crate::info;
+use crate::io::InputSource;
+
+// record子命令写入的记录（也兼容capture-profile生成的fixture.log）中的一个原始报告
+pub struct RecordedReport {
+    pub time: Duration, // 相对于第一个报告的时间
+    pub report: Vec<u8>,
+}
+
+// 每行为「毫秒时间戳 原始报告的十六进制」；忽略空行与以#开头的注释
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<RecordedReport>> {
+    let path = path.as_ref();
+    let content =
+        std::fs::read_to_string(path).context(format!("无法读取记录文件{}", path.display()))?;
+    let mut reports = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let time = parts
+            .next()
+            .and_then(|x| x.parse().ok())
+            .ok_or_else(|| anyhow!("记录文件第{}行的时间戳无效", i + 1))?;
+        let report = parts
+            .map(|x| u8::from_str_radix(x, 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow!("记录文件第{}行的报告不是有效的十六进制", i + 1))?;
+        if report.is_empty() {
+            return Err(anyhow!("记录文件第{}行没有报告", i + 1));
+        }
+        reports.push(RecordedReport {
+            time: Duration::from_millis(time),
+            report,
+        });
+    }
+    if reports.is_empty() {
+        return Err(anyhow!("记录文件{}中没有报告", path.display()));
+    }
+    Ok(reports)
+}
+
+// 不创建虚拟设备时无法从事件设备获得坐标与压力的最大值；
+// 设备描述与配置中均未指定时，使用记录中观测到的最大值
+pub fn fill_missing_ranges(conf: &mut Config, reports: &[RecordedReport]) {
+    let (mut x_max, mut y_max, mut pressure_max) = (0, 0, 0);
+    for report in reports {
+        let buf = &report.report;
+        if buf.len() < 8 || buf[0] != 0x02 || !matches!(buf[1] & 0xf0, 0xa0 | 0xc0) {
+            continue;
+        }
+        // 调换原始输入的X、Y坐标
+        y_max = y_max.max(u16::from_le_bytes([buf[2], buf[3]]));
+        x_max = x_max.max(u16::from_le_bytes([buf[4], buf[5]]));
+        pressure_max = pressure_max.max(u16::from_le_bytes([buf[6], buf[7]]));
+    }
+    for (name, field, observed) in [
+        ("X坐标", &mut conf.x_max_value, x_max),
+        ("Y坐标", &mut conf.y_max_value, y_max),
+        ("压力", &mut conf.pressure_max_value, pressure_max),
+    ] {
+        if *field == 0 {
+            info!(
+                "未指定{}的最大值，使用记录中观测到的最大值{}",
+                name, observed
+            );
+            *field = observed.max(1);
+        }
+    }
+}
+
+// 按记录中的时间间隔依次返回记录的报告，代替与设备之间的连接
+pub struct ReplaySource {
+    cancel_token: CancelToken, // 取消时不再等待记录中较长的时间间隔
+    reports: Vec<RecordedReport>,
+    next: Cell<usize>,
+    started_at: Instant,
+}
+impl ReplaySource {
+    pub fn new(cancel_token: CancelToken, reports: Vec<RecordedReport>) -> Self {
+        Self {
+            cancel_token,
+            reports,
+            next: Cell::new(0),
+            started_at: Instant::now(),
+        }
+    }
+}
+impl InputSource for ReplaySource {
+    fn kind(&self) -> &'static str {
+        "replay"
+    }
+
+    fn read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize, UsbError> {
+        let Some(recorded) = self.reports.get(self.next.get()) else {
+            return Err(UsbError::Timeout);
+        };
+        let remaining = (self.started_at + recorded.time).saturating_duration_since(Instant::now());
+        if remaining > timeout {
+            self.cancel_token.wait_timeout(timeout);
+            return Err(UsbError::Timeout);
+        }
+        if self.cancel_token.wait_timeout(remaining) {
+            return Err(UsbError::Timeout);
+        }
+        let len = recorded.report.len().min(buf.len());
+        buf[..len].copy_from_slice(&recorded.report[..len]);
+        self.next.set(self.next.get() + 1);
+        Ok(len)
+    }
+
+    fn finished(&self) -> bool {
+        self.next.get() >= self.reports.len()
+    }
+}
@@ -0,0 +1,129 @@
+use std::str::FromStr;
+
+use anyhow::{Error, Result, anyhow};
+
+use crate::regex::Regex;
+use crate::screen::FocusedWindow;
+
+// 自动切换规则：所有已配置的条件都满足时生效，同时选择按键映射方案、区域预设与压力曲线；
+// 由[[rule]]配置，[[appProfile]]也会转换为只包含app条件与schema的规则
+#[derive(Clone)]
+pub struct Rule {
+    pub name: Option<String>, // 用于日志输出
+    pub app: Option<String>,  // 与窗口的WM_CLASS（实例名或类名）完全相同，不区分大小写
+    pub class: Option<Regex>, // 匹配窗口的WM_CLASS（实例名或类名）
+    pub title: Option<Regex>, // 匹配窗口的标题
+    pub monitor: Option<String>,
+    pub time: Option<TimeRange>,
+    pub schema: Option<usize>,
+    pub area_preset: Option<usize>,
+    pub pressure_curve: Option<f32>,
+}
+// 规则匹配时参考的环境
+pub struct Environment<'a> {
+    pub window: Option<&'a FocusedWindow>,
+    pub monitor: Option<&'a str>, // 绘图板当前映射到的显示器
+    pub minute: u16,              // 本地时间，从0点开始的分钟数
+}
+impl Rule {
+    // 是否需要查询获得焦点的窗口
+    pub fn needs_window(&self) -> bool {
+        self.app.is_some() || self.class.is_some() || self.title.is_some()
+    }
+
+    pub fn matches(&self, env: &Environment) -> bool {
+        if self.needs_window() {
+            let Some(window) = env.window else {
+                return false;
+            };
+            if let Some(app) = &self.app
+                && !app.eq_ignore_ascii_case(&window.instance)
+                && !app.eq_ignore_ascii_case(&window.class)
+            {
+                return false;
+            }
+            if let Some(class) = &self.class
+                && !class.is_match(&window.instance)
+                && !class.is_match(&window.class)
+            {
+                return false;
+            }
+            if let Some(title) = &self.title
+                && !title.is_match(&window.title)
+            {
+                return false;
+            }
+        }
+        if let Some(monitor) = &self.monitor
+            && env.monitor != Some(monitor.as_str())
+        {
+            return false;
+        }
+        if let Some(time) = &self.time
+            && !time.contains(env.minute)
+        {
+            return false;
+        }
+        true
+    }
+
+    pub fn describe(&self, index: usize) -> String {
+        match &self.name {
+            Some(name) => format!("规则{}（{}）", index, name),
+            None => format!("规则{}", index),
+        }
+    }
+}
+
+// 一天中的时间段，格式为"HH:MM-HH:MM"；结束时间早于开始时间时表示跨越0点，例如"22:00-06:00"
+#[derive(Clone, Copy)]
+pub struct TimeRange {
+    start: u16,
+    end: u16,
+}
+impl TimeRange {
+    pub fn contains(&self, minute: u16) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute)
+        } else {
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+impl FromStr for TimeRange {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let err = || anyhow!("'{}'不是有效的时间段（格式：HH:MM-HH:MM）", s);
+        let parse = |x: &str| -> Result<u16> {
+            let (hour, minute) = x.trim().split_once(':').ok_or_else(err)?;
+            let hour: u16 = hour.parse().map_err(|_| err())?;
+            let minute: u16 = minute.parse().map_err(|_| err())?;
+            if hour > 24 || minute > 59 || (hour == 24 && minute > 0) {
+                return Err(err());
+            }
+            Ok(hour * 60 + minute)
+        };
+        let (start, end) = s.split_once('-').ok_or_else(err)?;
+        let range = Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        };
+        if range.start == range.end {
+            return Err(err());
+        }
+        Ok(range)
+    }
+}
+
+// 本地时间，从0点开始的分钟数
+pub fn local_minute_of_day() -> u16 {
+    // SAFETY: localtime_r只写入传入的tm结构体
+    unsafe {
+        let now = nix::libc::time(std::ptr::null_mut());
+        let mut tm: nix::libc::tm = std::mem::zeroed();
+        if nix::libc::localtime_r(&now, &mut tm).is_null() {
+            return 0;
+        }
+        (tm.tm_hour * 60 + tm.tm_min) as u16
+    }
+}
@@ -0,0 +1,283 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Result, anyhow};
+use nix::errno::Errno;
+use nix::libc::{self, sock_filter, sock_fprog};
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e; // AUDIT_ARCH_X86_64
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7; // AUDIT_ARCH_AARCH64
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const AUDIT_ARCH: u32 = 0; // 不支持，install直接返回错误
+
+// x86_64上x32 ABI的系统调用号带有该标志，架构字段却与x86_64相同，需要单独拒绝
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+// seccomp_data中各字段的偏移；参数为64位，取低32位（小端）
+const OFFSET_NR: u32 = 0;
+const OFFSET_ARCH: u32 = 4;
+const OFFSET_ARG0: u32 = 16;
+
+// 事件循环与各任务在初始化之后用到的系统调用：读写设备与文件、epoll、inotify、eventfd、
+// 控制套接字、线程同步、内存分配、时钟与信号；不包括创建进程与执行程序
+const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_pread64,
+    libc::SYS_pwrite64,
+    libc::SYS_openat,
+    libc::SYS_close,
+    libc::SYS_close_range,
+    libc::SYS_newfstatat,
+    libc::SYS_fstat,
+    libc::SYS_statx,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_lseek,
+    libc::SYS_ioctl,
+    libc::SYS_fcntl,
+    libc::SYS_flock,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_getdents64,
+    libc::SYS_readlinkat,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat,
+    libc::SYS_renameat2,
+    libc::SYS_fchmod,
+    libc::SYS_fchmodat,
+    libc::SYS_ftruncate,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_getcwd,
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_pwait2,
+    libc::SYS_eventfd2,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_settime,
+    libc::SYS_timerfd_gettime,
+    libc::SYS_inotify_init1,
+    libc::SYS_inotify_add_watch,
+    libc::SYS_inotify_rm_watch,
+    libc::SYS_signalfd4,
+    libc::SYS_bind,
+    libc::SYS_accept4,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_shutdown,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_rt_sigtimedwait,
+    libc::SYS_sigaltstack,
+    libc::SYS_tgkill,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_getrandom,
+    libc::SYS_futex,
+    libc::SYS_futex_waitv,
+    libc::SYS_set_robust_list,
+    libc::SYS_get_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_nanosleep,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_getres,
+    libc::SYS_gettimeofday,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_prctl,
+    libc::SYS_prlimit64,
+    libc::SYS_uname,
+    libc::SYS_restart_syscall,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+];
+
+// 旧接口，只存在于x86_64；glibc与libusb在部分版本中仍会使用
+#[cfg(target_arch = "x86_64")]
+const ALLOWED_LEGACY_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_open,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_poll,
+    libc::SYS_pipe,
+    libc::SYS_access,
+    libc::SYS_readlink,
+    libc::SYS_unlink,
+    libc::SYS_rename,
+    libc::SYS_mkdir,
+    libc::SYS_epoll_wait,
+    libc::SYS_time,
+];
+#[cfg(not(target_arch = "x86_64"))]
+const ALLOWED_LEGACY_SYSCALLS: &[libc::c_long] = &[];
+
+// 是否已经安装了seccomp过滤器；安装后无法再运行外部程序
+pub fn installed() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+// 初始化完成后安装seccomp过滤器：只允许ALLOWED_SYSCALLS中的系统调用，其余的返回EPERM；
+// 套接字只能创建Unix域与netlink套接字，clone只能创建线程。以TSYNC方式安装，
+// 对已经创建的线程（例如libusb的事件线程）同样生效；过滤器会被子进程继承，因此需要运行外部程序时不能启用
+pub fn install() -> Result<()> {
+    if !cfg!(any(target_arch = "x86_64", target_arch = "aarch64")) {
+        return Err(anyhow!("不支持当前的CPU架构"));
+    }
+    let program = build_filter();
+    let prog = sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_ptr() as *mut sock_filter,
+    };
+    // SAFETY: prog指向的过滤器在调用期间有效，内核会复制一份
+    let result = unsafe {
+        if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            return Err(anyhow!("无法设置PR_SET_NO_NEW_PRIVS: {}", Errno::last()));
+        }
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &prog as *const sock_fprog,
+        )
+    };
+    // TSYNC失败时返回无法同步的线程ID
+    match result {
+        0 => {
+            INSTALLED.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        x if x > 0 => Err(anyhow!("无法对线程{}应用seccomp过滤器", x)),
+        _ => Err(anyhow!("无法安装seccomp过滤器: {}", Errno::last())),
+    }
+}
+
+fn build_filter() -> Vec<sock_filter> {
+    let errno = |e: Errno| libc::SECCOMP_RET_ERRNO | e as u32;
+    let mut program = vec![
+        // 架构不符时系统调用号的含义不同，直接结束进程
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, OFFSET_ARCH),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            AUDIT_ARCH,
+            1,
+            0,
+        ),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, OFFSET_NR),
+        jump(
+            libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K,
+            X32_SYSCALL_BIT,
+            0,
+            1,
+        ),
+        stmt(libc::BPF_RET | libc::BPF_K, errno(Errno::EPERM)),
+    ];
+    for &nr in ALLOWED_SYSCALLS.iter().chain(ALLOWED_LEGACY_SYSCALLS) {
+        program.push(jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            nr as u32,
+            0,
+            1,
+        ));
+        program.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+    }
+    // clone3的参数位于内存中，无法检查；返回ENOSYS使glibc退回到clone
+    program.extend([
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::SYS_clone3 as u32,
+            0,
+            1,
+        ),
+        stmt(libc::BPF_RET | libc::BPF_K, errno(Errno::ENOSYS)),
+    ]);
+    // 以下的检查会覆盖累加器中的系统调用号，每个分支都直接返回
+    program.extend([
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::SYS_clone as u32,
+            0,
+            4,
+        ),
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, OFFSET_ARG0),
+        jump(
+            libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K,
+            libc::CLONE_THREAD as u32,
+            1,
+            0,
+        ),
+        stmt(libc::BPF_RET | libc::BPF_K, errno(Errno::EPERM)),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+    ]);
+    program.extend([
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::SYS_socket as u32,
+            0,
+            4,
+        ),
+        stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, OFFSET_ARG0),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::AF_UNIX as u32,
+            3,
+            0,
+        ),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            libc::AF_NETLINK as u32,
+            2,
+            0,
+        ),
+        stmt(libc::BPF_RET | libc::BPF_K, errno(Errno::EAFNOSUPPORT)),
+    ]);
+    program.extend([
+        stmt(libc::BPF_RET | libc::BPF_K, errno(Errno::EPERM)),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+    ]);
+    program
+}
+
+fn stmt(code: u32, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
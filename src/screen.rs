@@ -0,0 +1,332 @@
+use std::process::Command;
+use std::str::FromStr;
+
+use anyhow::{Context, Error, Result, anyhow};
+
+#[derive(Clone, Copy)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+impl FromStr for Geometry {
+    type Err = Error;
+    // 格式同xrandr：WIDTHxHEIGHT+X+Y
+    fn from_str(s: &str) -> Result<Self> {
+        let err = || anyhow!("'{}'不是有效的几何区域（格式：宽x高+X+Y）", s);
+        let (size, offset) = s.split_once('+').ok_or_else(err)?;
+        let (width, height) = size.split_once('x').ok_or_else(err)?;
+        let (x, y) = offset.split_once('+').ok_or_else(err)?;
+        let geometry = Self {
+            x: x.trim().parse().map_err(|_| err())?,
+            y: y.trim().parse().map_err(|_| err())?,
+            width: width.trim().parse().map_err(|_| err())?,
+            height: height.trim().parse().map_err(|_| err())?,
+        };
+        if geometry.width == 0 || geometry.height == 0 {
+            return Err(err());
+        }
+        Ok(geometry)
+    }
+}
+
+pub struct Monitor {
+    pub name: String,
+    pub geometry: Geometry,
+}
+
+pub fn is_wayland() -> bool {
+    std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+// 查询当前桌面环境的显示器布局；Wayland下使用wlr-randr，X11下使用xrandr
+pub fn query_monitors() -> Result<Vec<Monitor>> {
+    let monitors = if is_wayland() {
+        let output = run_command("wlr-randr", &[])?;
+        parse_wlr_randr_output(&output)
+    } else {
+        let output = run_command("xrandr", &["--query"])?;
+        parse_xrandr_output(&output)
+    };
+    if monitors.is_empty() {
+        return Err(anyhow!("没有查询到任何已启用的显示器"));
+    }
+    Ok(monitors)
+}
+
+// 查询鼠标指针在桌面中的位置；目前仅支持X11（通过xdotool）
+pub fn query_pointer() -> Result<(i32, i32)> {
+    if is_wayland() {
+        return Err(anyhow!("Wayland下不支持查询鼠标指针的位置"));
+    }
+    // 例：X=1234\nY=567\nSCREEN=0\nWINDOW=41943046
+    let output = run_command("xdotool", &["getmouselocation", "--shell"])?;
+    let mut x = None;
+    let mut y = None;
+    for line in output.lines() {
+        match line.split_once('=') {
+            Some(("X", value)) => x = value.trim().parse().ok(),
+            Some(("Y", value)) => y = value.trim().parse().ok(),
+            _ => {}
+        }
+    }
+    match (x, y) {
+        (Some(x), Some(y)) => Ok((x, y)),
+        _ => Err(anyhow!("无法解析xdotool的输出: {}", output.trim())),
+    }
+}
+
+// 获得焦点的窗口的WM_CLASS与标题
+#[derive(Clone, PartialEq)]
+pub struct FocusedWindow {
+    pub instance: String, // WM_CLASS的第一个字段，例如"krita"
+    pub class: String,    // WM_CLASS的第二个字段，例如"Krita"
+    pub title: String,
+}
+
+// 查询获得焦点的窗口；目前仅支持X11（通过xdotool与xprop）。没有窗口获得焦点时返回None
+pub fn query_focused_window() -> Result<Option<FocusedWindow>> {
+    if is_wayland() {
+        return Err(anyhow!("Wayland下不支持查询获得焦点的窗口"));
+    }
+    let output = Command::new("xdotool")
+        .arg("getactivewindow")
+        .output()
+        .context("无法执行xdotool")?;
+    // 焦点在桌面（根窗口）上时xdotool执行失败，且不输出窗口ID
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    let window_id = output.trim();
+    if window_id.is_empty() {
+        return Ok(None);
+    }
+    // 例：
+    // WM_CLASS(STRING) = "krita", "Krita"
+    // _NET_WM_NAME(UTF8_STRING) = "image.kra - Krita"
+    // WM_NAME:  not found.
+    let output = run_command(
+        "xprop",
+        &["-id", window_id, "WM_CLASS", "_NET_WM_NAME", "WM_NAME"],
+    )?;
+    let mut class = None;
+    let mut title = None;
+    for line in output.lines() {
+        let Some((property, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        if property.starts_with("WM_CLASS(") {
+            let mut fields = value
+                .split(',')
+                .map(|x| x.trim().trim_matches('"').to_string());
+            let instance = fields.next().unwrap_or_default();
+            let class_name = fields.next().unwrap_or_else(|| instance.clone());
+            class = Some((instance, class_name));
+        } else if property.starts_with("_NET_WM_NAME(")
+            || (property.starts_with("WM_NAME(") && title.is_none())
+        {
+            title = Some(value.trim().trim_matches('"').replace("\\\"", "\""));
+        }
+    }
+    // 窗口没有设置WM_CLASS
+    let Some((instance, class)) = class else {
+        return Ok(None);
+    };
+    Ok(Some(FocusedWindow {
+        instance,
+        class,
+        title: title.unwrap_or_default(),
+    }))
+}
+
+fn run_command(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .context(format!("无法执行{}", program))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}执行失败: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// 例：DP-1 connected primary 2560x1440+1920+0 (normal left inverted right x axis y axis) 597mm x 336mm
+fn parse_xrandr_output(output: &str) -> Vec<Monitor> {
+    let mut monitors = vec![];
+    for line in output.lines() {
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let mut tokens = line.split_whitespace();
+        let (Some(name), Some("connected")) = (tokens.next(), tokens.next()) else {
+            continue;
+        };
+        if let Some(geometry) = tokens.find_map(|x| x.parse::<Geometry>().ok()) {
+            monitors.push(Monitor {
+                name: name.to_string(),
+                geometry,
+            });
+        }
+    }
+    monitors
+}
+
+// 例：
+// DP-1 "Dell Inc. DELL U2720Q"
+//   Enabled: yes
+//   Modes:
+//     3840x2160 px, 59.997002 Hz (preferred, current)
+//   Position: 1920,0
+//   Transform: normal
+//   Scale: 1.500000
+fn parse_wlr_randr_output(output: &str) -> Vec<Monitor> {
+    struct Output<'a> {
+        name: &'a str,
+        enabled: bool,
+        mode: Option<(u32, u32)>,
+        position: Option<(i32, i32)>,
+        rotated: bool,
+        scale: f32,
+    }
+    let mut outputs: Vec<Output> = vec![];
+    for line in output.lines() {
+        if !line.starts_with(char::is_whitespace) {
+            if let Some(name) = line.split_whitespace().next() {
+                outputs.push(Output {
+                    name,
+                    enabled: false,
+                    mode: None,
+                    position: None,
+                    rotated: false,
+                    scale: 1f32,
+                });
+            }
+            continue;
+        }
+        let Some(current) = outputs.last_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("Enabled:") {
+            current.enabled = value.trim() == "yes";
+        } else if let Some(value) = line.strip_prefix("Position:") {
+            current.position = value
+                .trim()
+                .split_once(',')
+                .and_then(|(x, y)| Some((x.parse().ok()?, y.parse().ok()?)));
+        } else if let Some(value) = line.strip_prefix("Transform:") {
+            current.rotated = matches!(value.trim(), "90" | "270" | "flipped-90" | "flipped-270");
+        } else if let Some(value) = line.strip_prefix("Scale:") {
+            current.scale = value.trim().parse().unwrap_or(1f32);
+        } else if line.contains("current") {
+            current.mode = line
+                .split_whitespace()
+                .next()
+                .and_then(|x| x.split_once('x'))
+                .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+        }
+    }
+
+    let mut monitors = vec![];
+    for output in outputs {
+        let (true, Some((width, height)), Some((x, y))) =
+            (output.enabled, output.mode, output.position)
+        else {
+            continue;
+        };
+        let (width, height) = if output.rotated {
+            (height, width)
+        } else {
+            (width, height)
+        };
+        // Wayland下的位置使用逻辑坐标，需要按缩放比例换算显示器的尺寸
+        let scale = if output.scale > 0f32 {
+            output.scale
+        } else {
+            1f32
+        };
+        monitors.push(Monitor {
+            name: output.name.to_string(),
+            geometry: Geometry {
+                x,
+                y,
+                width: (width as f32 / scale).round() as u32,
+                height: (height as f32 / scale).round() as u32,
+            },
+        });
+    }
+    monitors
+}
+
+// 所有显示器组成的桌面区域
+pub fn desktop_geometry(monitors: &[Monitor]) -> Option<Geometry> {
+    let min_x = monitors.iter().map(|m| m.geometry.x).min()?;
+    let min_y = monitors.iter().map(|m| m.geometry.y).min()?;
+    let max_x = monitors
+        .iter()
+        .map(|m| m.geometry.x + m.geometry.width as i32)
+        .max()?;
+    let max_y = monitors
+        .iter()
+        .map(|m| m.geometry.y + m.geometry.height as i32)
+        .max()?;
+    Some(Geometry {
+        x: min_x,
+        y: min_y,
+        width: (max_x - min_x) as u32,
+        height: (max_y - min_y) as u32,
+    })
+}
+
+// 计算将绘图板映射到桌面中指定区域时所需的xMap、yMap
+pub fn compute_map(desktop: Geometry, target: Geometry) -> Result<((f32, f32), (f32, f32))> {
+    let ratio = |offset: i32, origin: i32, total: u32| {
+        ((offset - origin) as f32 / total as f32).clamp(0f32, 1f32)
+    };
+    let x_map = (
+        ratio(target.x, desktop.x, desktop.width),
+        ratio(target.x + target.width as i32, desktop.x, desktop.width),
+    );
+    let y_map = (
+        ratio(target.y, desktop.y, desktop.height),
+        ratio(target.y + target.height as i32, desktop.y, desktop.height),
+    );
+    if x_map.0 >= x_map.1 || y_map.0 >= y_map.1 {
+        return Err(anyhow!("目标区域不在桌面范围内"));
+    }
+    // Y轴在上报前会被翻转，因此需要同样翻转映射区间
+    let y_map = (1f32 - y_map.1, 1f32 - y_map.0);
+    Ok((x_map, y_map))
+}
+
+// 将绘图板映射到某个显示器时所需的xMap、yMap
+#[derive(Clone)]
+pub struct MonitorMap {
+    pub name: String,
+    pub x_map: (f32, f32),
+    pub y_map: (f32, f32),
+}
+
+// 按显示器布局从左到右、从上到下的顺序，预先计算映射到每个显示器时所需的xMap、yMap
+pub fn monitor_maps() -> Result<Vec<MonitorMap>> {
+    let mut monitors = query_monitors()?;
+    monitors.sort_by_key(|x| (x.geometry.x, x.geometry.y));
+    let desktop = desktop_geometry(&monitors).context("无法计算桌面区域")?;
+    let mut result = Vec::with_capacity(monitors.len());
+    for monitor in monitors {
+        let (x_map, y_map) = compute_map(desktop, monitor.geometry)
+            .with_context(|| format!("无法计算显示器'{}'的映射区域", monitor.name))?;
+        result.push(MonitorMap {
+            name: monitor.name,
+            x_map,
+            y_map,
+        });
+    }
+    Ok(result)
+}
@@ -0,0 +1,134 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::cli::InstallServiceArgs;
+use crate::{paths, udev};
+
+pub const SUBCOMMAND: &str = "install-service";
+
+const UNIT_NAME: &str = "parblo-intangbo-m-driver.service";
+const SYSTEM_UNIT_DIR: &str = "/etc/systemd/system";
+
+// install-service子命令：生成以当前程序与指定的配置文件运行的systemd服务，写入系统的服务目录
+// （--user时为当前用户的服务目录）并重新加载；--enable时同时启用并启动服务，--print时只打印服务文件
+pub fn install(args: InstallServiceArgs) -> Result<()> {
+    let InstallServiceArgs {
+        user,
+        enable,
+        print,
+        config,
+    } = args;
+    let mut conf_path = config.path();
+    // 服务的工作目录为/，相对路径需要先转换为绝对路径
+    if let Some(path) = &mut conf_path {
+        if !path.is_file() {
+            return Err(anyhow!("配置文件{}不存在", path.display()));
+        }
+        *path = std::path::absolute(&*path).context("无法解析配置文件的路径")?;
+    }
+    let exe = std::env::current_exe().context("无法获取当前程序的路径")?;
+    let unit = render_unit(&exe, conf_path.as_deref(), user);
+    if print {
+        print!("{}", unit);
+        return Ok(());
+    }
+
+    let dir = match user {
+        true => paths::user_unit_dir(),
+        false => PathBuf::from(SYSTEM_UNIT_DIR),
+    };
+    std::fs::create_dir_all(&dir).with_context(|| format!("无法创建目录{}", dir.display()))?;
+    let path = dir.join(UNIT_NAME);
+    std::fs::write(&path, &unit).with_context(|| {
+        format!(
+            "无法写入{}（系统服务需要root权限，或使用--print打印服务文件）",
+            path.display()
+        )
+    })?;
+    eprintln!("已写入{}", path.display());
+    systemctl(user, &["daemon-reload"])?;
+    // 连接设备后才会发送READY=1，不等待启动完成
+    if enable {
+        systemctl(user, &["enable", "--now", "--no-block", UNIT_NAME])?;
+        eprintln!("已启用并启动{}", UNIT_NAME);
+    } else {
+        eprintln!(
+            "可以通过systemctl {}enable --now {}启用并启动服务",
+            if user { "--user " } else { "" },
+            UNIT_NAME
+        );
+    }
+    if user {
+        eprintln!(
+            "用户服务以当前用户运行，需要能够访问绘图板与uinput，参见{}子命令",
+            udev::SUBCOMMAND
+        );
+    }
+    Ok(())
+}
+
+// 与README中的示例相同：Type=notify，在连接设备后才就绪，因此不限制启动时间；
+// 用户服务随图形会话启动与停止，以便查询显示器布局、发送通知等
+fn render_unit(exe: &Path, conf_path: Option<&Path>, user: bool) -> String {
+    let mut exec_start = quote(&exe.to_string_lossy());
+    if let Some(path) = conf_path {
+        exec_start.push(' ');
+        exec_start.push_str(&quote(&path.to_string_lossy()));
+    }
+    let (after, install) = match user {
+        true => (
+            "After=graphical-session.target\nPartOf=graphical-session.target",
+            "WantedBy=graphical-session.target",
+        ),
+        false => (
+            "After=systemd-udevd.service systemd-modules-load.service",
+            "WantedBy=multi-user.target",
+        ),
+    };
+    format!(
+        "# 由{} {}生成
+[Unit]
+Description=Parblo Intangbo M绘图板驱动程序
+{}
+
+[Service]
+Type=notify
+ExecStart={}
+Restart=on-failure
+RestartSec=2
+WatchdogSec=10
+TimeoutStartSec=infinity
+
+[Install]
+{}
+",
+        env!("CARGO_PKG_NAME"),
+        SUBCOMMAND,
+        after,
+        exec_start,
+        install
+    )
+}
+
+// 按systemd的命令行语法引用参数：包含空白或引号时加上双引号；%需要写成%%
+fn quote(arg: &str) -> String {
+    let arg = arg.replace('%', "%%");
+    if !arg.contains(|x: char| x.is_whitespace() || x == '"' || x == '\'' || x == '\\') {
+        return arg;
+    }
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn systemctl(user: bool, args: &[&str]) -> Result<()> {
+    let mut command = Command::new("systemctl");
+    if user {
+        command.arg("--user");
+    }
+    let status = command.args(args).status().context("无法运行systemctl")?;
+    if !status.success() {
+        return Err(anyhow!("systemctl {}失败：{}", args.join(" "), status));
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+use nix::errno::Errno;
+use nix::sys::inotify::{self, AddWatchFlags, Inotify};
+use nix::unistd::{User, getuid};
+
+use crate::event_loop::EventHandler;
+use crate::info;
+
+// logind在这些目录中为每个会话、座位维护一个key=value格式的状态文件（先写入临时文件再重命名）
+const SESSIONS_DIR: &str = "/run/systemd/sessions";
+const SEATS_DIR: &str = "/run/systemd/seats";
+const SEAT: &str = "seat0";
+
+type SessionCallback = Box<dyn FnMut(bool) + Send>;
+
+// 判断是否应当注入事件的依据
+enum Target {
+    Session(String), // 程序所在的登录会话：该会话处于活动状态时
+    User(u32),       // 不属于任何会话时：seat0上的活动会话属于该用户时
+}
+
+// 监视logind的会话状态，在切换虚拟终端、切换用户等使会话变为非活动状态时通知驱动任务暂停，
+// 避免把按键输入到其他用户的会话中
+pub struct SessionWatchTask {
+    target: Target,
+    inotify: Inotify,
+    active: bool,
+    callbacks: Vec<SessionCallback>,
+}
+impl SessionWatchTask {
+    // 优先使用程序所在的会话；不属于任何会话时（例如作为用户服务或系统服务运行）使用session_user，
+    // 未配置时使用当前用户（root除外）
+    pub fn new(session_user: Option<&str>) -> Result<Self> {
+        let target = match (own_session(), session_user) {
+            (Some(id), _) => Target::Session(id),
+            (None, Some(name)) => {
+                let user = User::from_name(name)
+                    .context("无法查询用户信息")?
+                    .ok_or_else(|| anyhow!("用户'{}'不存在", name))?;
+                Target::User(user.uid.as_raw())
+            }
+            (None, None) if !getuid().is_root() => Target::User(getuid().as_raw()),
+            (None, None) => {
+                return Err(anyhow!(
+                    "程序以root身份运行且不属于任何登录会话（例如作为系统服务运行），需要配置sessionUser"
+                ));
+            }
+        };
+        let inotify = Inotify::init(inotify::InitFlags::all()).context("Inotify::init")?;
+        let flags =
+            AddWatchFlags::IN_MOVED_TO | AddWatchFlags::IN_CLOSE_WRITE | AddWatchFlags::IN_DELETE;
+        let dir = match target {
+            Target::Session(_) => SESSIONS_DIR,
+            Target::User(_) => SEATS_DIR,
+        };
+        inotify
+            .add_watch(dir, flags)
+            .with_context(|| format!("无法监视{}（系统未使用systemd-logind？）", dir))?;
+        let mut task = Self {
+            target,
+            inotify,
+            active: true,
+            callbacks: Vec::new(),
+        };
+        task.active = task.query_active();
+        match &task.target {
+            Target::Session(id) => info!("跟随登录会话{}的活动状态", id),
+            Target::User(uid) => info!("跟随{}上用户{}的会话的活动状态", SEAT, uid),
+        }
+        Ok(task)
+    }
+
+    // 注册时立即以当前状态调用一次
+    pub fn register_callback<F>(&mut self, mut f: F)
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        f(self.active);
+        self.callbacks.push(Box::new(f));
+    }
+
+    // 状态文件暂时不存在（例如会话已结束）时视为非活动
+    fn query_active(&self) -> bool {
+        match &self.target {
+            Target::Session(id) => {
+                read_field(&Path::new(SESSIONS_DIR).join(id), "ACTIVE").as_deref() == Some("1")
+            }
+            Target::User(uid) => read_field(&Path::new(SEATS_DIR).join(SEAT), "ACTIVE_UID")
+                .is_some_and(|x| x == uid.to_string()),
+        }
+    }
+}
+impl EventHandler for SessionWatchTask {
+    fn fds(&self) -> Vec<BorrowedFd<'_>> {
+        vec![self.inotify.as_fd()]
+    }
+
+    fn handle(&mut self, _: usize) -> Result<()> {
+        loop {
+            match self.inotify.read_events() {
+                Ok(_) => continue,
+                Err(Errno::EAGAIN) => break,
+                Err(e) => Err(e).context("Inotify::read_events")?,
+            }
+        }
+        let active = self.query_active();
+        if active == self.active {
+            return Ok(());
+        }
+        self.active = active;
+        for callback in &mut self.callbacks {
+            callback(active);
+        }
+        Ok(())
+    }
+}
+
+// 程序所在的会话：XDG_SESSION_ID，或cgroup路径中的session-<id>.scope
+fn own_session() -> Option<String> {
+    if let Ok(id) = std::env::var("XDG_SESSION_ID")
+        && !id.is_empty()
+    {
+        return Some(id);
+    }
+    let cgroup = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+    cgroup
+        .split('/')
+        .find_map(|x| x.trim().strip_prefix("session-")?.strip_suffix(".scope"))
+        .map(String::from)
+}
+
+fn read_field(path: &Path, key: &str) -> Option<String> {
+    let content = std::fs::read_to_string(path).ok()?;
+    content
+        .lines()
+        .find_map(|x| x.strip_prefix(key)?.strip_prefix('='))
+        .map(String::from)
+}
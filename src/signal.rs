@@ -1,99 +1,99 @@
-use std::sync::Arc;
+use std::os::fd::{AsFd, BorrowedFd};
 
 use anyhow::{Context, Result};
-use nix::sys::epoll::{Epoll, EpollCreateFlags, EpollEvent, EpollFlags, EpollTimeout};
-use nix::sys::eventfd::{EfdFlags, EventFd};
 use nix::sys::signal::{SigSet, SigmaskHow, Signal, sigprocmask};
 use nix::sys::signalfd::{SfdFlags, SignalFd};
 
 use crate::cancel::CancelToken;
-use crate::{error, info};
+use crate::config::ReloadTrigger;
+use crate::event_loop::EventHandler;
+use crate::{info, warn};
 
+type SwitchSchemaCallback = Box<dyn FnMut() + Send + Sync>;
+
+// 监视信号：SIGINT、SIGTERM、SIGHUP通知所有任务退出；
+// SIGUSR1重新加载配置文件，SIGUSR2使所有绘图板切换到下一个按键映射方案
 pub struct ExitSignal {
     cancel_token: CancelToken,
     signalfd: SignalFd,
-    epoll: Epoll,
+    reload_trigger: Option<ReloadTrigger>, // 未指定配置文件时为None
+    switch_schema_callbacks: Vec<SwitchSchemaCallback>,
 }
 impl ExitSignal {
-    const EPOLL_CANCEL_EVENT: u64 = 0;
-    const EPOLL_SIGNAL_EVENT: u64 = 1;
-
     pub fn new(cancel_token: CancelToken) -> Result<Self> {
         let mut sigset = SigSet::empty();
         sigset.add(Signal::SIGINT);
         sigset.add(Signal::SIGTERM);
         sigset.add(Signal::SIGHUP);
+        sigset.add(Signal::SIGUSR1);
+        sigset.add(Signal::SIGUSR2);
         sigprocmask(SigmaskHow::SIG_BLOCK, Some(&sigset), None).context("sigprocmask")?;
         let signalfd =
             SignalFd::with_flags(&sigset, SfdFlags::SFD_NONBLOCK).context("SignalFd::new")?;
 
-        let cancel_eventfd =
-            EventFd::from_value_and_flags(0, EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_SEMAPHORE)
-                .context("EventFd::from_value_and_flags")?;
-        let cancel_eventfd = Arc::new(cancel_eventfd);
-        {
-            let cancel_eventfd = cancel_eventfd.clone();
-            cancel_token.register_callback(move || {
-                if let Err(e) = cancel_eventfd.write(1) {
-                    error!("无法通过写eventfd通知退出信号监视任务结束执行: {}", e);
-                }
-            });
-        }
+        Ok(Self {
+            cancel_token,
+            signalfd,
+            reload_trigger: None,
+            switch_schema_callbacks: Vec::new(),
+        })
+    }
 
-        let epoll = Epoll::new(EpollCreateFlags::all()).context("Epoll::new")?;
-        epoll
-            .add(
-                &cancel_eventfd,
-                EpollEvent::new(EpollFlags::EPOLLIN, Self::EPOLL_CANCEL_EVENT),
-            )
-            .context("Epoll::add(EventFd)")?;
-        epoll
-            .add(
-                &signalfd,
-                EpollEvent::new(EpollFlags::EPOLLIN, Self::EPOLL_SIGNAL_EVENT),
-            )
-            .context("Epoll::add(SignalFd)")?;
+    pub fn set_reload_trigger(&mut self, trigger: ReloadTrigger) {
+        self.reload_trigger.replace(trigger);
+    }
 
-        Ok(Self { cancel_token, signalfd, epoll })
+    pub fn register_switch_schema_callback<F>(&mut self, f: F)
+    where
+        F: FnMut() + Send + Sync + 'static,
+    {
+        self.switch_schema_callbacks.push(Box::new(f));
+    }
+}
+impl EventHandler for ExitSignal {
+    fn fds(&self) -> Vec<BorrowedFd<'_>> {
+        vec![self.signalfd.as_fd()]
     }
 
-    pub fn wait(self) -> Result<()> {
-        let mut events = [EpollEvent::empty(); 1];
-        loop {
-            let n = self.epoll.wait(&mut events, EpollTimeout::NONE)?;
-            if n == 0 {
-                continue;
-            }
-            match events[0].data() {
-                Self::EPOLL_CANCEL_EVENT => return Ok(()),
-                Self::EPOLL_SIGNAL_EVENT => {
-                    let siginfo = loop {
-                        match self
-                            .signalfd
-                            .read_signal()
-                            .context("SignalFd::read_signal")?
-                        {
-                            Some(x) => break x,
-                            None => continue,
-                        }
-                    };
-                    match siginfo.ssi_signo {
-                        x if x == Signal::SIGINT as _ => {
-                            info!("接收到SIGINT信号，准备退出");
-                        }
-                        x if x == Signal::SIGTERM as _ => {
-                            info!("接收到SIGTERM信号，准备退出");
+    fn handle(&mut self, _: usize) -> Result<()> {
+        while let Some(siginfo) = self
+            .signalfd
+            .read_signal()
+            .context("SignalFd::read_signal")?
+        {
+            match siginfo.ssi_signo {
+                x if x == Signal::SIGINT as _ => {
+                    info!("接收到SIGINT信号，准备退出");
+                }
+                x if x == Signal::SIGTERM as _ => {
+                    info!("接收到SIGTERM信号，准备退出");
+                }
+                x if x == Signal::SIGHUP as _ => {
+                    info!("接收到SIGHUP信号，准备退出");
+                }
+                x if x == Signal::SIGUSR1 as _ => {
+                    match &self.reload_trigger {
+                        Some(trigger) => {
+                            info!("接收到SIGUSR1信号，重新加载配置文件");
+                            trigger.trigger();
                         }
-                        x if x == Signal::SIGHUP as _ => {
-                            info!("接收到SIGHUP信号，准备退出");
+                        None => {
+                            warn!("接收到SIGUSR1信号，但启动时未指定配置文件，忽略");
                         }
-                        _ => unreachable!(),
                     }
-                    self.cancel_token.cancel();
-                    return Ok(());
+                    continue;
+                }
+                x if x == Signal::SIGUSR2 as _ => {
+                    info!("接收到SIGUSR2信号，切换到下一个按键映射方案");
+                    for callback in &mut self.switch_schema_callbacks {
+                        callback();
+                    }
+                    continue;
                 }
                 _ => unreachable!(),
             }
+            self.cancel_token.cancel();
         }
+        Ok(())
     }
 }
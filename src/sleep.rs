@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use nix::time::{ClockId, clock_gettime};
+
+// 两次检查之间休眠时长的增量超过该值时，视为系统经历了一次挂起；避免时钟的微小抖动被误判
+const MIN_SLEEP_DURATION: Duration = Duration::from_secs(1);
+
+// 检测系统挂起后的恢复：CLOCK_MONOTONIC在挂起期间停止，CLOCK_BOOTTIME继续计时，
+// 两者之差即为系统启动以来挂起的总时长。不依赖logind的PrepareForSleep信号（需要D-Bus），
+// 在进程被冻结前后都能工作
+pub struct SleepDetector {
+    slept: Duration,
+}
+impl SleepDetector {
+    pub fn new() -> Self {
+        Self { slept: slept() }
+    }
+
+    // 自上次调用以来系统挂起过时返回挂起的时长
+    pub fn check(&mut self) -> Option<Duration> {
+        let slept = slept();
+        let delta = slept.saturating_sub(self.slept);
+        self.slept = slept;
+        (delta >= MIN_SLEEP_DURATION).then_some(delta)
+    }
+}
+
+fn slept() -> Duration {
+    let now = |id| {
+        clock_gettime(id)
+            .map(Duration::from)
+            .unwrap_or(Duration::ZERO)
+    };
+    now(ClockId::CLOCK_BOOTTIME).saturating_sub(now(ClockId::CLOCK_MONOTONIC))
+}
@@ -0,0 +1,85 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::paths;
+
+// 运行时切换到的状态；变化时写入状态文件，启动时恢复，使重启驱动后保持之前的按键映射方案等。
+// 画笔模式由按键映射方案决定，因此随方案一起恢复
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeState {
+    schema: usize,
+    schema_name: Option<String>, // 方案配置了名称时优先按名称恢复，配置文件中方案的顺序变化后依然有效
+    area: Option<String>,        // 区域预设的名称；为None时使用配置中的area
+    monitor: Option<String>,     // 通过cycleMonitor或followMonitor切换到的显示器
+    #[serde(default)]
+    pressure_adjust: f32, // 通过pressureSofter、pressureHarder对压力曲线的调整
+}
+impl RuntimeState {
+    pub fn load(tablet_index: usize) -> Option<Self> {
+        let content = std::fs::read_to_string(Self::path(tablet_index)).ok()?;
+        toml::from_str(&content).ok()
+    }
+
+    pub fn new(
+        conf: &Config,
+        keymap_index: usize,
+        area_index: Option<usize>,
+        monitor_index: Option<usize>,
+        pressure_adjust: f32,
+    ) -> Self {
+        Self {
+            schema: keymap_index,
+            schema_name: conf.keymaps.get(keymap_index).and_then(|x| x.name.clone()),
+            area: area_index
+                .and_then(|x| conf.area_presets.get(x))
+                .map(|x| x.name.clone()),
+            monitor: monitor_index
+                .and_then(|x| conf.monitor_maps.get(x))
+                .map(|x| x.name.clone()),
+            pressure_adjust,
+        }
+    }
+
+    pub fn pressure_adjust(&self) -> f32 {
+        self.pressure_adjust
+    }
+
+    // 按当前的配置解析各状态对应的下标；找不到时使用默认值
+    pub fn resolve(&self, conf: &Config) -> (usize, Option<usize>, Option<usize>) {
+        let keymap_index = match &self.schema_name {
+            Some(name) => conf
+                .keymaps
+                .iter()
+                .position(|x| x.name.as_ref() == Some(name)),
+            None => None,
+        }
+        .or(Some(self.schema).filter(|x| *x < conf.keymaps.len()))
+        .unwrap_or(0);
+        let area_index = self
+            .area
+            .as_ref()
+            .and_then(|name| conf.area_presets.iter().position(|x| &x.name == name));
+        let monitor_index = self
+            .monitor
+            .as_ref()
+            .and_then(|name| conf.monitor_maps.iter().position(|x| &x.name == name));
+        (keymap_index, area_index, monitor_index)
+    }
+
+    pub fn save(&self, tablet_index: usize) -> Result<()> {
+        let path = Self::path(tablet_index);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(format!("无法创建目录{}", parent.display()))?;
+        }
+        let content = toml::to_string(self).context("无法序列化运行时状态")?;
+        std::fs::write(&path, content).context(format!("无法写入文件{}", path.display()))
+    }
+
+    fn path(tablet_index: usize) -> PathBuf {
+        paths::tablet_state_file("state", tablet_index)
+    }
+}
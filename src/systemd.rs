@@ -0,0 +1,81 @@
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::Once;
+use std::time::{Duration, Instant};
+
+use crate::{debug, warn};
+
+const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+
+// 按sd_notify的协议向systemd发送状态通知；未作为Type=notify的服务运行（未设置NOTIFY_SOCKET）时不做任何事
+pub fn notify(state: &str) {
+    let Some(path) = std::env::var_os(NOTIFY_SOCKET_ENV) else {
+        return;
+    };
+    let result = UnixDatagram::unbound().and_then(|socket| {
+        // 以@开头的路径表示抽象命名空间中的套接字
+        match path.as_bytes().strip_prefix(b"@") {
+            Some(name) => {
+                socket.send_to_addr(state.as_bytes(), &SocketAddr::from_abstract_name(name)?)
+            }
+            None => socket.send_to(state.as_bytes(), &path),
+        }
+    });
+    let state = state.replace('\n', " ");
+    match result {
+        Ok(_) => {
+            debug!("已向systemd发送通知: {}", state);
+        }
+        Err(e) => {
+            warn!("无法向systemd发送通知{}: {}", state, e);
+        }
+    }
+}
+
+// 首个绘图板的虚拟设备创建完成、设备连接后通知systemd服务已就绪；之后再次调用不做任何事
+pub fn notify_ready() {
+    static READY: Once = Once::new();
+    READY.call_once(|| notify("READY=1\nSTATUS=已连接设备"));
+}
+
+// 服务配置了WatchdogSec时，由驱动任务的主循环定期发送WATCHDOG=1；主循环卡住时systemd会重启服务
+pub struct Watchdog {
+    interval: Option<Duration>, // 为None时未启用看门狗
+    last_ping: Option<Instant>,
+}
+impl Watchdog {
+    pub fn new() -> Self {
+        Self {
+            interval: watchdog_interval(),
+            last_ping: None,
+        }
+    }
+
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    // 以看门狗超时时间的一半为间隔发送，留出余量
+    pub fn ping(&mut self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        if self.last_ping.is_some_and(|x| x.elapsed() < interval / 2) {
+            return;
+        }
+        self.last_ping = Some(Instant::now());
+        notify("WATCHDOG=1");
+    }
+}
+
+fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    // 设置了WATCHDOG_PID时，只有该进程需要发送WATCHDOG=1
+    if let Ok(pid) = std::env::var("WATCHDOG_PID")
+        && pid.parse() != Ok(std::process::id())
+    {
+        return None;
+    }
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
@@ -0,0 +1,207 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use nix::unistd::{AccessFlags, access};
+use rusb::UsbContext;
+
+use crate::cli::InstallUdevRulesArgs;
+use crate::config::Config;
+use crate::{hidraw, paths};
+
+pub const SUBCOMMAND: &str = "install-udev-rules";
+
+const RULES_PATH: &str = "/etc/udev/rules.d/70-parblo-intangbo-m.rules";
+const UINPUT_PATH: &str = "/dev/uinput";
+
+// install-udev-rules子命令：先检查当前用户能否访问uinput与已连接的绘图板，
+// 再生成授予访问权限的udev规则，使驱动程序无需root权限即可运行；
+// 默认写入RULES_PATH并通知udev重新加载，指定了--print时只打印规则
+pub fn install(args: InstallUdevRulesArgs) -> Result<()> {
+    let print = args.print;
+    let group = args.group.as_deref();
+    let conf = match args.config.path().or_else(paths::find_config) {
+        Some(path) => Config::load(&path).context("加载配置文件失败")?,
+        None => Config::default(),
+    };
+    let ids = device_ids(&conf);
+
+    // 检查结果输出到标准错误，--print时标准输出只包含规则，便于重定向
+    eprintln!("当前用户的访问权限：");
+    let all_accessible = check_access(&ids);
+    let rules = render_rules(&ids, group);
+    if print {
+        print!("{}", rules);
+        return Ok(());
+    }
+    if all_accessible && group.is_none() {
+        eprintln!("当前用户已经能够访问所需的设备节点；仍会写入规则，使设备重新插入后保持可访问");
+    }
+    if std::fs::read_to_string(RULES_PATH).is_ok_and(|x| x == rules) {
+        eprintln!("{}已是最新，无需修改", RULES_PATH);
+        return Ok(());
+    }
+    std::fs::write(RULES_PATH, &rules).with_context(|| {
+        format!(
+            "无法写入{}（需要root权限，或使用--print打印规则）",
+            RULES_PATH
+        )
+    })?;
+    eprintln!("已写入{}", RULES_PATH);
+    reload_udev();
+    match group {
+        Some(group) => eprintln!("请把运行驱动程序的用户加入用户组{}，并重新登录", group),
+        None => {
+            eprintln!("规则对当前登录会话中的用户生效；若仍无法访问，请重新插入绘图板或重新登录")
+        }
+    }
+    Ok(())
+}
+
+// 所有绘图板的vendor_id、product_id（包括无线接收器与蓝牙）
+fn device_ids(conf: &Config) -> Vec<(u16, u16)> {
+    let mut ids = vec![];
+    for tablet in std::iter::once(conf).chain(&conf.tablets) {
+        let device = &tablet.device;
+        let bluetooth = device.bluetooth_product_id.map(|x| (device.vendor_id, x));
+        for id in device.ids().into_iter().chain(bluetooth) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+    ids
+}
+
+// USB设备节点供libusb打开；hidraw与事件设备的父设备名称形如0003:0483:A013.0001，
+// 按此匹配可以同时覆盖USB与蓝牙连接；uinput用于创建虚拟设备
+fn render_rules(ids: &[(u16, u16)], group: Option<&str>) -> String {
+    let permission = match group {
+        Some(group) => format!("GROUP=\"{}\", MODE=\"0660\"", group),
+        None => "TAG+=\"uaccess\"".to_string(),
+    };
+    let mut rules = format!(
+        "# 由parblo-intangbo-m-driver {}生成：允许{}访问绘图板与uinput，驱动程序无需root权限即可运行\n",
+        SUBCOMMAND,
+        match group {
+            Some(group) => format!("用户组{}中的用户", group),
+            None => "当前登录的用户".to_string(),
+        }
+    );
+    for (vendor_id, product_id) in ids {
+        let _ = writeln!(
+            rules,
+            "SUBSYSTEM==\"usb\", ATTR{{idVendor}}==\"{:04x}\", ATTR{{idProduct}}==\"{:04x}\", {}",
+            vendor_id, product_id, permission
+        );
+        for subsystem in ["hidraw", "input"] {
+            let _ = writeln!(
+                rules,
+                "SUBSYSTEM==\"{}\", KERNELS==\"*:{:04X}:{:04X}.*\", {}",
+                subsystem, vendor_id, product_id, permission
+            );
+        }
+    }
+    let _ = writeln!(
+        rules,
+        "KERNEL==\"uinput\", SUBSYSTEM==\"misc\", OPTIONS+=\"static_node=uinput\", {}",
+        permission
+    );
+    rules
+}
+
+// 逐个输出设备节点能否读写；全部可以读写时返回true，未连接绘图板时只检查uinput
+fn check_access(ids: &[(u16, u16)]) -> bool {
+    let mut nodes = vec![PathBuf::from(UINPUT_PATH)];
+    match usb_nodes(ids) {
+        Ok(x) => nodes.extend(x),
+        Err(e) => eprintln!("  （无法列出USB设备：{:#}）", e),
+    }
+    match hidraw::list() {
+        Ok(infos) => nodes.extend(
+            infos
+                .into_iter()
+                .filter(|x| ids.contains(&(x.vendor_id, x.product_id)))
+                .map(|x| x.path),
+        ),
+        Err(e) => eprintln!("  （{:#}）", e),
+    }
+    nodes.extend(event_nodes(ids));
+    if nodes.len() == 1 {
+        eprintln!("  （未找到已连接的绘图板，只检查uinput）");
+    }
+    let mut all_accessible = true;
+    for node in nodes {
+        let result = access(&node, AccessFlags::R_OK | AccessFlags::W_OK);
+        all_accessible &= result.is_ok();
+        match result {
+            Ok(()) => eprintln!("  {}：可以读写", node.display()),
+            Err(e) => eprintln!("  {}：无法读写（{}）", node.display(), e.desc()),
+        }
+    }
+    all_accessible
+}
+
+fn usb_nodes(ids: &[(u16, u16)]) -> Result<Vec<PathBuf>> {
+    // 先创建上下文：libusb初始化失败时，rusb::devices会直接panic
+    let context = rusb::Context::new().context("rusb::Context::new")?;
+    let mut nodes = vec![];
+    for usb_device in context.devices().context("rusb::Context::devices")?.iter() {
+        let Ok(descriptor) = usb_device.device_descriptor() else {
+            continue;
+        };
+        if ids.contains(&(descriptor.vendor_id(), descriptor.product_id())) {
+            nodes.push(PathBuf::from(format!(
+                "/dev/bus/usb/{:03}/{:03}",
+                usb_device.bus_number(),
+                usb_device.address()
+            )));
+        }
+    }
+    Ok(nodes)
+}
+
+// 通过sysfs匹配事件设备，不需要打开设备节点
+fn event_nodes(ids: &[(u16, u16)]) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir("/sys/class/input") else {
+        return vec![];
+    };
+    let read_id = |sys_path: &Path, name: &str| {
+        let value = std::fs::read_to_string(sys_path.join("device/id").join(name)).ok()?;
+        u16::from_str_radix(value.trim(), 16).ok()
+    };
+    let mut nodes: Vec<_> = entries
+        .flatten()
+        .filter(|x| x.file_name().to_string_lossy().starts_with("event"))
+        .filter(|x| {
+            let id = (read_id(&x.path(), "vendor"), read_id(&x.path(), "product"));
+            matches!(id, (Some(v), Some(p)) if ids.contains(&(v, p)))
+        })
+        .map(|x| Path::new("/dev/input").join(x.file_name()))
+        .collect();
+    nodes.sort();
+    nodes
+}
+
+// 重新加载规则并对已连接的设备重新应用；失败时只输出提示，规则会在下次插入设备时生效
+fn reload_udev() {
+    let commands: [&[&str]; 2] = [
+        &["control", "--reload-rules"],
+        &[
+            "trigger",
+            "--action=change",
+            "--subsystem-match=usb",
+            "--subsystem-match=hidraw",
+            "--subsystem-match=input",
+            "--subsystem-match=misc",
+        ],
+    ];
+    for args in commands {
+        match Command::new("udevadm").args(args).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!("udevadm {}失败：{}", args.join(" "), status),
+            Err(e) => eprintln!("无法运行udevadm：{}", e),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::cell::RefCell;
+use std::os::fd::BorrowedFd;
+
+use anyhow::{Context, Result, anyhow};
+use evdev_rs::enums::{EV_SYN, EventCode};
+use evdev_rs::{InputEvent, TimeVal, UInputDevice};
+use nix::libc::input_event;
+
+use crate::io::OutputSink;
+
+// 虚拟设备：缓存写入的事件，收到SYN_REPORT时一次性写入uinput，
+// 使一个硬件报告对应的所有事件只产生一次系统调用（而不是每个事件一次）
+pub struct VirtualDevice {
+    device: UInputDevice,
+    pending: RefCell<Vec<input_event>>,
+}
+impl VirtualDevice {
+    pub fn new(device: UInputDevice) -> Self {
+        Self {
+            device,
+            pending: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn flush(&self) -> Result<()> {
+        let mut pending = self.pending.borrow_mut();
+        if pending.is_empty() {
+            return Ok(());
+        }
+        let fd = self.device.as_fd().context("UInputDevice::as_fd")?;
+        // SAFETY: fd由UInputDevice持有，在self的生命周期内有效；input_event为repr(C)的纯数据结构
+        let (fd, buf) = unsafe {
+            (
+                BorrowedFd::borrow_raw(fd),
+                std::slice::from_raw_parts(
+                    pending.as_ptr() as *const u8,
+                    std::mem::size_of_val(pending.as_slice()),
+                ),
+            )
+        };
+        let len = buf.len();
+        let result = nix::unistd::write(fd, buf);
+        pending.clear();
+        match result {
+            Ok(n) if n == len => Ok(()),
+            Ok(n) => Err(anyhow!("写入uinput时只写入了{}/{}字节", n, len)),
+            Err(e) => Err(e).context("nix::unistd::write(uinput)"),
+        }
+    }
+}
+impl OutputSink for VirtualDevice {
+    fn write_event(&self, code: EventCode, value: i32) -> Result<()> {
+        // 时间戳为0时由内核在收到事件时填写
+        let dummy_timeval = TimeVal::new(0, 0);
+        self.pending
+            .borrow_mut()
+            .push(InputEvent::new(&dummy_timeval, &code, value).as_raw());
+        if code == EventCode::EV_SYN(EV_SYN::SYN_REPORT) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, PadMode, PenMode};
+use crate::paths;
+
+// 本地的功能使用统计；仅在配置了usageStatistics = true时启用，不会上传到任何地方，由用户自行决定是否分享
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct UsageSummary {
+    // 累计运行次数
+    sessions: u64,
+
+    // 最近一次运行时的设备型号
+    device_model: String,
+
+    // 最近一次运行时的按键映射方案数量
+    schema_count: usize,
+
+    // 各按键映射方案被切换到的次数
+    schema_activations: BTreeMap<String, u64>,
+
+    // 各特殊行为被触发的次数
+    actions: BTreeMap<String, u64>,
+
+    // 启用了各项功能的运行次数
+    features: BTreeMap<String, u64>,
+}
+
+pub struct UsageStatistics {
+    path: PathBuf,
+    summary: UsageSummary,
+}
+impl UsageStatistics {
+    pub fn new(conf: &Config, device_model: String, tablet_index: usize) -> Self {
+        let path = paths::tablet_state_file("usage", tablet_index);
+        let mut summary: UsageSummary = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|x| toml::from_str(&x).ok())
+            .unwrap_or_default();
+        summary.sessions += 1;
+        summary.device_model = device_model;
+        summary.schema_count = conf.keymaps.len();
+        let features = [
+            ("xMap", conf.x_map.is_some()),
+            ("yMap", conf.y_map.is_some()),
+            ("area", conf.area.is_some()),
+            ("areaPreset", !conf.area_presets.is_empty()),
+            ("xGamma", conf.x_gamma.is_some()),
+            ("yGamma", conf.y_gamma.is_some()),
+            ("followMonitor", conf.follow_monitor),
+            ("notifications", conf.notifications),
+            ("osd", conf.osd),
+            ("nativePad", conf.pad_mode == PadMode::Native),
+            (
+                "relativePenMode",
+                conf.keymaps.iter().any(|x| x.pen_mode == PenMode::Relative),
+            ),
+            (
+                "schemaMap",
+                conf.keymaps
+                    .iter()
+                    .any(|x| x.x_map.is_some() || x.y_map.is_some()),
+            ),
+        ];
+        for (feature, enabled) in features {
+            if enabled {
+                *summary.features.entry(feature.to_string()).or_default() += 1;
+            }
+        }
+        Self { path, summary }
+    }
+
+    pub fn record_schema(&mut self, index: usize) {
+        *self
+            .summary
+            .schema_activations
+            .entry(index.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn record_action(&mut self, action: &str) {
+        *self.summary.actions.entry(action.to_string()).or_default() += 1;
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).context(format!("无法创建目录{}", parent.display()))?;
+        }
+        let content = toml::to_string(&self.summary).context("无法序列化使用统计")?;
+        std::fs::write(&self.path, content)
+            .context(format!("无法写入文件{}", self.path.display()))?;
+        Ok(self.path.clone())
+    }
+}
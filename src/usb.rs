@@ -0,0 +1,144 @@
+use std::ffi::c_void;
+use std::os::fd::{AsFd, BorrowedFd, OwnedFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::{Duration, Instant};
+
+use nix::libc::{self, c_int};
+use nix::sys::epoll::EpollFlags;
+use nix::sys::eventfd::{EfdFlags, EventFd};
+use rusb::ffi::{self, constants::*};
+use rusb::{DeviceHandle, Error as UsbError, GlobalContext, UsbContext};
+
+use crate::error;
+use crate::event_loop::{EventLoop, Watch};
+
+// 异步传输的状态：libusb在处理事件的线程中调用回调，该线程可能是共用全局上下文的其他绘图板的驱动任务，
+// 因此回调还要写eventfd唤醒等待的线程
+struct TransferState {
+    completed: AtomicI32, // 同时作为libusb_handle_events_*_completed的completed参数
+    eventfd: EventFd,
+}
+
+extern "system" fn transfer_callback(transfer: *mut ffi::libusb_transfer) {
+    // SAFETY: user_data指向read_interrupt_until中的TransferState，在completed置位之前不会被释放
+    let state = unsafe { &*((*transfer).user_data as *const TransferState) };
+    let _ = state.eventfd.write(1);
+    // 置位之后不能再访问state
+    state.completed.store(1, Ordering::Release);
+}
+
+// 读取中断端点，不设超时，直到收到报告、事件循环中的其他任务处理了事件（例如被唤醒）、取消或超过timeout，
+// 后几种情况返回Ok(None)。同步的read_interrupt无法被中断（libusb收到LIBUSB_ERROR_INTERRUPTED后会继续等待），
+// 因此提交异步传输，并把libusb的文件描述符加入事件循环，在同一个epoll中等待
+pub fn read_interrupt_until(
+    handle: &DeviceHandle<GlobalContext>,
+    endpoint: u8,
+    buf: &mut [u8],
+    event_loop: &mut EventLoop,
+    timeout: Option<Duration>,
+) -> Result<Option<usize>, UsbError> {
+    let context = handle.context().as_raw();
+    let state = TransferState {
+        completed: AtomicI32::new(0),
+        eventfd: EventFd::from_flags(EfdFlags::EFD_NONBLOCK).map_err(|_| UsbError::Io)?,
+    };
+    let mut fds = vec![(dup(state.eventfd.as_fd())?, EpollFlags::EPOLLIN)];
+    // SAFETY: 返回的数组以空指针结尾，使用完毕后通过libusb_free_pollfds释放
+    unsafe {
+        let pollfds = ffi::libusb_get_pollfds(context);
+        if pollfds.is_null() {
+            return Err(UsbError::NotSupported);
+        }
+        let mut i = 0;
+        while !(*pollfds.add(i)).is_null() {
+            let pollfd = &**pollfds.add(i);
+            // POLLIN、POLLOUT与EPOLLIN、EPOLLOUT的取值相同
+            let flags = EpollFlags::from_bits_truncate(pollfd.events as i32);
+            match dup(BorrowedFd::borrow_raw(pollfd.fd)) {
+                Ok(fd) => fds.push((fd, flags)),
+                Err(e) => {
+                    ffi::libusb_free_pollfds(pollfds);
+                    return Err(e);
+                }
+            }
+            i += 1;
+        }
+        ffi::libusb_free_pollfds(pollfds);
+    }
+    let index = event_loop
+        .add("USB设备", Watch(fds))
+        .map_err(|_| UsbError::Io)?;
+
+    // SAFETY: 传输结束之前（completed置位之前）不会离开本函数，buf与state在此期间一直有效
+    let (status, actual_length) = unsafe {
+        let transfer = ffi::libusb_alloc_transfer(0);
+        if transfer.is_null() {
+            event_loop.remove(index);
+            return Err(UsbError::NoMem);
+        }
+        ffi::libusb_fill_interrupt_transfer(
+            transfer,
+            handle.as_raw(),
+            endpoint,
+            buf.as_mut_ptr(),
+            buf.len() as c_int,
+            transfer_callback,
+            &state as *const TransferState as *mut c_void,
+            0,
+        );
+        let result = ffi::libusb_submit_transfer(transfer);
+        if result != 0 {
+            ffi::libusb_free_transfer(transfer);
+            event_loop.remove(index);
+            return Err(match result {
+                LIBUSB_ERROR_NO_DEVICE => UsbError::NoDevice,
+                _ => UsbError::Io,
+            });
+        }
+
+        let zero = libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        };
+        let deadline = timeout.map(|x| Instant::now() + x);
+        loop {
+            let remaining = deadline.map(|x| x.saturating_duration_since(Instant::now()));
+            let handled = match event_loop.wait(remaining) {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("等待USB设备的报告时发生错误: {:?}", e);
+                    vec![]
+                }
+            };
+            ffi::libusb_handle_events_timeout_completed(context, &zero, state.completed.as_ptr());
+            if state.completed.load(Ordering::Acquire) != 0 {
+                break;
+            }
+            // libusb的文件描述符上也可能是其他绘图板的传输，只有这种情况下继续等待
+            if handled.is_empty() || handled.iter().any(|x| *x != index) {
+                ffi::libusb_cancel_transfer(transfer);
+                while state.completed.load(Ordering::Acquire) == 0 {
+                    ffi::libusb_handle_events_completed(context, state.completed.as_ptr());
+                }
+                break;
+            }
+        }
+        event_loop.remove(index);
+        let result = ((*transfer).status, (*transfer).actual_length);
+        ffi::libusb_free_transfer(transfer);
+        result
+    };
+    match status {
+        LIBUSB_TRANSFER_COMPLETED => Ok(Some(actual_length as usize)),
+        LIBUSB_TRANSFER_CANCELLED | LIBUSB_TRANSFER_TIMED_OUT => Ok(None),
+        LIBUSB_TRANSFER_NO_DEVICE => Err(UsbError::NoDevice),
+        LIBUSB_TRANSFER_STALL => Err(UsbError::Pipe),
+        LIBUSB_TRANSFER_OVERFLOW => Err(UsbError::Overflow),
+        _ => Err(UsbError::Io),
+    }
+}
+
+// 事件循环在移除时注销文件描述符，为避免与libusb的关闭操作交错，只注册复制的文件描述符
+fn dup(fd: BorrowedFd) -> Result<OwnedFd, UsbError> {
+    fd.try_clone_to_owned().map_err(|_| UsbError::Io)
+}
@@ -0,0 +1,81 @@
+use anyhow::{Context, Result, anyhow};
+
+use crate::cli::ImportXsetwacomArgs;
+use crate::config;
+use crate::screen::{self, Geometry};
+
+type Maps = ((f32, f32), (f32, f32)); // (xMap, yMap)
+
+// 把xsetwacom的Area、MapToOutput转换为本程序的area与xMap、yMap配置并打印；
+// 指定了--write <配置文件>时，同时把xMap、yMap写入配置文件（与校准模式相同）
+pub fn import(args: &ImportXsetwacomArgs) -> Result<()> {
+    let area = match &args.area {
+        Some(values) => {
+            let values: Vec<_> = values
+                .iter()
+                .flat_map(|x| x.split_whitespace())
+                .map(String::from)
+                .collect();
+            Some(parse_area(&values)?)
+        }
+        None => None,
+    };
+    let maps = match args.map_to_output.as_deref() {
+        Some(output) => resolve_output(output)?,
+        None => None,
+    };
+    println!("# 由xsetwacom的配置转换");
+    if let Some((x1, y1, x2, y2)) = area {
+        println!("area = {{ x = [{}, {}], y = [{}, {}] }}", x1, x2, y1, y2);
+    }
+    if let Some((x_map, y_map)) = maps {
+        println!("xMap = [{:.4}, {:.4}]", x_map.0, x_map.1);
+        println!("yMap = [{:.4}, {:.4}]", y_map.0, y_map.1);
+    }
+    if let Some(path) = &args.write {
+        let (x_map, y_map) = maps.context("--write需要同时指定--map-to-output")?;
+        config::save_maps(path, x_map, y_map)?;
+        println!("# 已将xMap、yMap写入{}", path.display());
+    }
+    Ok(())
+}
+
+// xsetwacom的Area为"x1 y1 x2 y2"，使用虚拟绘图板的坐标；对应area配置的设备坐标（unit = "raw"）
+fn parse_area(values: &[String]) -> Result<(u16, u16, u16, u16)> {
+    let err = || anyhow!("'{}'不是有效的Area（格式：x1 y1 x2 y2）", values.join(" "));
+    let [x1, y1, x2, y2] = values else {
+        return Err(err());
+    };
+    let parse = |x: &String| x.parse::<u16>().map_err(|_| err());
+    let (x1, y1, x2, y2) = (parse(x1)?, parse(y1)?, parse(x2)?, parse(y2)?);
+    if x1 >= x2 || y1 >= y2 {
+        return Err(err());
+    }
+    Ok((x1, y1, x2, y2))
+}
+
+// MapToOutput支持显示器名称、HEAD-<序号>（按xrandr输出的顺序）、桌面中的几何区域，或desktop（整个桌面）；
+// 为desktop时不需要xMap、yMap
+fn resolve_output(output: &str) -> Result<Option<Maps>> {
+    if output.eq_ignore_ascii_case("desktop") {
+        return Ok(None);
+    }
+    let monitors = screen::query_monitors().context("无法查询显示器布局")?;
+    let desktop = screen::desktop_geometry(&monitors).context("无法计算桌面区域")?;
+    let target = if let Some(index) = output.strip_prefix("HEAD-") {
+        let index: usize = index
+            .parse()
+            .map_err(|_| anyhow!("'{}'不是有效的MapToOutput", output))?;
+        monitors
+            .get(index)
+            .with_context(|| format!("找不到第{}个显示器（共{}个）", index, monitors.len()))?
+            .geometry
+    } else if let Some(monitor) = monitors.iter().find(|x| x.name == output) {
+        monitor.geometry
+    } else {
+        output
+            .parse::<Geometry>()
+            .with_context(|| format!("找不到名为'{}'的显示器", output))?
+    };
+    screen::compute_map(desktop, target).map(Some)
+}
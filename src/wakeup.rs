@@ -0,0 +1,43 @@
+use std::os::fd::{AsFd, BorrowedFd};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nix::errno::Errno;
+use nix::sys::eventfd::{EfdFlags, EventFd};
+
+use crate::error;
+use crate::event_loop::EventHandler;
+
+// 唤醒在暂停、等待设备插入或空闲时阻塞的驱动任务：其他任务放入新的配置、控制命令、热插拔通知等之后调用wake；
+// 驱动任务把eventfd加入自己的事件循环，因此不必定期醒来检查
+#[derive(Clone)]
+pub struct Wakeup {
+    eventfd: Arc<EventFd>,
+}
+impl Wakeup {
+    pub fn new() -> Result<Self> {
+        let eventfd = EventFd::from_flags(EfdFlags::EFD_NONBLOCK).context("EventFd::from_flags")?;
+        Ok(Self {
+            eventfd: Arc::new(eventfd),
+        })
+    }
+
+    pub fn wake(&self) {
+        if let Err(e) = self.eventfd.write(1) {
+            error!("无法通过写eventfd唤醒驱动任务: {}", e);
+        }
+    }
+}
+impl EventHandler for Wakeup {
+    fn fds(&self) -> Vec<BorrowedFd<'_>> {
+        vec![self.eventfd.as_fd()]
+    }
+
+    // 只需清零计数：驱动任务醒来后总会检查其他任务放入的变动
+    fn handle(&mut self, _: usize) -> Result<()> {
+        match self.eventfd.read() {
+            Ok(_) | Err(Errno::EAGAIN) => Ok(()),
+            Err(e) => Err(e).context("EventFd::read"),
+        }
+    }
+}